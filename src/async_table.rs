@@ -0,0 +1,211 @@
+use std::io;
+use std::sync::Arc;
+
+use tokio::task;
+
+use crate::table::*;
+use crate::table_trait::*;
+use crate::table_error::*;
+
+
+/// AsyncTable gives async access to a table for the code running in
+/// a tokio runtime. The blocking file operations run on the blocking thread
+/// pool of tokio (the way **tokio::fs** does), so the runtime is not
+/// blocked and the file format, the locks, the cache and the WAL are those
+/// of **Table**. It is cheap to clone, the clones share the table.
+#[derive(Debug, Clone)]
+pub struct AsyncTable {
+    table: Arc<Table>,
+}
+
+
+impl AsyncTable {
+    /// Creates or opens a file to work, see **Table::open**.
+    pub async fn open<T: TableTrait>(path: &str) -> Result<Self, TableError> {
+        let path = path.to_string();
+        let table = _blocking(move || Table::open::<T>(&path)).await??;
+        Ok(Self::from_table(table))
+    }
+
+    /// Wraps a table opened in another way (e.g. **Table::with_wal**).
+    pub fn from_table(table: Table) -> Self {
+        Self { table: Arc::new(table) }
+    }
+
+    /// The table to call the methods that do not touch the file (e.g.
+    /// **size**). Its blocking methods must not be called from async code.
+    pub fn table(&self) -> &Table {
+        &self.table
+    }
+
+    /// Gets bytes of a record by its index.
+    pub async fn get(&self, idx: usize) -> Result<Vec<u8>, io::Error> {
+        self._run(move |table| table.get(idx)).await
+    }
+
+    /// Inserts data bytes to the end of file.
+    pub async fn append(&self, block: &[u8]) -> Result<usize, io::Error> {
+        let block = block.to_vec();
+        self._run(move |table| table.append(&block)).await
+    }
+
+    /// Inserts data bytes to the block of a deleted record if any, else to
+    /// the end of file.
+    pub async fn insert(&self, block: &[u8]) -> Result<usize, io::Error> {
+        let block = block.to_vec();
+        self._run(move |table| table.insert(&block)).await
+    }
+
+    /// Updates data bytes located by the index.
+    pub async fn update(
+                &self,
+                block: &[u8],
+                idx: usize
+            ) -> Result<(), io::Error> {
+        let block = block.to_vec();
+        self._run(move |table| table.update(&block, idx)).await
+    }
+
+    /// Extracts the record from the table by id, see **TableTrait::get**.
+    pub async fn get_record<T: TableTrait + Send + 'static>(
+                &self,
+                id: usize
+            ) -> Result<T, io::Error> {
+        self._run(move |table| T::get(table, id)).await
+    }
+
+    /// Inserts the record to the table setting its id, see
+    /// **TableTrait::insert**.
+    pub async fn insert_record<T: TableTrait + Send + 'static>(
+                &self,
+                record: &mut T
+            ) -> Result<usize, io::Error> {
+        let mut inserted = *record;
+        let (id, inserted) = self._run(move |table| {
+            inserted.insert(table).map(|id| (id, inserted))
+        }).await?;
+        *record = inserted;
+        Ok(id)
+    }
+
+    /// Updates the record in the table, see **TableTrait::update**.
+    pub async fn update_record<T: TableTrait + Send + 'static>(
+                &self,
+                record: &T
+            ) -> Result<(), io::Error> {
+        let record = *record;
+        self._run(move |table| record.update(table)).await
+    }
+
+    async fn _run<R: Send + 'static>(
+                &self,
+                f: impl FnOnce(&Table) -> Result<R, io::Error> + Send + 'static
+            ) -> Result<R, io::Error> {
+        let table = Arc::clone(&self.table);
+        _blocking(move || f(&table)).await?
+    }
+}
+
+
+async fn _blocking<R: Send + 'static>(
+            f: impl FnOnce() -> R + Send + 'static
+        ) -> Result<R, io::Error> {
+    task::spawn_blocking(f).await
+        .map_err(io::Error::other)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tokio::runtime;
+
+    use crate::varchar::*;
+    use super::*;
+
+    const TABLE_PATH: &str = "test-async-person.tbl";
+
+    #[derive(Debug, Copy, Clone)]
+    struct Person {
+        id: usize,
+        name: Varchar<20>,
+        age: u32,
+    }
+
+    impl TableTrait for Person {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    #[test]
+    fn test_async_table() {
+        _ensure_removed_tables();
+
+        let runtime = runtime::Builder::new_current_thread()
+            .build().unwrap();
+        runtime.block_on(async {
+            let table = AsyncTable::open::<Person>(TABLE_PATH).await
+                .unwrap();
+
+            let mut alex = Person {
+                id: 0, name: Varchar::<20>::new("alex"), age: 32
+            };
+            assert_eq!(table.insert_record(&mut alex).await.unwrap(), 1);
+            assert_eq!(alex.id, 1);
+            alex.age = 33;
+            table.update_record(&alex).await.unwrap();
+
+            let bob = Person {
+                id: 2, name: Varchar::<20>::new("bob"), age: 25
+            };
+            assert_eq!(table.append(bob.as_bytes()).await.unwrap(), 1);
+            assert_eq!(table.table().size().unwrap(), 2);
+
+            let person: Person = table.get_record(1).await.unwrap();
+            assert_eq!(person.age, 33);
+            let block = table.get(1).await.unwrap();
+            assert_eq!(Person::from_bytes(&block).name.to_string(), "bob");
+            let err = table.get_record::<Person>(3).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+            // Concurrent tasks share the table
+            let tasks: Vec<_> = (0..4).map(|_| {
+                let table = table.clone();
+                tokio::spawn(async move {
+                    let mut person = Person {
+                        id: 0, name: Varchar::<20>::new("carl"), age: 40
+                    };
+                    table.insert_record(&mut person).await.unwrap()
+                })
+            }).collect();
+            let mut ids = Vec::new();
+            for task in tasks {
+                ids.push(task.await.unwrap());
+            }
+            ids.sort();
+            assert_eq!(ids, vec![3, 4, 5, 6]);
+        });
+
+        // The same file format
+        let table = Table::open::<Person>(TABLE_PATH).unwrap();
+        assert_eq!(Person::get(&table, 1).unwrap().age, 33);
+        assert_eq!(Person::get(&table, 6).unwrap().name.to_string(), "carl");
+
+        _ensure_removed_tables();
+    }
+
+    fn _ensure_removed_tables() {
+        for ext in &["", ".free"] {
+            let path = format!("{}{}", TABLE_PATH, ext);
+            if fs::metadata(&path).is_ok() {
+                fs::remove_file(&path).unwrap();
+            }
+        }
+    }
+}
@@ -0,0 +1,328 @@
+use std::io;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::table::*;
+use crate::table_error::*;
+use crate::table_trait::*;
+
+
+const WORDS: usize = 8;
+const BITS: usize = 64 * WORDS;
+
+
+type ValueBlocks<T> = Vec<(T, Vec<usize>)>;
+
+
+/// Bitmap is a set of record ids, one bit per id. The bitmaps of several
+/// values are combined by **and** and **or** to filter by several fields.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Bitmap {
+    words: Vec<u64>,
+}
+
+
+impl Bitmap {
+    /// Creates an empty bitmap.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the **id** to the set.
+    pub fn insert(&mut self, id: usize) {
+        if self.words.len() <= id / 64 {
+            self.words.resize(id / 64 + 1, 0);
+        }
+        self.words[id / 64] |= 1 << (id % 64);
+    }
+
+    /// Removes the **id** from the set.
+    pub fn remove(&mut self, id: usize) {
+        if let Some(word) = self.words.get_mut(id / 64) {
+            *word &= !(1 << (id % 64));
+        }
+    }
+
+    /// Returns true if the **id** is in the set, else false.
+    pub fn contains(&self, id: usize) -> bool {
+        match self.words.get(id / 64) {
+            Some(word) => word & (1 << (id % 64)) != 0,
+            None => false,
+        }
+    }
+
+    /// The number of ids in the set.
+    pub fn len(&self) -> usize {
+        self.words.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    /// Returns true if the set is empty, else false.
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|word| *word == 0)
+    }
+
+    /// The ids that are in both sets.
+    pub fn and(&self, other: &Self) -> Self {
+        Self {
+            words: self.words.iter().zip(other.words.iter())
+                .map(|(a, b)| a & b)
+                .collect()
+        }
+    }
+
+    /// The ids that are in any of the sets.
+    pub fn or(&self, other: &Self) -> Self {
+        let (long, short) = if self.words.len() >= other.words.len() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        let mut words = long.words.clone();
+        for (word, other) in words.iter_mut().zip(short.words.iter()) {
+            *word |= other;
+        }
+        Self { words }
+    }
+
+    /// Iterates the ids in ascending order.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = usize> + '_> {
+        Box::new(self.words.iter().enumerate().flat_map(|(pos, word)| {
+            (0..64).filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| 64 * pos + bit)
+        }))
+    }
+}
+
+
+#[derive(Debug, Copy, Clone)]
+struct BitmapBlock<T> {
+    id: usize,
+    value: T,
+    chunk: usize,
+    bits: [u64; WORDS],
+}
+
+
+impl<T: Copy> TableTrait for BitmapBlock<T> {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn set_id(&mut self, id: usize) {
+        self.id = id;
+    }
+}
+
+
+/// BitmapIndex is an index for fields with a few distinct values (e.g.
+/// a flag or an enum), where a tree keeps too many nodes per value. Each
+/// value has a bitmap of the ids of its records, stored in the blocks of
+/// 512 ids. The ids of the blocks are kept in memory by value.
+pub struct BitmapIndex<T> {
+    table: Table,
+    blocks: Mutex<ValueBlocks<T>>,
+}
+
+
+impl<T: Copy + PartialEq> BitmapIndex<T> {
+    /// Creates or opens the index file.
+    pub fn open(path: &str) -> Result<Self, TableError> {
+        let table = Table::open::<BitmapBlock<T>>(path)?;
+        let mut blocks: ValueBlocks<T> = Vec::new();
+
+        for block in BitmapBlock::<T>::all(&table) {
+            let block = block?;
+            let pos = match blocks.iter().position(|(v, _)| *v == block.value) {
+                Some(pos) => pos,
+                None => {
+                    blocks.push((block.value, Vec::new()));
+                    blocks.len() - 1
+                },
+            };
+            let ids = &mut blocks[pos].1;
+            if ids.len() <= block.chunk {
+                ids.resize(block.chunk + 1, 0);
+            }
+            ids[block.chunk] = block.id;
+        }
+
+        Ok(Self { table, blocks: Mutex::new(blocks) })
+    }
+
+    /// The table of the index.
+    pub fn table(&self) -> &Table {
+        &self.table
+    }
+
+    /// The distinct values in the order of their first adding.
+    pub fn values(&self) -> Result<Vec<T>, io::Error> {
+        Ok(self._blocks()?.iter().map(|(value, _)| *value).collect())
+    }
+
+    /// Adds the record with **table_id** to the bitmap of the **value**.
+    pub fn add(&self, value: &T, table_id: usize) -> Result<(), io::Error> {
+        self._set(value, table_id, true)
+    }
+
+    /// Removes the record with **table_id** from the bitmap of
+    /// the **value**.
+    pub fn exclude(&self, value: &T, table_id: usize) -> Result<(), io::Error> {
+        self._set(value, table_id, false)
+    }
+
+    /// Moves the record with **table_id** to the bitmap of the **new**
+    /// value if it differs from the **old** one.
+    pub fn update(
+                &self,
+                old: &T,
+                new: &T,
+                table_id: usize
+            ) -> Result<(), io::Error> {
+        if old != new {
+            self.exclude(old, table_id)?;
+            self.add(new, table_id)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the bitmap of the ids of the records with the **value**.
+    pub fn bitmap(&self, value: &T) -> Result<Bitmap, io::Error> {
+        let blocks = self._blocks()?;
+        let mut bitmap = Bitmap::new();
+
+        if let Some((_, ids)) = blocks.iter().find(|(v, _)| v == value) {
+            bitmap.words = vec![0; ids.len() * WORDS];
+            for (chunk, id) in ids.iter().enumerate() {
+                if *id > 0 {
+                    let block = BitmapBlock::<T>::get(&self.table, *id)?;
+                    bitmap.words[chunk * WORDS..(chunk + 1) * WORDS]
+                        .clone_from_slice(&block.bits);
+                }
+            }
+        }
+
+        Ok(bitmap)
+    }
+
+    fn _set(
+                &self,
+                value: &T,
+                table_id: usize,
+                on: bool
+            ) -> Result<(), io::Error> {
+        let mut blocks = self._blocks()?;
+        let (chunk, bit) = (table_id / BITS, table_id % BITS);
+
+        let pos = match blocks.iter().position(|(v, _)| v == value) {
+            Some(pos) => pos,
+            None if on => {
+                blocks.push((*value, Vec::new()));
+                blocks.len() - 1
+            },
+            None => return Ok(()),
+        };
+        let ids = &mut blocks[pos].1;
+        if ids.len() <= chunk {
+            ids.resize(chunk + 1, 0);
+        }
+
+        let mut block = if ids[chunk] > 0 {
+            BitmapBlock::<T>::get(&self.table, ids[chunk])?
+        } else if on {
+            BitmapBlock { id: 0, value: *value, chunk, bits: [0; WORDS] }
+        } else {
+            return Ok(());
+        };
+
+        if on {
+            block.bits[bit / 64] |= 1 << (bit % 64);
+        } else {
+            block.bits[bit / 64] &= !(1 << (bit % 64));
+        }
+
+        if block.id == 0 {
+            ids[chunk] = block.insert(&self.table)?;
+        } else {
+            block.update(&self.table)?;
+        }
+        Ok(())
+    }
+
+    fn _blocks(&self) -> Result<MutexGuard<'_, ValueBlocks<T>>, io::Error> {
+        self.blocks.lock().map_err(
+            |_| io::Error::other("bitmap index lock")
+        )
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    const TABLE_INDEX_PATH: &str = "test-bitmap-index.tbl";
+
+    #[derive(Debug, Copy, Clone, PartialEq)]
+    enum Role {
+        User,
+        Admin,
+    }
+
+    #[test]
+    fn test_bitmap_index() {
+        _ensure_removed_table_file();
+
+        let active = BitmapIndex::<bool>::open(TABLE_INDEX_PATH).unwrap();
+        for id in 1..=1000 {
+            active.add(&(id % 3 == 0), id).unwrap();
+        }
+        assert_eq!(active.values().unwrap(), vec![false, true]);
+        assert_eq!(active.table().size().unwrap(), 4);
+        assert_eq!(active.bitmap(&true).unwrap().len(), 333);
+
+        // Update
+        active.update(&true, &false, 3).unwrap();
+        active.update(&false, &false, 4).unwrap();
+        let bitmap = active.bitmap(&true).unwrap();
+        assert!(!bitmap.contains(3));
+        assert!(bitmap.contains(999));
+        assert_eq!(bitmap.iter().take(2).collect::<Vec<usize>>(), vec![6, 9]);
+        drop(active);
+
+        // Reopen
+        let active = BitmapIndex::<bool>::open(TABLE_INDEX_PATH).unwrap();
+        assert_eq!(active.bitmap(&true).unwrap().len(), 332);
+        assert_eq!(active.bitmap(&false).unwrap().len(), 668);
+
+        // Combination
+        let mut admins = Bitmap::new();
+        for id in &[5, 6, 601, 999] {
+            admins.insert(*id);
+        }
+        let active_admins = admins.and(&active.bitmap(&true).unwrap());
+        assert_eq!(active_admins.iter().collect::<Vec<usize>>(), vec![6, 999]);
+        let any = admins.or(&active.bitmap(&true).unwrap());
+        assert_eq!(any.len(), 334);
+        assert!(BitmapIndex::<bool>::open(TABLE_INDEX_PATH).unwrap()
+            .bitmap(&true).unwrap().and(&Bitmap::new()).is_empty());
+
+        _ensure_removed_table_file();
+
+        // Enum values
+        let roles = BitmapIndex::<Role>::open(TABLE_INDEX_PATH).unwrap();
+        roles.add(&Role::Admin, 1).unwrap();
+        roles.add(&Role::User, 2).unwrap();
+        roles.exclude(&Role::User, 2).unwrap();
+        assert_eq!(roles.bitmap(&Role::Admin).unwrap().len(), 1);
+        assert!(roles.bitmap(&Role::User).unwrap().is_empty());
+
+        _ensure_removed_table_file();
+    }
+
+    fn _ensure_removed_table_file() {
+        if fs::metadata(TABLE_INDEX_PATH).is_ok() {
+            fs::remove_file(TABLE_INDEX_PATH).unwrap();
+        }
+    }
+}
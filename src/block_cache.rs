@@ -0,0 +1,190 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+
+/// BlockCache keeps up to **capacity** blocks in memory by their indices.
+/// The least recently used block is evicted first: each access takes
+/// the next tick, the ticks are ordered in a tree to find the oldest one.
+/// Besides, up to **pin_capacity** blocks can be pinned by their indices
+/// (e.g. the upper levels of an index), they are never evicted.
+#[derive(Debug)]
+pub struct BlockCache {
+    capacity: usize,
+    tick: u64,
+    blocks: HashMap<usize, (u64, Vec<u8>)>,
+    order: BTreeMap<u64, usize>,
+    pin_capacity: usize,
+    pins: HashSet<usize>,
+    pinned: HashMap<usize, Vec<u8>>,
+}
+
+
+impl BlockCache {
+    /// Creates an empty cache.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_pins(capacity, 0)
+    }
+
+    /// Creates an empty cache that can pin up to **pin_capacity** blocks
+    /// besides the **capacity** recently used ones.
+    pub fn with_pins(capacity: usize, pin_capacity: usize) -> Self {
+        Self {
+            capacity,
+            tick: 0,
+            blocks: HashMap::new(),
+            order: BTreeMap::new(),
+            pin_capacity,
+            pins: HashSet::new(),
+            pinned: HashMap::new(),
+        }
+    }
+
+    /// The number of cached blocks including the pinned ones.
+    pub fn len(&self) -> usize {
+        self.blocks.len() + self.pinned.len()
+    }
+
+    /// Returns true if no block is cached, else false.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The greatest number of pinned blocks.
+    pub fn pin_capacity(&self) -> usize {
+        self.pin_capacity
+    }
+
+    /// Replaces the pinned indices with the first **pin_capacity** of
+    /// **idxs**. The blocks of the indices that stay pinned are kept,
+    /// the others are dropped, the new ones are cached on the next put.
+    pub fn pin(&mut self, idxs: &[usize]) {
+        self.pins = idxs.iter().take(self.pin_capacity).copied().collect();
+        let pins = &self.pins;
+        self.pinned.retain(|idx, _| pins.contains(idx));
+        for idx in self.pins.iter() {
+            if let Some((used, block)) = self.blocks.remove(idx) {
+                self.order.remove(&used);
+                self.pinned.insert(*idx, block);
+            }
+        }
+    }
+
+    /// Gets a copy of the cached block and marks it as recently used.
+    pub fn get(&mut self, idx: usize) -> Option<Vec<u8>> {
+        if let Some(block) = self.pinned.get(&idx) {
+            return Some(block.clone());
+        }
+        let tick = self._next_tick();
+        let (used, block) = self.blocks.get_mut(&idx)?;
+        self.order.remove(used);
+        self.order.insert(tick, idx);
+        *used = tick;
+        Some(block.clone())
+    }
+
+    /// Caches the block evicting the least recently used one if the cache
+    /// is full.
+    pub fn put(&mut self, idx: usize, block: Vec<u8>) {
+        if self.pins.contains(&idx) {
+            self.pinned.insert(idx, block);
+            return;
+        }
+        if self.capacity == 0 {
+            return;
+        }
+        self.remove(idx);
+        if self.blocks.len() >= self.capacity {
+            let oldest = self.order.iter().next()
+                .map(|(used, idx)| (*used, *idx));
+            if let Some((used, evicted)) = oldest {
+                self.order.remove(&used);
+                self.blocks.remove(&evicted);
+            }
+        }
+        let tick = self._next_tick();
+        self.order.insert(tick, idx);
+        self.blocks.insert(idx, (tick, block));
+    }
+
+    /// Drops the block from the cache. A pinned index stays pinned.
+    pub fn remove(&mut self, idx: usize) {
+        self.pinned.remove(&idx);
+        if let Some((used, _)) = self.blocks.remove(&idx) {
+            self.order.remove(&used);
+        }
+    }
+
+    /// Drops all blocks. The pinned indices stay pinned.
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+        self.order.clear();
+        self.pinned.clear();
+    }
+
+    fn _next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_cache() {
+        let mut cache = BlockCache::new(2);
+        assert!(cache.get(0).is_none());
+
+        cache.put(0, vec![1]);
+        cache.put(1, vec![2]);
+        assert_eq!(cache.get(0), Some(vec![1]));
+
+        // The least recently used block is evicted
+        cache.put(2, vec![3]);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(1).is_none());
+        assert_eq!(cache.get(2), Some(vec![3]));
+
+        // Replacement and removal
+        cache.put(0, vec![4]);
+        assert_eq!(cache.get(0), Some(vec![4]));
+        cache.remove(0);
+        assert!(cache.get(0).is_none());
+        cache.clear();
+        assert!(cache.is_empty());
+
+        let mut cache = BlockCache::new(0);
+        cache.put(0, vec![1]);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_block_cache_pins() {
+        let mut cache = BlockCache::with_pins(1, 2);
+        cache.put(0, vec![1]);
+        cache.pin(&[0, 1, 2]);
+        assert_eq!(cache.pin_capacity(), 2);
+
+        // The pinned blocks are not evicted
+        cache.put(1, vec![2]);
+        cache.put(2, vec![3]);
+        cache.put(3, vec![4]);
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get(0), Some(vec![1]));
+        assert_eq!(cache.get(1), Some(vec![2]));
+        assert!(cache.get(2).is_none());
+        assert_eq!(cache.get(3), Some(vec![4]));
+
+        // A removed block is pinned again on the next put
+        cache.remove(1);
+        assert!(cache.get(1).is_none());
+        cache.put(1, vec![5]);
+        cache.put(4, vec![6]);
+        assert_eq!(cache.get(1), Some(vec![5]));
+
+        cache.pin(&[1]);
+        assert!(cache.get(0).is_none());
+        assert_eq!(cache.get(1), Some(vec![5]));
+    }
+}
@@ -1,8 +1,32 @@
-use std::fmt;
+use std::{cmp, error, fmt, hash};
+use std::convert::TryFrom;
 
+use crate::index_key::*;
 
-/// A structure to store bytes of data and the length.
-#[derive(Copy, Clone, PartialOrd, PartialEq, Eq, Hash, Ord)]
+
+/// The error of a byte slice longer than the capacity of **Bytes**.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BytesTooLong {
+    /// The length of the slice.
+    pub len: usize,
+    /// The capacity of the Bytes.
+    pub capacity: usize,
+}
+
+
+impl fmt::Display for BytesTooLong {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} bytes exceed bytes of {}", self.len, self.capacity)
+    }
+}
+
+
+impl error::Error for BytesTooLong {}
+
+
+/// A structure to store bytes of data and the length. Bytes are compared
+/// by the length first and then by the stored bytes.
+#[derive(Copy, Clone)]
 pub struct Bytes<const N: usize> {
     length: usize,
     bytes: [u8; N],
@@ -10,27 +34,196 @@ pub struct Bytes<const N: usize> {
 
 
 impl<const N: usize> Bytes<N> {
-    /// Creates Bytes from [u8] slice.
+    /// Creates Bytes from [u8] slice. It panics if **b** is longer than
+    /// **N**, see **try_new**.
     pub fn new(b: &[u8]) -> Self {
         let length = b.len();
         assert!(length <= N);
         let mut bytes = [0u8; N];
-        bytes[..length].clone_from_slice(&b);
+        bytes[..length].clone_from_slice(b);
         Self { bytes, length }
     }
+
+    /// Creates Bytes from [u8] slice, **BytesTooLong** is returned if **b**
+    /// is longer than **N**.
+    pub fn try_new(b: &[u8]) -> Result<Self, BytesTooLong> {
+        if b.len() > N {
+            return Err(BytesTooLong { len: b.len(), capacity: N });
+        }
+        Ok(Self::new(b))
+    }
+
+    /// Gets the stored bytes. The length read from a damaged file is cut
+    /// to the capacity.
+    pub fn as_slice(&self) -> &[u8] {
+        &self.bytes[..self.len()]
+    }
+
+    /// The number of the stored bytes.
+    pub fn len(&self) -> usize {
+        self.length.min(N)
+    }
+
+    /// Returns true if no bytes are stored, else false.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The greatest number of the stored bytes.
+    pub fn capacity(&self) -> usize {
+        N
+    }
+}
+
+
+impl<const N: usize> Default for Bytes<N> {
+    /// Creates empty Bytes.
+    fn default() -> Self {
+        Self { bytes: [0u8; N], length: 0 }
+    }
+}
+
+
+impl<const N: usize> PartialEq for Bytes<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+
+impl<const N: usize> Eq for Bytes<N> {}
+
+
+impl<const N: usize> PartialOrd for Bytes<N> {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+
+impl<const N: usize> Ord for Bytes<N> {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.len().cmp(&other.len())
+            .then_with(|| self.as_slice().cmp(other.as_slice()))
+    }
+}
+
+
+impl<const N: usize> hash::Hash for Bytes<N> {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        // The stored bytes padded by zeros are hashed like all the bytes
+        // before, so the hash indexes over Bytes keep their buckets
+        let mut bytes = [0u8; N];
+        bytes[..self.len()].clone_from_slice(self.as_slice());
+        self.len().hash(state);
+        bytes.hash(state);
+    }
+}
+
+
+impl<const N: usize, const M: usize> From<&[u8; M]> for Bytes<N> {
+    /// Creates Bytes from an array (e.g. a hash or a UUID). It panics if
+    /// **M** is greater than **N** like **new**.
+    fn from(b: &[u8; M]) -> Self {
+        Self::new(b)
+    }
+}
+
+
+impl<const N: usize> TryFrom<&[u8]> for Bytes<N> {
+    type Error = BytesTooLong;
+
+    fn try_from(b: &[u8]) -> Result<Self, Self::Error> {
+        Self::try_new(b)
+    }
+}
+
+
+impl<const N: usize> IndexKey for Bytes<N> {
+    fn key_tag() -> u64 {
+        key_tag_of(&format!("Bytes/{}", N))
+    }
+}
+
+
+impl<const N: usize> AsRef<[u8]> for Bytes<N> {
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
 }
 
 
 impl<const N: usize> fmt::Display for Bytes<N> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self.bytes)
+        write!(f, "{:?}", self.as_slice())
     }
 }
 
 
 impl<const N: usize> fmt::Debug for Bytes<N> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Bytes<{}>{:?}", N, self.bytes)
+        write!(f, "Bytes<{}>{:?}", N, self.as_slice())
+    }
+}
+
+
+#[cfg(feature = "serde")]
+impl<const N: usize> serde::Serialize for Bytes<N> {
+    fn serialize<S: serde::Serializer>(
+                &self,
+                serializer: S
+            ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.as_slice())
+    }
+}
+
+
+#[cfg(feature = "serde")]
+impl<'de, const N: usize> serde::Deserialize<'de> for Bytes<N> {
+    /// The bytes are accepted as bytes or a sequence of numbers (as in
+    /// JSON), more than **N** of them is an error.
+    fn deserialize<D: serde::Deserializer<'de>>(
+                deserializer: D
+            ) -> Result<Self, D::Error> {
+        deserializer.deserialize_bytes(BytesVisitor::<N>)
+    }
+}
+
+
+#[cfg(feature = "serde")]
+struct BytesVisitor<const N: usize>;
+
+
+#[cfg(feature = "serde")]
+impl<'de, const N: usize> serde::de::Visitor<'de> for BytesVisitor<N> {
+    type Value = Bytes<N>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at most {} bytes", N)
+    }
+
+    fn visit_bytes<E: serde::de::Error>(
+                self,
+                v: &[u8]
+            ) -> Result<Self::Value, E> {
+        if v.len() > N {
+            return Err(E::invalid_length(v.len(), &self));
+        }
+        Ok(Bytes::new(v))
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                mut seq: A
+            ) -> Result<Self::Value, A::Error> {
+        let mut bytes = Vec::new();
+        while let Some(byte) = seq.next_element::<u8>()? {
+            if bytes.len() == N {
+                return Err(serde::de::Error::invalid_length(N + 1, &self));
+            }
+            bytes.push(byte);
+        }
+        Ok(Bytes::new(&bytes))
     }
 }
 
@@ -44,7 +237,38 @@ mod tests {
     #[test]
     fn test_bytes() {
         let b = Bytes::<32>::new(b"bytes32");
-        assert_eq!(b.to_string(), String::from("[98, 121, 116, 101, 115, 51, 50, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]"));
+        assert_eq!(b.to_string(), "[98, 121, 116, 101, 115, 51, 50]");
+        assert_eq!(format!("{:?}", b),
+                   "Bytes<32>[98, 121, 116, 101, 115, 51, 50]");
         assert_eq!(mem::size_of::<Bytes::<32>>(), 40);
+
+        // Bytes read from a damaged file are cut to the capacity
+        let damaged = Bytes::<4> { length: 100, bytes: [1, 2, 3, 4] };
+        assert_eq!(damaged.as_slice(), &[1, 2, 3, 4]);
+        assert_eq!(damaged.to_string(), "[1, 2, 3, 4]");
+        assert_eq!(damaged, Bytes::<4>::new(&[1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn test_bytes_slice_api() {
+        let hash = [0xabu8; 16];
+        let b = Bytes::<16>::from(&hash);
+        assert_eq!(b.as_slice(), &hash[..]);
+        assert_eq!(b.len(), 16);
+        assert_eq!(b.capacity(), 16);
+        assert_eq!(b.as_ref(), &hash[..]);
+
+        let b = Bytes::<32>::from(b"uuid");
+        assert_eq!(b, Bytes::<32>::new(b"uuid"));
+        assert!(Bytes::<32>::default().is_empty());
+
+        assert_eq!(Bytes::<4>::try_new(&[1, 2, 3, 4, 5]).unwrap_err(),
+                   BytesTooLong { len: 5, capacity: 4 });
+        let b = Bytes::<4>::try_from(&[1u8, 2][..]).unwrap();
+        assert_eq!(b.as_slice(), &[1, 2]);
+
+        // Shorter bytes go first
+        assert!(Bytes::<4>::new(&[9]) < Bytes::<4>::new(&[1, 2]));
+        assert!(Bytes::<4>::new(&[1, 2]) < Bytes::<4>::new(&[1, 3]));
     }
 }
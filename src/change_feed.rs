@@ -0,0 +1,70 @@
+use std::fmt;
+use std::sync::{RwLock, PoisonError};
+
+
+/// The kind of a write to a block of a table.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ChangeKind {
+    /// The block was added to the end of the table (**append**,
+    /// **append_many** or **insert** without a free block).
+    Append,
+    /// The block was rewritten (**update**, **insert** into the block of
+    /// a deleted record or **store_all**).
+    Update,
+    /// The block was filled with zeros (**erase** or **delete**).
+    Erase,
+    /// The blocks from the index on were cut from the table
+    /// (**truncate_to** or **clear**), the index is the new size.
+    Truncate,
+}
+
+
+/// A committed write to a record of a table of a database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeEvent {
+    /// The name of the table in the schema.
+    pub table: String,
+    /// The id of the record, for *Truncate* the first removed id.
+    pub id: usize,
+    /// The kind of the write.
+    pub kind: ChangeKind,
+}
+
+
+type Watcher = Box<dyn Fn(usize, ChangeKind) + Send + Sync>;
+
+
+/// The callbacks of a table called with the index of the block after each
+/// finished write.
+#[derive(Default)]
+pub(crate) struct Watchers {
+    watchers: RwLock<Vec<Watcher>>,
+}
+
+
+impl Watchers {
+    pub(crate) fn add(
+                &self,
+                watcher: impl Fn(usize, ChangeKind) + Send + Sync + 'static
+            ) {
+        self.watchers.write().unwrap_or_else(PoisonError::into_inner)
+            .push(Box::new(watcher));
+    }
+
+    pub(crate) fn notify(&self, idx: usize, kind: ChangeKind) {
+        let watchers = self.watchers.read()
+            .unwrap_or_else(PoisonError::into_inner);
+        for watcher in watchers.iter() {
+            watcher(idx, kind);
+        }
+    }
+}
+
+
+impl fmt::Debug for Watchers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count = self.watchers.read()
+            .unwrap_or_else(PoisonError::into_inner).len();
+        write!(f, "Watchers({})", count)
+    }
+}
@@ -0,0 +1,155 @@
+use std::fmt;
+
+use crate::index_key::*;
+
+
+/// The position of the key among the keys with the same first value.
+/// Stored keys are exact, the edges are used as range bounds only.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+enum Edge {
+    Before,
+    Exact,
+    After,
+}
+
+
+/// CompositeKey is a value of **TableIndex** over two fields (e.g.
+/// *(last_name, first_name)*). The keys are ordered lexicographically:
+/// by **first** and then by **second**. Tuples *(A, B)* are ordered the same
+/// way and can be used as the index value too, the composite key adds
+/// the bounds to iterate all keys with the same first value.
+#[derive(Copy, Clone, PartialEq, PartialOrd)]
+pub struct CompositeKey<A, B> {
+    first: A,
+    edge: Edge,
+    second: B,
+}
+
+
+impl<A: Copy, B: Copy> CompositeKey<A, B> {
+    /// Creates the key from the values of the fields.
+    pub fn new(first: A, second: B) -> Self {
+        Self { first, edge: Edge::Exact, second }
+    }
+
+    /// Gets the value of the first field.
+    pub fn first(&self) -> A {
+        self.first
+    }
+
+    /// Gets the value of the second field.
+    pub fn second(&self) -> B {
+        self.second
+    }
+
+    /// Returns the keys that are less and greater than all keys with
+    /// the **first** value, so they can be used as included bounds of
+    /// **TableIndex::iter_range** to iterate the keys with this prefix.
+    pub fn prefix_bounds(first: A) -> (Self, Self) where B: Default {
+        (
+            Self { first, edge: Edge::Before, second: B::default() },
+            Self { first, edge: Edge::After, second: B::default() },
+        )
+    }
+}
+
+
+impl<A: Copy, B: Copy> From<(A, B)> for CompositeKey<A, B> {
+    fn from(pair: (A, B)) -> Self {
+        Self::new(pair.0, pair.1)
+    }
+}
+
+
+impl<A: Copy, B: Copy> From<CompositeKey<A, B>> for (A, B) {
+    fn from(key: CompositeKey<A, B>) -> Self {
+        (key.first, key.second)
+    }
+}
+
+
+impl<A: IndexKey, B: IndexKey> IndexKey for CompositeKey<A, B> {
+    fn key_tag() -> u64 {
+        key_tag_of(&format!(
+            "CompositeKey({:x},{:x})", A::key_tag(), B::key_tag()
+        ))
+    }
+}
+
+
+impl<A: fmt::Debug, B: fmt::Debug> fmt::Debug for CompositeKey<A, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.edge {
+            Edge::Exact => write!(
+                f, "CompositeKey({:?}, {:?})", self.first, self.second
+            ),
+            edge => write!(f, "CompositeKey({:?}, {:?})", self.first, edge),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::ops::Bound;
+
+    use crate::table::*;
+    use crate::table_index::*;
+    use crate::varchar::*;
+    use super::*;
+
+    const TABLE_NAME_INDEX_PATH: &str = "test-composite-key-name-index.tbl";
+
+    type NameKey = CompositeKey<Varchar<20>, Varchar<20>>;
+
+    #[test]
+    fn test_composite_key() {
+        _ensure_removed_table_file();
+
+        let name_index = Table::open::<TableIndex<NameKey>>(
+            TABLE_NAME_INDEX_PATH
+        ).unwrap();
+
+        let persons = [
+            ("smith", "john"), ("brown", "anna"), ("smith", "adam"),
+            ("stone", "bob"), ("smith", "kate"),
+        ];
+        for (id, (last, first)) in persons.iter().enumerate() {
+            let key = NameKey::new(
+                Varchar::<20>::new(last), Varchar::<20>::new(first)
+            );
+            TableIndex::add(&name_index, &key, id + 1).unwrap();
+        }
+
+        // Exact key
+        let key = NameKey::from(
+            (Varchar::<20>::new("smith"), Varchar::<20>::new("adam"))
+        );
+        assert_eq!(TableIndex::search_one(&name_index, &key).unwrap(), 3);
+        let (last, first): (Varchar<20>, Varchar<20>) = key.into();
+        assert_eq!((last.to_string(), first.to_string()),
+                   ("smith".to_string(), "adam".to_string()));
+
+        // Prefix
+        let (from, to) = NameKey::prefix_bounds(Varchar::<20>::new("smith"));
+        let ids: Vec<usize> = TableIndex::iter_range(
+            &name_index, Bound::Included(&from), Bound::Included(&to)
+        ).collect::<Result<_, _>>().unwrap();
+        assert_eq!(ids, vec![3, 1, 5]);
+
+        let (from, to) = NameKey::prefix_bounds(Varchar::<20>::new("green"));
+        assert_eq!(TableIndex::iter_range(
+            &name_index, Bound::Included(&from), Bound::Included(&to)
+        ).count(), 0);
+
+        _ensure_removed_table_file();
+    }
+
+    fn _ensure_removed_table_file() {
+        if fs::metadata(TABLE_NAME_INDEX_PATH).is_ok() {
+            fs::remove_file(TABLE_NAME_INDEX_PATH).unwrap();
+        }
+    }
+}
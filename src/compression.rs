@@ -0,0 +1,287 @@
+use std::io;
+
+use crate::table::*;
+
+
+/// The slot keeps the block encoded by zero suppression.
+const TAG_ENCODED: u8 = 0;
+
+/// The slot keeps the index of the raw block in the overflow table.
+const TAG_OVERFLOW: u8 = 1;
+
+/// The slot keeps the block compressed by LZ4.
+#[cfg(feature = "lz4")]
+const TAG_LZ4: u8 = 2;
+
+/// The slot keeps the block compressed by Zstandard.
+#[cfg(feature = "zstd")]
+const TAG_ZSTD: u8 = 3;
+
+/// The tag and the index of the overflow block.
+pub(crate) const MIN_SLOT_SIZE: usize = 9;
+
+/// The key of the metadata area keeping the name of the compression kind.
+pub(crate) const COMPRESSION_META_KEY: &str = "compression";
+
+/// A pair of the number of zeros and the number of literal bytes.
+const RUN_SIZE: usize = 4;
+
+/// The tag and the length of the compressed bytes (2 bytes LE).
+#[cfg(any(feature = "lz4", feature = "zstd"))]
+const FRAME_SIZE: usize = 3;
+
+/// The level of Zstandard, the default one of the library.
+#[cfg(feature = "zstd")]
+const ZSTD_LEVEL: i32 = 3;
+
+
+/// The way the blocks of a compressed table are encoded into its slots. It
+/// is chosen when the table is created and stored in its header, see
+/// **Table::with_compression_kind**.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CompressionKind {
+    /// The runs of zeros are suppressed, the fastest way for the padding
+    /// of the wide fields.
+    Zeros,
+    /// LZ4 also compresses the repeated bytes, it is nearly as fast.
+    #[cfg(feature = "lz4")]
+    Lz4,
+    /// Zstandard compresses best, but it is slower.
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+
+impl CompressionKind {
+    /// The name stored in the header.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Zeros => "zeros",
+            #[cfg(feature = "lz4")]
+            Self::Lz4 => "lz4",
+            #[cfg(feature = "zstd")]
+            Self::Zstd => "zstd",
+        }
+    }
+}
+
+
+/// Compression stores the blocks of a table in slots smaller than
+/// the blocks. A block is written to its slot as runs of zeros and literal
+/// bytes, each run starting with the number of zeros and the number of
+/// literal bytes (2 bytes LE each), the trailing zeros are omitted and
+/// a run of two zero numbers ends the slot. A block that does not fit
+/// the slot is written raw to the overflow table (**path.overflow**)
+/// and the slot keeps its index. An all-zero slot is an all-zero block,
+/// so the erased and the appended blocks need no overflow. The other kinds
+/// write the tag, the length of the compressed bytes (2 bytes LE) and
+/// the bytes, an all-zero block is written as runs by any kind.
+#[derive(Debug)]
+pub(crate) struct Compression {
+    kind: CompressionKind,
+    data_size: usize,
+    overflow: Box<Table>,
+}
+
+
+impl Compression {
+    pub(crate) fn open(
+                path: &str,
+                kind: CompressionKind,
+                data_size: usize
+            ) -> Result<Self, io::Error> {
+        let overflow = Table::open_with_block_size(
+            &format!("{}.overflow", path), data_size
+        )?;
+        Ok(Self { kind, data_size, overflow: Box::new(overflow) })
+    }
+
+    pub(crate) fn kind(&self) -> CompressionKind {
+        self.kind
+    }
+
+    pub(crate) fn data_size(&self) -> usize {
+        self.data_size
+    }
+
+    /// Encodes **block** into a slot of **slot_size** bytes, it is written
+    /// to the overflow table if it does not fit.
+    pub(crate) fn encode(
+                &self,
+                block: &[u8],
+                slot_size: usize
+            ) -> Result<Vec<u8>, io::Error> {
+        if let Some(slot) = self._encode_slot(block, slot_size)? {
+            return Ok(slot);
+        }
+
+        let idx = self.overflow.insert(block)?;
+        let mut slot = vec![TAG_OVERFLOW];
+        slot.extend_from_slice(&(idx as u64).to_le_bytes());
+        slot.resize(slot_size, 0);
+        Ok(slot)
+    }
+
+    /// Decodes the block from **slot**.
+    pub(crate) fn decode(&self, slot: &[u8]) -> Result<Vec<u8>, io::Error> {
+        match slot[0] {
+            TAG_ENCODED => self._decode_runs(slot),
+            TAG_OVERFLOW => {
+                let mut block = self.overflow.get(Self::_overflow_idx(slot))?;
+                block.resize(self.data_size, 0);
+                Ok(block)
+            },
+            #[cfg(feature = "lz4")]
+            TAG_LZ4 => self._check_size(
+                lz4_flex::block::decompress(
+                    Self::_unframe(slot)?, self.data_size
+                ).map_err(
+                    |err| io::Error::new(io::ErrorKind::InvalidData, err)
+                )?
+            ),
+            #[cfg(feature = "zstd")]
+            TAG_ZSTD => self._check_size(
+                zstd::bulk::decompress(Self::_unframe(slot)?, self.data_size)?
+            ),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "slot tag")),
+        }
+    }
+
+    /// Deletes the overflow block of the replaced **slot** if any.
+    pub(crate) fn release(&self, slot: &[u8]) -> Result<(), io::Error> {
+        if slot[0] == TAG_OVERFLOW {
+            self.overflow.delete(Self::_overflow_idx(slot))?;
+        }
+        Ok(())
+    }
+
+    /// Tells if the overflow block of the replaced **slot** still keeps
+    /// **block**.
+    pub(crate) fn keeps(
+                &self,
+                slot: &[u8],
+                block: &[u8]
+            ) -> Result<bool, io::Error> {
+        if slot[0] != TAG_OVERFLOW {
+            return Ok(false);
+        }
+        let mut kept = self.overflow.get(Self::_overflow_idx(slot))?;
+        kept.resize(self.data_size, 0);
+        Ok(kept == block)
+    }
+
+    fn _encode_slot(
+                &self,
+                block: &[u8],
+                slot_size: usize
+            ) -> Result<Option<Vec<u8>>, io::Error> {
+        if block.iter().all(|byte| *byte == 0) {
+            return Ok(Self::_encode_runs(block, slot_size));
+        }
+
+        Ok(match self.kind {
+            CompressionKind::Zeros => Self::_encode_runs(block, slot_size),
+            #[cfg(feature = "lz4")]
+            CompressionKind::Lz4 => Self::_frame(
+                TAG_LZ4, &lz4_flex::block::compress(block), slot_size
+            ),
+            #[cfg(feature = "zstd")]
+            CompressionKind::Zstd => Self::_frame(
+                TAG_ZSTD, &zstd::bulk::compress(block, ZSTD_LEVEL)?, slot_size
+            ),
+        })
+    }
+
+    #[cfg(any(feature = "lz4", feature = "zstd"))]
+    fn _frame(tag: u8, bytes: &[u8], slot_size: usize) -> Option<Vec<u8>> {
+        if (FRAME_SIZE + bytes.len() > slot_size) ||
+                (bytes.len() > u16::MAX as usize) {
+            return None;
+        }
+
+        let mut slot = vec![tag];
+        slot.extend_from_slice(&(bytes.len() as u16).to_le_bytes());
+        slot.extend_from_slice(bytes);
+        slot.resize(slot_size, 0);
+        Some(slot)
+    }
+
+    #[cfg(any(feature = "lz4", feature = "zstd"))]
+    fn _unframe(slot: &[u8]) -> Result<&[u8], io::Error> {
+        let len = u16::from_le_bytes([slot[1], slot[2]]) as usize;
+        slot.get(FRAME_SIZE..FRAME_SIZE + len).ok_or_else(
+            || io::Error::new(io::ErrorKind::InvalidData, "slot")
+        )
+    }
+
+    #[cfg(any(feature = "lz4", feature = "zstd"))]
+    fn _check_size(&self, block: Vec<u8>) -> Result<Vec<u8>, io::Error> {
+        if block.len() != self.data_size {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "slot"));
+        }
+        Ok(block)
+    }
+
+    fn _encode_runs(block: &[u8], slot_size: usize) -> Option<Vec<u8>> {
+        let end = block.iter().rposition(|byte| *byte != 0)
+            .map_or(0, |pos| pos + 1);
+        let mut slot = vec![TAG_ENCODED];
+        let mut pos = 0;
+
+        while pos < end {
+            let zeros = block[pos..end].iter().take(u16::MAX as usize)
+                .take_while(|byte| **byte == 0).count();
+            pos += zeros;
+
+            // The literal bytes go on until the zeros are worth a new run
+            let start = pos;
+            while (pos < end) && (pos - start < u16::MAX as usize) &&
+                    !block[pos..].starts_with(&[0; RUN_SIZE]) {
+                pos += 1;
+            }
+
+            slot.extend_from_slice(&(zeros as u16).to_le_bytes());
+            slot.extend_from_slice(&((pos - start) as u16).to_le_bytes());
+            slot.extend_from_slice(&block[start..pos]);
+            if slot.len() > slot_size {
+                return None;
+            }
+        }
+
+        slot.resize(slot_size, 0);
+        Some(slot)
+    }
+
+    fn _decode_runs(&self, slot: &[u8]) -> Result<Vec<u8>, io::Error> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "slot");
+        let number = |pos: usize| {
+            u16::from_le_bytes([slot[pos], slot[pos + 1]]) as usize
+        };
+
+        let mut block = Vec::with_capacity(self.data_size);
+        let mut pos = 1;
+        while pos + RUN_SIZE <= slot.len() {
+            let (zeros, count) = (number(pos), number(pos + 2));
+            if (zeros == 0) && (count == 0) {
+                break;
+            }
+            pos += RUN_SIZE;
+            if (pos + count > slot.len()) ||
+                    (block.len() + zeros + count > self.data_size) {
+                return Err(invalid());
+            }
+            block.resize(block.len() + zeros, 0);
+            block.extend_from_slice(&slot[pos..pos + count]);
+            pos += count;
+        }
+
+        block.resize(self.data_size, 0);
+        Ok(block)
+    }
+
+    fn _overflow_idx(slot: &[u8]) -> usize {
+        let mut bytes = [0u8; 8];
+        bytes.clone_from_slice(&slot[1..MIN_SLOT_SIZE]);
+        u64::from_le_bytes(bytes) as usize
+    }
+}
@@ -0,0 +1,496 @@
+use std::{fs, io};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use crate::table::*;
+use crate::table_trait::*;
+use crate::table_index::*;
+use crate::index_key::*;
+use crate::table_error::*;
+use crate::wal::WriteAheadLog;
+#[cfg(feature = "tokio")]
+use crate::change_feed::ChangeEvent;
+
+#[cfg(feature = "tokio")]
+use tokio::sync::broadcast;
+#[cfg(feature = "tokio")]
+use tokio_stream::wrappers::BroadcastStream;
+
+
+/// The number of change events kept for the slowest subscriber, after that
+/// it skips the oldest ones (see **Database::subscribe**).
+#[cfg(feature = "tokio")]
+pub const CHANGE_BUFFER_SIZE: usize = 1024;
+
+
+/// The stream of the changes of a database returned by
+/// **Database::subscribe**.
+#[cfg(feature = "tokio")]
+pub type ChangeStream = BroadcastStream<ChangeEvent>;
+
+
+trait SchemaTable {
+    fn name(&self) -> &str;
+    fn open(&self, path: &str) -> Result<Table, TableError>;
+}
+
+
+struct TableSpec<T> {
+    name: String,
+    record: PhantomData<T>,
+}
+
+
+impl<T: TableTrait> SchemaTable for TableSpec<T> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn open(&self, path: &str) -> Result<Table, TableError> {
+        Table::open::<T>(path)
+    }
+}
+
+
+trait SchemaIndex {
+    fn name(&self) -> &str;
+    fn table(&self) -> &str;
+    fn open(&self, path: &str) -> Result<Table, TableError>;
+    fn verify(&self, index: &Table, data: &Table) -> Result<(), io::Error>;
+    fn rebuild(&self, path: &str, data: &Table) -> Result<Table, io::Error>;
+}
+
+
+struct KeyIndexSpec<T, K> {
+    name: String,
+    table: String,
+    key: fn(&T) -> K,
+}
+
+
+impl<T: TableTrait, K: Copy + PartialOrd + IndexKey> KeyIndexSpec<T, K> {
+    fn _entries(&self, data: &Table) -> Result<Vec<(K, usize)>, io::Error> {
+        let mut entries = Vec::new();
+        for record in T::all(data) {
+            let record = record?;
+            if record.id() != 0 {
+                entries.push(((self.key)(&record), record.id()));
+            }
+        }
+        Ok(entries)
+    }
+}
+
+
+impl<T: TableTrait, K: Copy + PartialOrd + IndexKey> SchemaIndex for KeyIndexSpec<T, K> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn table(&self) -> &str {
+        &self.table
+    }
+
+    fn open(&self, path: &str) -> Result<Table, TableError> {
+        TableIndex::<K>::open(path)
+    }
+
+    fn verify(&self, index: &Table, data: &Table) -> Result<(), io::Error> {
+        TableIndex::<K>::check(index)?;
+
+        let mut stored = TableIndex::<K>::entries(index)?;
+        let mut expected = self._entries(data)?;
+        stored.sort_by_key(|entry| entry.1);
+        expected.sort_by_key(|entry| entry.1);
+
+        let same = (stored.len() == expected.len()) &&
+            stored.iter().zip(expected.iter()).all(|(a, b)| {
+                (a.1 == b.1) && (a.0 == b.0)
+            });
+        if same {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::InvalidData, "index entries"))
+        }
+    }
+
+    fn rebuild(&self, path: &str, data: &Table) -> Result<Table, io::Error> {
+        if fs::metadata(path).is_ok() {
+            fs::remove_file(path)?;
+        }
+        let index = Table::open::<TableIndex<K>>(path)?;
+        TableIndex::add_batch(&index, &self._entries(data)?)?;
+        Ok(index)
+    }
+}
+
+
+/// DatabaseSchema lists the tables of a database directory and
+/// the indexes over them, so they can be opened and checked together by
+/// **Database::open_or_repair**. A table named *person* is stored in
+/// the file *person.tbl*, its index named *age* in *person-age-index.tbl*.
+pub struct DatabaseSchema {
+    tables: Vec<Box<dyn SchemaTable>>,
+    indexes: Vec<Box<dyn SchemaIndex>>,
+    rebuild: bool,
+}
+
+
+impl DatabaseSchema {
+    /// Creates an empty schema.
+    pub fn new() -> Self {
+        Self { tables: Vec::new(), indexes: Vec::new(), rebuild: false }
+    }
+
+    /// Adds a table of the records **T** with the **name**.
+    pub fn table<T: TableTrait + 'static>(mut self, name: &str) -> Self {
+        self.tables.push(Box::new(TableSpec::<T> {
+            name: name.to_string(),
+            record: PhantomData,
+        }));
+        self
+    }
+
+    /// Adds an index with the **name** over the value extracted by **key**
+    /// from the records of the table **table**.
+    pub fn index<T, K>(
+                mut self,
+                table: &str,
+                name: &str,
+                key: fn(&T) -> K
+            ) -> Self
+            where T: TableTrait + 'static, K: Copy + PartialOrd + IndexKey + 'static {
+        self.indexes.push(Box::new(KeyIndexSpec {
+            name: name.to_string(),
+            table: table.to_string(),
+            key,
+        }));
+        self
+    }
+
+    /// Rebuilds the broken indexes from their tables on open, else they are
+    /// only reported. It is off by default.
+    pub fn rebuild_indexes(mut self, rebuild: bool) -> Self {
+        self.rebuild = rebuild;
+        self
+    }
+}
+
+
+impl Default for DatabaseSchema {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// What was found and repaired by **Database::open_or_repair**. The names of
+/// indexes are given as *table/index*.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    /// The files whose write-ahead log was replayed with the number of
+    /// the finished writes.
+    pub replayed: Vec<(String, usize)>,
+    /// The indexes that failed the check with the reason.
+    pub broken_indexes: Vec<(String, String)>,
+    /// The broken indexes rebuilt from their tables.
+    pub rebuilt_indexes: Vec<String>,
+}
+
+
+impl RepairReport {
+    /// Returns true if nothing was replayed or broken, else false.
+    pub fn clean(&self) -> bool {
+        self.replayed.is_empty() && self.broken_indexes.is_empty()
+    }
+}
+
+
+/// Database is a directory of tables and indexes opened together.
+pub struct Database {
+    tables: HashMap<String, Table>,
+    indexes: HashMap<String, Table>,
+    #[cfg(feature = "tokio")]
+    changes: broadcast::Sender<ChangeEvent>,
+}
+
+
+impl Database {
+    /// Opens the tables and indexes of the **schema** in the directory
+    /// **dir** (it is created if missing) after an unclean shutdown:
+    /// the headers are checked, the write-ahead logs are replayed and
+    /// the indexes are checked against their tables. A table with a wrong
+    /// header is an error. A broken index is rebuilt if the schema allows
+    /// it, else it is reported and left closed.
+    pub fn open_or_repair(
+                dir: &str,
+                schema: &DatabaseSchema
+            ) -> Result<(Self, RepairReport), io::Error> {
+        fs::create_dir_all(dir)?;
+
+        let mut report = RepairReport::default();
+        let mut tables = HashMap::new();
+        let mut indexes = HashMap::new();
+
+        for spec in schema.tables.iter() {
+            let path = format!("{}/{}.tbl", dir, spec.name());
+            Self::_count_replayed(&path, spec.name(), &mut report)?;
+            tables.insert(spec.name().to_string(), spec.open(&path)?);
+        }
+
+        for spec in schema.indexes.iter() {
+            let name = format!("{}/{}", spec.table(), spec.name());
+            let path = format!(
+                "{}/{}-{}-index.tbl", dir, spec.table(), spec.name()
+            );
+            let data = tables.get(spec.table()).ok_or_else(
+                || io::Error::new(io::ErrorKind::NotFound, spec.table())
+            )?;
+
+            Self::_count_replayed(&path, &name, &mut report)?;
+
+            let checked = spec.open(&path).map_err(io::Error::from)
+                .and_then(|index| {
+                    spec.verify(&index, data)?;
+                    Ok(index)
+                });
+
+            match checked {
+                Ok(index) => {
+                    indexes.insert(name, index);
+                },
+                Err(err) => {
+                    report.broken_indexes.push(
+                        (name.clone(), err.to_string())
+                    );
+                    if schema.rebuild {
+                        let index = spec.rebuild(&path, data)?;
+                        indexes.insert(name.clone(), index);
+                        report.rebuilt_indexes.push(name);
+                    }
+                },
+            }
+        }
+
+        #[cfg(feature = "tokio")]
+        let changes = Self::_watch_tables(&tables);
+
+        Ok((
+            Self {
+                tables,
+                indexes,
+                #[cfg(feature = "tokio")]
+                changes,
+            },
+            report
+        ))
+    }
+
+    /// The table with the **name**.
+    pub fn table(&self, name: &str) -> Option<&Table> {
+        self.tables.get(name)
+    }
+
+    /// The index with the **name** over the table **table**, None if it
+    /// is broken and was not rebuilt.
+    pub fn index(&self, table: &str, name: &str) -> Option<&Table> {
+        self.indexes.get(&format!("{}/{}", table, name))
+    }
+
+    /// Subscribes to the writes to the tables of the database finished
+    /// after this call (through **table** by any thread). The stream yields
+    /// a **ChangeEvent** per written record. The events are buffered up to
+    /// **CHANGE_BUFFER_SIZE**, a subscriber that falls behind skips
+    /// the oldest ones and gets *Lagged* with their number instead.
+    /// The writes to the indexes are not reported.
+    #[cfg(feature = "tokio")]
+    pub fn subscribe(&self) -> ChangeStream {
+        BroadcastStream::new(self.changes.subscribe())
+    }
+
+    #[cfg(feature = "tokio")]
+    fn _watch_tables(
+                tables: &HashMap<String, Table>
+            ) -> broadcast::Sender<ChangeEvent> {
+        let (changes, _) = broadcast::channel(CHANGE_BUFFER_SIZE);
+        for (name, table) in tables.iter() {
+            let changes = changes.clone();
+            let name = name.clone();
+            table.watch(move |idx, kind| {
+                // No subscribers is not an error
+                let _ = changes.send(ChangeEvent {
+                    table: name.clone(), id: idx + 1, kind
+                });
+            });
+        }
+        changes
+    }
+
+    fn _count_replayed(
+                path: &str,
+                name: &str,
+                report: &mut RepairReport
+            ) -> Result<(), io::Error> {
+        let wal_path = format!("{}.wal", path);
+        if fs::metadata(&wal_path).is_ok() {
+            let count = WriteAheadLog::open(&wal_path)?.entries()?.len();
+            if count > 0 {
+                report.replayed.push((name.to_string(), count));
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::varchar::*;
+    use super::*;
+
+    const DATABASE_DIR: &str = "test-database";
+
+    #[derive(Debug, Copy, Clone)]
+    struct Person {
+        id: usize,
+        name: Varchar<20>,
+        age: u32,
+    }
+
+    impl TableTrait for Person {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    fn schema() -> DatabaseSchema {
+        DatabaseSchema::new()
+            .table::<Person>("person")
+            .index::<Person, u32>("person", "age", |p| p.age)
+    }
+
+    #[test]
+    fn test_open_or_repair() {
+        _ensure_removed_dir();
+
+        // Clean open
+        let (db, report) = Database::open_or_repair(
+            DATABASE_DIR, &schema()
+        ).unwrap();
+        assert!(report.clean());
+
+        let table = db.table("person").unwrap();
+        let age_index = db.index("person", "age").unwrap();
+        for (name, age) in &[("alex", 32), ("bob", 25), ("carl", 40)] {
+            let mut person = Person {
+                id: 0, name: Varchar::<20>::new(name), age: *age
+            };
+            let id = person.insert(table).unwrap();
+            TableIndex::add(age_index, age, id).unwrap();
+        }
+        drop(db);
+
+        // Interrupted write and an index out of sync
+        let path = format!("{}/person.tbl", DATABASE_DIR);
+        let carl = Person { id: 3, name: Varchar::<20>::new("carl"), age: 41 };
+        WriteAheadLog::open(&format!("{}.wal", path)).unwrap()
+            .log(2, carl.as_bytes()).unwrap();
+
+        let (db, report) = Database::open_or_repair(
+            DATABASE_DIR, &schema()
+        ).unwrap();
+        assert_eq!(report.replayed, vec![("person".to_string(), 1)]);
+        assert_eq!(report.broken_indexes.len(), 1);
+        assert_eq!(report.broken_indexes[0].0, "person/age");
+        assert!(report.rebuilt_indexes.is_empty());
+        assert!(db.index("person", "age").is_none());
+        let table = db.table("person").unwrap();
+        let replayed = Person::get(table, 3).unwrap();
+        assert_eq!((replayed.name.to_string(), replayed.age),
+                   ("carl".to_string(), 41));
+        drop(db);
+
+        // Rebuild
+        let (db, report) = Database::open_or_repair(
+            DATABASE_DIR, &schema().rebuild_indexes(true)
+        ).unwrap();
+        assert_eq!(report.rebuilt_indexes, vec!["person/age".to_string()]);
+        let age_index = db.index("person", "age").unwrap();
+        assert_eq!(TableIndex::search_one(age_index, &41u32).unwrap(), 3);
+        drop(db);
+
+        let (_, report) = Database::open_or_repair(
+            DATABASE_DIR, &schema()
+        ).unwrap();
+        assert!(report.clean());
+
+        _ensure_removed_dir();
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_subscribe() {
+        use tokio::runtime;
+        use tokio_stream::StreamExt;
+        use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+
+        use crate::change_feed::*;
+
+        const CHANGES_DIR: &str = "test-database-changes";
+        if fs::metadata(CHANGES_DIR).is_ok() {
+            fs::remove_dir_all(CHANGES_DIR).unwrap();
+        }
+
+        let (db, _) = Database::open_or_repair(CHANGES_DIR, &schema())
+            .unwrap();
+        let table = db.table("person").unwrap();
+        let mut alex = Person {
+            id: 0, name: Varchar::<20>::new("alex"), age: 32
+        };
+        alex.insert(table).unwrap();
+
+        let runtime = runtime::Builder::new_current_thread()
+            .build().unwrap();
+        runtime.block_on(async {
+            // Only the writes after the subscription
+            let mut changes = db.subscribe();
+            let mut bob = Person {
+                id: 0, name: Varchar::<20>::new("bob"), age: 25
+            };
+            bob.insert(table).unwrap();
+            alex.delete(table).unwrap();
+            TableIndex::add(db.index("person", "age").unwrap(), &25u32, 2)
+                .unwrap();
+
+            let event = |id, kind| Some(Ok(ChangeEvent {
+                table: "person".to_string(), id, kind
+            }));
+            assert_eq!(changes.next().await, event(2, ChangeKind::Append));
+            assert_eq!(changes.next().await, event(2, ChangeKind::Update));
+            assert_eq!(changes.next().await, event(1, ChangeKind::Erase));
+
+            // A slow subscriber skips the oldest events
+            let mut persons = vec![alex; CHANGE_BUFFER_SIZE + 10];
+            for person in persons.iter_mut() {
+                person.set_id(0);
+            }
+            Person::insert_many(&mut persons, table).unwrap();
+            assert_eq!(changes.next().await,
+                       Some(Err(BroadcastStreamRecvError::Lagged(10))));
+            assert_eq!(changes.next().await, event(13, ChangeKind::Append));
+        });
+
+        drop(db);
+        fs::remove_dir_all(CHANGES_DIR).unwrap();
+    }
+
+    fn _ensure_removed_dir() {
+        if fs::metadata(DATABASE_DIR).is_ok() {
+            fs::remove_dir_all(DATABASE_DIR).unwrap();
+        }
+    }
+}
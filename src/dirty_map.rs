@@ -0,0 +1,144 @@
+use std::{fs, io};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::file_ext::FileExt;
+
+
+const TOKEN_SIZE: usize = 8;
+
+
+/// DirtyMap is a bitmap of the table blocks changed since the last backup.
+/// It is stored in its own file: the first 8 bytes keep the backup token,
+/// the rest is one bit per block. The marks are made under a lock, so
+/// concurrent writers of the blocks sharing a byte do not lose them.
+#[derive(Debug)]
+pub struct DirtyMap {
+    file: Mutex<fs::File>,
+}
+
+
+impl DirtyMap {
+    /// Creates or opens a bitmap file.
+    pub fn open(path: &str) -> Result<Self, io::Error> {
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        if file.metadata()?.len() < TOKEN_SIZE as u64 {
+            file.write_all_at(&[0u8; TOKEN_SIZE], 0)?;
+        }
+        Ok(Self { file: Mutex::new(file) })
+    }
+
+    /// The token of the last backup.
+    pub fn token(&self) -> Result<u64, io::Error> {
+        let mut bytes = [0u8; TOKEN_SIZE];
+        self._file()?.read_exact_at(&mut bytes, 0)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// Marks the block by its index as changed.
+    pub fn mark(&self, idx: usize) -> Result<(), io::Error> {
+        let file = self._file()?;
+        let offset = (TOKEN_SIZE + idx / 8) as u64;
+        let mut byte = [0u8; 1];
+        if offset < file.metadata()?.len() {
+            file.read_exact_at(&mut byte, offset)?;
+        }
+        byte[0] |= 1 << (idx % 8);
+        file.write_all_at(&byte, offset)
+    }
+
+    /// Returns true if the block was changed since the last backup.
+    pub fn is_dirty(&self, idx: usize) -> Result<bool, io::Error> {
+        let file = self._file()?;
+        let offset = (TOKEN_SIZE + idx / 8) as u64;
+        let mut byte = [0u8; 1];
+        if offset < file.metadata()?.len() {
+            file.read_exact_at(&mut byte, offset)?;
+        }
+        Ok(byte[0] & (1 << (idx % 8)) != 0)
+    }
+
+    /// Clears all marks and stores the token of the new backup.
+    pub fn reset(&self, token: u64) -> Result<(), io::Error> {
+        let file = self._file()?;
+        file.set_len(TOKEN_SIZE as u64)?;
+        file.write_all_at(&token.to_le_bytes(), 0)?;
+        file.sync_data()
+    }
+
+    fn _file(&self) -> Result<MutexGuard<'_, fs::File>, io::Error> {
+        self.file.lock().map_err(
+            |_| io::Error::other("dirty map lock")
+        )
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, thread};
+    use std::sync::Arc;
+
+    use super::*;
+
+    const DIRTY_MAP_PATH: &str = "test-dirty-map.dirty";
+    const DIRTY_MAP_CONCURRENT_PATH: &str = "test-dirty-map-concurrent.dirty";
+
+    #[test]
+    fn test_dirty_map() {
+        _ensure_removed_file();
+
+        let dirty = DirtyMap::open(DIRTY_MAP_PATH).unwrap();
+        assert_eq!(dirty.token().unwrap(), 0);
+
+        dirty.mark(3).unwrap();
+        dirty.mark(20).unwrap();
+        assert!(dirty.is_dirty(3).unwrap());
+        assert!(dirty.is_dirty(20).unwrap());
+        assert!(!dirty.is_dirty(4).unwrap());
+        assert!(!dirty.is_dirty(100).unwrap());
+
+        dirty.reset(7).unwrap();
+        assert_eq!(DirtyMap::open(DIRTY_MAP_PATH).unwrap().token().unwrap(), 7);
+        assert!(!dirty.is_dirty(3).unwrap());
+
+        _ensure_removed_file();
+    }
+
+    #[test]
+    fn test_concurrent_marks() {
+        if fs::metadata(DIRTY_MAP_CONCURRENT_PATH).is_ok() {
+            fs::remove_file(DIRTY_MAP_CONCURRENT_PATH).unwrap();
+        }
+
+        // Each thread marks its own bit of every byte
+        let dirty = Arc::new(
+            DirtyMap::open(DIRTY_MAP_CONCURRENT_PATH).unwrap()
+        );
+        let writers: Vec<_> = (0..8).map(|bit| {
+            let dirty = dirty.clone();
+            thread::spawn(move || {
+                for byte in 0..200 {
+                    dirty.mark(byte * 8 + bit).unwrap();
+                }
+            })
+        }).collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        assert!((0..1600).all(|idx| dirty.is_dirty(idx).unwrap()));
+
+        fs::remove_file(DIRTY_MAP_CONCURRENT_PATH).unwrap();
+    }
+
+    fn _ensure_removed_file() {
+        if fs::metadata(DIRTY_MAP_PATH).is_ok() {
+            fs::remove_file(DIRTY_MAP_PATH).unwrap();
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use std::{fmt, io};
+
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+
+use crate::table::CorruptBlock;
+
+
+/// The size of the key of an encrypted table in bytes.
+pub const ENCRYPTION_KEY_SIZE: usize = 32;
+
+/// The bytes added to each block of an encrypted table: the random nonce
+/// (24 bytes) and the authentication tag (16 bytes).
+pub const ENCRYPTION_OVERHEAD: usize = NONCE_SIZE + 16;
+
+const NONCE_SIZE: usize = 24;
+
+/// The additional data of the key check, the blocks use their indices.
+const KEY_CHECK_DATA: &[u8] = b"MYTABLE key check";
+
+
+/// Encryption seals the blocks of a table by XChaCha20-Poly1305. Each write
+/// takes a new random nonce stored before the ciphertext, the nonces are
+/// long enough to be chosen at random for any number of writes. The index
+/// of the block is authenticated with it, so a block copied to another
+/// place fails to open as well as a changed one. The erased records are
+/// sealed too, so an all-zero block is never written and fails to open
+/// like any changed one.
+#[derive(Clone)]
+pub(crate) struct Encryption {
+    cipher: XChaCha20Poly1305,
+    data_size: usize,
+}
+
+
+impl Encryption {
+    pub(crate) fn new(
+                key: &[u8; ENCRYPTION_KEY_SIZE],
+                data_size: usize
+            ) -> Self {
+        Self {
+            cipher: XChaCha20Poly1305::new(Key::from_slice(key)),
+            data_size,
+        }
+    }
+
+    pub(crate) fn data_size(&self) -> usize {
+        self.data_size
+    }
+
+    /// Encrypts **block** written to the index **idx**.
+    pub(crate) fn seal(
+                &self,
+                block: &[u8],
+                idx: usize
+            ) -> Result<Vec<u8>, io::Error> {
+        let mut data = block.to_vec();
+        data.resize(self.data_size, 0);
+        self._seal(&data, &(idx as u64).to_le_bytes())
+    }
+
+    /// Decrypts the block read from the index **idx**, an error of kind
+    /// *InvalidData* with **CorruptBlock** is returned if it was changed
+    /// or encrypted by another key.
+    pub(crate) fn open(
+                &self,
+                sealed: &[u8],
+                idx: usize
+            ) -> Result<Vec<u8>, io::Error> {
+        self._open(sealed, &(idx as u64).to_le_bytes()).map_err(
+            |_| io::Error::new(io::ErrorKind::InvalidData, CorruptBlock { idx })
+        )
+    }
+
+    /// A value stored in the header to check the key on open (hex).
+    pub(crate) fn key_check(&self) -> Result<String, io::Error> {
+        Ok(self._seal(&[], KEY_CHECK_DATA)?.iter()
+            .map(|byte| format!("{:02x}", byte)).collect())
+    }
+
+    /// Returns true if **check** was made by **key_check** with the same
+    /// key, else false.
+    pub(crate) fn verify_key(&self, check: &str) -> bool {
+        let bytes: Option<Vec<u8>> = (0..check.len()).step_by(2)
+            .map(|pos| {
+                check.get(pos..pos + 2)
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            })
+            .collect();
+        match bytes {
+            Some(bytes) => self._open(&bytes, KEY_CHECK_DATA).is_ok(),
+            None => false,
+        }
+    }
+
+    fn _seal(&self, data: &[u8], aad: &[u8]) -> Result<Vec<u8>, io::Error> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let sealed = self.cipher.encrypt(&nonce, Payload { msg: data, aad })
+            .map_err(|_| io::Error::other("encryption"))?;
+
+        let mut block = nonce.to_vec();
+        block.extend_from_slice(&sealed);
+        Ok(block)
+    }
+
+    fn _open(&self, sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>, io::Error> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "sealed");
+        if sealed.len() < ENCRYPTION_OVERHEAD {
+            return Err(invalid());
+        }
+        let (nonce, msg) = sealed.split_at(NONCE_SIZE);
+        self.cipher.decrypt(XNonce::from_slice(nonce), Payload { msg, aad })
+            .map_err(|_| invalid())
+    }
+}
+
+
+impl fmt::Debug for Encryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The key is not shown
+        write!(f, "Encryption({})", self.data_size)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::file_ext::FileExt;
+    use crate::varchar::*;
+    use crate::table::*;
+    use crate::table_trait::*;
+    use crate::table_error::TableError;
+    use super::*;
+
+    const TABLE_PATH: &str = "test-encrypted-person.tbl";
+
+    const KEY: [u8; ENCRYPTION_KEY_SIZE] = [7; ENCRYPTION_KEY_SIZE];
+
+    #[derive(Debug, Copy, Clone)]
+    struct Person {
+        id: usize,
+        name: Varchar<20>,
+        age: u32,
+    }
+
+    impl TableTrait for Person {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    impl Person {
+        fn new(name: &str, age: u32) -> Self {
+            Self { id: 0, name: Varchar::<20>::new(name), age }
+        }
+    }
+
+    #[test]
+    fn test_encryption() {
+        _ensure_removed_tables();
+
+        let mut table = Table::new_encrypted::<Person>(TABLE_PATH, &KEY)
+            .unwrap();
+        assert_eq!(table.block_size(),
+                   Person::block_size() + ENCRYPTION_OVERHEAD);
+        Person::new("alexander", 32).insert(&table).unwrap();
+        let mut persons = vec![Person::new("bob", 25), Person::new("carl", 40)];
+        Person::insert_many(&mut persons, &table).unwrap();
+        let alexander = Person::get(&table, 1).unwrap();
+        assert_eq!(alexander.name.to_string(), "alexander");
+        assert_eq!(alexander.age, 32);
+
+        // The names are not stored as they are
+        let bytes = fs::read(TABLE_PATH).unwrap();
+        assert!(!bytes.windows(9).any(|window| window == b"alexander"));
+
+        // The same record is encrypted anew by each write
+        let block = _read_raw(&table, 1);
+        Person::get(&table, 2).unwrap().update(&table).unwrap();
+        assert_ne!(_read_raw(&table, 1), block);
+
+        // A block changed or moved on the disk fails to open
+        let mut changed = _read_raw(&table, 2);
+        changed[30] ^= 1;
+        _write_raw(&table, 2, &changed);
+        _write_raw(&table, 0, &block);
+        let err = Person::get(&table, 3).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(table.verify().unwrap(), vec![0, 2]);
+
+        // An all-zero block was not written by the table
+        Person::get(&table, 2).unwrap().delete(&table).unwrap();
+        assert_eq!(Person::get(&table, 2).unwrap().age, 0);
+        _write_raw(&table, 1, &vec![0; table.block_size()]);
+        let err = Person::get(&table, 2).unwrap_err();
+        assert!(err.get_ref().unwrap().is::<CorruptBlock>());
+
+        // Store and reopen by the same key only
+        let mut records = vec![Person::new("dan", 19), Person::new("eve", 35)];
+        table.store_all(&mut records).unwrap();
+        drop(table);
+        let table = Table::new_encrypted::<Person>(TABLE_PATH, &KEY)
+            .unwrap();
+        let persons: Vec<(String, u32)> = Person::all(&table)
+            .map(|p| p.unwrap())
+            .map(|p| (p.name.to_string(), p.age)).collect();
+        assert_eq!(persons, vec![("dan".to_string(), 19),
+                                 ("eve".to_string(), 35)]);
+        assert!(matches!(
+            Table::new_encrypted::<Person>(TABLE_PATH, &[8; 32]),
+            Err(TableError::InvalidHeader(_))
+        ));
+        assert!(Table::open::<Person>(TABLE_PATH).is_err());
+
+        _ensure_removed_tables();
+    }
+
+    fn _read_raw(table: &Table, idx: usize) -> Vec<u8> {
+        let file = fs::File::open(TABLE_PATH).unwrap();
+        let mut block = vec![0; table.block_size()];
+        file.read_exact_at(&mut block, _offset(table, idx)).unwrap();
+        block
+    }
+
+    fn _write_raw(table: &Table, idx: usize, block: &[u8]) {
+        let file = fs::OpenOptions::new().write(true).open(TABLE_PATH)
+            .unwrap();
+        file.write_all_at(block, _offset(table, idx)).unwrap();
+    }
+
+    fn _offset(table: &Table, idx: usize) -> u64 {
+        let len = fs::metadata(TABLE_PATH).unwrap().len() as usize;
+        (len - (table.size().unwrap() - idx) * table.block_size()) as u64
+    }
+
+    fn _ensure_removed_tables() {
+        for ext in &["", ".free"] {
+            let path = format!("{}{}", TABLE_PATH, ext);
+            if fs::metadata(&path).is_ok() {
+                fs::remove_file(&path).unwrap();
+            }
+        }
+    }
+}
@@ -0,0 +1,327 @@
+use std::io;
+use std::collections::HashMap;
+
+use crate::table::*;
+use crate::table_error::*;
+use crate::table_tagged::*;
+use crate::table_trait::*;
+use crate::varchar::*;
+
+
+const NAME_SIZE: usize = 32;
+
+
+type Reducer<'a> = Box<
+    dyn Fn(&Table, &TaggedTable, usize) -> Result<(), io::Error> + 'a
+>;
+
+
+#[derive(Debug, Copy, Clone)]
+struct Checkpoint {
+    id: usize,
+    name: Varchar<NAME_SIZE>,
+    position: usize,
+}
+
+
+impl TableTrait for Checkpoint {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn set_id(&mut self, id: usize) {
+        self.id = id;
+    }
+}
+
+
+/// Projection builds a state table from the events. It is identified by
+/// **name** to keep its position in the event log, the events are applied
+/// by the reducers registered for their types.
+pub struct Projection<'a> {
+    name: String,
+    state: &'a Table,
+    reducers: HashMap<u32, Reducer<'a>>,
+}
+
+
+impl<'a> Projection<'a> {
+    /// Creates a projection named **name** (up to 32 bytes) that keeps its
+    /// result in the **state** table.
+    pub fn new(name: &str, state: &'a Table) -> Self {
+        Self { name: name.to_string(), state, reducers: HashMap::new() }
+    }
+
+    /// Registers the reducer applying the events of type **E** to
+    /// the state table. Events of types without reducer are skipped.
+    pub fn on<E: TableTagged>(
+                mut self,
+                reduce: impl Fn(&Table, &E) -> Result<(), io::Error> + 'a
+            ) -> Self {
+        self.reducers.insert(E::TAG, Box::new(
+            move |state, events, id| reduce(state, &events.get::<E>(id)?)
+        ));
+        self
+    }
+}
+
+
+/// EventLog is an append-only log of typed events kept in a **TaggedTable**.
+/// Projections replay the events into state tables, the position of each
+/// projection is checkpointed in the file **path.checkpoints**, so only
+/// new events are applied on the next run.
+pub struct EventLog {
+    events: TaggedTable,
+    checkpoints: Table,
+}
+
+
+impl EventLog {
+    /// Creates or opens the event log with events up to
+    /// **max_event_size** bytes.
+    pub fn open(
+                path: &str,
+                max_event_size: usize
+            ) -> Result<Self, TableError> {
+        Ok(Self {
+            events: TaggedTable::open(path, max_event_size)?,
+            checkpoints: Table::open::<Checkpoint>(
+                &format!("{}.checkpoints", path)
+            )?,
+        })
+    }
+
+    /// The table of the events.
+    pub fn events(&self) -> &TaggedTable {
+        &self.events
+    }
+
+    /// Appends the event to the log. Returns its position (id).
+    pub fn append<E: TableTagged>(
+                &self,
+                event: &mut E
+            ) -> Result<usize, io::Error> {
+        self.events.insert(event)
+    }
+
+    /// The position of the last event applied by the projection named
+    /// **name**, 0 if none.
+    pub fn position(&self, name: &str) -> Result<usize, io::Error> {
+        Ok(self._checkpoint(name)?.map(|cp| cp.position).unwrap_or(0))
+    }
+
+    /// Applies the events appended after the checkpoint of the projection
+    /// and moves the checkpoint. If a reducer fails, the checkpoint stays
+    /// after the last applied event and the error is returned. Returns
+    /// the number of applied events.
+    pub fn project(
+                &self,
+                projection: &Projection
+            ) -> Result<usize, io::Error> {
+        let mut checkpoint = match self._checkpoint(&projection.name)? {
+            Some(checkpoint) => checkpoint,
+            None => Checkpoint {
+                id: 0, name: Self::_name(&projection.name)?, position: 0
+            },
+        };
+
+        let mut applied = 0;
+        let mut result = Ok(());
+
+        for id in checkpoint.position + 1..=self.events.table().size()? {
+            let tag = self.events.tag(id)?;
+            if let Some(reduce) = projection.reducers.get(&tag) {
+                result = reduce(projection.state, &self.events, id);
+                if result.is_err() {
+                    break;
+                }
+                applied += 1;
+            }
+            checkpoint.position = id;
+        }
+
+        if checkpoint.id == 0 {
+            checkpoint.insert(&self.checkpoints)?;
+        } else {
+            checkpoint.update(&self.checkpoints)?;
+        }
+
+        result.map(|_| applied)
+    }
+
+    /// Moves the checkpoint of the projection to the start, so the next
+    /// **project** replays all events. The state table must be emptied
+    /// by the caller.
+    pub fn reset(&self, name: &str) -> Result<(), io::Error> {
+        if let Some(mut checkpoint) = self._checkpoint(name)? {
+            checkpoint.position = 0;
+            checkpoint.update(&self.checkpoints)?;
+        }
+        Ok(())
+    }
+
+    fn _checkpoint(
+                &self,
+                name: &str
+            ) -> Result<Option<Checkpoint>, io::Error> {
+        let name = Self::_name(name)?;
+        for cp in Checkpoint::all(&self.checkpoints) {
+            let cp = cp?;
+            if (cp.id > 0) && (cp.name == name) {
+                return Ok(Some(cp));
+            }
+        }
+        Ok(None)
+    }
+
+    fn _name(name: &str) -> Result<Varchar<NAME_SIZE>, io::Error> {
+        if name.len() > NAME_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("projection name longer than {} bytes", NAME_SIZE)
+            ));
+        }
+        Ok(Varchar::<NAME_SIZE>::new(name))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, mem};
+
+    use super::*;
+
+    const TABLE_PATH: &str = "test-eventlog-events.tbl";
+    const TABLE_BALANCE_PATH: &str = "test-eventlog-balance.tbl";
+
+    #[derive(Debug, Copy, Clone)]
+    struct Deposited {
+        id: usize,
+        account: usize,
+        amount: u64,
+    }
+
+    impl TableTrait for Deposited {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    impl TableTagged for Deposited {
+        const TAG: u32 = 1;
+    }
+
+    #[derive(Debug, Copy, Clone)]
+    struct Withdrawn {
+        id: usize,
+        account: usize,
+        amount: u64,
+    }
+
+    impl TableTrait for Withdrawn {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    impl TableTagged for Withdrawn {
+        const TAG: u32 = 2;
+    }
+
+    #[derive(Debug, Copy, Clone)]
+    struct Balance {
+        id: usize,
+        amount: u64,
+    }
+
+    impl TableTrait for Balance {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    fn _balances(state: &Table) -> Projection<'_> {
+        Projection::new("balances", state)
+            .on::<Deposited>(|state, event| {
+                let mut balance = Balance::get(state, event.account)?;
+                balance.amount += event.amount;
+                balance.update(state)
+            })
+            .on::<Withdrawn>(|state, event| {
+                let mut balance = Balance::get(state, event.account)?;
+                if balance.amount < event.amount {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData, "overdraft"
+                    ));
+                }
+                balance.amount -= event.amount;
+                balance.update(state)
+            })
+    }
+
+    #[test]
+    fn test_eventlog() {
+        _ensure_removed_tables();
+
+        let size = mem::size_of::<Deposited>().max(mem::size_of::<Withdrawn>());
+        let log = EventLog::open(TABLE_PATH, size).unwrap();
+        let state = Table::open::<Balance>(TABLE_BALANCE_PATH).unwrap();
+        Balance { id: 0, amount: 0 }.insert(&state).unwrap();
+
+        log.append(&mut Deposited { id: 0, account: 1, amount: 100 }).unwrap();
+        log.append(&mut Withdrawn { id: 0, account: 1, amount: 30 }).unwrap();
+
+        // Replay
+        assert_eq!(log.project(&_balances(&state)).unwrap(), 2);
+        assert_eq!(Balance::get(&state, 1).unwrap().amount, 70);
+        assert_eq!(log.position("balances").unwrap(), 2);
+
+        // Only new events are applied after the checkpoint
+        log.append(&mut Deposited { id: 0, account: 1, amount: 5 }).unwrap();
+        let log = EventLog::open(TABLE_PATH, size).unwrap();
+        assert_eq!(log.project(&_balances(&state)).unwrap(), 1);
+        assert_eq!(log.project(&_balances(&state)).unwrap(), 0);
+        assert_eq!(Balance::get(&state, 1).unwrap().amount, 75);
+
+        // A failed reducer keeps the checkpoint before the event
+        log.append(&mut Withdrawn { id: 0, account: 1, amount: 500 }).unwrap();
+        assert!(log.project(&_balances(&state)).is_err());
+        assert_eq!(log.position("balances").unwrap(), 3);
+
+        // Rebuild from the start
+        Balance { id: 1, amount: 0 }.update(&state).unwrap();
+        log.reset("balances").unwrap();
+        assert!(log.project(&_balances(&state)).is_err());
+        assert_eq!(Balance::get(&state, 1).unwrap().amount, 75);
+
+        // Events without reducers are skipped
+        let deposits = Projection::new("deposits", &state)
+            .on::<Deposited>(|_, _| Ok(()));
+        assert_eq!(log.project(&deposits).unwrap(), 2);
+        assert_eq!(log.position("deposits").unwrap(), 4);
+
+        _ensure_removed_tables();
+    }
+
+    fn _ensure_removed_tables() {
+        let checkpoints = format!("{}.checkpoints", TABLE_PATH);
+        for path in &[TABLE_PATH, TABLE_BALANCE_PATH, &checkpoints] {
+            if fs::metadata(path).is_ok() {
+                fs::remove_file(path).unwrap();
+            }
+        }
+    }
+}
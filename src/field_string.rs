@@ -0,0 +1,141 @@
+use std::io;
+use std::str::FromStr;
+
+use crate::bytes::*;
+use crate::varchar::*;
+use crate::ordered_float::*;
+
+
+/// FieldString converts a field of the record to a text and back, e.g. for
+/// CSV. The text does not depend on the platform or the locale and is
+/// parsed back to the same value: the numbers are written in the shortest
+/// form that is parsed back exactly (*NaN*, *inf* and *-inf* for
+/// the special floats), **bool** as *true* or *false*, **Varchar** as
+/// the string and **Bytes** as a lowercase hex string.
+pub trait FieldString: Sized {
+    /// Renders the field as a text.
+    fn to_field_string(&self) -> String;
+
+    /// Parses the field from a text written by **to_field_string**,
+    /// an error is of kind *InvalidData*.
+    fn from_field_string(s: &str) -> Result<Self, io::Error>;
+}
+
+
+macro_rules! field_string_from_str {
+    ($($ty:ty),*) => {
+        $(
+            impl FieldString for $ty {
+                fn to_field_string(&self) -> String {
+                    self.to_string()
+                }
+
+                fn from_field_string(s: &str) -> Result<Self, io::Error> {
+                    <$ty>::from_str(s).map_err(|_| _invalid(s))
+                }
+            }
+        )*
+    };
+}
+
+
+field_string_from_str!(
+    u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize,
+    f32, f64, bool, char
+);
+
+
+macro_rules! field_string_ordered_float {
+    ($($name:ident, $float:ty);*) => {
+        $(
+            impl FieldString for $name {
+                fn to_field_string(&self) -> String {
+                    self.value().to_field_string()
+                }
+
+                fn from_field_string(s: &str) -> Result<Self, io::Error> {
+                    Ok(Self::new(<$float>::from_field_string(s)?))
+                }
+            }
+        )*
+    };
+}
+
+
+field_string_ordered_float!(OrderedF64, f64; OrderedF32, f32);
+
+
+impl<const N: usize> FieldString for Varchar<N> {
+    fn to_field_string(&self) -> String {
+        self.as_str().to_string()
+    }
+
+    fn from_field_string(s: &str) -> Result<Self, io::Error> {
+        Varchar::<N>::try_new(s).map_err(|_| _invalid(s))
+    }
+}
+
+
+impl<const N: usize> FieldString for Bytes<N> {
+    fn to_field_string(&self) -> String {
+        self.as_slice().iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    fn from_field_string(s: &str) -> Result<Self, io::Error> {
+        if (s.len() > 2 * N) || !s.len().is_multiple_of(2) || !s.is_ascii() {
+            return Err(_invalid(s));
+        }
+        let bytes = (0..s.len()).step_by(2)
+            .map(|pos| u8::from_str_radix(&s[pos..pos + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| _invalid(s))?;
+        Ok(Bytes::<N>::new(&bytes))
+    }
+}
+
+
+fn _invalid(s: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("field value {}", s))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<T: FieldString>(value: &T) -> T {
+        T::from_field_string(&value.to_field_string()).unwrap()
+    }
+
+    #[test]
+    fn test_field_string() {
+        // Numbers
+        for value in &[0.1f64, -0.0, 1e300, 5e-324, f64::MAX, f64::INFINITY] {
+            let parsed = round_trip(value);
+            assert_eq!(parsed.to_bits(), value.to_bits());
+        }
+        assert!(round_trip(&f64::NAN).is_nan());
+        assert_eq!(0.1f32.to_field_string(), "0.1");
+        assert_eq!(1e21f64.to_field_string(), "1000000000000000000000");
+        assert_eq!(f64::NEG_INFINITY.to_field_string(), "-inf");
+        assert_eq!(round_trip(&i64::MIN), i64::MIN);
+        assert_eq!(round_trip(&u128::MAX), u128::MAX);
+        assert_eq!(round_trip(&OrderedF32::new(2.5)), OrderedF32::new(2.5));
+        assert!(u8::from_field_string("256").is_err());
+        assert!(i32::from_field_string(" 5").is_err());
+        assert!(f64::from_field_string("1,5").is_err());
+
+        // Strings and bytes
+        let name = Varchar::<16>::new("café, \"x\"");
+        assert_eq!(round_trip(&name), name);
+        assert!(Varchar::<4>::from_field_string("alexander").is_err());
+
+        let bytes = Bytes::<4>::new(&[0xca, 0xfe, 0x00]);
+        assert_eq!(bytes.to_field_string(), "cafe00");
+        assert_eq!(round_trip(&bytes), bytes);
+        assert!(Bytes::<4>::from_field_string("CAFE").is_ok());
+        assert!(Bytes::<4>::from_field_string("caf").is_err());
+        assert!(Bytes::<4>::from_field_string("cafe00000000").is_err());
+        assert!(bool::from_field_string("True").is_err());
+    }
+}
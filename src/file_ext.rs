@@ -0,0 +1,132 @@
+use std::{fs, io};
+
+
+/// FileExt reads and writes the file at an offset on every platform:
+/// with **pread** and **pwrite** on Unix, with **seek_read** and
+/// **seek_write** on Windows (where they also move the file cursor).
+/// It also takes advisory locks of the file with **flock** on Unix.
+pub trait FileExt {
+    /// Reads exactly **buf.len()** bytes starting from **offset**.
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()>;
+
+    /// Writes the whole **buf** starting from **offset**.
+    fn write_all_at(&self, buf: &[u8], offset: u64) -> io::Result<()>;
+
+    /// Takes an advisory lock of the file without waiting: an **exclusive**
+    /// one or a shared one. An error of kind *WouldBlock* is returned if
+    /// the file is locked in a conflicting mode. The lock is released when
+    /// the file is closed.
+    fn lock_advisory(&self, exclusive: bool) -> io::Result<()>;
+}
+
+
+#[cfg(unix)]
+impl FileExt for fs::File {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        std::os::unix::fs::FileExt::read_exact_at(self, buf, offset)
+    }
+
+    fn write_all_at(&self, buf: &[u8], offset: u64) -> io::Result<()> {
+        std::os::unix::fs::FileExt::write_all_at(self, buf, offset)
+    }
+
+    fn lock_advisory(&self, exclusive: bool) -> io::Result<()> {
+        use std::os::unix::io::AsRawFd;
+
+        let mode = if exclusive { libc::LOCK_EX } else { libc::LOCK_SH };
+        let res = unsafe {
+            libc::flock(self.as_raw_fd(), mode | libc::LOCK_NB)
+        };
+        if res == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+
+#[cfg(windows)]
+impl FileExt for fs::File {
+    fn read_exact_at(
+                &self,
+                mut buf: &mut [u8],
+                mut offset: u64
+            ) -> io::Result<()> {
+        use std::os::windows::fs::FileExt;
+
+        while !buf.is_empty() {
+            match self.seek_read(buf, offset) {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof, "failed to fill buffer"
+                    ));
+                },
+                Ok(size) => {
+                    buf = &mut buf[size..];
+                    offset += size as u64;
+                },
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {},
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    fn write_all_at(&self, mut buf: &[u8], mut offset: u64) -> io::Result<()> {
+        use std::os::windows::fs::FileExt;
+
+        while !buf.is_empty() {
+            match self.seek_write(buf, offset) {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::WriteZero, "failed to write whole buffer"
+                    ));
+                },
+                Ok(size) => {
+                    buf = &buf[size..];
+                    offset += size as u64;
+                },
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {},
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    fn lock_advisory(&self, _exclusive: bool) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Other, "advisory locks are not supported"
+        ))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE_PATH: &str = "test-file-ext.tbl";
+
+    #[test]
+    fn test_file_ext() {
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(FILE_PATH)
+            .unwrap();
+
+        file.write_all_at(b"world", 6).unwrap();
+        file.write_all_at(b"hello ", 0).unwrap();
+
+        let mut buf = [0u8; 5];
+        file.read_exact_at(&mut buf, 6).unwrap();
+        assert_eq!(&buf, b"world");
+        let err = file.read_exact_at(&mut buf, 8).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        fs::remove_file(FILE_PATH).unwrap();
+    }
+}
@@ -0,0 +1,153 @@
+use std::{env, fs, io, process};
+use std::ops::Deref;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::table_trait::*;
+use crate::repository::*;
+use crate::index_key::*;
+use crate::table_error::*;
+
+
+static FIXTURE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+
+type IndexAdder<T> =
+    Box<dyn FnOnce(Repository<T>, &str) -> Result<Repository<T>, TableError>>;
+
+
+/// FixtureBuilder describes a dataset of generated records, see
+/// **Fixture::builder**.
+pub struct FixtureBuilder<T> {
+    count: usize,
+    generate: Box<dyn Fn(usize) -> T>,
+    indexes: Vec<(String, IndexAdder<T>)>,
+}
+
+
+impl<T: TableTrait + 'static> FixtureBuilder<T> {
+    /// Registers an index with the **name** over the value extracted from
+    /// the record by **key**, see **Repository::with_index**.
+    pub fn with_index<K: Copy + PartialOrd + IndexKey + 'static>(
+                mut self,
+                name: &str,
+                key: fn(&T) -> K
+            ) -> Self {
+        let index_name = name.to_string();
+        self.indexes.push((name.to_string(), Box::new(
+            move |repository, path| repository.with_index(&index_name, path, key)
+        )));
+        self
+    }
+
+    /// Creates the table with its indexes in a new temporary directory and
+    /// saves the generated records into it.
+    pub fn build(self) -> Result<Fixture<T>, io::Error> {
+        let dir = env::temp_dir().join(format!(
+            "mytable-fixture-{}-{}", process::id(),
+            FIXTURE_COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::create_dir_all(&dir)?;
+
+        let path = dir.join("table.tbl");
+        let mut repository = Repository::<T>::new(&path.to_string_lossy())?;
+        for (name, add_index) in self.indexes {
+            let index_path = dir.join(format!("{}.idx", name));
+            repository = add_index(repository, &index_path.to_string_lossy())?;
+        }
+
+        let fixture = Fixture { dir, repository };
+        for n in 0..self.count {
+            let mut record = (self.generate)(n);
+            record.set_id(0);
+            fixture.repository.save(&mut record)?;
+        }
+
+        Ok(fixture)
+    }
+}
+
+
+/// Fixture is a repository filled with generated records for tests.
+/// Its files are located in a temporary directory that is removed when
+/// the fixture is dropped. It dereferences to the **Repository**.
+pub struct Fixture<T> {
+    dir: PathBuf,
+    repository: Repository<T>,
+}
+
+
+impl<T: TableTrait + 'static> Fixture<T> {
+    /// Starts a dataset of **count** records, the record number **n**
+    /// (starting from 0) is created by **generate(n)**. Any id of
+    /// the generated record is replaced on saving.
+    pub fn builder(
+                count: usize,
+                generate: impl Fn(usize) -> T + 'static
+            ) -> FixtureBuilder<T> {
+        FixtureBuilder { count, generate: Box::new(generate), indexes: vec![] }
+    }
+
+    /// The temporary directory with the files of the fixture.
+    pub fn dir(&self) -> &PathBuf {
+        &self.dir
+    }
+}
+
+
+impl<T> Deref for Fixture<T> {
+    type Target = Repository<T>;
+
+    fn deref(&self) -> &Repository<T> {
+        &self.repository
+    }
+}
+
+
+impl<T> Drop for Fixture<T> {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.dir).ok();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::varchar::*;
+    use super::*;
+
+    #[derive(Debug, Copy, Clone)]
+    struct Person {
+        id: usize,
+        name: Varchar<20>,
+        age: u32,
+    }
+
+    impl TableTrait for Person {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    #[test]
+    fn test_fixture() {
+        let fixture = Fixture::builder(100, |n| Person {
+            id: 0,
+            name: Varchar::<20>::new(&format!("person-{}", n)),
+            age: 18 + (n % 50) as u32,
+        }).with_index("age", |p: &Person| p.age).build().unwrap();
+
+        assert_eq!(fixture.table().size().unwrap(), 100);
+        assert_eq!(fixture.find(3).unwrap().name.to_string(), "person-2");
+        assert_eq!(fixture.find_by("age", &20u32).unwrap().len(), 2);
+
+        let dir = fixture.dir().clone();
+        assert!(dir.join("age.idx").exists());
+        drop(fixture);
+        assert!(!dir.exists());
+    }
+}
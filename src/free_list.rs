@@ -0,0 +1,174 @@
+use std::{fs, io};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::file_ext::FileExt;
+
+
+const ENTRY_SIZE: usize = 8;
+
+
+/// FreeList is a stack of indices of deleted blocks that can be reused by
+/// later inserts. It is stored in its own file as 8 bytes per index, the
+/// file is created on the first push only.
+#[derive(Debug)]
+pub struct FreeList {
+    path: String,
+    file: Mutex<Option<fs::File>>,
+}
+
+
+impl FreeList {
+    /// Opens the free list file if it exists.
+    pub fn open(path: &str) -> Result<Self, io::Error> {
+        let file = if fs::metadata(path).is_ok() {
+            Some(Self::_open_file(path)?)
+        } else {
+            None
+        };
+        Ok(Self { path: path.to_string(), file: Mutex::new(file) })
+    }
+
+    /// The number of free blocks.
+    pub fn len(&self) -> Result<usize, io::Error> {
+        match self._file()?.as_ref() {
+            Some(file) => Ok(file.metadata()?.len() as usize / ENTRY_SIZE),
+            None => Ok(0),
+        }
+    }
+
+    /// Returns true if there are no free blocks, else false.
+    pub fn is_empty(&self) -> Result<bool, io::Error> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Adds the index of the deleted block.
+    pub fn push(&self, idx: usize) -> Result<(), io::Error> {
+        let mut file = self._file()?;
+        if file.is_none() {
+            *file = Some(Self::_open_file(&self.path)?);
+        }
+        match file.as_ref() {
+            Some(file) => {
+                let offset = file.metadata()?.len();
+                file.write_all_at(&(idx as u64).to_le_bytes(), offset)
+            },
+            None => Ok(()),
+        }
+    }
+
+    /// Takes the index of the latest deleted block if any.
+    pub fn pop(&self) -> Result<Option<usize>, io::Error> {
+        let file = self._file()?;
+        let file = match file.as_ref() {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+
+        let len = file.metadata()?.len();
+        if len < ENTRY_SIZE as u64 {
+            return Ok(None);
+        }
+
+        let offset = len - ENTRY_SIZE as u64;
+        let mut bytes = [0u8; ENTRY_SIZE];
+        file.read_exact_at(&mut bytes, offset)?;
+        file.set_len(offset)?;
+        Ok(Some(u64::from_le_bytes(bytes) as usize))
+    }
+
+    /// Forgets the free blocks from the index **size** on, e.g. after they
+    /// were cut from the table.
+    pub fn retain_below(&self, size: usize) -> Result<(), io::Error> {
+        self.retain(|idx| idx < size)
+    }
+
+    /// Forgets the free blocks whose indices do not satisfy **keep**, e.g.
+    /// the ones filled by the records of a rewritten table.
+    pub fn retain(&self, keep: impl Fn(usize) -> bool) -> Result<(), io::Error> {
+        let file = self._file()?;
+        let file = match file.as_ref() {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+
+        let mut bytes = vec![0u8; file.metadata()?.len() as usize];
+        file.read_exact_at(&mut bytes, 0)?;
+        let kept: Vec<u8> = bytes.chunks(ENTRY_SIZE)
+            .filter(|entry| {
+                let mut idx = [0u8; ENTRY_SIZE];
+                idx.clone_from_slice(entry);
+                keep(u64::from_le_bytes(idx) as usize)
+            })
+            .flatten().copied().collect();
+        file.write_all_at(&kept, 0)?;
+        file.set_len(kept.len() as u64)
+    }
+
+    /// Forgets all free blocks, e.g. after they were filled by a compaction.
+    pub fn clear(&self) -> Result<(), io::Error> {
+        match self._file()?.as_ref() {
+            Some(file) => file.set_len(0),
+            None => Ok(()),
+        }
+    }
+
+    fn _file(&self) -> Result<MutexGuard<'_, Option<fs::File>>, io::Error> {
+        self.file.lock().map_err(
+            |_| io::Error::other("free list lock")
+        )
+    }
+
+    fn _open_file(path: &str) -> Result<fs::File, io::Error> {
+        fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FREE_LIST_PATH: &str = "test-free-list.tbl.free";
+
+    #[test]
+    fn test_free_list() {
+        _ensure_removed_file();
+
+        let free = FreeList::open(FREE_LIST_PATH).unwrap();
+        assert_eq!(free.pop().unwrap(), None);
+        assert!(fs::metadata(FREE_LIST_PATH).is_err());
+
+        free.push(3).unwrap();
+        free.push(7).unwrap();
+        assert_eq!(free.len().unwrap(), 2);
+
+        let free = FreeList::open(FREE_LIST_PATH).unwrap();
+        assert_eq!(free.pop().unwrap(), Some(7));
+        assert_eq!(free.pop().unwrap(), Some(3));
+        assert!(free.is_empty().unwrap());
+
+        free.push(5).unwrap();
+        free.push(2).unwrap();
+        free.push(8).unwrap();
+        free.retain_below(5).unwrap();
+        assert_eq!(free.len().unwrap(), 1);
+        assert_eq!(free.pop().unwrap(), Some(2));
+
+        free.push(5).unwrap();
+        free.clear().unwrap();
+        assert_eq!(free.pop().unwrap(), None);
+
+        _ensure_removed_file();
+    }
+
+    fn _ensure_removed_file() {
+        if fs::metadata(FREE_LIST_PATH).is_ok() {
+            fs::remove_file(FREE_LIST_PATH).unwrap();
+        }
+    }
+}
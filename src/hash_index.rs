@@ -0,0 +1,533 @@
+use std::{any, fs, io, iter, mem};
+use std::hash::{Hash, Hasher};
+
+use crate::table::*;
+use crate::table_trait::*;
+use crate::table_error::*;
+
+
+const HASH_INDEX_MAGIC: &[u8; 8] = b"MYTHSH01";
+const HASH_INDEX_HEADER_SIZE: usize = 32;
+
+/// The number of buckets of an index created by the first **add**.
+pub const HASH_INDEX_BUCKETS: usize = 1024;
+
+
+/// FNV-1a hasher with the numbers hashed as little endian 64-bit, so
+/// the buckets of the values are the same on every platform and Rust
+/// version (unlike the hasher of *std*).
+struct StableHasher(u64);
+
+
+impl StableHasher {
+    fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn write_u16(&mut self, n: u16) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, n: u32) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.write_u64(n as u64);
+    }
+
+    fn write_i16(&mut self, n: i16) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_i32(&mut self, n: i32) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_i64(&mut self, n: i64) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_isize(&mut self, n: isize) {
+        self.write_i64(n as i64);
+    }
+}
+
+
+/// HashIndexHeader is stored in the first block of the hash index table.
+/// It keeps the number of buckets, the number of entries and the tag of
+/// the value type the index was created with.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct HashIndexHeader {
+    /// The number of buckets.
+    pub buckets: usize,
+    /// The number of entries.
+    pub count: usize,
+    /// The tag of the value type.
+    pub key_tag: u64,
+}
+
+
+impl HashIndexHeader {
+    /// Reads the header from the index table, None if the table is empty.
+    pub fn read(table: &Table) -> Result<Option<Self>, io::Error> {
+        if table.empty()? {
+            return Ok(None);
+        }
+
+        let block = table.get(0)?;
+        if (block.len() < HASH_INDEX_HEADER_SIZE) ||
+                (&block[..8] != HASH_INDEX_MAGIC) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData, "hash index header"
+            ));
+        }
+
+        let field = |pos: usize| {
+            let mut bytes = [0u8; 8];
+            bytes.clone_from_slice(&block[8 * pos..8 * pos + 8]);
+            u64::from_le_bytes(bytes)
+        };
+
+        Ok(Some(Self {
+            buckets: field(1) as usize,
+            count: field(2) as usize,
+            key_tag: field(3),
+        }))
+    }
+
+    /// Writes the header to the first block of the index table.
+    pub fn write(&self, table: &Table) -> Result<(), io::Error> {
+        let mut block = HASH_INDEX_MAGIC.to_vec();
+        for field in &[self.buckets as u64, self.count as u64, self.key_tag] {
+            block.extend_from_slice(&field.to_le_bytes());
+        }
+
+        if table.empty()? {
+            table.append(&block)?;
+        } else {
+            table.update(&block, 0)?;
+        }
+        Ok(())
+    }
+}
+
+
+/// HashIndex is an index for exact-match lookups (e.g. by UUID) without
+/// comparisons of values on the way. It is a record that has TableTrait
+/// implemented, so it keeps its own table file like **TableIndex**: after
+/// the header the table keeps a block per bucket with the id of the first
+/// entry of its chain, and each entry keeps the id of the next one.
+/// The blocks of the excluded entries are reused.
+#[derive(Debug, Copy, Clone)]
+pub struct HashIndex<T> {
+    id: usize,
+    value: T,
+    table_id: usize,
+    next: usize,
+}
+
+
+impl<T: Copy> TableTrait for HashIndex<T> {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn set_id(&mut self, id: usize) {
+        self.id = id;
+    }
+}
+
+
+impl<'a, T: 'a + Copy + Hash + PartialEq> HashIndex<T> {
+    /// Creates or opens the index table. A new index gets **buckets**
+    /// buckets, the number of an existing one is kept (see **rehash**).
+    pub fn open(path: &str, buckets: usize) -> Result<Table, TableError> {
+        let table = Table::open::<Self>(path)?;
+        if table.empty()? {
+            Self::_create(&table, buckets)?;
+        } else {
+            Self::header(&table)?;
+        }
+        Ok(table)
+    }
+
+    /// Reads the header of the index checking that it was created for
+    /// the same value type. A header without buckets is returned if
+    /// the table is empty.
+    pub fn header(table: &Table) -> Result<HashIndexHeader, io::Error> {
+        match HashIndexHeader::read(table)? {
+            Some(header) => {
+                if header.key_tag != Self::_key_tag() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData, "index key type"
+                    ));
+                }
+                Ok(header)
+            },
+            None => Ok(HashIndexHeader {
+                buckets: 0,
+                count: 0,
+                key_tag: Self::_key_tag(),
+            }),
+        }
+    }
+
+    /// Adds an index value to the table. An empty table gets
+    /// **HASH_INDEX_BUCKETS** buckets. Other threads cannot write to
+    /// the index meanwhile (see **Table::exclusive**), the caller must not
+    /// hold its exclusive guard.
+    pub fn add(
+                table: &Table,
+                value: &T,
+                table_id: usize
+            ) -> Result<(), io::Error> {
+        // The head of the chain and the count in the header are read and
+        // written back, so the concurrent adds and excludes are serialized
+        let _exclusive = table.exclusive();
+
+        if table.empty()? {
+            Self::_create(table, HASH_INDEX_BUCKETS)?;
+        }
+        let mut header = Self::header(table)?;
+
+        let bucket_id = Self::_bucket_id(&header, value);
+        let mut rec = Self {
+            id: 0,
+            value: *value,
+            table_id,
+            next: Self::_get_node(table, bucket_id)?.next,
+        };
+        rec.insert(table)?;
+        Self::_set_next(table, bucket_id, rec.id)?;
+
+        header.count += 1;
+        header.write(table)
+    }
+
+    /// Searches for a node by **value**. The **id** of original
+    /// record is returned.
+    pub fn search_one(
+                table: &Table,
+                value: &T
+            ) -> Result<usize, io::Error> {
+        Self::search_many(table, value).next().ok_or_else(
+            || io::Error::new(io::ErrorKind::NotFound, "hash index")
+        )?
+    }
+
+    /// Searches for all nodes with given **value**.
+    /// It returns an iterator that yields **id** of original records.
+    /// A node that fails to read (or the header of another value type)
+    /// is yielded as the error, the iteration stops after it.
+    pub fn search_many(
+                table: &'a Table,
+                value: &'a T
+            ) -> Box<dyn Iterator<Item = Result<usize, io::Error>> + 'a> {
+        Box::new(
+            Self::_iter_chain(table, Self::_first(table, value))
+                .filter(move |rec| {
+                    !matches!(rec, Ok(rec) if rec.value != *value)
+                })
+                .map(|rec| rec.map(|rec| rec.table_id))
+        )
+    }
+
+    /// Removes the node with the **value** bound to **table_id**, its block
+    /// is reused by the next **add**. Other threads cannot write to
+    /// the index meanwhile, as in **add**.
+    pub fn exclude(
+                table: &Table,
+                value: &T,
+                table_id: usize
+            ) -> Result<(), io::Error> {
+        let _exclusive = table.exclusive();
+
+        let mut header = Self::header(table)?;
+        if header.buckets == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound, table_id.to_string()
+            ));
+        }
+
+        let mut prev = Self::_bucket_id(&header, value);
+        let mut id = Self::_get_node(table, prev)?.next;
+        while id > 0 {
+            let rec = Self::get(table, id)?;
+            if (rec.value == *value) && (rec.table_id == table_id) {
+                Self::_set_next(table, prev, rec.next)?;
+                rec.delete(table)?;
+
+                header.count = header.count.saturating_sub(1);
+                return header.write(table);
+            }
+            prev = id;
+            id = rec.next;
+        }
+
+        Err(io::Error::new(io::ErrorKind::NotFound, table_id.to_string()))
+    }
+
+    /// The values of the nodes with **id** of original records in the order
+    /// of the buckets.
+    pub fn entries(table: &Table) -> Result<Vec<(T, usize)>, io::Error> {
+        let header = Self::header(table)?;
+        let mut entries = Vec::with_capacity(header.count);
+        for bucket_id in 2..header.buckets + 2 {
+            let first = Self::_get_node(table, bucket_id)?.next;
+            for rec in Self::_iter_chain(table, Ok(first)) {
+                let rec = rec?;
+                entries.push((rec.value, rec.table_id));
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Writes the index with **buckets** buckets to a new file at **path**
+    /// (it must not exist or be empty), e.g. when the chains get long as
+    /// the index grows.
+    pub fn rehash(
+                table: &Table,
+                path: &str,
+                buckets: usize
+            ) -> Result<Table, io::Error> {
+        if fs::metadata(path).map(|meta| meta.len() > 0).unwrap_or(false) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, path));
+        }
+
+        let entries = Self::entries(table)?;
+
+        let rehashed = Self::open(path, buckets)?;
+        for (value, table_id) in entries.iter() {
+            Self::add(&rehashed, value, *table_id)?;
+        }
+        Ok(rehashed)
+    }
+
+    fn _create(table: &Table, buckets: usize) -> Result<(), io::Error> {
+        if buckets == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput, "hash index buckets"
+            ));
+        }
+        if table.block_size() < HASH_INDEX_HEADER_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput, "index block size"
+            ));
+        }
+
+        HashIndexHeader {
+            buckets,
+            count: 0,
+            key_tag: Self::_key_tag(),
+        }.write(table)?;
+
+        let block_size = table.block_size();
+        table.append_many(buckets, |_, _| vec![0; block_size])?;
+        Ok(())
+    }
+
+    fn _first(table: &Table, value: &T) -> Result<usize, io::Error> {
+        let header = Self::header(table)?;
+        if header.buckets == 0 {
+            return Ok(0);
+        }
+        Ok(Self::_get_node(table, Self::_bucket_id(&header, value))?.next)
+    }
+
+    fn _iter_chain(
+                table: &'a Table,
+                first: Result<usize, io::Error>
+            ) -> impl Iterator<Item = Result<Self, io::Error>> + 'a {
+        // The iteration stops after an error, the rest of the chain
+        // cannot be found without the node
+        let mut next = Some(first);
+        iter::from_fn(move || {
+            let id = match next.take()? {
+                Ok(0) => return None,
+                Ok(id) => id,
+                Err(err) => return Some(Err(err)),
+            };
+            let rec = Self::get(table, id);
+            if let Ok(rec) = &rec {
+                next = Some(Ok(rec.next));
+            }
+            Some(rec)
+        })
+    }
+
+    fn _get_node(table: &Table, id: usize) -> Result<Self, io::Error> {
+        // The empty buckets are zero blocks read with id 0
+        let mut rec = Self::get(table, id)?;
+        rec.id = id;
+        Ok(rec)
+    }
+
+    fn _set_next(
+                table: &Table,
+                id: usize,
+                next: usize
+            ) -> Result<(), io::Error> {
+        let mut rec = Self::_get_node(table, id)?;
+        rec.next = next;
+        rec.update(table)
+    }
+
+    fn _bucket_id(header: &HashIndexHeader, value: &T) -> usize {
+        let mut hasher = StableHasher::new();
+        value.hash(&mut hasher);
+        2 + (hasher.finish() % header.buckets as u64) as usize
+    }
+
+    fn _key_tag() -> u64 {
+        // FNV-1a over the type name and the size of the value
+        let mut hasher = StableHasher::new();
+        hasher.write(
+            format!("{}/{}", any::type_name::<T>(), mem::size_of::<T>())
+                .as_bytes()
+        );
+        hasher.finish()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::sync::Arc;
+
+    use super::*;
+
+    const HASH_INDEX_PATH: &str = "test-hash-index.tbl";
+    const HASH_REHASHED_PATH: &str = "test-hash-index-rehashed.tbl";
+    const HASH_CONCURRENT_PATH: &str = "test-hash-index-concurrent.tbl";
+
+    #[test]
+    fn test_hash_index() {
+        _ensure_removed_files(&[HASH_INDEX_PATH, HASH_REHASHED_PATH]);
+
+        let index = HashIndex::<u64>::open(HASH_INDEX_PATH, 8).unwrap();
+        assert!(HashIndex::search_one(&index, &5u64).is_err());
+
+        // The chains are long with 8 buckets
+        for id in 1..=100 {
+            HashIndex::add(&index, &(id as u64 * 7), id).unwrap();
+        }
+        HashIndex::add(&index, &70u64, 200).unwrap();
+        assert_eq!(HashIndex::<u64>::header(&index).unwrap().count, 101);
+        assert_eq!(HashIndex::search_one(&index, &49u64).unwrap(), 7);
+        let mut ids: Vec<usize> = HashIndex::search_many(&index, &70u64)
+            .collect::<Result<_, _>>().unwrap();
+        ids.sort();
+        assert_eq!(ids, vec![10, 200]);
+        assert_eq!(HashIndex::search_many(&index, &71u64).count(), 0);
+
+        // The block of the excluded node is reused
+        HashIndex::exclude(&index, &70u64, 10).unwrap();
+        let ids: Vec<usize> = HashIndex::search_many(&index, &70u64)
+            .collect::<Result<_, _>>().unwrap();
+        assert_eq!(ids, vec![200]);
+        let err = HashIndex::exclude(&index, &70u64, 10).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        let size = index.size().unwrap();
+        HashIndex::add(&index, &3u64, 300).unwrap();
+        assert_eq!(index.size().unwrap(), size);
+        assert_eq!(HashIndex::<u64>::entries(&index).unwrap().len(), 101);
+
+        // Rehash
+        let rehashed = HashIndex::<u64>::rehash(
+            &index, HASH_REHASHED_PATH, 64
+        ).unwrap();
+        assert_eq!(HashIndex::<u64>::header(&rehashed).unwrap().buckets, 64);
+        assert_eq!(HashIndex::search_one(&rehashed, &3u64).unwrap(), 300);
+        assert_eq!(HashIndex::search_one(&rehashed, &700u64).unwrap(), 100);
+        assert!(HashIndex::<u64>::rehash(
+            &index, HASH_REHASHED_PATH, 64
+        ).is_err());
+
+        // Reopen keeps the buckets, another value type is an error
+        drop(index);
+        let index = HashIndex::<u64>::open(HASH_INDEX_PATH, 1024).unwrap();
+        assert_eq!(HashIndex::<u64>::header(&index).unwrap().buckets, 8);
+        assert!(HashIndex::<i64>::header(&index).is_err());
+        let mut found = HashIndex::search_many(&index, &70i64);
+        assert!(found.next().unwrap().is_err());
+        assert!(found.next().is_none());
+        assert!(HashIndex::search_one(&index, &70i64).is_err());
+
+        _ensure_removed_files(&[HASH_INDEX_PATH, HASH_REHASHED_PATH]);
+    }
+
+    #[test]
+    fn test_concurrent_adds() {
+        _ensure_removed_files(&[HASH_CONCURRENT_PATH]);
+
+        // The threads add to and exclude from the same chains, none of
+        // the entries is lost
+        let index = Arc::new(
+            HashIndex::<u64>::open(HASH_CONCURRENT_PATH, 4).unwrap()
+        );
+        let writers: Vec<_> = (0..4).map(|n| {
+            let index = index.clone();
+            thread::spawn(move || {
+                let ids: Vec<usize> = (n * 50 + 1..=n * 50 + 50).collect();
+                for id in ids.iter() {
+                    HashIndex::add(&index, &(*id as u64 % 8), *id).unwrap();
+                }
+                for id in ids[..25].iter() {
+                    HashIndex::exclude(&index, &(*id as u64 % 8), *id)
+                        .unwrap();
+                }
+            })
+        }).collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        assert_eq!(HashIndex::<u64>::header(&index).unwrap().count, 100);
+        assert_eq!(HashIndex::<u64>::entries(&index).unwrap().len(), 100);
+
+        _ensure_removed_files(&[HASH_CONCURRENT_PATH]);
+    }
+
+    #[test]
+    fn test_stable_hasher() {
+        let mut hasher = StableHasher::new();
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63dc4c8601ec8c);
+
+        let mut a = StableHasher::new();
+        5usize.hash(&mut a);
+        let mut b = StableHasher::new();
+        5u64.hash(&mut b);
+        assert_eq!(a.finish(), b.finish());
+    }
+
+    fn _ensure_removed_files(paths: &[&str]) {
+        for path in paths {
+            for ext in &["", ".free"] {
+                let path = format!("{}{}", path, ext);
+                if fs::metadata(&path).is_ok() {
+                    fs::remove_file(&path).unwrap();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::io;
+use std::iter::FromIterator;
+
+use roaring::RoaringTreemap;
+
+use crate::bitmap_index::*;
+use crate::table::*;
+
+
+/// The size of the length of the serialized set in the first block.
+const LENGTH_SIZE: usize = 8;
+
+
+/// IdSet is a compressed set of record ids (a roaring bitmap), so a result
+/// of an index search over millions of records takes little memory and
+/// the sets of several predicates are combined quickly.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IdSet {
+    ids: RoaringTreemap,
+}
+
+
+impl IdSet {
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates the set of the ids in the **bitmap**.
+    pub fn from_bitmap(bitmap: &Bitmap) -> Self {
+        bitmap.iter().collect()
+    }
+
+    /// Adds the **id** to the set.
+    pub fn insert(&mut self, id: usize) {
+        self.ids.insert(id as u64);
+    }
+
+    /// Removes the **id** from the set.
+    pub fn remove(&mut self, id: usize) {
+        self.ids.remove(id as u64);
+    }
+
+    /// Returns true if the **id** is in the set, else false.
+    pub fn contains(&self, id: usize) -> bool {
+        self.ids.contains(id as u64)
+    }
+
+    /// The number of ids in the set.
+    pub fn len(&self) -> usize {
+        self.ids.len() as usize
+    }
+
+    /// Returns true if the set is empty, else false.
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// The ids that are in any of the sets.
+    pub fn union(&self, other: &Self) -> Self {
+        Self { ids: &self.ids | &other.ids }
+    }
+
+    /// The ids that are in both sets.
+    pub fn intersection(&self, other: &Self) -> Self {
+        Self { ids: &self.ids & &other.ids }
+    }
+
+    /// The ids that are in this set but not in the **other**.
+    pub fn difference(&self, other: &Self) -> Self {
+        Self { ids: &self.ids - &other.ids }
+    }
+
+    /// Iterates the ids in ascending order.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = usize> + '_> {
+        Box::new(self.ids.iter().map(|id| id as usize))
+    }
+
+    /// Writes the set to the blocks of the **table** starting from
+    /// the first one. The first block keeps the length of the serialized
+    /// set, the blocks left from a larger set are not read.
+    pub fn store(&self, table: &Table) -> Result<(), io::Error> {
+        let mut bytes = (self.ids.serialized_size() as u64)
+            .to_le_bytes().to_vec();
+        self.ids.serialize_into(&mut bytes)?;
+
+        let size = table.size()?;
+        for (idx, block) in bytes.chunks(table.block_size()).enumerate() {
+            let mut block = block.to_vec();
+            block.resize(table.block_size(), 0);
+            if idx < size {
+                table.update(&block, idx)?;
+            } else {
+                table.append(&block)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the set written by **store** from the **table**. An empty
+    /// table is an empty set.
+    pub fn load(table: &Table) -> Result<Self, io::Error> {
+        if table.empty()? {
+            return Ok(Self::new());
+        }
+
+        let first = table.get(0)?;
+        if first.len() < LENGTH_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput, "id set block size"
+            ));
+        }
+        let mut length = [0u8; LENGTH_SIZE];
+        length.clone_from_slice(&first[..LENGTH_SIZE]);
+        let length = LENGTH_SIZE + u64::from_le_bytes(length) as usize;
+
+        let count = length.div_ceil(first.len());
+        if count > table.size()? {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "id set"));
+        }
+        let bytes: Vec<u8> = table.get_many(&(0..count).collect::<Vec<_>>())?
+            .concat();
+
+        Ok(Self {
+            ids: RoaringTreemap::deserialize_from(
+                &bytes[LENGTH_SIZE..length]
+            )?
+        })
+    }
+}
+
+
+impl FromIterator<usize> for IdSet {
+    fn from_iter<I: IntoIterator<Item = usize>>(ids: I) -> Self {
+        Self { ids: ids.into_iter().map(|id| id as u64).collect() }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::table_index::*;
+    use super::*;
+
+    const TABLE_AGE_INDEX_PATH: &str = "test-id-set-age-index.tbl";
+    const TABLE_SET_PATH: &str = "test-id-set.tbl";
+
+    #[test]
+    fn test_id_set() {
+        _ensure_removed_tables();
+
+        let age_index = Table::open::<TableIndex<u32>>(
+            TABLE_AGE_INDEX_PATH
+        ).unwrap();
+        let pairs: Vec<(u32, usize)> = (1..=1000)
+            .map(|id| ((id % 100) as u32, id))
+            .collect();
+        TableIndex::add_batch(&age_index, &pairs).unwrap();
+
+        // Combination of index searches
+        let young: IdSet = TableIndex::iter_between(&age_index, &0u32, &20)
+            .collect::<Result<_, _>>().unwrap();
+        assert_eq!(young.len(), 200);
+        let even: IdSet = (1..=1000).filter(|id| id % 2 == 0).collect();
+        assert_eq!(young.intersection(&even).len(), 100);
+        assert_eq!(young.union(&even).len(), 600);
+        let odd_young = young.difference(&even);
+        assert!(odd_young.contains(1) && !odd_young.contains(2));
+        let first: Vec<usize> = odd_young.iter().take(2).collect();
+        assert_eq!(first, vec![1, 3]);
+
+        // Stored in blocks
+        let table = Table::open_with_block_size(TABLE_SET_PATH, 64).unwrap();
+        assert!(IdSet::load(&table).unwrap().is_empty());
+        young.store(&table).unwrap();
+        assert!(table.size().unwrap() > 1);
+        assert_eq!(IdSet::load(&table).unwrap(), young);
+        let mut small = IdSet::new();
+        small.insert(5);
+        small.store(&table).unwrap();
+        assert_eq!(IdSet::load(&table).unwrap(), small);
+
+        // Bitmap
+        let mut bitmap = Bitmap::new();
+        bitmap.insert(7);
+        assert!(IdSet::from_bitmap(&bitmap).contains(7));
+
+        _ensure_removed_tables();
+    }
+
+    fn _ensure_removed_tables() {
+        for path in &[TABLE_AGE_INDEX_PATH, TABLE_SET_PATH] {
+            if fs::metadata(path).is_ok() {
+                fs::remove_file(path).unwrap();
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::mem;
+
+
+/// IndexKey is a value type of **TableIndex**. Its tag is stored in the
+/// header of the index, so the index is not read as the one of another
+/// value type. The tag is made of a stable name of the type (e.g. by
+/// **key_tag_of**), so it does not change between builds.
+pub trait IndexKey {
+    /// The tag of the value type.
+    fn key_tag() -> u64;
+}
+
+
+/// Makes a tag of the type by its stable **name** (FNV-1a).
+pub fn key_tag_of(name: &str) -> u64 {
+    name.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+
+macro_rules! index_key {
+    ($($prim:ty),*) => {
+        $(
+            impl IndexKey for $prim {
+                fn key_tag() -> u64 {
+                    key_tag_of(&format!(
+                        "{}/{}", stringify!($prim), mem::size_of::<$prim>()
+                    ))
+                }
+            }
+        )*
+    };
+}
+
+
+index_key!(
+    u8, u16, u32, u64, u128, usize,
+    i8, i16, i32, i64, i128, isize,
+    f32, f64, bool, char
+);
+
+
+impl<A: IndexKey, B: IndexKey> IndexKey for (A, B) {
+    fn key_tag() -> u64 {
+        key_tag_of(&format!("({:x},{:x})", A::key_tag(), B::key_tag()))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::bytes::*;
+    use crate::varchar::*;
+    use crate::key_ord::*;
+    use crate::ordered_float::*;
+    use crate::composite_key::*;
+    use super::*;
+
+    #[test]
+    fn test_index_key() {
+        // The tags are fixed, the index files keep them
+        assert_eq!(u32::key_tag(), key_tag_of("u32/4"));
+        assert_eq!(u32::key_tag(), 0x838fe28562e29aaa);
+        assert_eq!(Varchar::<20>::key_tag(), key_tag_of("Varchar/20"));
+        assert_eq!(Bytes::<16>::key_tag(), key_tag_of("Bytes/16"));
+        assert_eq!(OrderedF64::key_tag(), key_tag_of("OrderedF64/8"));
+
+        // The tags of the different types differ
+        let tags = [
+            u32::key_tag(), i32::key_tag(), u64::key_tag(),
+            Varchar::<20>::key_tag(), Varchar::<40>::key_tag(),
+            Bytes::<20>::key_tag(), OrderedF32::key_tag(),
+            CompositeKey::<u32, u64>::key_tag(),
+            CompositeKey::<u64, u32>::key_tag(),
+            OrdKey::<u32, Ascending>::key_tag(),
+            OrdKey::<u32, Descending>::key_tag(),
+        ];
+        for (pos, tag) in tags.iter().enumerate() {
+            assert!(!tags[pos + 1..].contains(tag));
+        }
+    }
+}
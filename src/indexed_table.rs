@@ -0,0 +1,258 @@
+use std::io;
+
+use crate::table::*;
+use crate::table_trait::*;
+use crate::table_index::*;
+use crate::index_key::*;
+use crate::table_error::*;
+use crate::repository::{RepositoryIndex, KeyIndex};
+
+
+/// IndexedTable owns the table of the records **T** and the indexes
+/// registered by **with_index**, so every insert, update and delete through
+/// it keeps all indexes in sync. Other threads cannot write to the table
+/// during each of them. The index named *age* of the table *person.tbl* is
+/// stored in the file *person.tbl.age.index*.
+pub struct IndexedTable<T> {
+    path: String,
+    table: Table,
+    indexes: Vec<Box<dyn RepositoryIndex<T>>>,
+}
+
+
+impl<T: TableTrait + 'static> IndexedTable<T> {
+    /// Creates or opens the table located by **path** without indexes.
+    pub fn open(path: &str) -> Result<Self, TableError> {
+        Ok(Self {
+            path: path.to_string(),
+            table: Table::open::<T>(path)?,
+            indexes: Vec::new(),
+        })
+    }
+
+    /// Registers the index with the **name** over the value extracted from
+    /// the record by **key**. A new index is built from the records of
+    /// the table.
+    pub fn with_index<K: Copy + PartialOrd + IndexKey + 'static>(
+                mut self,
+                name: &str,
+                key: fn(&T) -> K
+            ) -> Result<Self, TableError> {
+        if self.indexes.iter().any(|index| index.name() == name) {
+            return Err(TableError::Io(io::Error::new(
+                io::ErrorKind::AlreadyExists, name.to_string()
+            )));
+        }
+
+        let index = KeyIndex {
+            name: name.to_string(),
+            table: TableIndex::<K>::open(
+                &format!("{}.{}.index", self.path, name)
+            )?,
+            key,
+        };
+        if index.table.empty()? {
+            for record in T::all(&self.table) {
+                let record = record?;
+                if record.id() != 0 {
+                    index.add(&record)?;
+                }
+            }
+        }
+
+        self.indexes.push(Box::new(index));
+        Ok(self)
+    }
+
+    /// The table of the records. The records written directly to it are not
+    /// indexed.
+    pub fn table(&self) -> &Table {
+        &self.table
+    }
+
+    /// The table of the index with the **name** (e.g. for the range scans
+    /// of **TableIndex**).
+    pub fn index(&self, name: &str) -> Option<&Table> {
+        self.indexes.iter().find(|index| index.name() == name)?.table()
+    }
+
+    /// Extracts the record by id.
+    pub fn get(&self, id: usize) -> Result<T, io::Error> {
+        T::get(&self.table, id)
+    }
+
+    /// Inserts the record and adds it to the indexes. Returns its new id.
+    pub fn insert(&self, record: &mut T) -> Result<usize, io::Error> {
+        let _exclusive = self.table.exclusive();
+        let id = record.insert(&self.table)?;
+        if let Err(err) = self._add_indexes(record) {
+            let _ = record.delete(&self.table);
+            record.set_id(0);
+            return Err(err);
+        }
+        Ok(id)
+    }
+
+    /// Updates the record, the indexes are updated for the changed values
+    /// only.
+    pub fn update(&self, record: &T) -> Result<(), io::Error> {
+        let _exclusive = self.table.exclusive();
+        let old = self._existing(record.id())?;
+        for index in self.indexes.iter() {
+            if index.changed(&old, record) {
+                index.exclude(&old)?;
+                index.add(record)?;
+            }
+        }
+        record.update(&self.table)
+    }
+
+    /// Deletes the record by id: it is excluded from the indexes and its
+    /// block is reused by the next insert.
+    pub fn delete(&self, id: usize) -> Result<(), io::Error> {
+        let _exclusive = self.table.exclusive();
+        let record = self._existing(id)?;
+        for index in self.indexes.iter() {
+            index.exclude(&record)?;
+        }
+        record.delete(&self.table)
+    }
+
+    /// Finds all records with the **key** in the index named **name**.
+    pub fn find_by<K: Copy + PartialOrd + IndexKey + 'static>(
+                &self,
+                name: &str,
+                key: &K
+            ) -> Result<Vec<T>, io::Error> {
+        let index = self._index::<KeyIndex<T, K>>(name)?;
+        TableIndex::search_many(&index.table, key)
+            .map(|id| self.get(id?))
+            .collect()
+    }
+
+    fn _existing(&self, id: usize) -> Result<T, io::Error> {
+        let record = self.get(id)?;
+        if record.id() == 0 {
+            return Err(io::Error::new(io::ErrorKind::NotFound, id.to_string()));
+        }
+        Ok(record)
+    }
+
+    fn _add_indexes(&self, record: &T) -> Result<(), io::Error> {
+        for (pos, index) in self.indexes.iter().enumerate() {
+            if let Err(err) = index.add(record) {
+                for index in self.indexes[..pos].iter() {
+                    let _ = index.exclude(record);
+                }
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    fn _index<I: 'static>(&self, name: &str) -> Result<&I, io::Error> {
+        let index = self.indexes.iter().find(|index| index.name() == name)
+            .ok_or_else(
+                || io::Error::new(io::ErrorKind::NotFound, name.to_string())
+            )?;
+        index.as_any().downcast_ref::<I>().ok_or_else(
+            || io::Error::new(io::ErrorKind::InvalidInput, name.to_string())
+        )
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::varchar::*;
+    use super::*;
+
+    const TABLE_PATH: &str = "test-indexed-person.tbl";
+
+    #[derive(Debug, Copy, Clone)]
+    struct Person {
+        id: usize,
+        name: Varchar<20>,
+        age: u32,
+    }
+
+    impl TableTrait for Person {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    impl Person {
+        fn new(name: &str, age: u32) -> Self {
+            Self { id: 0, name: Varchar::<20>::new(name), age }
+        }
+    }
+
+    fn names(persons: Vec<Person>) -> Vec<String> {
+        persons.iter().map(|p| p.name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_indexed_table() {
+        _ensure_removed_tables();
+
+        let persons = IndexedTable::<Person>::open(TABLE_PATH).unwrap()
+            .with_index("age", |p: &Person| p.age).unwrap();
+        let mut alex = Person::new("alex", 32);
+        persons.insert(&mut alex).unwrap();
+        persons.insert(&mut Person::new("bob", 25)).unwrap();
+        persons.insert(&mut Person::new("carl", 32)).unwrap();
+        assert_eq!(names(persons.find_by("age", &32u32).unwrap()),
+                   vec!["alex", "carl"]);
+
+        // Update and delete keep the index in sync
+        alex.age = 33;
+        persons.update(&alex).unwrap();
+        persons.delete(2).unwrap();
+        assert_eq!(names(persons.find_by("age", &32u32).unwrap()),
+                   vec!["carl"]);
+        assert_eq!(persons.find_by("age", &33u32).unwrap()[0].id, 1);
+        assert!(persons.find_by("age", &25u32).unwrap().is_empty());
+        assert_eq!(persons.delete(2).unwrap_err().kind(),
+                   io::ErrorKind::NotFound);
+        assert!(persons.find_by("age", &32u64).is_err());
+        assert!(persons.find_by("name", &32u32).is_err());
+
+        // A new index is built from the table
+        drop(persons);
+        let persons = IndexedTable::<Person>::open(TABLE_PATH).unwrap()
+            .with_index("age", |p: &Person| p.age).unwrap()
+            .with_index("name", |p: &Person| p.name).unwrap();
+        let carl = Varchar::<20>::new("carl");
+        assert_eq!(persons.find_by("name", &carl).unwrap()[0].age, 32);
+        assert_eq!(TableIndex::<u32>::iter(persons.index("age").unwrap())
+                       .collect::<Result<Vec<_>, _>>().unwrap(), vec![3, 1]);
+        assert!(persons.index("email").is_none());
+
+        // The deleted block is reused
+        let mut dan = Person::new("dan", 19);
+        assert_eq!(persons.insert(&mut dan).unwrap(), 2);
+        assert_eq!(persons.find_by("age", &19u32).unwrap()[0].id, 2);
+
+        assert!(IndexedTable::<Person>::open(TABLE_PATH).unwrap()
+            .with_index("age", |p: &Person| p.age).unwrap()
+            .with_index("age", |p: &Person| p.age).is_err());
+
+        _ensure_removed_tables();
+    }
+
+    fn _ensure_removed_tables() {
+        for ext in &["", ".free", ".age.index", ".name.index"] {
+            let path = format!("{}{}", TABLE_PATH, ext);
+            if fs::metadata(&path).is_ok() {
+                fs::remove_file(&path).unwrap();
+            }
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use std::{io, iter};
+use std::marker::PhantomData;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard, PoisonError};
+
+use crate::table::*;
+use crate::table_trait::*;
+use crate::table_error::*;
+
+
+/// The size of an entry of the map: the index of the block plus one
+/// (8 bytes LE), 0 for a deleted id.
+const MAP_ENTRY_SIZE: usize = 8;
+
+
+/// IndirectTable keeps the records **T** in a data table and maps their ids
+/// to the blocks through the map table (**path.map**), so the blocks can be
+/// moved (e.g. by **compact**) without changing the ids. The ids are
+/// given in the order of inserts and never reused. The moves are exclusive
+/// to the other operations, so it can be shared by threads like **Table**.
+#[derive(Debug)]
+pub struct IndirectTable<T> {
+    data: Table,
+    map: Table,
+    moves: RwLock<()>,
+    record: PhantomData<T>,
+}
+
+
+impl<T: TableTrait> IndirectTable<T> {
+    /// Creates or opens the data table at **path** and its map.
+    pub fn open(path: &str) -> Result<Self, TableError> {
+        Ok(Self {
+            data: Table::open::<T>(path)?,
+            map: Table::open_with_block_size(
+                &format!("{}.map", path), MAP_ENTRY_SIZE
+            )?,
+            moves: RwLock::new(()),
+            record: PhantomData,
+        })
+    }
+
+    /// The data table. Its blocks must not be written directly, else
+    /// the map gets out of sync.
+    pub fn table(&self) -> &Table {
+        &self.data
+    }
+
+    /// The number of ids given including the deleted ones.
+    pub fn size(&self) -> Result<usize, io::Error> {
+        self.map.size()
+    }
+
+    /// The index of the block of the record by id, None if it is deleted.
+    pub fn block_of(&self, id: usize) -> Result<Option<usize>, io::Error> {
+        let _moves = self._read();
+        self._block_of(id)
+    }
+
+    /// Extracts the record by id.
+    pub fn get(&self, id: usize) -> Result<T, io::Error> {
+        let _moves = self._read();
+        let idx = self._existing_block_of(id)?;
+        let mut record = T::from_bytes(&self.data.get(idx)?);
+        record.set_id(id);
+        record.after_read();
+        Ok(record)
+    }
+
+    /// Inserts the record reusing the block of a deleted one if any.
+    /// Returns its new id.
+    pub fn insert(&self, record: &mut T) -> Result<usize, io::Error> {
+        let _moves = self._read();
+        let mut written = _written_record(record);
+        let idx = self.data.insert(written.as_bytes())?;
+        let id = self.map.append(&Self::_entry(Some(idx)))? + 1;
+        record.set_id(id);
+        written.set_id(id);
+        self.data.update(written.as_bytes(), idx)?;
+        Ok(id)
+    }
+
+    /// Updates the record in its block.
+    pub fn update(&self, record: &T) -> Result<(), io::Error> {
+        let _moves = self._read();
+        let idx = self._existing_block_of(record.id())?;
+        self.data.update(_written_record(record).as_bytes(), idx)
+    }
+
+    /// Deletes the record by id, its block is reused by the next insert
+    /// and the id is not.
+    pub fn delete(&self, id: usize) -> Result<(), io::Error> {
+        let _moves = self._read();
+        let idx = self._existing_block_of(id)?;
+        self.map.update(&Self::_entry(None), id - 1)?;
+        self.data.delete(idx)
+    }
+
+    /// Iterates the records skipping the deleted ones, a record that
+    /// fails to read is yielded as the error.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = Result<T, io::Error>> + '_> {
+        let size = match self.size() {
+            Ok(size) => size,
+            Err(err) => return Box::new(iter::once(Err(err))),
+        };
+        Box::new((1..=size).filter_map(move |id| match self.get(id) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            record => Some(record),
+        }))
+    }
+
+    /// Moves the last blocks into the blocks of deleted records and cuts
+    /// the end of the data file, the ids stay the same. A block is written
+    /// to its new place before the map is switched to it, so a crash
+    /// leaves a valid copy. Returns the number of moved blocks.
+    pub fn compact(&self) -> Result<usize, io::Error> {
+        let _moves = self._write();
+        let _exclusive = self.data.exclusive();
+
+        // The id of the record in each block, 0 for a free block
+        let mut owners = vec![0; self.data.size()?];
+        for id in 1..=self.size()? {
+            if let Some(idx) = self._block_of(id)? {
+                owners[idx] = id;
+            }
+        }
+
+        let mut moved = 0;
+        let mut hole = 0;
+        let mut end = owners.len();
+        loop {
+            while (hole < end) && (owners[hole] != 0) {
+                hole += 1;
+            }
+            while (end > hole) && (owners[end - 1] == 0) {
+                end -= 1;
+            }
+            if hole >= end {
+                break;
+            }
+
+            let id = owners[end - 1];
+            self.data.update(&self.data.get(end - 1)?, hole)?;
+            self.map.update(&Self::_entry(Some(hole)), id - 1)?;
+            self.data.erase(end - 1)?;
+            owners[hole] = id;
+            owners[end - 1] = 0;
+            moved += 1;
+        }
+
+        self.data._truncate(end)?;
+        Ok(moved)
+    }
+
+    fn _block_of(&self, id: usize) -> Result<Option<usize>, io::Error> {
+        if (id == 0) || (id > self.size()?) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, id.to_string()));
+        }
+
+        let mut bytes = [0u8; MAP_ENTRY_SIZE];
+        bytes.clone_from_slice(&self.map.get(id - 1)?[..MAP_ENTRY_SIZE]);
+        match u64::from_le_bytes(bytes) as usize {
+            0 => Ok(None),
+            entry => Ok(Some(entry - 1)),
+        }
+    }
+
+    fn _existing_block_of(&self, id: usize) -> Result<usize, io::Error> {
+        self._block_of(id)?.ok_or_else(
+            || io::Error::new(io::ErrorKind::NotFound, id.to_string())
+        )
+    }
+
+    fn _entry(idx: Option<usize>) -> Vec<u8> {
+        let entry = idx.map_or(0, |idx| idx as u64 + 1);
+        entry.to_le_bytes().to_vec()
+    }
+
+    fn _read(&self) -> RwLockReadGuard<'_, ()> {
+        // The lock keeps no state, so it is used even if a thread panicked
+        // holding it
+        self.moves.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn _write(&self) -> RwLockWriteGuard<'_, ()> {
+        self.moves.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, thread};
+    use std::sync::Arc;
+
+    use crate::varchar::*;
+    use super::*;
+
+    const TABLE_PATH: &str = "test-indirect-person.tbl";
+
+    #[derive(Debug, Copy, Clone)]
+    struct Person {
+        id: usize,
+        name: Varchar<20>,
+        age: u32,
+    }
+
+    impl TableTrait for Person {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    impl Person {
+        fn new(name: &str, age: u32) -> Self {
+            Self { id: 0, name: Varchar::<20>::new(name), age }
+        }
+    }
+
+    #[test]
+    fn test_indirect_table() {
+        _ensure_removed_tables();
+
+        let persons = IndirectTable::<Person>::open(TABLE_PATH).unwrap();
+        for (name, age) in &[
+                    ("alex", 32), ("bob", 25), ("carl", 40), ("dan", 19),
+                    ("eve", 28)
+                ] {
+            persons.insert(&mut Person::new(name, *age)).unwrap();
+        }
+
+        // The ids are not reused
+        persons.delete(2).unwrap();
+        persons.delete(4).unwrap();
+        assert_eq!(persons.get(2).unwrap_err().kind(),
+                   io::ErrorKind::NotFound);
+        let mut fred = Person::new("fred", 51);
+        assert_eq!(persons.insert(&mut fred).unwrap(), 6);
+        assert_eq!(persons.block_of(6).unwrap(), Some(3));
+        fred.age = 52;
+        persons.update(&fred).unwrap();
+
+        // The last blocks fill the holes keeping the ids
+        assert_eq!(persons.compact().unwrap(), 1);
+        assert_eq!(persons.table().size().unwrap(), 4);
+        assert_eq!(persons.table().free_count().unwrap(), 0);
+        assert_eq!(persons.block_of(5).unwrap(), Some(1));
+        assert_eq!(persons.block_of(2).unwrap(), None);
+        let names: Vec<(usize, String)> = persons.iter()
+            .map(|p| p.unwrap()).map(|p| (p.id, p.name.to_string()))
+            .collect();
+        assert_eq!(names, vec![
+            (1, "alex".to_string()), (3, "carl".to_string()),
+            (5, "eve".to_string()), (6, "fred".to_string()),
+        ]);
+        assert_eq!(persons.get(6).unwrap().age, 52);
+        assert_eq!(persons.compact().unwrap(), 0);
+
+        // Reopen
+        drop(persons);
+        let persons = IndirectTable::<Person>::open(TABLE_PATH).unwrap();
+        assert_eq!(persons.get(5).unwrap().name.to_string(), "eve");
+        assert_eq!(persons.insert(&mut Person::new("gus", 33)).unwrap(), 7);
+        assert_eq!(persons.block_of(7).unwrap(), Some(4));
+
+        // Reads go on while the blocks are moved
+        let persons = Arc::new(persons);
+        persons.delete(1).unwrap();
+        let reader = {
+            let persons = Arc::clone(&persons);
+            thread::spawn(move || {
+                for _ in 0..100 {
+                    assert_eq!(persons.get(7).unwrap().name.to_string(),
+                               "gus");
+                }
+            })
+        };
+        assert_eq!(persons.compact().unwrap(), 1);
+        reader.join().unwrap();
+        assert_eq!(persons.block_of(7).unwrap(), Some(0));
+
+        _ensure_removed_tables();
+    }
+
+    fn _ensure_removed_tables() {
+        for ext in &["", ".free", ".map"] {
+            let path = format!("{}{}", TABLE_PATH, ext);
+            if fs::metadata(&path).is_ok() {
+                fs::remove_file(&path).unwrap();
+            }
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::{cmp, fmt};
+use std::marker::PhantomData;
+
+use crate::index_key::*;
+
+
+/// KeyOrd defines a custom order of index values. It is implemented once
+/// per ordering and applied to any value with **OrdKey**.
+pub trait KeyOrd<T> {
+    /// The stable name of the order, it is a part of the tag of the index
+    /// value type (see **IndexKey**).
+    const NAME: &'static str;
+
+    /// Compares two values.
+    fn compare(a: &T, b: &T) -> cmp::Ordering;
+}
+
+
+/// The natural order of **PartialOrd**, incomparable values are equal.
+#[derive(Debug, Copy, Clone)]
+pub struct Ascending;
+
+
+/// The reversed natural order of **PartialOrd**.
+#[derive(Debug, Copy, Clone)]
+pub struct Descending;
+
+
+impl<T: PartialOrd> KeyOrd<T> for Ascending {
+    const NAME: &'static str = "Ascending";
+
+    fn compare(a: &T, b: &T) -> cmp::Ordering {
+        a.partial_cmp(b).unwrap_or(cmp::Ordering::Equal)
+    }
+}
+
+
+impl<T: PartialOrd> KeyOrd<T> for Descending {
+    const NAME: &'static str = "Descending";
+
+    fn compare(a: &T, b: &T) -> cmp::Ordering {
+        b.partial_cmp(a).unwrap_or(cmp::Ordering::Equal)
+    }
+}
+
+
+/// OrdKey is a value compared by the order **O**, so it can be used as
+/// a value of **TableIndex** to keep the index in this order.
+pub struct OrdKey<T, O> {
+    value: T,
+    order: PhantomData<O>,
+}
+
+
+impl<T, O> OrdKey<T, O> {
+    /// Creates the key from the value.
+    pub fn new(value: T) -> Self {
+        Self { value, order: PhantomData }
+    }
+
+    /// Gets the original value.
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+}
+
+
+impl<T: Copy, O> Copy for OrdKey<T, O> {}
+
+
+impl<T: Clone, O> Clone for OrdKey<T, O> {
+    fn clone(&self) -> Self {
+        Self::new(self.value.clone())
+    }
+}
+
+
+impl<T, O: KeyOrd<T>> PartialEq for OrdKey<T, O> {
+    fn eq(&self, other: &Self) -> bool {
+        O::compare(&self.value, &other.value) == cmp::Ordering::Equal
+    }
+}
+
+
+impl<T, O: KeyOrd<T>> PartialOrd for OrdKey<T, O> {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(O::compare(&self.value, &other.value))
+    }
+}
+
+
+impl<T: IndexKey, O: KeyOrd<T>> IndexKey for OrdKey<T, O> {
+    fn key_tag() -> u64 {
+        key_tag_of(&format!("OrdKey({:x},{})", T::key_tag(), O::NAME))
+    }
+}
+
+
+impl<T: fmt::Debug, O> fmt::Debug for OrdKey<T, O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "OrdKey({:?})", self.value)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::table::*;
+    use crate::table_index::*;
+    use crate::varchar::*;
+    use super::*;
+
+    const TABLE_NAME_INDEX_PATH: &str = "test-key-ord-name-index.tbl";
+    const TABLE_AGE_INDEX_PATH: &str = "test-key-ord-age-index.tbl";
+
+    struct CaseInsensitive;
+
+    impl KeyOrd<Varchar<20>> for CaseInsensitive {
+        const NAME: &'static str = "CaseInsensitive";
+
+        fn compare(a: &Varchar<20>, b: &Varchar<20>) -> cmp::Ordering {
+            a.to_string().to_lowercase().cmp(&b.to_string().to_lowercase())
+        }
+    }
+
+    type NameKey = OrdKey<Varchar<20>, CaseInsensitive>;
+    type AgeKey = OrdKey<u32, Descending>;
+
+    #[test]
+    fn test_key_ord() {
+        _ensure_removed_tables();
+
+        let name_index = Table::open::<TableIndex<NameKey>>(
+            TABLE_NAME_INDEX_PATH
+        ).unwrap();
+        let age_index = Table::open::<TableIndex<AgeKey>>(
+            TABLE_AGE_INDEX_PATH
+        ).unwrap();
+
+        let persons = [("bob", 25), ("Alex", 32), ("carl", 40)];
+        for (id, (name, age)) in persons.iter().enumerate() {
+            let name = NameKey::new(Varchar::<20>::new(name));
+            TableIndex::add(&name_index, &name, id + 1).unwrap();
+            TableIndex::add(&age_index, &AgeKey::new(*age), id + 1).unwrap();
+        }
+
+        // Custom order
+        let ids: Vec<usize> = TableIndex::<NameKey>::iter(&name_index)
+            .collect::<Result<_, _>>().unwrap();
+        assert_eq!(ids, vec![2, 1, 3]);
+        let alex = NameKey::new(Varchar::<20>::new("ALEX"));
+        assert_eq!(TableIndex::search_one(&name_index, &alex).unwrap(), 2);
+
+        // Descending order
+        let ids: Vec<usize> = TableIndex::<AgeKey>::iter(&age_index)
+            .collect::<Result<_, _>>().unwrap();
+        assert_eq!(ids, vec![3, 2, 1]);
+
+        _ensure_removed_tables();
+    }
+
+    fn _ensure_removed_tables() {
+        if fs::metadata(TABLE_NAME_INDEX_PATH).is_ok() {
+            fs::remove_file(TABLE_NAME_INDEX_PATH).unwrap();
+        }
+        if fs::metadata(TABLE_AGE_INDEX_PATH).is_ok() {
+            fs::remove_file(TABLE_AGE_INDEX_PATH).unwrap();
+        }
+    }
+}
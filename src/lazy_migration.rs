@@ -0,0 +1,206 @@
+use std::{fs, io};
+use std::collections::HashMap;
+
+use crate::table::*;
+use crate::table_trait::*;
+use crate::file_ext::FileExt;
+
+
+type Decoder<T> = Box<dyn Fn(&[u8]) -> T>;
+
+
+/// LazyMigration upgrades the records of older layouts when they are read
+/// instead of rewriting the whole table at once. The layout version of
+/// each block is kept in its own file (the table path with *.versions*
+/// extension), one byte per block. Blocks written before the versions were
+/// tracked have version 0. A block gets the current version on the next
+/// write through **insert** or **update**.
+///
+/// The size of the block must stay the same in all layouts, so a table
+/// whose records grow should be opened with an alignment that fits them
+/// (see **Table::with_alignment**).
+pub struct LazyMigration<T> {
+    version: u8,
+    versions: fs::File,
+    decoders: HashMap<u8, Decoder<T>>,
+}
+
+
+impl<T: TableTrait> LazyMigration<T> {
+    /// Opens the versions of the blocks of the table located by **path**.
+    /// **version** is the version of the current layout **T**.
+    pub fn open(path: &str, version: u8) -> Result<Self, io::Error> {
+        let versions = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(format!("{}.versions", path))?;
+        Ok(Self { version, versions, decoders: HashMap::new() })
+    }
+
+    /// Registers the function that upgrades a block of the layout
+    /// **version** to the current one.
+    pub fn with_decoder(
+                mut self,
+                version: u8,
+                decode: impl Fn(&[u8]) -> T + 'static
+            ) -> Self {
+        self.decoders.insert(version, Box::new(decode));
+        self
+    }
+
+    /// Extracts the record by id upgrading it if it has an older layout.
+    /// A layout without a decoder is an *InvalidData* error.
+    pub fn get(&self, table: &Table, id: usize) -> Result<T, io::Error> {
+        let idx = T::get_index_by_id(table, id)?;
+        let block = table.get(idx)?;
+        let version = self.block_version(idx)?;
+
+        if version == self.version {
+            return Ok(T::from_bytes(&block));
+        }
+
+        match self.decoders.get(&version) {
+            Some(decode) => {
+                let mut record = decode(&block);
+                record.set_id(id);
+                Ok(record)
+            },
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("no decoder for version {}", version)
+            )),
+        }
+    }
+
+    /// Inserts the record with the current layout.
+    pub fn insert(
+                &self,
+                table: &Table,
+                record: &mut T
+            ) -> Result<usize, io::Error> {
+        let id = record.insert(table)?;
+        self._set_version(id - 1)?;
+        Ok(id)
+    }
+
+    /// Updates the record rewriting it with the current layout.
+    pub fn update(&self, table: &Table, record: &T) -> Result<(), io::Error> {
+        record.update(table)?;
+        self._set_version(T::get_index_by_id(table, record.id())?)
+    }
+
+    /// The layout version of the block located by the index.
+    pub fn block_version(&self, idx: usize) -> Result<u8, io::Error> {
+        let mut byte = [0u8; 1];
+        if (idx as u64) < self.versions.metadata()?.len() {
+            self.versions.read_exact_at(&mut byte, idx as u64)?;
+        }
+        Ok(byte[0])
+    }
+
+    fn _set_version(&self, idx: usize) -> Result<(), io::Error> {
+        self.versions.write_all_at(&[self.version], idx as u64)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::varchar::*;
+    use super::*;
+
+    const TABLE_PATH: &str = "test-lazy-migration-person.tbl";
+
+    #[derive(Debug, Copy, Clone)]
+    struct PersonV0 {
+        id: usize,
+        name: Varchar<20>,
+        age: u32,
+    }
+
+    impl TableTrait for PersonV0 {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    #[derive(Debug, Copy, Clone)]
+    struct Person {
+        id: usize,
+        name: Varchar<20>,
+        age: u32,
+        score: u32,
+    }
+
+    impl TableTrait for Person {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    #[test]
+    fn test_lazy_migration() {
+        _ensure_removed_files();
+
+        // Old layout
+        {
+            let table = Table::with_alignment::<PersonV0>(TABLE_PATH, 64)
+                .unwrap();
+            for (name, age) in &[("alex", 32), ("bob", 25)] {
+                PersonV0 {
+                    id: 0, name: Varchar::<20>::new(name), age: *age
+                }.insert(&table).unwrap();
+            }
+        }
+
+        // New layout
+        let table = Table::with_alignment::<Person>(TABLE_PATH, 64)
+            .unwrap();
+        let migration = LazyMigration::<Person>::open(TABLE_PATH, 1).unwrap()
+            .with_decoder(0, |block| {
+                let old = PersonV0::from_bytes(block);
+                Person { id: old.id, name: old.name, age: old.age, score: 0 }
+            });
+
+        let mut alex = migration.get(&table, 1).unwrap();
+        assert_eq!(alex.name.to_string(), "alex");
+        assert_eq!(alex.score, 0);
+        assert_eq!(migration.block_version(0).unwrap(), 0);
+
+        alex.score = 10;
+        migration.update(&table, &alex).unwrap();
+        assert_eq!(migration.block_version(0).unwrap(), 1);
+        assert_eq!(migration.get(&table, 1).unwrap().score, 10);
+        assert_eq!(migration.get(&table, 2).unwrap().age, 25);
+
+        let mut carl = Person {
+            id: 0, name: Varchar::<20>::new("carl"), age: 40, score: 5
+        };
+        assert_eq!(migration.insert(&table, &mut carl).unwrap(), 3);
+        assert_eq!(migration.block_version(2).unwrap(), 1);
+
+        // Unknown layout
+        let migration = LazyMigration::<Person>::open(TABLE_PATH, 2).unwrap();
+        let err = migration.get(&table, 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        _ensure_removed_files();
+    }
+
+    fn _ensure_removed_files() {
+        for path in &[TABLE_PATH.to_string(), format!("{}.versions", TABLE_PATH)] {
+            if fs::metadata(path).is_ok() {
+                fs::remove_file(path).unwrap();
+            }
+        }
+    }
+}
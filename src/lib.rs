@@ -1,5 +1,9 @@
 #![feature(const_generics)]
 #![allow(incomplete_features)]
+#![cfg_attr(
+    all(feature = "strict", not(test)),
+    deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)
+)]
 
 /// Bytes implements a byte array with fixed size in bytes.
 pub mod bytes;
@@ -7,17 +11,219 @@ pub mod bytes;
 /// Varchar implements a string with fixed size in bytes.
 pub mod varchar;
 
+/// FileExt implements positional reads and writes and advisory locks.
+mod file_ext;
+
+/// Progress implements the reporting of long operations.
+pub mod progress;
+
+/// TableError implements the errors of opening a table.
+pub mod table_error;
+
 /// Table implements a logic to work with a file with the table data.
 pub mod table;
 
+/// Snapshot implements sealed read-only copies of a table for mapping.
+pub mod snapshot;
+
+/// Compression implements the zero suppression, LZ4 and Zstandard
+/// compression of the blocks of a table.
+mod compression;
+
+/// ReadTrace implements the recording of block reads for a lookup.
+pub mod read_trace;
+
+/// ChangeFeed implements the notifications of the writes to a table.
+pub mod change_feed;
+
+/// BlockCache implements a cache of the recently read blocks.
+pub mod block_cache;
+
+/// DirtyMap implements a bitmap of the blocks changed since the last backup.
+pub mod dirty_map;
+
+/// FreeList implements a stack of deleted blocks to reuse.
+pub mod free_list;
+
+/// WriteAheadLog implements a log of block writes finished after a crash.
+pub mod wal;
+
+/// TableLock implements an exclusive in-process lock of a table.
+pub mod table_lock;
+
 /// TableTrait implements special methods to interact with the table to store.
 pub mod table_trait;
 
+/// TypedTable implements a table bound to its record type.
+pub mod typed_table;
+
+/// IndirectTable implements a table with ids mapped to movable blocks.
+pub mod indirect_table;
+
+/// RecordCodec implements records stored by a chosen codec.
+pub mod record_codec;
+
+/// RecordGuard implements a change of a record written back on commit.
+pub mod record_guard;
+
+/// TableTagged implements a table storing records of several types.
+pub mod table_tagged;
+
+/// EventLog implements an event log replayed into projections.
+pub mod eventlog;
+
+/// IndexKey implements stable tags of the index value types.
+pub mod index_key;
+
+/// KeyOrd implements custom orders of index values.
+pub mod key_ord;
+
+/// OrderedFloat implements floats with total ordering for index values.
+pub mod ordered_float;
+
+/// CompositeKey implements index values over two fields.
+pub mod composite_key;
+
+/// LazyMigration implements an upgrade of old record layouts on read.
+pub mod lazy_migration;
+
+/// Validation implements checks of the record fields before writing.
+pub mod validation;
+
+/// VarcharPolicy implements allowed charsets and normalization of strings.
+pub mod varchar_policy;
+
+/// FieldString implements stable text forms of the field values.
+pub mod field_string;
+
 /// TableIndex implements an index for a value in the table.
 pub mod table_index;
 
+/// BitmapIndex implements an index of the fields with a few distinct values.
+pub mod bitmap_index;
+
+/// HashIndex implements an index for exact-match lookups by hash.
+pub mod hash_index;
+
+/// Database implements opening and repair of tables with their indexes.
+pub mod database;
+
+/// Migrator implements applying named migrations to a database once.
+pub mod migrator;
+
+/// Prefetch implements read ahead of records visited by an index scan.
+pub mod prefetch;
+
+/// Query implements selections of records composed lazily.
+pub mod query;
+
+/// Repository implements a facade over a table and its indexes.
+pub mod repository;
+
+/// IndexedTable implements a table owning its indexes kept in sync.
+pub mod indexed_table;
+
+/// Fixtures implement generated datasets in temporary tables for tests.
+pub mod fixtures;
+
+/// Relation implements associations between records of repositories.
+pub mod relation;
+
+/// TableDiff implements a comparison of two versions of a table.
+pub mod table_diff;
+
+/// TableSort implements an external sort of records by several keys.
+pub mod table_sort;
+
+/// TableImport implements an import of records keeping their ids.
+pub mod table_import;
+
+/// TableSync implements a merge of two diverged copies of a table.
+pub mod table_sync;
+
+/// TableCsv implements an export and import of records as CSV.
+#[cfg(feature = "csv")]
+pub mod table_csv;
+
+/// TableJson implements a dump of records to JSON and a load back.
+#[cfg(feature = "serde")]
+pub mod table_json;
+
+/// TableLedger implements a tamper-evident hash chain over the table blocks.
+#[cfg(feature = "ledger")]
+pub mod table_ledger;
+
+/// MappedBlocks implements zero-copy reads of the blocks of a table.
+#[cfg(feature = "mmap")]
+pub mod mapped_blocks;
+
+/// Encryption implements the encryption of the blocks of a table at rest.
+#[cfg(feature = "encryption")]
+pub mod encryption;
+
+/// AsyncTable implements async access to a table in a tokio runtime.
+#[cfg(feature = "tokio")]
+pub mod async_table;
+
+/// IdSet implements compressed sets of record ids (roaring bitmaps).
+#[cfg(feature = "roaring")]
+pub mod id_set;
+
 pub use bytes::*;
 pub use varchar::*;
+pub use progress::*;
+pub use table_error::*;
 pub use table::*;
+pub use snapshot::*;
+pub use compression::CompressionKind;
+pub use read_trace::*;
+pub use change_feed::*;
+pub use block_cache::*;
+pub use dirty_map::*;
+pub use free_list::*;
+pub use wal::*;
+pub use table_lock::*;
 pub use table_trait::*;
+pub use typed_table::*;
+pub use indirect_table::*;
+// Record is not exported, its methods would be ambiguous with TableTrait ones
+pub use record_codec::{Codec, RawCodec, RecordTable};
+#[cfg(feature = "bytemuck")]
+pub use record_codec::PodCodec;
+pub use record_guard::*;
+pub use table_tagged::*;
+pub use eventlog::*;
+pub use key_ord::*;
+pub use ordered_float::*;
+pub use composite_key::*;
+pub use lazy_migration::*;
+pub use validation::*;
+pub use varchar_policy::*;
+pub use field_string::*;
 pub use table_index::*;
+pub use bitmap_index::*;
+pub use hash_index::*;
+pub use database::*;
+pub use migrator::*;
+pub use prefetch::*;
+pub use query::*;
+pub use repository::*;
+pub use indexed_table::*;
+pub use fixtures::*;
+pub use relation::*;
+pub use table_diff::*;
+pub use table_sort::*;
+pub use table_import::*;
+pub use table_sync::*;
+#[cfg(feature = "csv")]
+pub use table_csv::*;
+#[cfg(feature = "ledger")]
+pub use table_ledger::*;
+#[cfg(feature = "mmap")]
+pub use mapped_blocks::*;
+#[cfg(feature = "encryption")]
+pub use encryption::*;
+#[cfg(feature = "tokio")]
+pub use async_table::*;
+#[cfg(feature = "roaring")]
+pub use id_set::*;
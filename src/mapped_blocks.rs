@@ -0,0 +1,194 @@
+use std::{fs, io};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use memmap2::Mmap;
+
+
+/// MappedBlocks is a view of the blocks of a table through a memory mapping
+/// of its file returned by **Table::mapped**: the blocks are borrowed from
+/// the mapping without copying, so the scans avoid a read call per block.
+/// The view covers the blocks that existed when it was taken, the blocks
+/// appended later are seen by a new view (the file is mapped again once it
+/// grew). The blocks updated meanwhile are seen with the new content.
+#[derive(Debug, Clone)]
+pub struct MappedBlocks {
+    map: Arc<Mmap>,
+    header_size: usize,
+    block_size: usize,
+    size: usize,
+}
+
+
+impl MappedBlocks {
+    /// The number of blocks in the view.
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    /// Returns true if there are no blocks in the view, else false.
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Gets bytes of a record by its index, the same as **Table::get**
+    /// returns.
+    pub fn get(&self, idx: usize) -> Result<&[u8], io::Error> {
+        if idx >= self.size {
+            return Err(
+                io::Error::new(io::ErrorKind::NotFound, idx.to_string())
+            );
+        }
+        let offset = self.header_size + idx * self.block_size;
+        Ok(&self.map[offset..offset + self.block_size])
+    }
+
+    /// Iterates the bytes of all blocks in the view.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = &[u8]> + '_> {
+        let blocks = &self.map[
+            self.header_size..self.header_size + self.size * self.block_size
+        ];
+        Box::new(blocks.chunks(self.block_size))
+    }
+}
+
+
+/// The mapping of the file of a table shared by its views.
+#[derive(Debug, Default)]
+pub(crate) struct Mapping {
+    map: Mutex<Option<Arc<Mmap>>>,
+}
+
+
+impl Mapping {
+    /// A view of **size** blocks of the **file**, it is mapped again if
+    /// the current mapping is too short.
+    pub(crate) fn view(
+                &self,
+                file: &fs::File,
+                header_size: usize,
+                block_size: usize,
+                size: usize
+            ) -> Result<MappedBlocks, io::Error> {
+        let mut map = self._lock();
+        let len = header_size + size * block_size;
+        let current = map.as_ref().filter(|map| map.len() >= len).cloned();
+        let map = match current {
+            Some(current) => current,
+            None => {
+                // The table never cuts the file while a view is alive (see
+                // release), so the mapped blocks stay valid
+                let new = Arc::new(unsafe { Mmap::map(file)? });
+                *map = Some(Arc::clone(&new));
+                new
+            },
+        };
+        Ok(MappedBlocks { map, header_size, block_size, size })
+    }
+
+    /// Drops the mapping before the file is cut or replaced. It is
+    /// an error if a view is alive. The returned guard keeps new views
+    /// from being taken until it is dropped.
+    pub(crate) fn release(
+                &self
+            ) -> Result<MutexGuard<'_, Option<Arc<Mmap>>>, io::Error> {
+        let mut map = self._lock();
+        if let Some(current) = map.as_ref() {
+            if Arc::strong_count(current) > 1 {
+                return Err(io::Error::other("mapped blocks in use"));
+            }
+        }
+        *map = None;
+        Ok(map)
+    }
+
+    fn _lock(&self) -> MutexGuard<'_, Option<Arc<Mmap>>> {
+        // The mapping is only a view of the file, so it is used even if
+        // a thread panicked holding the lock
+        self.map.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::table::*;
+    use crate::table_trait::*;
+    use super::*;
+
+    const TABLE_PATH: &str = "test-mapped-blocks.tbl";
+    const TABLE_CHECKSUMS_PATH: &str = "test-mapped-blocks-checksums.tbl";
+
+    #[derive(Debug, Copy, Clone)]
+    struct Point {
+        id: usize,
+        x: u64,
+    }
+
+    impl TableTrait for Point {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    #[test]
+    fn test_mapped_blocks() {
+        _ensure_removed_tables();
+
+        let table = Table::open::<Point>(TABLE_PATH).unwrap();
+        let mut points: Vec<Point> = (0..100)
+            .map(|x| Point { id: 0, x }).collect();
+        Point::insert_many(&mut points, &table).unwrap();
+
+        let blocks = table.mapped().unwrap();
+        assert_eq!(blocks.len(), 100);
+        assert_eq!(blocks.get(42).unwrap(), &table.get(42).unwrap()[..]);
+        assert_eq!(Point::from_bytes(blocks.get(42).unwrap()).x, 42);
+        let sum: u64 = blocks.iter().map(|block| Point::from_bytes(block).x)
+            .sum();
+        assert_eq!(sum, 4950);
+        assert_eq!(blocks.get(100).unwrap_err().kind(),
+                   io::ErrorKind::NotFound);
+
+        // Updates are seen, appends need a new view
+        Point { id: 1, x: 1000 }.update(&table).unwrap();
+        Point { id: 0, x: 2000 }.insert(&table).unwrap();
+        assert_eq!(Point::from_bytes(blocks.get(0).unwrap()).x, 1000);
+        assert_eq!(blocks.len(), 100);
+        let grown = table.mapped().unwrap();
+        assert_eq!(grown.len(), 101);
+        assert_eq!(Point::from_bytes(grown.get(100).unwrap()).x, 2000);
+
+        // The file is not cut under the views
+        assert!(table._truncate(50).is_err());
+        assert_eq!(table.size().unwrap(), 101);
+        drop(blocks);
+        drop(grown);
+        table._truncate(50).unwrap();
+        assert_eq!(table.mapped().unwrap().len(), 50);
+
+        // The stamped blocks cannot be borrowed as they are
+        let table = Table::with_checksums::<Point>(TABLE_CHECKSUMS_PATH)
+            .unwrap();
+        assert_eq!(table.mapped().unwrap_err().kind(),
+                   io::ErrorKind::Unsupported);
+
+        _ensure_removed_tables();
+    }
+
+    fn _ensure_removed_tables() {
+        for path in &[TABLE_PATH, TABLE_CHECKSUMS_PATH] {
+            for ext in &["", ".free"] {
+                let path = format!("{}{}", path, ext);
+                if fs::metadata(&path).is_ok() {
+                    fs::remove_file(&path).unwrap();
+                }
+            }
+        }
+    }
+}
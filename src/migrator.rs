@@ -0,0 +1,235 @@
+use std::{fs, io, time};
+use std::collections::HashSet;
+
+use crate::varchar::*;
+use crate::table::*;
+use crate::table_trait::*;
+
+
+/// The greatest length of the name of a migration in bytes.
+pub const MIGRATION_NAME_SIZE: usize = 64;
+
+/// The history table in the database directory.
+const HISTORY_FILE: &str = "migrations.tbl";
+
+
+/// A migration applied to the database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppliedMigration {
+    /// The name of the migration.
+    pub name: String,
+    /// The time of applying in seconds since the Unix epoch.
+    pub applied_at: u64,
+}
+
+
+#[derive(Debug, Copy, Clone)]
+struct HistoryRecord {
+    id: usize,
+    name: Varchar<MIGRATION_NAME_SIZE>,
+    applied_at: u64,
+}
+
+
+impl TableTrait for HistoryRecord {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn set_id(&mut self, id: usize) {
+        self.id = id;
+    }
+}
+
+
+type Migration = Box<dyn Fn(&str) -> Result<(), io::Error>>;
+
+
+/// Migrator applies named migrations (schema rewrites, index builds, data
+/// fixes) to a database directory exactly once. The applied names are kept
+/// in the history table (*migrations.tbl*) of the directory, so on each
+/// start only the new migrations are applied in the order they were added.
+pub struct Migrator {
+    migrations: Vec<(String, Migration)>,
+}
+
+
+impl Migrator {
+    /// Creates a migrator without migrations.
+    pub fn new() -> Self {
+        Self { migrations: Vec::new() }
+    }
+
+    /// Adds the migration **f** with the unique **name** (at most
+    /// **MIGRATION_NAME_SIZE** bytes). **f** takes the path of the database
+    /// directory.
+    pub fn migration(
+                mut self,
+                name: &str,
+                f: impl Fn(&str) -> Result<(), io::Error> + 'static
+            ) -> Self {
+        self.migrations.push((name.to_string(), Box::new(f)));
+        self
+    }
+
+    /// Applies the migrations missing in the history of the directory
+    /// **dir** (it is created if missing) in order. The history table is
+    /// locked by **Table::open_exclusive** meanwhile, so another process
+    /// cannot apply them too. A failed migration stops the run with its
+    /// error, the later ones are not applied. A migration is recorded once it
+    /// finished, so it is applied again if the process crashes before that.
+    /// Returns the names of the applied migrations.
+    pub fn run(&self, dir: &str) -> Result<Vec<String>, io::Error> {
+        self._check_names()?;
+        fs::create_dir_all(dir)?;
+
+        let history = Table::open_exclusive::<HistoryRecord>(
+            &Self::_history_path(dir)
+        )?;
+        let mut done = HashSet::new();
+        for record in HistoryRecord::all(&history) {
+            let record = record?;
+            if record.id() != 0 {
+                done.insert(record.name.to_string());
+            }
+        }
+
+        let mut applied = Vec::new();
+        for (name, migration) in self.migrations.iter() {
+            if done.contains(name) {
+                continue;
+            }
+
+            migration(dir)?;
+
+            let applied_at = time::SystemTime::now()
+                .duration_since(time::UNIX_EPOCH).unwrap_or_default()
+                .as_secs();
+            let mut record = HistoryRecord {
+                id: 0, name: Varchar::new(name), applied_at
+            };
+            record.insert(&history)?;
+            applied.push(name.clone());
+        }
+
+        Ok(applied)
+    }
+
+    /// The migrations applied to the directory **dir** in the order they
+    /// were applied.
+    pub fn history(dir: &str) -> Result<Vec<AppliedMigration>, io::Error> {
+        let path = Self::_history_path(dir);
+        if fs::metadata(&path).is_err() {
+            return Ok(Vec::new());
+        }
+
+        let history = Table::open_shared::<HistoryRecord>(&path)?;
+        let mut applied = Vec::new();
+        for record in HistoryRecord::all(&history) {
+            let record = record?;
+            if record.id() != 0 {
+                applied.push(AppliedMigration {
+                    name: record.name.to_string(),
+                    applied_at: record.applied_at,
+                });
+            }
+        }
+        Ok(applied)
+    }
+
+    fn _check_names(&self) -> Result<(), io::Error> {
+        let mut names = HashSet::new();
+        for (name, _) in self.migrations.iter() {
+            if name.is_empty() || (name.len() > MIGRATION_NAME_SIZE) ||
+                    !names.insert(name) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("migration name {}", name)
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn _history_path(dir: &str) -> String {
+        format!("{}/{}", dir, HISTORY_FILE)
+    }
+}
+
+
+impl Default for Migrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    const DATABASE_DIR: &str = "test-migrator";
+
+    fn migrator(calls: &Rc<Cell<usize>>, fail: bool) -> Migrator {
+        let first = Rc::clone(calls);
+        let second = Rc::clone(calls);
+        Migrator::new()
+            .migration("0001-create-person", move |dir| {
+                first.set(first.get() + 1);
+                fs::write(format!("{}/person.tbl", dir), b"")
+            })
+            .migration("0002-fix-ages", move |_| {
+                second.set(second.get() + 10);
+                if fail {
+                    return Err(io::Error::other("fix"));
+                }
+                Ok(())
+            })
+    }
+
+    #[test]
+    fn test_migrator() {
+        _ensure_removed_dir();
+
+        // The second migration fails and is applied on the next run
+        let calls = Rc::new(Cell::new(0));
+        let err = migrator(&calls, true).run(DATABASE_DIR).unwrap_err();
+        assert_eq!(err.to_string(), "fix");
+        assert_eq!(calls.get(), 11);
+        let history = Migrator::history(DATABASE_DIR).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].name, "0001-create-person");
+        assert!(history[0].applied_at > 0);
+
+        let applied = migrator(&calls, false).run(DATABASE_DIR).unwrap();
+        assert_eq!(applied, vec!["0002-fix-ages".to_string()]);
+        assert_eq!(calls.get(), 21);
+
+        // Nothing new
+        assert!(migrator(&calls, false).run(DATABASE_DIR).unwrap().is_empty());
+        assert_eq!(calls.get(), 21);
+        assert_eq!(Migrator::history(DATABASE_DIR).unwrap().len(), 2);
+
+        // Wrong names
+        let err = Migrator::new()
+            .migration("same", |_| Ok(()))
+            .migration("same", |_| Ok(()))
+            .run(DATABASE_DIR).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = Migrator::new()
+            .migration(&"x".repeat(MIGRATION_NAME_SIZE + 1), |_| Ok(()))
+            .run(DATABASE_DIR).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        _ensure_removed_dir();
+    }
+
+    fn _ensure_removed_dir() {
+        if fs::metadata(DATABASE_DIR).is_ok() {
+            fs::remove_dir_all(DATABASE_DIR).unwrap();
+        }
+    }
+}
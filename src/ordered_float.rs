@@ -0,0 +1,133 @@
+use std::{cmp, fmt, mem};
+
+use crate::index_key::*;
+
+
+macro_rules! ordered_float {
+    ($name:ident, $float:ty) => {
+        /// A float with total ordering, so it can be used as a value of
+        /// **TableIndex**. NaN is greater than any number and equal to any
+        /// other NaN, *-0.0* is equal to *0.0*.
+        #[derive(Copy, Clone, Default)]
+        pub struct $name($float);
+
+        impl $name {
+            /// Creates the value from the float.
+            pub fn new(value: $float) -> Self {
+                Self(value)
+            }
+
+            /// Gets the original float.
+            pub fn value(&self) -> $float {
+                self.0
+            }
+        }
+
+        impl From<$float> for $name {
+            fn from(value: $float) -> Self {
+                Self(value)
+            }
+        }
+
+        impl PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                self.cmp(other) == cmp::Ordering::Equal
+            }
+        }
+
+        impl Eq for $name {}
+
+        impl PartialOrd for $name {
+            fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for $name {
+            fn cmp(&self, other: &Self) -> cmp::Ordering {
+                match (self.0.is_nan(), other.0.is_nan()) {
+                    (true, true) => cmp::Ordering::Equal,
+                    (true, false) => cmp::Ordering::Greater,
+                    (false, true) => cmp::Ordering::Less,
+                    (false, false) => self.0.partial_cmp(&other.0)
+                        .unwrap_or(cmp::Ordering::Equal),
+                }
+            }
+        }
+
+        impl IndexKey for $name {
+            fn key_tag() -> u64 {
+                key_tag_of(&format!(
+                    "{}/{}", stringify!($name), mem::size_of::<$float>()
+                ))
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}({:?})", stringify!($name), self.0)
+            }
+        }
+    };
+}
+
+
+ordered_float!(OrderedF64, f64);
+ordered_float!(OrderedF32, f32);
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::table::*;
+    use crate::table_index::*;
+    use super::*;
+
+    const TABLE_SCORE_INDEX_PATH: &str = "test-ordered-float-score-index.tbl";
+
+    #[test]
+    fn test_ordered_float() {
+        _ensure_removed_table_file();
+
+        // Order
+        assert!(OrderedF64::new(f64::NAN) > OrderedF64::new(f64::INFINITY));
+        assert_eq!(OrderedF64::new(f64::NAN), OrderedF64::new(-f64::NAN));
+        assert_eq!(OrderedF32::new(-0.0), OrderedF32::new(0.0));
+        assert!(OrderedF32::new(-1.5) < OrderedF32::new(0.5));
+
+        // Index
+        let score_index = Table::open::<TableIndex<OrderedF64>>(
+            TABLE_SCORE_INDEX_PATH
+        ).unwrap();
+        let scores = [0.5, f64::NAN, -2.0, 3.25, f64::NAN, 1.0];
+        for (id, score) in scores.iter().enumerate() {
+            TableIndex::add(&score_index, &OrderedF64::new(*score), id + 1)
+                .unwrap();
+        }
+
+        let ids: Vec<usize> = TableIndex::<OrderedF64>::iter(&score_index)
+            .collect::<Result<_, _>>().unwrap();
+        assert_eq!(&ids[..4], &[3, 1, 6, 4]);
+
+        let nan = OrderedF64::new(f64::NAN);
+        let mut nan_ids: Vec<usize> = TableIndex::search_many(
+            &score_index, &nan
+        ).collect::<Result<_, _>>().unwrap();
+        nan_ids.sort();
+        assert_eq!(nan_ids, vec![2, 5]);
+
+        let ids: Vec<usize> = TableIndex::iter_between(
+            &score_index, &OrderedF64::new(0.0), &OrderedF64::new(2.0)
+        ).collect::<Result<_, _>>().unwrap();
+        assert_eq!(ids, vec![1, 6]);
+
+        _ensure_removed_table_file();
+    }
+
+    fn _ensure_removed_table_file() {
+        if fs::metadata(TABLE_SCORE_INDEX_PATH).is_ok() {
+            fs::remove_file(TABLE_SCORE_INDEX_PATH).unwrap();
+        }
+    }
+}
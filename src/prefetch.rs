@@ -0,0 +1,110 @@
+use std::io;
+use std::collections::VecDeque;
+
+use crate::table::*;
+
+
+/// Prefetch wraps an iterator of record ids (e.g. an index scan) and asks
+/// the OS to read the blocks of the next **depth** records ahead of
+/// the consumer, so their reads overlap with the processing of the current
+/// record. The errors of the scan are passed through in their order.
+pub struct Prefetch<'a, I> {
+    table: &'a Table,
+    ids: I,
+    ahead: VecDeque<Result<usize, io::Error>>,
+    depth: usize,
+}
+
+
+impl<'a, I: Iterator<Item = Result<usize, io::Error>>> Prefetch<'a, I> {
+    /// Creates the prefetching iterator over **ids** of the records in
+    /// the **table**.
+    pub fn new(table: &'a Table, ids: I, depth: usize) -> Self {
+        Self { table, ids, ahead: VecDeque::new(), depth: depth.max(1) }
+    }
+}
+
+
+impl<'a, I> Iterator for Prefetch<'a, I>
+        where I: Iterator<Item = Result<usize, io::Error>> {
+    type Item = Result<usize, io::Error>;
+
+    fn next(&mut self) -> Option<Result<usize, io::Error>> {
+        while self.ahead.len() < self.depth {
+            match self.ids.next() {
+                Some(id) => {
+                    if let Ok(id @ 1..) = id {
+                        // The hint is optional, a failure only loses speed
+                        self.table.advise_between(
+                            AccessPattern::WillNeed, id - 1, id
+                        ).ok();
+                    }
+                    self.ahead.push_back(id);
+                },
+                None => break,
+            }
+        }
+        self.ahead.pop_front()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::table_trait::*;
+    use crate::table_index::*;
+    use super::*;
+
+    const TABLE_PATH: &str = "test-prefetch-person.tbl";
+    const TABLE_AGE_INDEX_PATH: &str = "test-prefetch-person-age-index.tbl";
+
+    #[derive(Debug, Copy, Clone)]
+    struct Person {
+        id: usize,
+        age: u32,
+    }
+
+    impl TableTrait for Person {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    #[test]
+    fn test_prefetch() {
+        _ensure_removed_tables();
+
+        let table = Table::open::<Person>(TABLE_PATH).unwrap();
+        let age_index = Table::open::<TableIndex<u32>>(
+            TABLE_AGE_INDEX_PATH
+        ).unwrap();
+
+        for age in &[40, 25, 32, 19, 50] {
+            let mut person = Person { id: 0, age: *age };
+            let id = person.insert(&table).unwrap();
+            TableIndex::add(&age_index, age, id).unwrap();
+        }
+
+        let ages: Vec<u32> = Prefetch::new(
+            &table, TableIndex::<u32>::iter(&age_index), 2
+        ).map(|id| Person::get(&table, id.unwrap()).unwrap().age).collect();
+        assert_eq!(ages, vec![19, 25, 32, 40, 50]);
+
+        _ensure_removed_tables();
+    }
+
+    fn _ensure_removed_tables() {
+        if fs::metadata(TABLE_PATH).is_ok() {
+            fs::remove_file(TABLE_PATH).unwrap();
+        }
+        if fs::metadata(TABLE_AGE_INDEX_PATH).is_ok() {
+            fs::remove_file(TABLE_AGE_INDEX_PATH).unwrap();
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use std::io;
+use std::time::{Duration, Instant};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use crate::table::*;
+
+
+type ProgressCallback = Box<dyn Fn(&Progress) + Send + Sync>;
+
+
+/// The state of a long operation passed to the progress callback.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Progress {
+    /// The name of the current stage of the operation (e.g. *scan*).
+    pub stage: &'static str,
+    /// The number of records processed in the stage.
+    pub processed: usize,
+    /// The number of records to process in the stage if known.
+    pub total: Option<usize>,
+    /// The number of bytes read or written in the stage.
+    pub bytes: u64,
+    /// The time since the start of the stage.
+    pub elapsed: Duration,
+}
+
+
+impl Progress {
+    /// The processed part of the stage from 0 to 1 if the total is known.
+    pub fn fraction(&self) -> Option<f64> {
+        match self.total {
+            Some(0) => Some(1.0),
+            Some(total) => Some(self.processed as f64 / total as f64),
+            None => None,
+        }
+    }
+
+    /// The estimated time left to finish the stage at the current speed.
+    pub fn eta(&self) -> Option<Duration> {
+        let total = self.total?;
+        if self.processed == 0 {
+            return None;
+        }
+        let left = total.saturating_sub(self.processed) as f64;
+        Some(self.elapsed.mul_f64(left / self.processed as f64))
+    }
+}
+
+
+struct ProgressState {
+    progress: Progress,
+    started: Instant,
+    reported: usize,
+}
+
+
+/// ProgressHandle receives the progress of an operation (a scan, a sort,
+/// an import or a backup) and passes it to the callback every
+/// **interval** records and at the end of each stage.
+pub struct ProgressHandle {
+    callback: ProgressCallback,
+    interval: usize,
+    state: Mutex<ProgressState>,
+}
+
+
+impl ProgressHandle {
+    /// Creates the handle calling **callback** on every record.
+    pub fn new(callback: impl Fn(&Progress) + Send + Sync + 'static) -> Self {
+        Self {
+            callback: Box::new(callback),
+            interval: 1,
+            state: Mutex::new(ProgressState {
+                progress: Progress {
+                    stage: "", processed: 0, total: None, bytes: 0,
+                    elapsed: Duration::default(),
+                },
+                started: Instant::now(),
+                reported: 0,
+            }),
+        }
+    }
+
+    /// Calls the callback once per **interval** records only.
+    pub fn with_interval(mut self, interval: usize) -> Self {
+        self.interval = interval.max(1);
+        self
+    }
+
+    /// The last state of the operation.
+    pub fn progress(&self) -> Progress {
+        self._state().progress
+    }
+
+    /// Starts a new **stage** with **total** records to process.
+    pub fn start(&self, stage: &'static str, total: Option<usize>) {
+        let mut state = self._state();
+        state.progress = Progress {
+            stage, processed: 0, total, bytes: 0,
+            elapsed: Duration::default(),
+        };
+        state.started = Instant::now();
+        state.reported = 0;
+        (self.callback)(&state.progress);
+    }
+
+    /// Counts **records** processed with **bytes** read or written.
+    pub fn advance(&self, records: usize, bytes: u64) {
+        let mut state = self._state();
+        state.progress.processed += records;
+        state.progress.bytes += bytes;
+        state.progress.elapsed = state.started.elapsed();
+        if state.progress.processed - state.reported >= self.interval {
+            state.reported = state.progress.processed;
+            (self.callback)(&state.progress);
+        }
+    }
+
+    /// Finishes the stage reporting the final state.
+    pub fn finish(&self) {
+        let mut state = self._state();
+        state.progress.elapsed = state.started.elapsed();
+        state.reported = state.progress.processed;
+        (self.callback)(&state.progress);
+    }
+
+    /// Wraps the iterator to count its items in the **stage**, each item is
+    /// **item_size** bytes. The stage is finished when the iterator ends.
+    pub fn track<'a, I: Iterator>(
+                &'a self,
+                stage: &'static str,
+                items: I,
+                total: Option<usize>,
+                item_size: usize
+            ) -> Tracked<'a, I> {
+        self.start(stage, total);
+        Tracked { progress: self, items, item_size, finished: false }
+    }
+
+    /// Iterates all blocks of the **table** reporting the *scan* stage.
+    pub fn scan<'a>(
+                &'a self,
+                table: &'a Table
+            ) -> Tracked<
+                'a, Box<dyn Iterator<Item = Result<Vec<u8>, io::Error>> + 'a>
+            > {
+        self.track("scan", table.iter(), table.size().ok(), table.block_size())
+    }
+
+    fn _state(&self) -> MutexGuard<'_, ProgressState> {
+        // The state is only reported, so it is used even if a thread
+        // panicked holding the lock
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+
+/// An iterator counting its items, see **ProgressHandle::track**.
+pub struct Tracked<'a, I> {
+    progress: &'a ProgressHandle,
+    items: I,
+    item_size: usize,
+    finished: bool,
+}
+
+
+impl<'a, I: Iterator> Iterator for Tracked<'a, I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        match self.items.next() {
+            Some(item) => {
+                self.progress.advance(1, self.item_size as u64);
+                Some(item)
+            },
+            None => {
+                if !self.finished {
+                    self.finished = true;
+                    self.progress.finish();
+                }
+                None
+            },
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::sync::Arc;
+
+    use super::*;
+
+    const TABLE_PATH: &str = "test-progress.tbl";
+
+    #[test]
+    fn test_progress() {
+        _ensure_removed_table_file();
+
+        let table = Table::open_with_block_size(TABLE_PATH, 16).unwrap();
+        for _ in 0..10 {
+            table.append(&[1; 16]).unwrap();
+        }
+
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let reports_clone = reports.clone();
+        let progress = ProgressHandle::new(move |progress| {
+            reports_clone.lock().unwrap().push(*progress);
+        }).with_interval(4);
+
+        assert_eq!(progress.scan(&table).count(), 10);
+
+        // The start, every 4 records and the end
+        let reports = reports.lock().unwrap();
+        let processed: Vec<usize> = reports.iter()
+            .map(|progress| progress.processed)
+            .collect();
+        assert_eq!(processed, vec![0, 4, 8, 10]);
+        assert_eq!(reports[3].stage, "scan");
+        assert_eq!(reports[3].bytes, 160);
+        assert_eq!(reports[3].fraction(), Some(1.0));
+        assert_eq!(reports[3].eta(), Some(Duration::default()));
+        assert_eq!(reports[0].eta(), None);
+
+        _ensure_removed_table_file();
+    }
+
+    fn _ensure_removed_table_file() {
+        if fs::metadata(TABLE_PATH).is_ok() {
+            fs::remove_file(TABLE_PATH).unwrap();
+        }
+    }
+}
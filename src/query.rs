@@ -0,0 +1,234 @@
+use std::{cmp, io, iter};
+use std::ops::Bound;
+
+use crate::table::*;
+use crate::table_trait::*;
+use crate::table_index::*;
+use crate::index_key::*;
+
+
+type Filter<'a, T> = Box<dyn Fn(&T) -> bool + 'a>;
+type Order<'a, T> = Box<dyn Fn(&T, &T) -> cmp::Ordering + 'a>;
+
+
+/// Query selects the records **T** of a table by filters, an order and
+/// a limit composed lazily: nothing is read until the records are iterated.
+/// The records are scanned from the whole table, or only from a range of
+/// an index set by **index_range** (in the order of the index).
+pub struct Query<'a, T> {
+    table: &'a Table,
+    ids: Option<Box<dyn Iterator<Item = Result<usize, io::Error>> + 'a>>,
+    filters: Vec<Filter<'a, T>>,
+    order: Option<Order<'a, T>>,
+    offset: usize,
+    limit: Option<usize>,
+}
+
+
+impl<'a, T: TableTrait + 'a> Query<'a, T> {
+    /// Creates a query of all records of the **table**.
+    pub fn new(table: &'a Table) -> Self {
+        Self {
+            table,
+            ids: None,
+            filters: Vec::new(),
+            order: None,
+            offset: 0,
+            limit: None,
+        }
+    }
+
+    /// Scans only the records with the values between **from** and **to**
+    /// in the index **index** (see **TableIndex::iter_range**) instead of
+    /// the whole table.
+    pub fn index_range<K: Copy + PartialOrd + IndexKey + 'a>(
+                mut self,
+                index: &'a Table,
+                from: Bound<&'a K>,
+                to: Bound<&'a K>
+            ) -> Self {
+        self.ids = Some(TableIndex::<K>::iter_range(index, from, to));
+        self
+    }
+
+    /// Keeps the records for which **f** returns true. Several filters
+    /// must all pass.
+    pub fn filter(mut self, f: impl Fn(&T) -> bool + 'a) -> Self {
+        self.filters.push(Box::new(f));
+        self
+    }
+
+    /// Orders the records by the value extracted by **key** ascending.
+    /// The order needs all selected records in memory.
+    pub fn order_by<K: Ord>(self, key: impl Fn(&T) -> K + 'a) -> Self {
+        self._order(move |a, b| key(a).cmp(&key(b)))
+    }
+
+    /// Orders the records by the value extracted by **key** descending.
+    pub fn order_by_desc<K: Ord>(self, key: impl Fn(&T) -> K + 'a) -> Self {
+        self._order(move |a, b| key(b).cmp(&key(a)))
+    }
+
+    /// Skips the first **offset** records.
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Yields at most **limit** records.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Iterates the selected records, a record that fails to read is
+    /// yielded as the error (the only item if the records are ordered).
+    pub fn iter(self) -> Box<dyn Iterator<Item = Result<T, io::Error>> + 'a> {
+        let table = self.table;
+        let filters = self.filters;
+
+        let records: Box<dyn Iterator<Item = Result<T, io::Error>> + 'a> =
+            match self.ids {
+                Some(ids) => Box::new(ids.filter_map(
+                    move |id| match id.and_then(|id| T::get(table, id)) {
+                        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                            None
+                        },
+                        record => Some(record),
+                    }
+                )),
+                None => T::all(table),
+            };
+        let records = records.filter(move |record| match record {
+            Ok(record) => {
+                (record.id() != 0) && filters.iter().all(|f| f(record))
+            },
+            Err(_) => true,
+        });
+
+        let records: Box<dyn Iterator<Item = Result<T, io::Error>> + 'a> =
+            match self.order {
+                Some(order) => match records.collect::<Result<Vec<T>, _>>() {
+                    Ok(mut sorted) => {
+                        sorted.sort_by(|a, b| order(a, b));
+                        Box::new(sorted.into_iter().map(Ok))
+                    },
+                    Err(err) => Box::new(iter::once(Err(err))),
+                },
+                None => Box::new(records),
+            };
+
+        let records = records.skip(self.offset);
+        match self.limit {
+            Some(limit) => Box::new(records.take(limit)),
+            None => Box::new(records),
+        }
+    }
+
+    /// Collects the selected records.
+    pub fn to_vec(self) -> Result<Vec<T>, io::Error> {
+        self.iter().collect()
+    }
+
+    fn _order(
+                mut self,
+                order: impl Fn(&T, &T) -> cmp::Ordering + 'a
+            ) -> Self {
+        self.order = Some(Box::new(order));
+        self
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::varchar::*;
+    use super::*;
+
+    const TABLE_PATH: &str = "test-query-person.tbl";
+    const TABLE_AGE_INDEX_PATH: &str = "test-query-person-age-index.tbl";
+
+    #[derive(Debug, Copy, Clone)]
+    struct Person {
+        id: usize,
+        name: Varchar<20>,
+        age: u32,
+    }
+
+    impl TableTrait for Person {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    #[test]
+    fn test_query() {
+        _ensure_removed_tables();
+
+        let table = Table::open::<Person>(TABLE_PATH).unwrap();
+        let age_index =
+            Table::open::<TableIndex<u32>>(TABLE_AGE_INDEX_PATH).unwrap();
+        for (name, age) in &[
+                    ("alex", 32), ("bob", 25), ("carl", 40), ("dan", 19),
+                    ("eve", 35), ("fred", 40)
+                ] {
+            let mut person = Person {
+                id: 0, name: Varchar::<20>::new(name), age: *age
+            };
+            let id = person.insert(&table).unwrap();
+            TableIndex::add(&age_index, age, id).unwrap();
+        }
+        Person::erase(&table, 2).unwrap();
+
+        let names = |persons: Vec<Person>| -> Vec<String> {
+            persons.iter().map(|p| p.name.to_string()).collect()
+        };
+
+        // Full scan
+        let persons = Query::<Person>::new(&table)
+            .filter(|p| p.age > 30)
+            .order_by(|p| p.age)
+            .limit(3)
+            .to_vec().unwrap();
+        assert_eq!(names(persons), vec!["alex", "eve", "carl"]);
+
+        let persons = Query::<Person>::new(&table)
+            .filter(|p| p.age > 20)
+            .filter(|p| p.name.len() == 4)
+            .order_by_desc(|p| p.name)
+            .to_vec().unwrap();
+        assert_eq!(names(persons), vec!["fred", "carl", "alex"]);
+        assert_eq!(Query::<Person>::new(&table).iter().count(), 5);
+
+        // Index scan in the order of the index, the erased bob is skipped
+        let persons = Query::<Person>::new(&table)
+            .index_range(&age_index,
+                         Bound::Unbounded, Bound::Excluded(&40u32))
+            .offset(1)
+            .to_vec().unwrap();
+        assert_eq!(names(persons), vec!["alex", "eve"]);
+
+        let persons = Query::<Person>::new(&table)
+            .index_range(&age_index,
+                         Bound::Included(&40u32), Bound::Unbounded)
+            .filter(|p| p.name.as_str() != "carl")
+            .to_vec().unwrap();
+        assert_eq!(names(persons), vec!["fred"]);
+
+        _ensure_removed_tables();
+    }
+
+    fn _ensure_removed_tables() {
+        for path in &[TABLE_PATH, TABLE_AGE_INDEX_PATH] {
+            if fs::metadata(path).is_ok() {
+                fs::remove_file(path).unwrap();
+            }
+        }
+    }
+}
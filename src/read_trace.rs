@@ -0,0 +1,85 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+
+
+thread_local! {
+    static TRACE: RefCell<Option<Vec<BlockRead>>> =
+        const { RefCell::new(None) };
+}
+
+
+/// A block read from a table while tracing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockRead {
+    /// The path of the table.
+    pub table: String,
+    /// The index of the block.
+    pub idx: usize,
+    /// True if the block was taken from the cache of the table, else it was
+    /// read from the file.
+    pub cache_hit: bool,
+    /// The searched value compared with the value of the index node kept in
+    /// the block, None for the other blocks (e.g. the index header).
+    pub comparison: Option<Ordering>,
+    /// The data bytes of the block.
+    pub block: Vec<u8>,
+}
+
+
+/// The trace of a single lookup returned by **TableIndex::explain_search**.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LookupTrace {
+    /// The ids of the found records.
+    pub found: Vec<usize>,
+    /// The blocks read by the lookup in the order they were read.
+    pub reads: Vec<BlockRead>,
+}
+
+
+impl LookupTrace {
+    /// The number of the blocks read from the file (not from the cache).
+    pub fn misses(&self) -> usize {
+        self.reads.iter().filter(|read| !read.cache_hit).count()
+    }
+}
+
+
+/// Runs **f** recording every block read by **Table::get** and
+/// **Table::get_many** in the current thread (from any table), the reads of
+/// other threads are not recorded. Returns the result of **f** with
+/// the reads in the order they were done.
+pub fn trace_reads<R>(f: impl FnOnce() -> R) -> (R, Vec<BlockRead>) {
+    let outer = TRACE.with(|trace| trace.replace(Some(Vec::new())));
+    let result = f();
+    let reads = TRACE.with(|trace| trace.replace(outer))
+        .unwrap_or_default();
+
+    // A nested trace is seen by the outer one too
+    TRACE.with(|trace| {
+        if let Some(outer) = trace.borrow_mut().as_mut() {
+            outer.extend(reads.iter().cloned());
+        }
+    });
+
+    (result, reads)
+}
+
+
+pub(crate) fn _record(
+            table: &str,
+            idx: usize,
+            cache_hit: bool,
+            block: &[u8]
+        ) {
+    TRACE.with(|trace| {
+        if let Some(reads) = trace.borrow_mut().as_mut() {
+            reads.push(BlockRead {
+                table: table.to_string(),
+                idx,
+                cache_hit,
+                comparison: None,
+                block: block.to_vec(),
+            });
+        }
+    });
+}
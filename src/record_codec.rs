@@ -0,0 +1,281 @@
+use std::io;
+use std::marker::PhantomData;
+
+use crate::table::*;
+use crate::table_error::*;
+use crate::table_trait::*;
+
+
+/// Record is a value stored in a **RecordTable**. Unlike **TableTrait**,
+/// it says nothing about the layout in bytes, it is defined by the codec
+/// of the table. Every **TableTrait** type is a record. It is used as
+/// *mytable::record_codec::Record*, so its methods do not clash with
+/// **TableTrait** ones in the crate root.
+pub trait Record: Sized {
+    /// Gets a unique id of the record.
+    fn id(&self) -> usize;
+
+    /// Sets id to the record.
+    fn set_id(&mut self, id: usize);
+}
+
+
+impl<T: TableTrait> Record for T {
+    fn id(&self) -> usize {
+        TableTrait::id(self)
+    }
+
+    fn set_id(&mut self, id: usize) {
+        TableTrait::set_id(self, id)
+    }
+}
+
+
+/// Codec converts the records of type **T** to blocks of a fixed size and
+/// back. A block that does not hold a valid record is an error of kind
+/// *InvalidData* instead of a broken value.
+pub trait Codec<T> {
+    /// The size of a block in bytes.
+    fn block_size() -> usize;
+
+    /// Represents the record as bytes.
+    fn encode(record: &T) -> Vec<u8>;
+
+    /// Constructs the record from bytes.
+    fn decode(block: &[u8]) -> Result<T, io::Error>;
+}
+
+
+/// RawCodec copies the memory of the record as **TableTrait** does. It is
+/// as fast as possible, but the type must not contain references and all
+/// bytes of a block are trusted to be a valid record.
+#[derive(Debug, Copy, Clone)]
+pub struct RawCodec;
+
+
+impl<T: TableTrait> Codec<T> for RawCodec {
+    fn block_size() -> usize {
+        T::block_size()
+    }
+
+    fn encode(record: &T) -> Vec<u8> {
+        record.as_bytes().to_vec()
+    }
+
+    fn decode(block: &[u8]) -> Result<T, io::Error> {
+        if block.len() < T::block_size() {
+            return Err(
+                io::Error::new(io::ErrorKind::InvalidData, "block size")
+            );
+        }
+        Ok(T::from_bytes(block))
+    }
+}
+
+
+/// PodCodec stores plain old data checked by *bytemuck* at compile time:
+/// the type derives **NoUninit** (no padding, no references) and
+/// **CheckedBitPattern**, so the values of fields like *bool* or *enum*
+/// are checked on decoding.
+#[cfg(feature = "bytemuck")]
+#[derive(Debug, Copy, Clone)]
+pub struct PodCodec;
+
+
+#[cfg(feature = "bytemuck")]
+impl<T> Codec<T> for PodCodec
+        where T: bytemuck::NoUninit + bytemuck::CheckedBitPattern {
+    fn block_size() -> usize {
+        std::mem::size_of::<T>()
+    }
+
+    fn encode(record: &T) -> Vec<u8> {
+        bytemuck::bytes_of(record).to_vec()
+    }
+
+    fn decode(block: &[u8]) -> Result<T, io::Error> {
+        let size = std::mem::size_of::<T>();
+        if block.len() < size {
+            return Err(
+                io::Error::new(io::ErrorKind::InvalidData, "block size")
+            );
+        }
+        bytemuck::checked::try_pod_read_unaligned(&block[..size]).map_err(
+            |err| io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+        )
+    }
+}
+
+
+/// RecordTable is a table of records of type **T** written and read by
+/// the codec **C**.
+pub struct RecordTable<T, C> {
+    table: Table,
+    record: PhantomData<(T, C)>,
+}
+
+
+impl<T: Record, C: Codec<T>> RecordTable<T, C> {
+    /// Creates or opens a file to work.
+    pub fn open(path: &str) -> Result<Self, TableError> {
+        Ok(Self {
+            table: Table::open_with_block_size(path, C::block_size())?,
+            record: PhantomData,
+        })
+    }
+
+    /// The table of the records.
+    pub fn table(&self) -> &Table {
+        &self.table
+    }
+
+    /// Inserts the record to the table. Returns its id.
+    pub fn insert(&self, record: &mut T) -> Result<usize, io::Error> {
+        if record.id() != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "id"));
+        }
+        let idx = self.table.insert(&C::encode(record))?;
+        record.set_id(idx + 1);
+        self.table.update(&C::encode(record), idx)?;
+        Ok(record.id())
+    }
+
+    /// Updates the record in the table.
+    pub fn update(&self, record: &T) -> Result<(), io::Error> {
+        let idx = self._index(record.id())?;
+        self.table.update(&C::encode(record), idx)
+    }
+
+    /// Extracts the record from the table by id.
+    pub fn get(&self, id: usize) -> Result<T, io::Error> {
+        C::decode(&self.table.get(self._index(id)?)?)
+    }
+
+    /// Iterates all records, a block that cannot be decoded is an error.
+    pub fn all(&self) -> Box<dyn Iterator<Item = Result<T, io::Error>> + '_> {
+        Box::new(self.table.iter().map(|block| C::decode(&block?)))
+    }
+
+    fn _index(&self, id: usize) -> Result<usize, io::Error> {
+        if (id > 0) && (id <= self.table.size()?) {
+            Ok(id - 1)
+        } else {
+            Err(io::Error::new(io::ErrorKind::NotFound, id.to_string()))
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::varchar::*;
+    use super::*;
+
+    const TABLE_RAW_PATH: &str = "test-record-codec-raw.tbl";
+    #[cfg(feature = "bytemuck")]
+    const TABLE_POD_PATH: &str = "test-record-codec-pod.tbl";
+
+    #[derive(Debug, Copy, Clone)]
+    struct Person {
+        id: usize,
+        name: Varchar<20>,
+        age: u32,
+    }
+
+    impl TableTrait for Person {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    #[test]
+    fn test_raw_codec() {
+        _ensure_removed_table_file(TABLE_RAW_PATH);
+
+        let table = RecordTable::<Person, RawCodec>::open(TABLE_RAW_PATH)
+            .unwrap();
+        let mut alex = Person {
+            id: 0, name: Varchar::<20>::new("alex"), age: 32
+        };
+        assert_eq!(table.insert(&mut alex).unwrap(), 1);
+        alex.age = 33;
+        table.update(&alex).unwrap();
+        let alex = table.get(1).unwrap();
+        assert_eq!((alex.name.to_string(), alex.age), ("alex".to_string(), 33));
+        assert_eq!(Person::get(table.table(), 1).unwrap().age, 33);
+        assert!(table.get(2).is_err());
+
+        _ensure_removed_table_file(TABLE_RAW_PATH);
+    }
+
+    #[cfg(feature = "bytemuck")]
+    #[derive(Debug, Copy, Clone, PartialEq, Eq,
+             bytemuck::NoUninit, bytemuck::CheckedBitPattern)]
+    #[repr(u32)]
+    enum Status {
+        Active = 1,
+        Blocked = 2,
+    }
+
+    #[cfg(feature = "bytemuck")]
+    #[derive(Debug, Copy, Clone,
+             bytemuck::NoUninit, bytemuck::CheckedBitPattern)]
+    #[repr(C)]
+    struct Account {
+        id: u64,
+        balance: u64,
+        status: Status,
+        flags: u32,
+    }
+
+    #[cfg(feature = "bytemuck")]
+    impl Record for Account {
+        fn id(&self) -> usize {
+            self.id as usize
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id as u64;
+        }
+    }
+
+    #[cfg(feature = "bytemuck")]
+    #[test]
+    fn test_pod_codec() {
+        _ensure_removed_table_file(TABLE_POD_PATH);
+
+        let table = RecordTable::<Account, PodCodec>::open(TABLE_POD_PATH)
+            .unwrap();
+        assert_eq!(table.table().block_size(), 24);
+
+        let mut account = Account {
+            id: 0, balance: 100, status: Status::Active, flags: 0
+        };
+        table.insert(&mut account).unwrap();
+        account.status = Status::Blocked;
+        table.update(&account).unwrap();
+        assert_eq!(table.get(1).unwrap().status, Status::Blocked);
+
+        // An invalid value of the enum is rejected
+        let mut block = table.table().get(0).unwrap();
+        block[16] = 7;
+        table.table().update(&block, 0).unwrap();
+        let err = table.get(1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(table.all().next().unwrap().is_err());
+
+        _ensure_removed_table_file(TABLE_POD_PATH);
+    }
+
+    fn _ensure_removed_table_file(path: &str) {
+        if fs::metadata(path).is_ok() {
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::io;
+use std::ops::{Deref, DerefMut};
+
+use crate::table::*;
+use crate::table_trait::*;
+
+
+/// RecordGuard holds a record read from the table by **Table::edit**.
+/// The changes made through the guard are written back only once on
+/// **commit**, dropping the guard discards them.
+pub struct RecordGuard<'a, T: TableTrait> {
+    table: &'a Table,
+    record: T,
+}
+
+
+impl<'a, T: TableTrait> RecordGuard<'a, T> {
+    /// Creates the guard over the **record** of the **table**.
+    pub fn new(table: &'a Table, record: T) -> Self {
+        Self { table, record }
+    }
+
+    /// Writes the changed record back to the table.
+    pub fn commit(self) -> Result<(), io::Error> {
+        self.record.update(self.table)
+    }
+}
+
+
+impl<'a, T: TableTrait> Deref for RecordGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.record
+    }
+}
+
+
+impl<'a, T: TableTrait> DerefMut for RecordGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.record
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::varchar::*;
+    use super::*;
+
+    const TABLE_PATH: &str = "test-record-guard-person.tbl";
+
+    #[derive(Debug, Copy, Clone)]
+    struct Person {
+        id: usize,
+        name: Varchar<20>,
+        age: u32,
+    }
+
+    impl TableTrait for Person {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    #[test]
+    fn test_record_guard() {
+        _ensure_removed_table_file();
+
+        let table = Table::open::<Person>(TABLE_PATH).unwrap();
+
+        let mut alex = Person { id: 0, name: Varchar::<20>::new("alex"), age: 32 };
+        alex.insert(&table).unwrap();
+
+        // Commit
+        let mut guard = table.edit::<Person>(1).unwrap();
+        guard.age += 1;
+        assert_eq!(guard.name.to_string(), "alex");
+        guard.commit().unwrap();
+        assert_eq!(Person::get(&table, 1).unwrap().age, 33);
+
+        // Discard
+        {
+            let mut guard = table.edit::<Person>(1).unwrap();
+            guard.age = 0;
+        }
+        assert_eq!(Person::get(&table, 1).unwrap().age, 33);
+
+        assert!(table.edit::<Person>(2).is_err());
+
+        _ensure_removed_table_file();
+    }
+
+    fn _ensure_removed_table_file() {
+        if fs::metadata(TABLE_PATH).is_ok() {
+            fs::remove_file(TABLE_PATH).unwrap();
+        }
+    }
+}
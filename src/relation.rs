@@ -0,0 +1,143 @@
+use std::io;
+
+use crate::table_trait::*;
+use crate::repository::*;
+
+
+/// BelongsTo declares that the record refers to a parent record of type
+/// **P** by its id. The repository of the records must have an index named
+/// **PARENT_INDEX** over **parent_id** to find the children of a parent.
+pub trait BelongsTo<P: TableTrait + 'static>: TableTrait + 'static {
+    /// The name of the index over **parent_id** in the repository.
+    const PARENT_INDEX: &'static str;
+
+    /// Gets the id of the parent record.
+    fn parent_id(&self) -> usize;
+
+    /// Fetches the parent record from its repository.
+    fn parent(&self, parents: &Repository<P>) -> Result<P, io::Error> {
+        parents.find(self.parent_id())
+    }
+}
+
+
+/// HasMany is implemented for every record to fetch the children that
+/// refer to it with **BelongsTo**. The children are read only on the call.
+pub trait HasMany: TableTrait + 'static {
+    /// Fetches the children records from their repository.
+    fn children<C: BelongsTo<Self>>(
+                &self,
+                children: &Repository<C>
+            ) -> Result<Vec<C>, io::Error> {
+        children.find_by(C::PARENT_INDEX, &self.id())
+    }
+}
+
+
+impl<T: TableTrait + 'static> HasMany for T {}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::varchar::*;
+    use super::*;
+
+    const TABLE_PERSON_PATH: &str = "test-relation-person.tbl";
+    const TABLE_ORDER_PATH: &str = "test-relation-order.tbl";
+    const TABLE_ORDER_PERSON_INDEX_PATH: &str =
+        "test-relation-order-person-index.tbl";
+
+    #[derive(Debug, Copy, Clone)]
+    struct Person {
+        id: usize,
+        name: Varchar<20>,
+    }
+
+    impl TableTrait for Person {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    impl Person {
+        fn orders(&self, orders: &Repository<Order>) -> Vec<Order> {
+            self.children(orders).unwrap()
+        }
+    }
+
+    #[derive(Debug, Copy, Clone)]
+    struct Order {
+        id: usize,
+        person_id: usize,
+        amount: u32,
+    }
+
+    impl TableTrait for Order {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    impl BelongsTo<Person> for Order {
+        const PARENT_INDEX: &'static str = "person_id";
+
+        fn parent_id(&self) -> usize {
+            self.person_id
+        }
+    }
+
+    #[test]
+    fn test_relation() {
+        _ensure_removed_tables();
+
+        let persons = Repository::<Person>::new(TABLE_PERSON_PATH).unwrap();
+        let orders = Repository::<Order>::new(TABLE_ORDER_PATH).unwrap()
+            .with_index(
+                "person_id", TABLE_ORDER_PERSON_INDEX_PATH, |o| o.person_id
+            ).unwrap();
+
+        let mut alex = Person { id: 0, name: Varchar::<20>::new("alex") };
+        persons.save(&mut alex).unwrap();
+        let mut bob = Person { id: 0, name: Varchar::<20>::new("bob") };
+        persons.save(&mut bob).unwrap();
+
+        for amount in &[10, 20] {
+            let mut order = Order { id: 0, person_id: alex.id, amount: *amount };
+            orders.save(&mut order).unwrap();
+        }
+
+        // Has many
+        let alex_orders = alex.orders(&orders);
+        assert_eq!(alex_orders.len(), 2);
+        assert_eq!(alex_orders.iter().map(|o| o.amount).sum::<u32>(), 30);
+        assert!(bob.orders(&orders).is_empty());
+
+        // Belongs to
+        let person = alex_orders[0].parent(&persons).unwrap();
+        assert_eq!(person.name.to_string(), "alex");
+
+        _ensure_removed_tables();
+    }
+
+    fn _ensure_removed_tables() {
+        if fs::metadata(TABLE_PERSON_PATH).is_ok() {
+            fs::remove_file(TABLE_PERSON_PATH).unwrap();
+        }
+        if fs::metadata(TABLE_ORDER_PATH).is_ok() {
+            fs::remove_file(TABLE_ORDER_PATH).unwrap();
+        }
+        if fs::metadata(TABLE_ORDER_PERSON_INDEX_PATH).is_ok() {
+            fs::remove_file(TABLE_ORDER_PERSON_INDEX_PATH).unwrap();
+        }
+    }
+}
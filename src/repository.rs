@@ -0,0 +1,678 @@
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::{io, ptr};
+use std::sync::{Mutex, PoisonError};
+
+use crate::table::*;
+use crate::table_trait::*;
+use crate::table_index::*;
+use crate::index_key::*;
+use crate::bitmap_index::*;
+use crate::table_error::*;
+
+
+pub(crate) trait RepositoryIndex<T> {
+    fn name(&self) -> &str;
+    fn add(&self, record: &T) -> Result<(), io::Error>;
+    fn exclude(&self, record: &T) -> Result<(), io::Error>;
+    fn changed(&self, old: &T, new: &T) -> bool;
+    fn table(&self) -> Option<&Table>;
+    fn as_any(&self) -> &dyn Any;
+}
+
+
+pub(crate) struct KeyIndex<T, K> {
+    pub(crate) name: String,
+    pub(crate) table: Table,
+    pub(crate) key: fn(&T) -> K,
+}
+
+
+impl<T: TableTrait + 'static, K: Copy + PartialOrd + IndexKey + 'static>
+        RepositoryIndex<T> for KeyIndex<T, K> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn add(&self, record: &T) -> Result<(), io::Error> {
+        TableIndex::add(&self.table, &(self.key)(record), record.id())
+    }
+
+    fn exclude(&self, record: &T) -> Result<(), io::Error> {
+        TableIndex::exclude(&self.table, &(self.key)(record), record.id())
+    }
+
+    fn changed(&self, old: &T, new: &T) -> bool {
+        (self.key)(old) != (self.key)(new)
+    }
+
+    fn table(&self) -> Option<&Table> {
+        Some(&self.table)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+
+struct BitmapKeyIndex<T, K> {
+    name: String,
+    index: BitmapIndex<K>,
+    key: fn(&T) -> K,
+}
+
+
+impl<T: TableTrait + 'static, K: Copy + PartialEq + 'static>
+        RepositoryIndex<T> for BitmapKeyIndex<T, K> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn add(&self, record: &T) -> Result<(), io::Error> {
+        self.index.add(&(self.key)(record), record.id())
+    }
+
+    fn exclude(&self, record: &T) -> Result<(), io::Error> {
+        self.index.exclude(&(self.key)(record), record.id())
+    }
+
+    fn changed(&self, old: &T, new: &T) -> bool {
+        (self.key)(old) != (self.key)(new)
+    }
+
+    fn table(&self) -> Option<&Table> {
+        None
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+
+struct IdentityMap<T> {
+    capacity: usize,
+    records: HashMap<usize, T>,
+    order: VecDeque<usize>,
+}
+
+
+impl<T: Copy> IdentityMap<T> {
+    fn new(capacity: usize) -> Self {
+        Self { capacity, records: HashMap::new(), order: VecDeque::new() }
+    }
+
+    fn get(&mut self, id: usize) -> Option<T> {
+        let record = *self.records.get(&id)?;
+        self._touch(id);
+        Some(record)
+    }
+
+    fn put(&mut self, id: usize, record: T) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.records.insert(id, record).is_some() {
+            self._touch(id);
+            return;
+        }
+        self.order.push_back(id);
+        if self.order.len() > self.capacity {
+            if let Some(evicted) = self.order.pop_front() {
+                self.records.remove(&evicted);
+            }
+        }
+    }
+
+    fn remove(&mut self, id: usize) {
+        if self.records.remove(&id).is_some() {
+            self.order.retain(|other| *other != id);
+        }
+    }
+
+    fn _touch(&mut self, id: usize) {
+        self.order.retain(|other| *other != id);
+        self.order.push_back(id);
+    }
+}
+
+
+/// Repository bundles the table of records with its indexes, so the
+/// indexes are maintained on every save and delete.
+pub struct Repository<T> {
+    table: Table,
+    indexes: Vec<Box<dyn RepositoryIndex<T>>>,
+    identity_map: Option<Mutex<IdentityMap<T>>>,
+}
+
+
+impl<T: TableTrait + 'static> Repository<T> {
+    /// Creates a repository over the table located by **path**.
+    pub fn new(path: &str) -> Result<Self, TableError> {
+        Ok(Self {
+            table: Table::open::<T>(path)?,
+            indexes: Vec::new(),
+            identity_map: None,
+        })
+    }
+
+    /// Enables the identity map: up to **capacity** records found by id are
+    /// kept in memory (the least recently used ones are evicted first), so
+    /// repeated **find** calls do not read the disk. Saved records replace
+    /// the cached ones, deleted records are dropped from it.
+    pub fn with_identity_map(mut self, capacity: usize) -> Self {
+        self.identity_map = Some(Mutex::new(IdentityMap::new(capacity)));
+        self
+    }
+
+    /// Registers an index with the **name** stored in the file
+    /// **path** over the value extracted from the record by **key**.
+    pub fn with_index<K: Copy + PartialOrd + IndexKey + 'static>(
+                mut self,
+                name: &str,
+                path: &str,
+                key: fn(&T) -> K
+            ) -> Result<Self, TableError> {
+        self.indexes.push(Box::new(KeyIndex {
+            name: name.to_string(),
+            table: TableIndex::<K>::open(path)?,
+            key,
+        }));
+        Ok(self)
+    }
+
+    /// Registers a bitmap index with the **name** stored in the file
+    /// **path** over the value with a few distinct values (e.g. a flag)
+    /// extracted from the record by **key**.
+    pub fn with_bitmap_index<K: Copy + PartialEq + 'static>(
+                mut self,
+                name: &str,
+                path: &str,
+                key: fn(&T) -> K
+            ) -> Result<Self, TableError> {
+        self.indexes.push(Box::new(BitmapKeyIndex {
+            name: name.to_string(),
+            index: BitmapIndex::open(path)?,
+            key,
+        }));
+        Ok(self)
+    }
+
+    /// The table of the records.
+    pub fn table(&self) -> &Table {
+        &self.table
+    }
+
+    /// Inserts the record if it has no id yet, else updates it. The indexes
+    /// are updated for the changed values only. Returns the id. If an index
+    /// fails to add a new record, the record is deleted and excluded from
+    /// the indexes it was added to.
+    pub fn save(&self, record: &mut T) -> Result<usize, io::Error> {
+        if record.id() == 0 {
+            record.insert(&self.table)?;
+            if let Err(err) = self._add_indexes(record) {
+                let _ = record.delete(&self.table);
+                record.set_id(0);
+                return Err(err);
+            }
+        } else {
+            let old = self.find(record.id())?;
+            for index in self.indexes.iter() {
+                if index.changed(&old, record) {
+                    index.exclude(&old)?;
+                    index.add(record)?;
+                }
+            }
+            record.update(&self.table)?;
+        }
+        self._cache(|map| map.put(record.id(), *record));
+        Ok(record.id())
+    }
+
+    /// Finds the record by id.
+    pub fn find(&self, id: usize) -> Result<T, io::Error> {
+        if let Some(record) = self._cache(|map| map.get(id)).flatten() {
+            return Ok(record);
+        }
+        let record = T::get(&self.table, id)?;
+        if record.id() == 0 {
+            return Err(io::Error::new(io::ErrorKind::NotFound, id.to_string()));
+        }
+        self._cache(|map| map.put(id, record));
+        Ok(record)
+    }
+
+    /// Finds all records with the **key** in the index named **name**.
+    pub fn find_by<K: Copy + PartialOrd + IndexKey + 'static>(
+                &self,
+                name: &str,
+                key: &K
+            ) -> Result<Vec<T>, io::Error> {
+        let index = self._index::<KeyIndex<T, K>>(name)?;
+        TableIndex::search_many(&index.table, key)
+            .map(|id| self.find(id?))
+            .collect()
+    }
+
+    /// The ids of the records with the **key** in the bitmap index named
+    /// **name**. The bitmaps of several keys can be combined before
+    /// the records are read.
+    pub fn bitmap<K: Copy + PartialEq + 'static>(
+                &self,
+                name: &str,
+                key: &K
+            ) -> Result<Bitmap, io::Error> {
+        self._index::<BitmapKeyIndex<T, K>>(name)?.index.bitmap(key)
+    }
+
+    /// Finds the records with the ids in the **bitmap**.
+    pub fn find_bitmap(&self, bitmap: &Bitmap) -> Result<Vec<T>, io::Error> {
+        bitmap.iter().map(|id| self.find(id)).collect()
+    }
+
+    /// Deletes the record by id: it is excluded from the indexes (their
+    /// nodes are overwritten) and erased from the table, see
+    /// **TableTrait::erase**.
+    pub fn delete(&self, id: usize) -> Result<(), io::Error> {
+        let record = self.find(id)?;
+        for index in self.indexes.iter() {
+            index.exclude(&record)?;
+        }
+        self._cache(|map| map.remove(id));
+        T::erase(&self.table, id)
+    }
+
+    /// Drops the records after the id **n**: they are excluded from
+    /// the indexes and the table is cut, see **Table::truncate_to**.
+    /// Other threads cannot write to the table meanwhile.
+    pub fn truncate_to(&self, n: usize) -> Result<(), io::Error> {
+        let _exclusive = self.table.exclusive();
+        for id in n + 1..=self.table.size()? {
+            match self.find(id) {
+                Ok(record) => self._exclude_indexes(&record)?,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {},
+                Err(err) => return Err(err),
+            }
+            self._cache(|map| map.remove(id));
+        }
+        self.table.truncate_to(n)
+    }
+
+    /// Drops all records, see **truncate_to**.
+    pub fn clear(&self) -> Result<(), io::Error> {
+        self.truncate_to(0)
+    }
+
+    /// Lists the records of the page **page** (starting from 0) with
+    /// **page_size** blocks in it. Deleted records are skipped.
+    pub fn list(
+                &self,
+                page: usize,
+                page_size: usize
+            ) -> Result<Vec<T>, io::Error> {
+        let size = self.table.size()?;
+        let idx_from = (page * page_size).min(size);
+        let idx_to = (idx_from + page_size).min(size);
+
+        let mut records = Vec::new();
+        for block in self.table.iter_between(idx_from, idx_to)? {
+            let record = T::from_bytes(&block?);
+            if record.id() > 0 {
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+
+    fn _add_indexes(&self, record: &T) -> Result<(), io::Error> {
+        for (pos, index) in self.indexes.iter().enumerate() {
+            if let Err(err) = index.add(record) {
+                for index in self.indexes[..pos].iter() {
+                    let _ = index.exclude(record);
+                }
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    fn _exclude_indexes(&self, record: &T) -> Result<(), io::Error> {
+        for (pos, index) in self.indexes.iter().enumerate() {
+            if let Err(err) = index.exclude(record) {
+                for index in self.indexes[..pos].iter() {
+                    let _ = index.add(record);
+                }
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    fn _cache<R>(
+                &self,
+                f: impl FnOnce(&mut IdentityMap<T>) -> R
+            ) -> Option<R> {
+        // The cached records are only a copy of the table, so they are
+        // used even if a thread panicked holding the lock
+        self.identity_map.as_ref().map(
+            |map| f(&mut map.lock().unwrap_or_else(PoisonError::into_inner))
+        )
+    }
+
+    fn _index<I: 'static>(&self, name: &str) -> Result<&I, io::Error> {
+        let index = self.indexes.iter().find(|index| index.name() == name)
+            .ok_or_else(
+                || io::Error::new(io::ErrorKind::NotFound, name.to_string())
+            )?;
+        index.as_any().downcast_ref::<I>().ok_or_else(
+            || io::Error::new(io::ErrorKind::InvalidInput, name.to_string())
+        )
+    }
+}
+
+
+/// Moves the record with **id** from the repository **from** to **to**
+/// (e.g. to archive it): the record is inserted to **to** with a new id
+/// and added to its indexes, then it is excluded from the indexes of
+/// **from** and erased. Other threads cannot write to both tables
+/// meanwhile. If a step fails, the done steps are undone (as far as
+/// possible), so the record stays in **from** only. Returns the new id.
+pub fn move_record<T: TableTrait + 'static>(
+            from: &Repository<T>,
+            to: &Repository<T>,
+            id: usize
+        ) -> Result<usize, io::Error> {
+    if ptr::eq(from, to) {
+        return Err(
+            io::Error::new(io::ErrorKind::InvalidInput, "same repository")
+        );
+    }
+
+    let _from = from.table.exclusive();
+    let _to = to.table.exclusive();
+
+    let record = from.find(id)?;
+    let mut moved = record;
+    moved.set_id(0);
+    moved.insert(&to.table)?;
+
+    let undo_insert = |moved: &T| {
+        let _ = moved.delete(&to.table);
+    };
+
+    if let Err(err) = to._add_indexes(&moved) {
+        undo_insert(&moved);
+        return Err(err);
+    }
+    if let Err(err) = from._exclude_indexes(&record) {
+        let _ = to._exclude_indexes(&moved);
+        undo_insert(&moved);
+        return Err(err);
+    }
+    if let Err(err) = T::erase(&from.table, id) {
+        let _ = from._add_indexes(&record);
+        let _ = to._exclude_indexes(&moved);
+        undo_insert(&moved);
+        return Err(err);
+    }
+
+    from._cache(|map| map.remove(id));
+    to._cache(|map| map.put(moved.id(), moved));
+    Ok(moved.id())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::varchar::*;
+    use super::*;
+
+    const TABLE_PATH: &str = "test-repository-person.tbl";
+    const TABLE_AGE_INDEX_PATH: &str = "test-repository-person-age-index.tbl";
+    const TABLE_CACHED_PATH: &str = "test-repository-person-cached.tbl";
+    const TABLE_FLAGGED_PATH: &str = "test-repository-person-flagged.tbl";
+    const TABLE_ADULT_INDEX_PATH: &str =
+        "test-repository-person-adult-index.tbl";
+    const TABLE_THIRTIES_INDEX_PATH: &str =
+        "test-repository-person-thirties-index.tbl";
+    const TABLE_ACTIVE_PATH: &str = "test-repository-person-active.tbl";
+    const TABLE_ACTIVE_INDEX_PATH: &str =
+        "test-repository-person-active-index.tbl";
+    const TABLE_ARCHIVE_PATH: &str = "test-repository-person-archive.tbl";
+    const TABLE_ARCHIVE_INDEX_PATH: &str =
+        "test-repository-person-archive-index.tbl";
+    const TABLE_ROLLBACK_PATH: &str = "test-repository-person-rollback.tbl";
+    const TABLE_ROLLBACK_INDEX_PATH: &str =
+        "test-repository-person-rollback-index.tbl";
+    const TABLE_WIDE_INDEX_PATH: &str =
+        "test-repository-person-wide-index.tbl";
+
+    #[derive(Debug, Copy, Clone)]
+    struct Person {
+        id: usize,
+        name: Varchar<20>,
+        age: u32,
+    }
+
+    impl TableTrait for Person {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    impl Person {
+        fn new(name: &str, age: u32) -> Self {
+            Self { id: 0, name: Varchar::<20>::new(name), age }
+        }
+    }
+
+    #[test]
+    fn test_repository() {
+        _ensure_removed_tables();
+
+        let repo = Repository::<Person>::new(TABLE_PATH).unwrap()
+            .with_index("age", TABLE_AGE_INDEX_PATH, |p| p.age).unwrap();
+
+        // Save
+        let mut alex = Person::new("alex", 32);
+        repo.save(&mut alex).unwrap();
+        repo.save(&mut Person::new("bob", 25)).unwrap();
+        repo.save(&mut Person::new("carl", 32)).unwrap();
+        assert_eq!(repo.find(1).unwrap().name.to_string(), "alex");
+
+        // Find by index
+        let persons = repo.find_by("age", &32u32).unwrap();
+        assert_eq!(persons.len(), 2);
+        assert!(repo.find_by("age", &32u64).is_err());
+        assert!(repo.find_by("name", &32u32).is_err());
+
+        // Update with index
+        alex.age = 33;
+        repo.save(&mut alex).unwrap();
+        assert_eq!(repo.find_by("age", &32u32).unwrap().len(), 1);
+        assert_eq!(repo.find_by("age", &33u32).unwrap()[0].id, 1);
+
+        // Delete
+        repo.delete(2).unwrap();
+        assert!(repo.find(2).is_err());
+        assert!(repo.find_by("age", &25u32).unwrap().is_empty());
+
+        // List
+        let persons = repo.list(0, 2).unwrap();
+        assert_eq!(persons.len(), 1);
+        let persons = repo.list(1, 2).unwrap();
+        assert_eq!(persons[0].name.to_string(), "carl");
+        assert!(repo.list(2, 2).unwrap().is_empty());
+
+        // Truncate
+        repo.save(&mut Person::new("dan", 32)).unwrap();
+        repo.truncate_to(3).unwrap();
+        assert_eq!(repo.table().size().unwrap(), 3);
+        assert!(repo.find(4).is_err());
+        assert_eq!(repo.find_by("age", &32u32).unwrap()[0].id, 3);
+        repo.clear().unwrap();
+        assert!(repo.table().empty().unwrap());
+        assert!(repo.find_by("age", &32u32).unwrap().is_empty());
+        assert!(repo.find_by("age", &33u32).unwrap().is_empty());
+
+        _ensure_removed_tables();
+    }
+
+    #[test]
+    fn test_identity_map() {
+        _ensure_removed_table_file(TABLE_CACHED_PATH);
+
+        let repo = Repository::<Person>::new(TABLE_CACHED_PATH).unwrap()
+            .with_identity_map(1);
+
+        let mut alex = Person::new("alex", 32);
+        repo.save(&mut alex).unwrap();
+        repo.save(&mut Person::new("bob", 25)).unwrap();
+
+        // Cached records are served without reading the table
+        let mut bob = repo.find(2).unwrap();
+        bob.age = 26;
+        bob.update(repo.table()).unwrap();
+        assert_eq!(repo.find(2).unwrap().age, 25);
+
+        // The least recently used record is evicted
+        assert_eq!(repo.find(1).unwrap().age, 32);
+        assert_eq!(repo.find(2).unwrap().age, 26);
+
+        // Invalidation on save and delete
+        bob.age = 27;
+        repo.save(&mut bob).unwrap();
+        assert_eq!(repo.find(2).unwrap().age, 27);
+        repo.delete(2).unwrap();
+        assert!(repo.find(2).is_err());
+
+        _ensure_removed_table_file(TABLE_CACHED_PATH);
+    }
+
+    #[test]
+    fn test_bitmap_index() {
+        let paths = [
+            TABLE_FLAGGED_PATH, TABLE_ADULT_INDEX_PATH,
+            TABLE_THIRTIES_INDEX_PATH,
+        ];
+        paths.iter().for_each(|path| _ensure_removed_table_file(path));
+
+        let repo = Repository::<Person>::new(TABLE_FLAGGED_PATH).unwrap()
+            .with_bitmap_index("adult", TABLE_ADULT_INDEX_PATH, |p| p.age >= 18)
+            .unwrap()
+            .with_bitmap_index(
+                "thirties", TABLE_THIRTIES_INDEX_PATH, |p| p.age / 10 == 3
+            ).unwrap();
+
+        let mut alex = Person::new("alex", 17);
+        repo.save(&mut alex).unwrap();
+        repo.save(&mut Person::new("bob", 25)).unwrap();
+        repo.save(&mut Person::new("carl", 32)).unwrap();
+
+        // Combination
+        let adults = repo.bitmap("adult", &true).unwrap();
+        assert_eq!(adults.len(), 2);
+        let thirties = repo.bitmap("thirties", &true).unwrap();
+        let persons = repo.find_bitmap(&adults.and(&thirties)).unwrap();
+        assert_eq!(persons.len(), 1);
+        assert_eq!(persons[0].name.to_string(), "carl");
+        assert!(repo.bitmap("adult", &18u32).is_err());
+
+        // Maintained on save and delete
+        alex.age = 30;
+        repo.save(&mut alex).unwrap();
+        let adults = repo.bitmap("adult", &true).unwrap();
+        assert_eq!(adults.iter().collect::<Vec<usize>>(), vec![1, 2, 3]);
+        repo.delete(3).unwrap();
+        let thirties = repo.bitmap("thirties", &true).unwrap();
+        assert_eq!(thirties.iter().collect::<Vec<usize>>(), vec![1]);
+
+        paths.iter().for_each(|path| _ensure_removed_table_file(path));
+    }
+
+    #[test]
+    fn test_move_record() {
+        let paths = [
+            TABLE_ACTIVE_PATH, TABLE_ACTIVE_INDEX_PATH,
+            TABLE_ARCHIVE_PATH, TABLE_ARCHIVE_INDEX_PATH,
+        ];
+        paths.iter().for_each(|path| _ensure_removed_table_file(path));
+
+        let active = Repository::<Person>::new(TABLE_ACTIVE_PATH).unwrap()
+            .with_index("age", TABLE_ACTIVE_INDEX_PATH, |p| p.age).unwrap();
+        let archive = Repository::<Person>::new(TABLE_ARCHIVE_PATH).unwrap()
+            .with_index("age", TABLE_ARCHIVE_INDEX_PATH, |p| p.age).unwrap()
+            .with_identity_map(4);
+
+        active.save(&mut Person::new("alex", 32)).unwrap();
+        active.save(&mut Person::new("bob", 25)).unwrap();
+        archive.save(&mut Person::new("carl", 40)).unwrap();
+
+        // Move
+        assert_eq!(move_record(&active, &archive, 2).unwrap(), 2);
+        assert!(active.find(2).is_err());
+        assert!(active.find_by("age", &25u32).unwrap().is_empty());
+        let persons = archive.find_by("age", &25u32).unwrap();
+        assert_eq!(persons.len(), 1);
+        assert_eq!((persons[0].id, persons[0].name.to_string()),
+                   (2, "bob".to_string()));
+        assert_eq!(archive.find(2).unwrap().age, 25);
+
+        // Nothing changes on errors
+        let err = move_record(&active, &archive, 2).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        let err = move_record(&active, &active, 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(active.find_by("age", &32u32).unwrap().len(), 1);
+        assert_eq!(archive.table().size().unwrap(), 2);
+
+        paths.iter().for_each(|path| _ensure_removed_table_file(path));
+    }
+
+    #[test]
+    fn test_save_rollback() {
+        let paths = [
+            TABLE_ROLLBACK_PATH.to_string(),
+            format!("{}.free", TABLE_ROLLBACK_PATH),
+            TABLE_ROLLBACK_INDEX_PATH.to_string(),
+            TABLE_WIDE_INDEX_PATH.to_string(),
+        ];
+        paths.iter().for_each(|path| _ensure_removed_table_file(path));
+
+        // The second index was created for another key type, so its add
+        // fails after the first index got the entry
+        let wide = TableIndex::<u32>::open(TABLE_WIDE_INDEX_PATH).unwrap();
+        TableIndex::add(&wide, &1u32, 100).unwrap();
+        drop(wide);
+        let repo = Repository::<Person>::new(TABLE_ROLLBACK_PATH).unwrap()
+            .with_index("age", TABLE_ROLLBACK_INDEX_PATH, |p| p.age).unwrap()
+            .with_index("wide", TABLE_WIDE_INDEX_PATH, |p| p.age as u64)
+            .unwrap();
+
+        let mut alex = Person::new("alex", 32);
+        assert!(repo.save(&mut alex).is_err());
+        assert_eq!(alex.id, 0);
+        assert!(repo.find(1).is_err());
+        assert!(repo.find_by("age", &32u32).unwrap().is_empty());
+
+        paths.iter().for_each(|path| _ensure_removed_table_file(path));
+    }
+
+    fn _ensure_removed_tables() {
+        _ensure_removed_table_file(TABLE_PATH);
+        _ensure_removed_table_file(TABLE_AGE_INDEX_PATH);
+    }
+
+    fn _ensure_removed_table_file(path: &str) {
+        if fs::metadata(path).is_ok() {
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,356 @@
+use std::{fs, io};
+use std::io::Write;
+
+use crate::table::*;
+use crate::file_ext::FileExt;
+
+
+const SNAPSHOT_MAGIC: &[u8; 8] = b"MYTSNAP\0";
+const SNAPSHOT_VERSION: u64 = 1;
+const SNAPSHOT_HEADER_SIZE: usize = 64;
+
+/// The data section starts at a page boundary, so it can be mapped alone.
+const SNAPSHOT_PAGE_SIZE: usize = 4096;
+
+/// The records are padded to 8 bytes, so they are aligned when mapped.
+const SNAPSHOT_RECORD_ALIGNMENT: usize = 8;
+
+
+/// SnapshotHeader is stored at the beginning of a snapshot file. All numbers
+/// are 8 bytes LE after the magic: version, record size, stride, count,
+/// offset of the ids, offset of the data and the checksum.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SnapshotHeader {
+    /// The size of a record in bytes.
+    pub record_size: usize,
+    /// The distance between neighbouring records in the data section.
+    pub stride: usize,
+    /// The number of records.
+    pub count: usize,
+    /// The offset of the sorted ids (8 bytes LE each) in the file.
+    pub ids_offset: usize,
+    /// The offset of the records in the file, a multiple of the page size.
+    pub data_offset: usize,
+    /// The checksum (FNV-1a) of the ids and the data sections.
+    pub checksum: u64,
+}
+
+
+impl SnapshotHeader {
+    /// The offset in the file of the record at **pos** in the order of ids.
+    pub fn record_offset(&self, pos: usize) -> usize {
+        self.data_offset + pos * self.stride
+    }
+
+    fn _new(record_size: usize, count: usize) -> Self {
+        let ids_offset = SNAPSHOT_HEADER_SIZE;
+        let ids_end = ids_offset + 8 * count;
+        Self {
+            record_size,
+            stride: record_size.div_ceil(SNAPSHOT_RECORD_ALIGNMENT)
+                * SNAPSHOT_RECORD_ALIGNMENT,
+            count,
+            ids_offset,
+            data_offset: ids_end.div_ceil(SNAPSHOT_PAGE_SIZE)
+                * SNAPSHOT_PAGE_SIZE,
+            checksum: 0,
+        }
+    }
+
+    fn _to_bytes(&self) -> Vec<u8> {
+        let mut block = SNAPSHOT_MAGIC.to_vec();
+        for field in &[
+                    SNAPSHOT_VERSION, self.record_size as u64,
+                    self.stride as u64, self.count as u64,
+                    self.ids_offset as u64, self.data_offset as u64,
+                    self.checksum
+                ] {
+            block.extend_from_slice(&field.to_le_bytes());
+        }
+        block
+    }
+
+    fn _from_bytes(block: &[u8]) -> Result<Self, io::Error> {
+        let field = |pos: usize| {
+            let mut bytes = [0u8; 8];
+            bytes.clone_from_slice(&block[8 * pos..8 * pos + 8]);
+            u64::from_le_bytes(bytes)
+        };
+
+        if (&block[..8] != SNAPSHOT_MAGIC) || (field(1) != SNAPSHOT_VERSION) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData, "snapshot header"
+            ));
+        }
+
+        Ok(Self {
+            record_size: field(2) as usize,
+            stride: field(3) as usize,
+            count: field(4) as usize,
+            ids_offset: field(5) as usize,
+            data_offset: field(6) as usize,
+            checksum: field(7),
+        })
+    }
+}
+
+
+/// Snapshot is a sealed read-only copy of a table created by
+/// **Table::export_snapshot_mmapable**. It has no erased blocks: the ids of
+/// the records are stored sorted in one section and the records in the same
+/// order in the data section starting at a page boundary, so a reader can
+/// map the file and find a record by a binary search of the id. The file is
+/// checksummed and marked read-only.
+#[derive(Debug)]
+pub struct Snapshot {
+    file: fs::File,
+    header: SnapshotHeader,
+    ids: Vec<usize>,
+}
+
+
+impl Snapshot {
+    /// Opens the snapshot located by **path** and verifies its checksum.
+    pub fn open(path: &str) -> Result<Self, io::Error> {
+        let file = fs::File::open(path)?;
+
+        let mut block = [0u8; SNAPSHOT_HEADER_SIZE];
+        file.read_exact_at(&mut block, 0)?;
+        let header = SnapshotHeader::_from_bytes(&block)?;
+
+        let mut bytes = vec![0u8; 8 * header.count];
+        file.read_exact_at(&mut bytes, header.ids_offset as u64)?;
+        let ids = bytes.chunks(8).map(|chunk| {
+            let mut id = [0u8; 8];
+            id.clone_from_slice(chunk);
+            u64::from_le_bytes(id) as usize
+        }).collect();
+
+        let snapshot = Self { file, header, ids };
+        if snapshot._checksum()? != header.checksum {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData, "snapshot checksum"
+            ));
+        }
+        Ok(snapshot)
+    }
+
+    /// The header of the snapshot.
+    pub fn header(&self) -> &SnapshotHeader {
+        &self.header
+    }
+
+    /// The number of records.
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Returns true if there are no records, else false.
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// The ids of the records in ascending order.
+    pub fn ids(&self) -> &[usize] {
+        &self.ids
+    }
+
+    /// The position of the record with **id** in the data section.
+    pub fn position(&self, id: usize) -> Option<usize> {
+        self.ids.binary_search(&id).ok()
+    }
+
+    /// Gets bytes of the record by its **id**.
+    pub fn get(&self, id: usize) -> Result<Vec<u8>, io::Error> {
+        let pos = self.position(id).ok_or_else(
+            || io::Error::new(io::ErrorKind::NotFound, id.to_string())
+        )?;
+        let mut block = vec![0u8; self.header.record_size];
+        self.file.read_exact_at(
+            &mut block, self.header.record_offset(pos) as u64
+        )?;
+        Ok(block)
+    }
+
+    pub(crate) fn _export(
+                table: &Table,
+                record_size: usize,
+                path: &str
+            ) -> Result<SnapshotHeader, io::Error> {
+        if fs::metadata(path).is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, path));
+        }
+
+        let mut ids = Vec::new();
+        for idx in 0..table.size()? {
+            if table.get(idx)?.iter().any(|byte| *byte != 0) {
+                ids.push(idx + 1);
+            }
+        }
+
+        let mut header = SnapshotHeader::_new(record_size, ids.len());
+        let mut hash = 0xcbf29ce484222325u64;
+
+        // The file appears under the path only when it is complete, and
+        // the header is written last with the checksum of the sections
+        let tmp_path = format!("{}.tmp", path);
+        let mut file = io::BufWriter::new(fs::File::create(&tmp_path)?);
+
+        let mut section = vec![0u8; header.ids_offset];
+        for id in &ids {
+            section.extend_from_slice(&(*id as u64).to_le_bytes());
+        }
+        section.resize(header.data_offset, 0);
+        hash = Self::_hash(hash, &section[header.ids_offset..]);
+        file.write_all(&section)?;
+
+        for id in &ids {
+            let mut block = table.get(id - 1)?;
+            block.resize(header.stride, 0);
+            hash = Self::_hash(hash, &block);
+            file.write_all(&block)?;
+        }
+
+        header.checksum = hash;
+        let file = file.into_inner().map_err(|err| err.into_error())?;
+        file.write_all_at(&header._to_bytes(), 0)?;
+        file.sync_all()?;
+
+        let mut permissions = file.metadata()?.permissions();
+        permissions.set_readonly(true);
+        file.set_permissions(permissions)?;
+        fs::rename(&tmp_path, path)?;
+
+        Ok(header)
+    }
+
+    fn _checksum(&self) -> Result<u64, io::Error> {
+        let end = self.header.record_offset(self.header.count);
+        let mut hash = 0xcbf29ce484222325u64;
+        let mut offset = self.header.ids_offset;
+        let mut chunk = vec![0u8; SNAPSHOT_PAGE_SIZE];
+
+        while offset < end {
+            let size = (end - offset).min(SNAPSHOT_PAGE_SIZE);
+            self.file.read_exact_at(&mut chunk[..size], offset as u64)?;
+            hash = Self::_hash(hash, &chunk[..size]);
+            offset += size;
+        }
+
+        Ok(hash)
+    }
+
+    fn _hash(hash: u64, bytes: &[u8]) -> u64 {
+        bytes.iter().fold(hash, |hash, byte| {
+            (hash ^ (*byte as u64)).wrapping_mul(0x100000001b3)
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::varchar::*;
+    use crate::table_trait::*;
+    use super::*;
+
+    const TABLE_PATH: &str = "test-snapshot-person.tbl";
+    const SNAPSHOT_PATH: &str = "test-snapshot-person.snap";
+    const TABLE_CORRUPTED_PATH: &str = "test-snapshot-corrupted.tbl";
+    const SNAPSHOT_CORRUPTED_PATH: &str = "test-snapshot-corrupted.snap";
+
+    #[derive(Debug, Copy, Clone)]
+    struct Person {
+        id: usize,
+        name: Varchar<20>,
+        age: u32,
+    }
+
+    impl TableTrait for Person {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    #[test]
+    fn test_snapshot() {
+        _ensure_removed_files();
+
+        let table = Table::open::<Person>(TABLE_PATH).unwrap();
+        for (name, age) in &[("alex", 32), ("bob", 25), ("carl", 40)] {
+            let mut person = Person {
+                id: 0, name: Varchar::<20>::new(name), age: *age
+            };
+            person.insert(&table).unwrap();
+        }
+        Person::erase(&table, 2).unwrap();
+
+        // Export
+        let header = table.export_snapshot_mmapable(SNAPSHOT_PATH).unwrap();
+        assert_eq!(header.count, 2);
+        assert_eq!(header.data_offset % 4096, 0);
+        assert_eq!(header.stride % 8, 0);
+        assert!(fs::metadata(SNAPSHOT_PATH).unwrap().permissions().readonly());
+        assert!(table.export_snapshot_mmapable(SNAPSHOT_PATH).is_err());
+
+        // Read
+        let snapshot = Snapshot::open(SNAPSHOT_PATH).unwrap();
+        assert_eq!(snapshot.header(), &header);
+        assert_eq!(snapshot.ids(), &[1, 3]);
+        let carl = Person::from_bytes(&snapshot.get(3).unwrap());
+        assert_eq!((carl.id, carl.name.to_string(), carl.age),
+                   (3, "carl".to_string(), 40));
+        assert_eq!(
+            snapshot.get(2).unwrap_err().kind(), io::ErrorKind::NotFound
+        );
+
+        _ensure_removed_files();
+    }
+
+    #[test]
+    #[allow(clippy::permissions_set_readonly_false)]
+    fn test_snapshot_checksum() {
+        _remove_file(TABLE_CORRUPTED_PATH);
+        _remove_file(SNAPSHOT_CORRUPTED_PATH);
+
+        let table = Table::open::<Person>(TABLE_CORRUPTED_PATH).unwrap();
+        let mut person = Person {
+            id: 0, name: Varchar::<20>::new("alex"), age: 32
+        };
+        person.insert(&table).unwrap();
+        let header = table.export_snapshot_mmapable(
+            SNAPSHOT_CORRUPTED_PATH
+        ).unwrap();
+
+        let mut permissions = fs::metadata(SNAPSHOT_CORRUPTED_PATH).unwrap()
+            .permissions();
+        permissions.set_readonly(false);
+        fs::set_permissions(SNAPSHOT_CORRUPTED_PATH, permissions).unwrap();
+        let file = fs::OpenOptions::new().write(true)
+            .open(SNAPSHOT_CORRUPTED_PATH).unwrap();
+        file.write_all_at(&[0xff], header.data_offset as u64 + 8).unwrap();
+
+        let err = Snapshot::open(SNAPSHOT_CORRUPTED_PATH).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        _remove_file(TABLE_CORRUPTED_PATH);
+        _remove_file(SNAPSHOT_CORRUPTED_PATH);
+    }
+
+    fn _ensure_removed_files() {
+        _remove_file(TABLE_PATH);
+        _remove_file(SNAPSHOT_PATH);
+    }
+
+    fn _remove_file(path: &str) {
+        if fs::metadata(path).is_ok() {
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -1,97 +1,1197 @@
-use std::{fs, io, iter};
-use std::os::unix::prelude::FileExt;
+use std::{error, fmt, fs, io, iter, time};
+use std::collections::HashSet;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use crate::table_trait::TableTrait;
+use crate::block_cache::BlockCache;
+use crate::dirty_map::DirtyMap;
+use crate::free_list::FreeList;
+use crate::wal::WriteAheadLog;
+use crate::table_lock::{TableLock, ExclusiveGuard};
+use crate::record_guard::RecordGuard;
+use crate::typed_table::TypedTable;
+use crate::file_ext::FileExt;
+use crate::table_error::TableError;
+use crate::progress::ProgressHandle;
+use crate::snapshot::{Snapshot, SnapshotHeader};
+use crate::compression::{
+    Compression, CompressionKind, COMPRESSION_META_KEY, MIN_SLOT_SIZE
+};
+use crate::read_trace;
+use crate::change_feed::{ChangeKind, Watchers};
+#[cfg(feature = "mmap")]
+use crate::mapped_blocks::{MappedBlocks, Mapping};
+#[cfg(feature = "encryption")]
+use crate::encryption::{Encryption, ENCRYPTION_KEY_SIZE, ENCRYPTION_OVERHEAD};
+#[cfg(feature = "ledger")]
+use crate::table_ledger::Ledger;
+
+
+/// The expected way to access the blocks, it is passed to the OS
+/// by **Table::advise** to tune read ahead and caching.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AccessPattern {
+    /// No special treatment.
+    Normal,
+    /// The blocks will be read in order, so read ahead is useful.
+    Sequential,
+    /// The blocks will be read in random order, read ahead is wasteful.
+    Random,
+    /// The blocks will be read soon.
+    WillNeed,
+    /// The blocks will not be read soon, they can be dropped from cache.
+    DontNeed,
+}
+
+
+/// The item of the iterators of blocks, the error of reading the block.
+type BlockResult = Result<Vec<u8>, io::Error>;
+
+
+/// The advisory lock of the table file taken on open.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum FileLock {
+    None,
+    Shared,
+    Exclusive,
+}
+
+
+/// The size of the epoch stamp stored at each side of a stamped block.
+const EPOCH_SIZE: usize = 8;
+
+/// The size of the checksum stored at the end of a checksummed block.
+const CHECKSUM_SIZE: usize = 4;
+
+/// The key of the metadata area keeping the check of the encryption key.
+#[cfg(feature = "encryption")]
+const ENCRYPTION_META_KEY: &str = "encryption";
+
+/// The number of blocks read at once by **Table::verify**.
+const VERIFY_CHUNK_SIZE: usize = 256;
+
+/// The lookup table of CRC32 (IEEE), one entry per byte value.
+const CRC32_TABLE: [u32; 256] = _crc32_table();
+
+pub(crate) const TABLE_MAGIC: &[u8; 8] = b"MYTABLE\0";
+const TABLE_VERSION: u64 = 2;
+const TABLE_HEADER_SIZE: usize = 24;
+
+/// The metadata area follows the fields of the header since version 2,
+/// so the header takes 512 bytes.
+const TABLE_META_SIZE: usize = 488;
+
+
+/// How much of the table is read by **Table::quick_check**.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CheckDepth {
+    /// Only the header and the length of the file.
+    Header,
+    /// Also the given number of blocks spread over the table, the last one
+    /// (the latest append) included.
+    Sample(usize),
+    /// Also all blocks, like **verify**.
+    Deep,
+}
+
+
+/// What was found by **Table::quick_check**.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QuickCheckReport {
+    /// The bytes after the last whole block (e.g. left by an interrupted
+    /// append), they are not a part of any record.
+    pub trailing_bytes: usize,
+    /// The number of checked blocks.
+    pub checked: usize,
+    /// The indices of the checked blocks that fail the check of their
+    /// checksums or epoch stamps.
+    pub corrupt: Vec<usize>,
+}
+
+
+impl QuickCheckReport {
+    /// Returns true if nothing wrong was found, else false.
+    pub fn clean(&self) -> bool {
+        (self.trailing_bytes == 0) && self.corrupt.is_empty()
+    }
+}
+
+
+/// The error of a block whose head and tail epoch stamps differ, so it was
+/// written partially (e.g. by a crashed writer). It is carried inside
+/// **io::Error** of kind *InvalidData*.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TornWrite {
+    /// The index of the block.
+    pub idx: usize,
+}
+
+
+impl fmt::Display for TornWrite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "torn write of block {}", self.idx)
+    }
+}
+
+
+impl error::Error for TornWrite {}
+
+
+/// The error of a block whose checksum does not match its content, so it
+/// was changed on the disk (e.g. by silent corruption). It is carried
+/// inside **io::Error** of kind *InvalidData*.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CorruptBlock {
+    /// The index of the block.
+    pub idx: usize,
+}
+
+
+impl fmt::Display for CorruptBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "corrupt block {}", self.idx)
+    }
+}
+
+
+impl error::Error for CorruptBlock {}
 
 
 /// Table is represented as a struct with the information about the path,
-/// block size and the file object.
+/// block size and the file object. It is *Send* and *Sync*, so it can be
+/// shared by threads (e.g. in **Arc**): the blocks are read and written at
+/// their offsets and the appends are serialized, so every appended block
+/// gets its own index.
 #[derive(Debug)]
 pub struct Table {
     path: String,
+    header_size: usize,
+    meta_size: usize,
     block_size: usize,
-    file: fs::File
+    alignment: usize,
+    direct: bool,
+    file_lock: FileLock,
+    epoch: Option<AtomicU64>,
+    checksums: bool,
+    file: fs::File,
+    dirty: Option<DirtyMap>,
+    free: FreeList,
+    wal: Option<WriteAheadLog>,
+    cache: Option<Mutex<BlockCache>>,
+    compression: Option<Compression>,
+    #[cfg(feature = "encryption")]
+    encryption: Option<Encryption>,
+    #[cfg(feature = "ledger")]
+    ledger: Option<Ledger>,
+    extend: Mutex<()>,
+    meta: Mutex<()>,
+    watchers: Watchers,
+    #[cfg(feature = "mmap")]
+    mapping: Mapping,
+    lock: TableLock
 }
 
 
 impl Table {
     /// Creates or opens a file to work. **block_size** is the size of record
-    /// in bytes.
+    /// in bytes. It panics if the file cannot be opened, use **open** to get
+    /// the error instead.
+    #[deprecated(note = "use Table::open")]
+    #[allow(clippy::unwrap_used)]
     pub fn new<T: TableTrait>(path: &str) -> Self {
-        let file = fs::OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(path).unwrap();
-        Self {
-            path: path.to_string(),
-            block_size: T::block_size(),
-            file
+        Self::open::<T>(path).unwrap()
+    }
+
+    /// Creates or opens a file to work. The errors of opening the file and
+    /// checking its header are returned instead of panicking.
+    pub fn open<T: TableTrait>(path: &str) -> Result<Self, TableError> {
+        Self::_open(path, T::block_size(), 1, false)
+    }
+
+    /// Creates or opens a file to work bound to the record type **T**, so
+    /// only the records of this type can be read and written through it.
+    pub fn open_typed<T: TableTrait>(
+                path: &str
+            ) -> Result<TypedTable<T>, TableError> {
+        Ok(TypedTable::new(Self::open::<T>(path)?))
+    }
+
+    /// Creates or opens a file to work holding an exclusive advisory lock
+    /// of it until the table is dropped, so no other process can open
+    /// the file with **open_exclusive** or **open_shared** meanwhile.
+    /// **TableError::Locked** is returned if the file is already locked.
+    /// The lock is not supported on Windows.
+    pub fn open_exclusive<T: TableTrait>(
+                path: &str
+            ) -> Result<Self, TableError> {
+        Self::_open_locked(path, T::block_size(), 1, false, FileLock::Exclusive)
+    }
+
+    /// Opens an existing file to read holding a shared advisory lock of it,
+    /// so several readers can open it together while a writer with
+    /// **open_exclusive** cannot. The writes to the table are rejected with
+    /// an error of kind *PermissionDenied*. **TableError::PendingLog** is
+    /// returned if the write-ahead log keeps an interrupted write, it is
+    /// replayed by the next writer.
+    pub fn open_shared<T: TableTrait>(path: &str) -> Result<Self, TableError> {
+        Self::_open_locked(path, T::block_size(), 1, false, FileLock::Shared)
+    }
+
+    /// Creates or opens a file to work with blocks of **block_size** bytes
+    /// that are not tied to a single record type.
+    pub fn open_with_block_size(
+                path: &str,
+                block_size: usize
+            ) -> Result<Self, TableError> {
+        Self::_open(path, block_size, 1, false)
+    }
+
+    /// Creates or opens a file to work with blocks padded to a multiple of
+    /// **alignment** bytes (e.g. 512 or 4096), so every block starts on
+    /// the boundary and no write touches two disk sectors.
+    pub fn with_alignment<T: TableTrait>(
+                path: &str,
+                alignment: usize
+            ) -> Result<Self, TableError> {
+        Self::_open(path, T::block_size(), alignment, false)
+    }
+
+    /// Creates or opens a file with aligned blocks in direct I/O mode
+    /// (*O_DIRECT* on Linux, *F_NOCACHE* on macOS, *FILE_FLAG_NO_BUFFERING*
+    /// on Windows) bypassing the page cache.
+    /// **alignment** must be a multiple of the logical sector size of
+    /// the device.
+    pub fn with_direct_io<T: TableTrait>(
+                path: &str,
+                alignment: usize
+            ) -> Result<Self, TableError> {
+        Self::_open(path, T::block_size(), alignment, true)
+    }
+
+    /// Creates or opens a file to work with each block surrounded by
+    /// the epoch stamps of its write, so a partially written block is
+    /// detected on read and reported as **TornWrite**.
+    pub fn with_epoch_stamps<T: TableTrait>(
+                path: &str
+            ) -> Result<Self, TableError> {
+        let mut table = Self::_open(
+            path, T::block_size() + 2 * EPOCH_SIZE, 1, false
+        )?;
+        let now = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH).unwrap_or_default();
+        table.epoch = Some(AtomicU64::new(now.as_nanos() as u64));
+        Ok(table)
+    }
+
+    /// Creates or opens a file to work with the checksum (CRC32) of each
+    /// block stored at its end. It is verified on every read, so a block
+    /// changed on the disk is reported as **CorruptBlock** instead of
+    /// garbage, see also **verify**. An all-zero block is not checked.
+    pub fn with_checksums<T: TableTrait>(
+                path: &str
+            ) -> Result<Self, TableError> {
+        let mut table = Self::_open(
+            path, T::block_size() + CHECKSUM_SIZE, 1, false
+        )?;
+        table.checksums = true;
+        Ok(table)
+    }
+
+    /// Creates or opens a file to work with each block encrypted by **key**
+    /// (XChaCha20-Poly1305) on write and decrypted on read, a block changed
+    /// on the disk is reported as **CorruptBlock**. The erased records are
+    /// encrypted too, so an all-zero block is reported the same way.
+    /// The nonces are chosen by the table, each block takes 40 bytes more.
+    /// The key is checked against the value stored in the header when
+    /// the file was created, a wrong key returns
+    /// **TableError::InvalidHeader**.
+    #[cfg(feature = "encryption")]
+    pub fn new_encrypted<T: TableTrait>(
+                path: &str,
+                key: &[u8; ENCRYPTION_KEY_SIZE]
+            ) -> Result<Self, TableError> {
+        let mut table = Self::_open(
+            path, T::block_size() + ENCRYPTION_OVERHEAD, 1, false
+        )?;
+        let encryption = Encryption::new(key, T::block_size());
+
+        match table.get_meta(ENCRYPTION_META_KEY)? {
+            Some(check) if encryption.verify_key(&check) => {},
+            Some(_) => {
+                return Err(TableError::InvalidHeader(
+                    "encryption key".to_string()
+                ));
+            },
+            None if table.empty()? => {
+                table.set_meta(ENCRYPTION_META_KEY, &encryption.key_check()?)?;
+            },
+            None => {
+                return Err(TableError::InvalidHeader(
+                    "not encrypted".to_string()
+                ));
+            },
+        }
+
+        table.encryption = Some(encryption);
+        Ok(table)
+    }
+
+    /// Creates or opens a file to work signing every appended block into
+    /// the ledger (the file **path.ledger**, see **TableLedger**) with
+    /// **key**, so the table is a tamper-evident log checked by
+    /// **verify_ledger**. The blocks of deleted records are not reused by
+    /// **insert**, as the chain follows the order of the appends, and any
+    /// later **update**, **erase** or **delete** breaks the chain. The table
+    /// must be signed up to its end, e.g. a block appended by a crash before
+    /// it was signed returns **TableError::InvalidHeader**.
+    #[cfg(feature = "ledger")]
+    pub fn with_ledger<T: TableTrait>(
+                path: &str,
+                key: &[u8]
+            ) -> Result<Self, TableError> {
+        let mut table = Self::open::<T>(path)?;
+        let ledger = Ledger::open(&format!("{}.ledger", path), key)?;
+        if ledger.size()? != table.size()? {
+            return Err(TableError::InvalidHeader("ledger size".to_string()));
         }
+        table.ledger = Some(ledger);
+        Ok(table)
+    }
+
+    /// Creates or opens a file to work tracking the changed blocks in
+    /// the file **path.dirty** to make incremental backups.
+    pub fn with_dirty_map<T: TableTrait>(
+                path: &str
+            ) -> Result<Self, TableError> {
+        let mut table = Self::open::<T>(path)?;
+        let dirty_path = format!("{}.dirty", path);
+        table.dirty = Some(DirtyMap::open(&dirty_path)?);
+        Ok(table)
+    }
+
+    /// Creates or opens a file to work writing each block to the log
+    /// **path.wal** first, so a write interrupted by a crash is finished
+    /// by the next open instead of leaving a partially written block.
+    /// Every write is flushed to the disk twice, so it is slower.
+    pub fn with_wal<T: TableTrait>(path: &str) -> Result<Self, TableError> {
+        let mut table = Self::open::<T>(path)?;
+        table.wal = Some(WriteAheadLog::open(&format!("{}.wal", path))?);
+        Ok(table)
+    }
+
+    /// Creates or opens a file to work keeping up to **capacity** recently
+    /// read blocks in memory (e.g. the top nodes of an index), so they are
+    /// not read from the disk again. Written blocks are dropped from
+    /// the cache. The reads and writes of the table are serialized.
+    pub fn with_cache<T: TableTrait>(
+                path: &str,
+                capacity: usize
+            ) -> Result<Self, TableError> {
+        let mut table = Self::open::<T>(path)?;
+        table.cache = Some(Mutex::new(BlockCache::new(capacity)));
+        Ok(table)
+    }
+
+    /// Creates or opens a file to work keeping the blocks pinned by **pin**
+    /// in memory up to **budget** bytes, see **TableIndex::open_cached**.
+    pub fn with_pinned_cache<T: TableTrait>(
+                path: &str,
+                budget: usize
+            ) -> Result<Self, TableError> {
+        let mut table = Self::open::<T>(path)?;
+        let pin_capacity = budget / table.block_size;
+        table.cache = Some(Mutex::new(BlockCache::with_pins(0, pin_capacity)));
+        Ok(table)
+    }
+
+    /// Creates or opens a file to work storing the blocks in slots of
+    /// **slot_size** bytes: zeros are suppressed (e.g. the padding of short
+    /// **Varchar** fields), so the file shrinks. A block that does not fit
+    /// its slot is stored raw in the file **path.overflow**. The slot must
+    /// take at least 9 bytes.
+    pub fn with_compression<T: TableTrait>(
+                path: &str,
+                slot_size: usize
+            ) -> Result<Self, TableError> {
+        Self::with_compression_kind::<T>(
+            path, CompressionKind::Zeros, slot_size
+        )
+    }
+
+    /// Creates or opens a file to work storing the blocks in slots of
+    /// **slot_size** bytes compressed by **kind** (e.g. LZ4 with the feature
+    /// *lz4*), see **with_compression**. The kind is stored in the metadata
+    /// area of the header when the file is created, a file opened with
+    /// another kind returns **TableError::InvalidHeader**. The files created
+    /// without the kind are compressed by *Zeros*.
+    pub fn with_compression_kind<T: TableTrait>(
+                path: &str,
+                kind: CompressionKind,
+                slot_size: usize
+            ) -> Result<Self, TableError> {
+        if slot_size < MIN_SLOT_SIZE {
+            return Err(TableError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput, "slot size"
+            )));
+        }
+        let mut table = Self::_open(path, slot_size, 1, false)?;
+
+        let stored = match table.get_meta(COMPRESSION_META_KEY)? {
+            Some(name) => name,
+            None if table.empty()? => {
+                table.set_meta(COMPRESSION_META_KEY, kind.name())?;
+                kind.name().to_string()
+            },
+            None => CompressionKind::Zeros.name().to_string(),
+        };
+        if stored != kind.name() {
+            return Err(TableError::InvalidHeader(
+                format!("compression {}", stored)
+            ));
+        }
+
+        table.compression = Some(
+            Compression::open(path, kind, T::block_size())?
+        );
+        Ok(table)
+    }
+
+    /// Pins the blocks **idxs** in memory instead of the pinned before and
+    /// reads them. Only as many blocks as fit the budget of
+    /// **with_pinned_cache** are pinned, the table opened another way
+    /// returns an error of kind *Unsupported*. The written blocks stay
+    /// pinned and are read again.
+    pub fn pin(&self, idxs: &[usize]) -> Result<(), io::Error> {
+        let pin_capacity = self.pin_capacity();
+        match self._cache() {
+            Some(mut cache) if pin_capacity > 0 => cache.pin(idxs),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported, "pinned cache"
+                ));
+            },
+        }
+
+        let size = self.size()?;
+        for idx in idxs.iter().take(pin_capacity) {
+            if *idx < size {
+                self.get(*idx)?;
+            }
+        }
+        Ok(())
     }
 
-    /// The number of records inserted.
-    pub fn size(&self) -> usize {
-        self.file.metadata().unwrap().len() as usize / self.block_size
+    /// The greatest number of blocks pinned in memory, 0 if the table is
+    /// opened without **with_pinned_cache**.
+    pub fn pin_capacity(&self) -> usize {
+        self._cache().map_or(0, |cache| cache.pin_capacity())
     }
 
-    /// Returns true if the table is empty, else false.
-    pub fn empty(&self) -> bool {
-        self.size() == 0
+    /// The path of the file.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The number of records inserted. The error of reading the length of
+    /// the file is returned (it returned a bare number before).
+    pub fn size(&self) -> Result<usize, io::Error> {
+        let len = self.file.metadata()?.len() as usize;
+        Ok(len.saturating_sub(self.header_size) / self.block_size)
+    }
+
+    /// Returns true if the table is empty, else false. The error of reading
+    /// the length of the file is returned like by **size**.
+    pub fn empty(&self) -> Result<bool, io::Error> {
+        Ok(self.size()? == 0)
+    }
+
+    /// The size of a block in the file including the alignment padding
+    /// and the epoch stamps.
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Gets the value stored with **key** in the metadata area of
+    /// the header (e.g. a schema version or the time of the last sync),
+    /// None if there is no such key.
+    pub fn get_meta(&self, key: &str) -> Result<Option<String>, io::Error> {
+        let _meta = self._meta();
+        Ok(self._read_meta()?.into_iter()
+            .find(|entry| entry.0 == key).map(|entry| entry.1))
+    }
+
+    /// Stores **value** with **key** in the metadata area of the header
+    /// replacing the previous value. The area takes 488 bytes shared by all
+    /// keys, an error of kind *InvalidInput* is returned if they do not fit
+    /// or the key is empty or longer than 255 bytes. The files created by
+    /// the versions without the area (or without the header at all) return
+    /// *Unsupported* until they are rewritten by **store_all**.
+    pub fn set_meta(&self, key: &str, value: &str) -> Result<(), io::Error> {
+        self._check_writable()?;
+        if self.meta_size == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported, "table version 1"
+            ));
+        }
+        if key.is_empty() || (key.len() > u8::MAX as usize) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "key"));
+        }
+
+        let _meta = self._meta();
+        let mut entries = self._read_meta()?;
+        match entries.iter_mut().find(|entry| entry.0 == key) {
+            Some(entry) => entry.1 = value.to_string(),
+            None => entries.push((key.to_string(), value.to_string())),
+        }
+        self._write_meta(&entries)
+    }
+
+    /// Removes **key** from the metadata area of the header. Returns true
+    /// if it was there, else false.
+    pub fn remove_meta(&self, key: &str) -> Result<bool, io::Error> {
+        self._check_writable()?;
+
+        let _meta = self._meta();
+        let mut entries = self._read_meta()?;
+        let count = entries.len();
+        entries.retain(|entry| entry.0 != key);
+        if entries.len() == count {
+            return Ok(false);
+        }
+        self._write_meta(&entries)?;
+        Ok(true)
+    }
+
+    /// All keys and values in the metadata area of the header in the order
+    /// they were added.
+    pub fn meta(&self) -> Result<Vec<(String, String)>, io::Error> {
+        let _meta = self._meta();
+        self._read_meta()
     }
 
     /// Gets bytes of a record by its index.
     pub fn get(&self, idx: usize) -> Result<Vec<u8>, io::Error> {
-        let mut block: Vec<u8> = vec![0; self.block_size];
-        self.file.read_exact_at(&mut block, (idx * self.block_size) as u64)?;
+        if let Some(mut cache) = self._cache() {
+            if let Some(block) = cache.get(idx) {
+                read_trace::_record(&self.path, idx, true, &block);
+                return Ok(block);
+            }
+            let block = self._unstamp(&self._read_blocks(idx, 1)?, idx)?;
+            read_trace::_record(&self.path, idx, false, &block);
+            cache.put(idx, block.clone());
+            return Ok(block);
+        }
+
+        let block = self._unstamp(&self._read_blocks(idx, 1)?, idx)?;
+        read_trace::_record(&self.path, idx, false, &block);
         Ok(block)
     }
 
+    /// Maps the file to read the blocks without copying, see
+    /// **MappedBlocks**. The blocks with checksums, epoch stamps,
+    /// compression or encryption are not stored as they are read, so such
+    /// tables give an error of kind *Unsupported*.
+    #[cfg(feature = "mmap")]
+    pub fn mapped(&self) -> Result<MappedBlocks, io::Error> {
+        if self.compression.is_some() || self.epoch.is_some() ||
+                self.checksums || self._encrypted() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported, "mapped blocks"
+            ));
+        }
+        self.mapping.view(
+            &self.file, self.header_size, self.block_size, self.size()?
+        )
+    }
+
+    /// Gets bytes of several records by their indices keeping the order.
+    /// The blocks are read in the order of the file and neighbouring
+    /// blocks are read by a single call.
+    pub fn get_many(&self, idxs: &[usize]) -> Result<Vec<Vec<u8>>, io::Error> {
+        let mut order: Vec<usize> = (0..idxs.len()).collect();
+        order.sort_by_key(|pos| idxs[*pos]);
+
+        let mut blocks = vec![Vec::new(); idxs.len()];
+        let mut start = 0;
+
+        while start < order.len() {
+            let mut end = start + 1;
+            while (end < order.len()) &&
+                    (idxs[order[end]] <= idxs[order[end - 1]] + 1) {
+                end += 1;
+            }
+
+            let first = idxs[order[start]];
+            let count = idxs[order[end - 1]] - first + 1;
+            let chunk = self._read_blocks(first, count)?;
+
+            for pos in &order[start..end] {
+                let offset = (idxs[*pos] - first) * self.block_size;
+                blocks[*pos] = self._unstamp(
+                    &chunk[offset..offset + self.block_size], idxs[*pos]
+                )?;
+                read_trace::_record(
+                    &self.path, idxs[*pos], false, &blocks[*pos]
+                );
+            }
+
+            start = end;
+        }
+
+        Ok(blocks)
+    }
+
     /// Inserts data bytes to the end of file.
     pub fn append(&self, block: &[u8]) -> Result<usize, io::Error> {
-        let idx = self.size();
-        self.file.write_all_at(block, (idx * self.block_size) as u64)?;
+        let _write = self.lock.write();
+        let _extend = self._extend();
+        let idx = self.size()?;
+        self._write_block(block, idx)?;
+        self._mark_dirty(idx, ChangeKind::Append)?;
+        self._sign(idx)?;
         Ok(idx)
     }
 
+    /// Inserts **count** blocks to the end of file by a single write.
+    /// **fill** is called with the position in the batch and the index of
+    /// the block to get its data bytes. Returns the index of the first
+    /// block. The blocks of deleted records are not reused.
+    pub fn append_many(
+                &self,
+                count: usize,
+                mut fill: impl FnMut(usize, usize) -> Vec<u8>
+            ) -> Result<usize, io::Error> {
+        let _write = self.lock.write();
+        let _extend = self._extend();
+        let first = self.size()?;
+
+        let mut blocks = Vec::with_capacity(count * self.block_size);
+        for pos in 0..count {
+            let mut block = match &self.compression {
+                Some(compression) => compression.encode(
+                    &fill(pos, first + pos), self.block_size
+                )?,
+                None => self._seal(&fill(pos, first + pos), first + pos)?,
+            };
+            block.resize(self.block_size, 0);
+            blocks.extend_from_slice(&block);
+        }
+
+        if count > 0 {
+            self._write_raw_block(&blocks, first)?;
+        }
+        for idx in first..first + count {
+            self._mark_dirty(idx, ChangeKind::Append)?;
+            self._sign(idx)?;
+        }
+        Ok(first)
+    }
+
+    /// Inserts data bytes to the block of a deleted record if any, else to
+    /// the end of file. Returns the index of the block.
+    pub fn insert(&self, block: &[u8]) -> Result<usize, io::Error> {
+        if self._signed() {
+            return self.append(block);
+        }
+        match self.free.pop()? {
+            Some(idx) => {
+                self.update(block, idx)?;
+                Ok(idx)
+            },
+            None => self.append(block),
+        }
+    }
+
+    /// Allocates an id for a record (the index of its block plus one)
+    /// writing an empty block, so the record can be referenced before its
+    /// data is ready. The block of a deleted record is reused if any.
+    /// The record is written later by **update** with this id, till then it
+    /// is read back with **id** 0 like an erased one.
+    pub fn reserve_id(&self) -> Result<usize, io::Error> {
+        let zeros: Vec<u8> = vec![0; self._data_size()];
+        Ok(self.insert(&zeros)? + 1)
+    }
+
     /// Updates data bytes located by the index.
     pub fn update(
                 &self,
                 block: &[u8],
                 idx: usize
             ) -> Result<(), io::Error> {
-        self.file.write_all_at(block, (idx * self.block_size) as u64)?;
+        let _write = self.lock.write();
+        self._write_block(block, idx)?;
+        self._mark_dirty(idx, ChangeKind::Update)?;
+        Ok(())
+    }
+
+    /// Drops the records after the id **n** (the blocks from the index
+    /// **n** on) cutting the end of file, the header with the metadata is
+    /// kept. The deleted blocks below **n** stay free. The watchers (see
+    /// **watch**) get *Truncate* with **n**, so the indexes over the table
+    /// must be updated by the caller (see **Repository::truncate_to**).
+    /// An incremental backup does not record the cut, take a full one.
+    pub fn truncate_to(&self, n: usize) -> Result<(), io::Error> {
+        self._cut(n, true)
+    }
+
+    /// Drops all records keeping the header with the metadata, see
+    /// **truncate_to**.
+    pub fn clear(&self) -> Result<(), io::Error> {
+        self.truncate_to(0)
+    }
+
+    /// Overwrites the block located by the index with zeros, flushes it to
+    /// the disk and checks that no original bytes remain: neither in
+    /// the block, nor in the write-ahead log (**with_wal**), nor in
+    /// the overflow table of a compressed table (its block is erased too).
+    /// The copies outside the table files (e.g. backups) are not touched.
+    pub fn erase(&self, idx: usize) -> Result<(), io::Error> {
+        if idx >= self.size()? {
+            return Err(
+                io::Error::new(io::ErrorKind::NotFound, idx.to_string())
+            );
+        }
+
+        let _write = self.lock.write();
+        let slot = self._read_blocks(idx, 1)?;
+        let block = self.get(idx)?;
+        let zeros: Vec<u8> = vec![0; self._data_size()];
+        self._write_block(&zeros, idx)?;
+        self.file.sync_data()?;
+        self._mark_dirty(idx, ChangeKind::Erase)?;
+
+        if self.get(idx)? != zeros || self._keeps_copy(idx, &slot, &block)? {
+            return Err(io::Error::other("erase"));
+        }
+
+        Ok(())
+    }
+
+    /// Erases the block located by the index and keeps the index in
+    /// the free list (the file with *.free* extension), so the block is
+    /// reused by the next **insert**. Deleting an erased block is an error.
+    pub fn delete(&self, idx: usize) -> Result<(), io::Error> {
+        // The check and the push are done under the lock of the appends, so
+        // concurrent deletes of the same block cannot push it twice
+        let _write = self.lock.write();
+        let _extend = self._extend();
+
+        if idx >= self.size()? {
+            return Err(
+                io::Error::new(io::ErrorKind::NotFound, idx.to_string())
+            );
+        }
+
+        if self.get(idx)?.iter().all(|byte| *byte == 0) {
+            return Err(
+                io::Error::new(io::ErrorKind::NotFound, idx.to_string())
+            );
+        }
+
+        self.erase(idx)?;
+        self.free.push(idx)
+    }
+
+    /// The number of deleted blocks waiting to be reused.
+    pub fn free_count(&self) -> Result<usize, io::Error> {
+        self.free.len()
+    }
+
+    /// Tells the OS how the whole table is going to be accessed.
+    pub fn advise(&self, pattern: AccessPattern) -> Result<(), io::Error> {
+        self.advise_between(pattern, 0, self.size()?)
+    }
+
+    /// Tells the OS how the blocks between given indices
+    /// (**>= idx_from** and **< idx_to**) are going to be accessed.
+    /// It does nothing on the platforms without *posix_fadvise*.
+    pub fn advise_between(
+                &self,
+                pattern: AccessPattern,
+                idx_from: usize,
+                idx_to: usize
+            ) -> Result<(), io::Error> {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::unix::io::AsRawFd;
+
+            let advice = match pattern {
+                AccessPattern::Normal => libc::POSIX_FADV_NORMAL,
+                AccessPattern::Sequential => libc::POSIX_FADV_SEQUENTIAL,
+                AccessPattern::Random => libc::POSIX_FADV_RANDOM,
+                AccessPattern::WillNeed => libc::POSIX_FADV_WILLNEED,
+                AccessPattern::DontNeed => libc::POSIX_FADV_DONTNEED,
+            };
+            let offset = (self.header_size + idx_from * self.block_size)
+                as libc::off_t;
+            let len = (idx_to.saturating_sub(idx_from) * self.block_size)
+                as libc::off_t;
+
+            let code = unsafe {
+                libc::posix_fadvise(self.file.as_raw_fd(), offset, len, advice)
+            };
+            if code != 0 {
+                return Err(io::Error::from_raw_os_error(code));
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = (pattern, idx_from, idx_to);
+        }
+
+        Ok(())
+    }
+
+    /// Reads the record by **id** to change it through the returned guard.
+    /// The record is written back on **RecordGuard::commit**.
+    pub fn edit<T: TableTrait>(
+                &self,
+                id: usize
+            ) -> Result<RecordGuard<'_, T>, io::Error> {
+        Ok(RecordGuard::new(self, T::get(self, id)?))
+    }
+
+    /// Reads all records of a small table (e.g. a configuration) into
+    /// memory to change them freely and write back with **store_all**.
+    /// Erased records are skipped.
+    pub fn load_all<T: TableTrait>(&self) -> Result<Vec<T>, io::Error> {
+        let idxs: Vec<usize> = (0..self.size()?).collect();
+        let mut records = Vec::with_capacity(idxs.len());
+        for block in self.get_many(&idxs)? {
+            let record = T::from_bytes(&block);
+            if record.id() > 0 {
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+
+    /// Replaces the content of the table with **records** atomically: they
+    /// are written to a temporary file (**path.tmp**) that is renamed to
+    /// the table file, so a crash leaves either the old or the new table.
+    /// Each record is written by its id, the records without id get new
+    /// ids after the greatest one, the blocks of missing ids are erased.
+    /// The metadata of the header is kept, the deleted blocks given to
+    /// the records are removed from the free list.
+    pub fn store_all<T: TableTrait>(
+                &mut self,
+                records: &mut [T]
+            ) -> Result<(), io::Error> {
+        self._check_writable()?;
+
+        let mut next_id = records.iter().map(|rec| rec.id()).max().unwrap_or(0);
+        for record in records.iter_mut() {
+            if record.id() == 0 {
+                next_id += 1;
+                record.set_id(next_id);
+            }
+        }
+
+        let tmp_path = format!("{}.tmp", self.path);
+        let tmp_overflow_path = format!("{}.overflow", tmp_path);
+        for path in &[&tmp_path, &tmp_overflow_path] {
+            if fs::metadata(path).is_ok() {
+                fs::remove_file(path)?;
+            }
+        }
+        {
+            let mut tmp = Self::_open(
+                &tmp_path, self.block_size, self.alignment, self.direct
+            )?;
+            if let Some(epoch) = &self.epoch {
+                tmp.epoch = Some(AtomicU64::new(epoch.load(Ordering::SeqCst)));
+            }
+            tmp.checksums = self.checksums;
+            #[cfg(feature = "encryption")]
+            {
+                tmp.encryption = self.encryption.clone();
+            }
+            if let Some(compression) = &self.compression {
+                tmp.compression = Some(Compression::open(
+                    &tmp_path, compression.kind(), compression.data_size()
+                )?);
+            }
+            tmp._write_meta(&self.meta()?)?;
+
+            let zeros: Vec<u8> = vec![0; self._data_size()];
+            for idx in 0..next_id {
+                tmp._write_block(&zeros, idx)?;
+            }
+            for record in records.iter() {
+                tmp._write_block(record.as_bytes(), record.id() - 1)?;
+            }
+            tmp.file.sync_all()?;
+
+            if let (Some(epoch), Some(tmp_epoch)) = (&self.epoch, &tmp.epoch) {
+                epoch.store(tmp_epoch.load(Ordering::SeqCst), Ordering::SeqCst);
+            }
+        }
+
+        // The overflow blocks are written anew, so the old ones and their
+        // free list are replaced
+        if let Some(compression) = &self.compression {
+            let overflow_path = format!("{}.overflow", self.path);
+            let free_path = format!("{}.free", overflow_path);
+            if fs::metadata(&free_path).is_ok() {
+                fs::remove_file(&free_path)?;
+            }
+            fs::rename(&tmp_overflow_path, &overflow_path)?;
+            self.compression = Some(Compression::open(
+                &self.path, compression.kind(), compression.data_size()
+            )?);
+        }
+
+        // The lock is taken again as the file is a new one
+        #[cfg(feature = "mmap")]
+        let _mapping = self.mapping.release()?;
+        fs::rename(&tmp_path, &self.path)?;
+        let table = Self::_open_locked(
+            &self.path, self.block_size, self.alignment, self.direct,
+            self.file_lock
+        )?;
+        self.header_size = table.header_size;
+        self.meta_size = table.meta_size;
+        self.file = table.file;
+        if let Some(mut cache) = self._cache() {
+            cache.clear();
+        }
+
+        // The deleted blocks that got records are not free anymore
+        let stored: HashSet<usize> = records.iter()
+            .map(|record| record.id() - 1).collect();
+        self.free.retain(|idx| (idx < next_id) && !stored.contains(&idx))?;
+
+        for idx in 0..next_id {
+            self._mark_dirty(idx, ChangeKind::Update)?;
+        }
         Ok(())
     }
 
-    /// Iterates all records as data blocks.
-    pub fn iter(&self) -> Box<dyn Iterator<Item = Vec<u8>> + '_> {
-        self.iter_between(0, self.size()).unwrap()
+    /// Calls **watcher** with the index of the block and the kind of
+    /// the write after each finished write to a block, in the thread that
+    /// wrote it. It must be quick as the writes of the table wait for it.
+    /// The watchers are kept until the table is dropped.
+    pub fn watch(
+                &self,
+                watcher: impl Fn(usize, ChangeKind) + Send + Sync + 'static
+            ) {
+        self.watchers.add(watcher);
+    }
+
+    /// Blocks the writes from other threads of the process until the
+    /// returned guard is dropped, the current thread writes as usual.
+    /// It is useful for maintenance like compaction or migration.
+    pub fn exclusive(&self) -> ExclusiveGuard<'_> {
+        self.lock.exclusive()
+    }
+
+    /// Writes all blocks to **writer** and starts tracking the changes for
+    /// the next incremental backup. Returns the token of the backup.
+    pub fn backup_full(
+                &self,
+                writer: &mut dyn io::Write
+            ) -> Result<u64, io::Error> {
+        let token = self._dirty_map()?.token()? + 1;
+        self._write_backup(writer, false, None)?;
+        self._dirty_map()?.reset(token)?;
+        Ok(token)
+    }
+
+    /// Writes all blocks to **writer** like **backup_full** reporting
+    /// the *backup* stage to **progress**.
+    pub fn backup_full_with_progress(
+                &self,
+                writer: &mut dyn io::Write,
+                progress: &ProgressHandle
+            ) -> Result<u64, io::Error> {
+        let token = self._dirty_map()?.token()? + 1;
+        self._write_backup(writer, false, Some(progress))?;
+        self._dirty_map()?.reset(token)?;
+        Ok(token)
+    }
+
+    /// Writes the blocks changed since the backup with **since_token** to
+    /// **writer**. Returns the token of the new backup.
+    pub fn backup_incremental(
+                &self,
+                writer: &mut dyn io::Write,
+                since_token: u64
+            ) -> Result<u64, io::Error> {
+        let token = self._dirty_map()?.token()?;
+        if token != since_token {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput, since_token.to_string()
+            ));
+        }
+        self._write_backup(writer, true, None)?;
+        self._dirty_map()?.reset(token + 1)?;
+        Ok(token + 1)
+    }
+
+    /// Writes the records to a sealed read-only **Snapshot** file located
+    /// by **path** for the readers that map it into memory: the erased
+    /// blocks are dropped, the records are sorted by id and the file is
+    /// checksummed. The file must not exist.
+    pub fn export_snapshot_mmapable(
+                &self,
+                path: &str
+            ) -> Result<SnapshotHeader, io::Error> {
+        Snapshot::_export(self, self._data_size(), path)
+    }
+
+    /// Applies a full or an incremental backup from **reader** to the table.
+    /// Returns the number of restored blocks.
+    pub fn restore_backup(
+                &self,
+                reader: &mut dyn io::Read
+            ) -> Result<usize, io::Error> {
+        let mut bytes = [0u8; 8];
+        reader.read_exact(&mut bytes)?;
+        if u64::from_le_bytes(bytes) as usize != self._data_size() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData, "block size"
+            ));
+        }
+
+        let mut block: Vec<u8> = vec![0; self._data_size()];
+        let mut count = 0;
+
+        loop {
+            match reader.read_exact(&mut bytes) {
+                Ok(()) => {},
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                    break;
+                },
+                Err(err) => {
+                    return Err(err);
+                },
+            }
+            reader.read_exact(&mut block)?;
+            self.update(&block, u64::from_le_bytes(bytes) as usize)?;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Reads all blocks skipping the cache and returns the indices of
+    /// the blocks that fail the check of their checksums (see
+    /// **with_checksums**) or epoch stamps.
+    pub fn verify(&self) -> Result<Vec<usize>, io::Error> {
+        let size = self.size()?;
+        let mut corrupt = Vec::new();
+        let mut first = 0;
+
+        while first < size {
+            let count = (size - first).min(VERIFY_CHUNK_SIZE);
+            let chunk = self._read_blocks(first, count)?;
+            for (pos, block) in chunk.chunks(self.block_size).enumerate() {
+                match self._unstamp(block, first + pos) {
+                    Ok(_) => {},
+                    Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                        corrupt.push(first + pos);
+                    },
+                    Err(err) => {
+                        return Err(err);
+                    },
+                }
+            }
+            first += count;
+        }
+
+        Ok(corrupt)
+    }
+
+    /// Checks the table fast to fail on a corrupted file at start: the header
+    /// is read again and compared with the table, the file must end with
+    /// a whole block and, depending on **depth**, the checksums or epoch
+    /// stamps of a sample of blocks or of all blocks are checked (see
+    /// **verify**). A wrong header is an error, the other findings are
+    /// reported.
+    pub fn quick_check(
+                &self,
+                depth: CheckDepth
+            ) -> Result<QuickCheckReport, TableError> {
+        self._recheck_header()?;
+
+        let len = self.file.metadata()?.len() as usize;
+        let mut report = QuickCheckReport {
+            trailing_bytes: (len - self.header_size) % self.block_size,
+            ..QuickCheckReport::default()
+        };
+
+        let size = self.size()?;
+        let idxs = match depth {
+            CheckDepth::Header => Vec::new(),
+            CheckDepth::Sample(count) => _sample(size, count),
+            CheckDepth::Deep => {
+                report.checked = size;
+                report.corrupt = self.verify()?;
+                return Ok(report);
+            },
+        };
+
+        for idx in idxs {
+            match self._unstamp(&self._read_blocks(idx, 1)?, idx) {
+                Ok(_) => {},
+                Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                    report.corrupt.push(idx);
+                },
+                Err(err) => {
+                    return Err(err.into());
+                },
+            }
+            report.checked += 1;
+        }
+
+        Ok(report)
+    }
+
+    /// Verifies the chain of the ledger of the table opened by
+    /// **with_ledger**, see **TableLedger::verify_chain**. A table without
+    /// the ledger returns an error of kind *Unsupported*.
+    #[cfg(feature = "ledger")]
+    pub fn verify_ledger(&self) -> Result<(), io::Error> {
+        match &self.ledger {
+            Some(ledger) => ledger.verify(self),
+            None => Err(io::Error::new(io::ErrorKind::Unsupported, "ledger")),
+        }
+    }
+
+    /// Iterates all records as data blocks. A block that fails to read
+    /// (e.g. **TornWrite** or **CorruptBlock**) is yielded as the error and
+    /// the iteration goes on with the next one.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = BlockResult> + '_> {
+        match self.size() {
+            Ok(size) => Box::new(iter::from_fn(self._next_block(0, size))),
+            Err(err) => Box::new(iter::once(Err(err))),
+        }
     }
 
     /// Iterates records as data blocks between given indices
-    /// (**>= idx_from** and **< idx_to**).
+    /// (**>= idx_from** and **< idx_to**), see **iter**.
     pub fn iter_between(
                 &self,
                 idx_from: usize,
                 idx_to: usize
-            ) -> Result<
-                Box<dyn Iterator<Item = Vec<u8>> + '_>,
-                io::Error
-            > {
-        let mut idx = idx_from;
+            ) -> Result<Box<dyn Iterator<Item = BlockResult> + '_>, io::Error> {
+        Ok(Box::new(iter::from_fn(self._next_block(idx_from, idx_to))))
+    }
 
-        Ok(Box::new(iter::from_fn(move || {
-            let result;
-            if idx < idx_to {
-                let block = self.get(idx).unwrap();
-                result = Some(block);
-                idx += 1;
-            } else {
-                result = None;
-            }
-            result
-        })))
+    /// Iterates the page of at most **limit** data blocks starting from
+    /// the index **offset**. The blocks before the page are not read, so
+    /// a page is as cheap at the end of the table as at its start. The page
+    /// is shorter or empty at the end of the table.
+    pub fn iter_page(
+                &self,
+                offset: usize,
+                limit: usize
+            ) -> Result<Box<dyn Iterator<Item = BlockResult> + '_>, io::Error> {
+        let size = self.size()?;
+        let idx_from = offset.min(size);
+        let idx_to = idx_from.saturating_add(limit).min(size);
+        self.iter_between(idx_from, idx_to)
     }
 
     /// Finds an index of a first block that has the given **value**.
@@ -99,22 +1199,729 @@ impl Table {
     pub fn find_sorted<T: PartialOrd>(
                 &self,
                 value: T,
-                get_value: &dyn Fn(&[u8]
-            ) -> T) -> usize {
+                get_value: &dyn Fn(&[u8]) -> T
+            ) -> Result<usize, io::Error> {
         let mut idx = 0;
-        let mut size = self.size();
+        let mut size = self.size()?;
 
         while size > 0 {
-            let block = self.get(idx + size / 2).unwrap();
+            let block = self.get(idx + size / 2)?;
 
             if value > get_value(&block) {
                 idx += size / 2 + 1;
                 size = size / 2 + size % 2 - 1;
             } else {
-                size = size / 2;
+                size /= 2;
+            }
+        }
+
+        Ok(idx)
+    }
+
+    fn _next_block(
+                &self,
+                idx_from: usize,
+                idx_to: usize
+            ) -> impl FnMut() -> Option<BlockResult> + '_ {
+        let mut idx = idx_from;
+        move || {
+            if idx < idx_to {
+                idx += 1;
+                Some(self.get(idx - 1))
+            } else {
+                None
             }
         }
+    }
 
-        idx
+    fn _open(
+                path: &str,
+                record_size: usize,
+                alignment: usize,
+                direct: bool
+            ) -> Result<Self, TableError> {
+        Self::_open_locked(path, record_size, alignment, direct, FileLock::None)
     }
+
+    fn _open_locked(
+                path: &str,
+                record_size: usize,
+                alignment: usize,
+                direct: bool,
+                lock: FileLock
+            ) -> Result<Self, TableError> {
+        if alignment == 0 {
+            return Err(TableError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput, "alignment"
+            )));
+        }
+
+        let mut options = fs::OpenOptions::new();
+        options.read(true).write(true).create(lock != FileLock::Shared);
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            if direct {
+                options.custom_flags(libc::O_DIRECT);
+            }
+        }
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::OpenOptionsExt;
+            // FILE_FLAG_NO_BUFFERING
+            if direct {
+                options.custom_flags(0x20000000);
+            }
+        }
+        let file = options.open(path)?;
+        if lock != FileLock::None {
+            file.lock_advisory(lock == FileLock::Exclusive).map_err(
+                |err| match err.kind() {
+                    io::ErrorKind::WouldBlock => TableError::Locked,
+                    _ => TableError::Io(err),
+                }
+            )?;
+        }
+        #[cfg(target_os = "macos")]
+        {
+            use std::os::unix::io::AsRawFd;
+            if direct {
+                unsafe { libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1) };
+            }
+        }
+
+        // The header takes whole blocks, so the blocks stay aligned
+        let block_size = record_size.div_ceil(alignment) * alignment;
+        let mut table = Self {
+            path: path.to_string(),
+            header_size: (TABLE_HEADER_SIZE + TABLE_META_SIZE)
+                .div_ceil(block_size) * block_size,
+            meta_size: TABLE_META_SIZE,
+            block_size,
+            alignment,
+            direct,
+            file_lock: lock,
+            epoch: None,
+            checksums: false,
+            file,
+            dirty: None,
+            free: FreeList::open(&format!("{}.free", path))?,
+            wal: None,
+            cache: None,
+            compression: None,
+            #[cfg(feature = "encryption")]
+            encryption: None,
+            #[cfg(feature = "ledger")]
+            ledger: None,
+            extend: Mutex::new(()),
+            meta: Mutex::new(()),
+            watchers: Watchers::default(),
+            #[cfg(feature = "mmap")]
+            mapping: Mapping::default(),
+            lock: TableLock::default()
+        };
+        table._check_header()?;
+
+        // A reader must not write the table, the writes are replayed
+        // under the exclusive lock of a writer
+        let wal_path = format!("{}.wal", path);
+        if lock == FileLock::Shared {
+            if fs::metadata(&wal_path).map(|meta| meta.len() > 0)
+                    .unwrap_or(false) {
+                return Err(TableError::PendingLog);
+            }
+        } else if fs::metadata(&wal_path).is_ok() {
+            table._replay_wal(&WriteAheadLog::open(&wal_path)?)?;
+        }
+
+        Ok(table)
+    }
+
+    fn _recheck_header(&self) -> Result<(), TableError> {
+        // There is nothing to check in a file without the header
+        if self.header_size == 0 {
+            return Ok(());
+        }
+
+        let len = self.file.metadata()?.len() as usize;
+        let size = TABLE_HEADER_SIZE.div_ceil(self.alignment) * self.alignment;
+        let mut buffer = vec![0; size + self.alignment];
+        let header = self._aligned(&mut buffer, size);
+        if (len < self.header_size) || (len < size) ||
+                self.file.read_exact_at(header, 0).is_err() ||
+                (&header[..8] != TABLE_MAGIC) {
+            return Err(
+                TableError::InvalidHeader("not a table file".to_string())
+            );
+        }
+
+        let field = |pos: usize| {
+            let mut bytes = [0u8; 8];
+            bytes.clone_from_slice(&header[8 * pos..8 * pos + 8]);
+            u64::from_le_bytes(bytes)
+        };
+
+        // Version 1 has no metadata area
+        let version = if self.meta_size == 0 { 1 } else { TABLE_VERSION };
+        if field(1) != version {
+            return Err(TableError::InvalidHeader(
+                format!("table version {}", field(1))
+            ));
+        }
+        if field(2) != self.block_size as u64 {
+            return Err(TableError::BlockSize {
+                stored: field(2) as usize,
+                expected: self.block_size,
+            });
+        }
+        Ok(())
+    }
+
+    fn _check_header(&mut self) -> Result<(), TableError> {
+        let len = self.file.metadata()?.len() as usize;
+
+        if len == 0 {
+            // The metadata area is empty as it is filled with zeros
+            let mut buffer = vec![0; self.header_size + self.alignment];
+            let header = self._aligned(&mut buffer, self.header_size);
+            header[..8].clone_from_slice(TABLE_MAGIC);
+            header[8..16].clone_from_slice(&TABLE_VERSION.to_le_bytes());
+            header[16..24].clone_from_slice(
+                &(self.block_size as u64).to_le_bytes()
+            );
+            self.file.write_all_at(header, 0)?;
+            return Ok(());
+        }
+
+        // The fields are read first as the size of the header depends on
+        // the version
+        let size = TABLE_HEADER_SIZE.div_ceil(self.alignment) * self.alignment;
+        let mut buffer = vec![0; size + self.alignment];
+        let header = self._aligned(&mut buffer, size);
+        if (len < size) || self.file.read_exact_at(header, 0).is_err() ||
+                (&header[..8] != TABLE_MAGIC) {
+            // The files created before the header have the blocks from
+            // the start of the file, so its length is a multiple of
+            // the block size. They are read as version 1 without
+            // the header until they are rewritten by **store_all**.
+            if len.is_multiple_of(self.block_size) {
+                self.header_size = 0;
+                self.meta_size = 0;
+                return Ok(());
+            }
+            return Err(
+                TableError::InvalidHeader("not a table file".to_string())
+            );
+        }
+
+        let field = |pos: usize| {
+            let mut bytes = [0u8; 8];
+            bytes.clone_from_slice(&header[8 * pos..8 * pos + 8]);
+            u64::from_le_bytes(bytes)
+        };
+
+        match field(1) {
+            TABLE_VERSION => {},
+            // Version 1 has no metadata area
+            1 => {
+                self.header_size = TABLE_HEADER_SIZE.div_ceil(self.block_size)
+                    * self.block_size;
+                self.meta_size = 0;
+            },
+            version => {
+                return Err(TableError::InvalidHeader(
+                    format!("table version {}", version)
+                ));
+            },
+        }
+        if field(2) != self.block_size as u64 {
+            return Err(TableError::BlockSize {
+                stored: field(2) as usize,
+                expected: self.block_size,
+            });
+        }
+        if len < self.header_size {
+            return Err(
+                TableError::InvalidHeader("not a table file".to_string())
+            );
+        }
+
+        Ok(())
+    }
+
+    fn _read_meta(&self) -> Result<Vec<(String, String)>, io::Error> {
+        if self.meta_size == 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut buffer = vec![0; self.header_size + self.alignment];
+        let header = self._aligned(&mut buffer, self.header_size);
+        self.file.read_exact_at(header, 0)?;
+        let area = &header[
+            TABLE_HEADER_SIZE..TABLE_HEADER_SIZE + self.meta_size
+        ];
+
+        // Each entry is the length of the key (1 byte), the key, the length
+        // of the value (2 bytes LE) and the value, a zero length ends them
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "metadata");
+        let mut entries = Vec::new();
+        let mut pos = 0;
+        while (pos < area.len()) && (area[pos] != 0) {
+            let key_end = pos + 1 + area[pos] as usize;
+            if key_end + 2 > area.len() {
+                return Err(invalid());
+            }
+            let value_size = u16::from_le_bytes(
+                [area[key_end], area[key_end + 1]]
+            ) as usize;
+            let value_end = key_end + 2 + value_size;
+            if value_end > area.len() {
+                return Err(invalid());
+            }
+            let key = String::from_utf8(area[pos + 1..key_end].to_vec())
+                .map_err(|_| invalid())?;
+            let value = String::from_utf8(area[key_end + 2..value_end].to_vec())
+                .map_err(|_| invalid())?;
+            entries.push((key, value));
+            pos = value_end;
+        }
+
+        Ok(entries)
+    }
+
+    fn _write_meta(
+                &self,
+                entries: &[(String, String)]
+            ) -> Result<(), io::Error> {
+        let mut area = Vec::new();
+        for (key, value) in entries.iter() {
+            area.push(key.len() as u8);
+            area.extend_from_slice(key.as_bytes());
+            area.extend_from_slice(&(value.len() as u16).to_le_bytes());
+            area.extend_from_slice(value.as_bytes());
+        }
+        if area.len() > self.meta_size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput, "metadata does not fit the header"
+            ));
+        }
+        area.resize(self.meta_size, 0);
+
+        let mut buffer = vec![0; self.header_size + self.alignment];
+        let header = self._aligned(&mut buffer, self.header_size);
+        self.file.read_exact_at(header, 0)?;
+        header[TABLE_HEADER_SIZE..TABLE_HEADER_SIZE + self.meta_size]
+            .clone_from_slice(&area);
+        self.file.write_all_at(header, 0)
+    }
+
+    fn _meta(&self) -> MutexGuard<'_, ()> {
+        // The metadata is read from the file under the lock
+        self.meta.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn _aligned<'b>(
+                &self,
+                buffer: &'b mut [u8],
+                size: usize
+            ) -> &'b mut [u8] {
+        let offset = buffer.as_ptr().align_offset(self.alignment);
+        &mut buffer[offset..offset + size]
+    }
+
+    fn _read_blocks(
+                &self,
+                idx: usize,
+                count: usize
+            ) -> Result<Vec<u8>, io::Error> {
+        let offset = (self.header_size + idx * self.block_size) as u64;
+        let size = count * self.block_size;
+
+        if self.direct {
+            let mut buffer = vec![0; size + self.alignment];
+            let blocks = self._aligned(&mut buffer, size);
+            self.file.read_exact_at(blocks, offset)?;
+            return Ok(blocks.to_vec());
+        }
+
+        let mut blocks: Vec<u8> = vec![0; size];
+        self.file.read_exact_at(&mut blocks, offset)?;
+        Ok(blocks)
+    }
+
+    fn _data_size(&self) -> usize {
+        if let Some(compression) = &self.compression {
+            return compression.data_size();
+        }
+        #[cfg(feature = "encryption")]
+        {
+            if let Some(encryption) = &self.encryption {
+                return encryption.data_size();
+            }
+        }
+        if self.checksums {
+            return self.block_size - CHECKSUM_SIZE;
+        }
+        match self.epoch {
+            Some(_) => self.block_size - 2 * EPOCH_SIZE,
+            None => self.block_size,
+        }
+    }
+
+    fn _unstamp(&self, block: &[u8], idx: usize) -> Result<Vec<u8>, io::Error> {
+        if let Some(compression) = &self.compression {
+            return compression.decode(block);
+        }
+        #[cfg(feature = "encryption")]
+        {
+            if let Some(encryption) = &self.encryption {
+                return encryption.open(block, idx);
+            }
+        }
+        if self.checksums {
+            let tail = self.block_size - CHECKSUM_SIZE;
+            if (_crc32(&block[..tail]).to_le_bytes() != block[tail..]) &&
+                    block.iter().any(|byte| *byte != 0) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData, CorruptBlock { idx }
+                ));
+            }
+            return Ok(block[..tail].to_vec());
+        }
+        if self.epoch.is_none() {
+            return Ok(block.to_vec());
+        }
+
+        let tail = self.block_size - EPOCH_SIZE;
+        if block[..EPOCH_SIZE] != block[tail..] {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData, TornWrite { idx }
+            ));
+        }
+
+        Ok(block[EPOCH_SIZE..tail].to_vec())
+    }
+
+    fn _keeps_copy(
+                &self,
+                idx: usize,
+                slot: &[u8],
+                block: &[u8]
+            ) -> Result<bool, io::Error> {
+        if block.iter().all(|byte| *byte == 0) {
+            return Ok(false);
+        }
+
+        if let Some(wal) = &self.wal {
+            let entries = wal.entries()?;
+            if entries.iter().any(|(pos, bytes)| *pos == idx && bytes == slot) {
+                return Ok(true);
+            }
+        }
+
+        match &self.compression {
+            Some(compression) => compression.keeps(slot, block),
+            None => Ok(false),
+        }
+    }
+
+    fn _write_block(&self, block: &[u8], idx: usize) -> Result<(), io::Error> {
+        if let Some(compression) = &self.compression {
+            self._check_writable()?;
+
+            // The overflow block of the replaced slot is released after
+            // the new slot is written
+            let old = match idx < self.size()? {
+                true => Some(self._read_blocks(idx, 1)?),
+                false => None,
+            };
+            self._write_raw_block(
+                &compression.encode(block, self.block_size)?, idx
+            )?;
+            if let Some(old) = old {
+                compression.release(&old)?;
+            }
+            return Ok(());
+        }
+
+        if self.epoch.is_some() || self.checksums || self._encrypted() {
+            return self._write_raw_block(&self._seal(block, idx)?, idx);
+        }
+
+        self._write_raw_block(block, idx)
+    }
+
+    #[cfg_attr(not(feature = "encryption"), allow(unused_variables))]
+    fn _seal(&self, block: &[u8], idx: usize) -> Result<Vec<u8>, io::Error> {
+        // The index is authenticated with the encrypted block
+        #[cfg(feature = "encryption")]
+        {
+            if let Some(encryption) = &self.encryption {
+                return encryption.seal(block, idx);
+            }
+        }
+        Ok(self._stamp(block))
+    }
+
+    #[cfg_attr(not(feature = "ledger"), allow(unused_variables))]
+    fn _sign(&self, idx: usize) -> Result<(), io::Error> {
+        #[cfg(feature = "ledger")]
+        {
+            if let Some(ledger) = &self.ledger {
+                return ledger.sign(self, idx + 1);
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn _signed(&self) -> bool {
+        #[cfg(feature = "ledger")]
+        {
+            self.ledger.is_some()
+        }
+        #[cfg(not(feature = "ledger"))]
+        {
+            false
+        }
+    }
+
+    fn _encrypted(&self) -> bool {
+        #[cfg(feature = "encryption")]
+        {
+            self.encryption.is_some()
+        }
+        #[cfg(not(feature = "encryption"))]
+        {
+            false
+        }
+    }
+
+    fn _stamp(&self, block: &[u8]) -> Vec<u8> {
+        if self.checksums {
+            let mut stamped = block.to_vec();
+            stamped.resize(self.block_size - CHECKSUM_SIZE, 0);
+            let checksum = _crc32(&stamped);
+            stamped.extend_from_slice(&checksum.to_le_bytes());
+            return stamped;
+        }
+        match &self.epoch {
+            Some(epoch) => {
+                let stamp = epoch.fetch_add(1, Ordering::SeqCst).to_le_bytes();
+                let mut stamped = stamp.to_vec();
+                stamped.extend_from_slice(block);
+                stamped.resize(self.block_size - EPOCH_SIZE, 0);
+                stamped.extend_from_slice(&stamp);
+                stamped
+            },
+            None => block.to_vec(),
+        }
+    }
+
+    fn _write_raw_block(
+                &self,
+                block: &[u8],
+                idx: usize
+            ) -> Result<(), io::Error> {
+        self._check_writable()?;
+
+        // The cache is locked until the blocks are written, so a reader
+        // cannot put their old content back
+        if let Some(mut cache) = self._cache() {
+            let count = block.len().div_ceil(self.block_size);
+            for pos in idx..idx + count.max(1) {
+                cache.remove(pos);
+            }
+            return self._write_logged_block(block, idx);
+        }
+
+        self._write_logged_block(block, idx)
+    }
+
+    fn _write_logged_block(
+                &self,
+                block: &[u8],
+                idx: usize
+            ) -> Result<(), io::Error> {
+        match &self.wal {
+            Some(wal) => wal.write(idx, block, || {
+                self._write_file_block(block, idx)?;
+                self.file.sync_data()
+            }),
+            None => self._write_file_block(block, idx),
+        }
+    }
+
+    fn _write_file_block(
+                &self,
+                block: &[u8],
+                idx: usize
+            ) -> Result<(), io::Error> {
+        let offset = (self.header_size + idx * self.block_size) as u64;
+
+        if !self.direct && block.len().is_multiple_of(self.block_size) {
+            return self.file.write_all_at(block, offset);
+        }
+
+        // Several blocks can be written at once, the last one is padded
+        let len = block.len().max(1);
+        let size = len.div_ceil(self.block_size) * self.block_size;
+        let mut buffer = vec![0; size + self.alignment];
+        let aligned = self._aligned(&mut buffer, size);
+        aligned[..block.len()].clone_from_slice(block);
+        self.file.write_all_at(aligned, offset)
+    }
+
+    /// Cuts the blocks from **size** to the end of file and forgets
+    /// the free blocks, so the blocks below **size** must be in use.
+    pub(crate) fn _truncate(&self, size: usize) -> Result<(), io::Error> {
+        self._cut(size, false)
+    }
+
+    fn _cut(&self, size: usize, keep_free: bool) -> Result<(), io::Error> {
+        self._check_writable()?;
+
+        let _write = self.lock.write();
+        let _extend = self._extend();
+        let old_size = self.size()?;
+        if size >= old_size {
+            return Ok(());
+        }
+
+        #[cfg(feature = "mmap")]
+        let _mapping = self.mapping.release()?;
+        if let Some(compression) = &self.compression {
+            for idx in size..old_size {
+                compression.release(&self._read_blocks(idx, 1)?)?;
+            }
+        }
+        match keep_free {
+            true => self.free.retain_below(size)?,
+            false => self.free.clear()?,
+        }
+        self.file.set_len((self.header_size + size * self.block_size) as u64)?;
+        if let Some(mut cache) = self._cache() {
+            for idx in size..old_size {
+                cache.remove(idx);
+            }
+        }
+        self.watchers.notify(size, ChangeKind::Truncate);
+        Ok(())
+    }
+
+    fn _extend(&self) -> MutexGuard<'_, ()> {
+        // The size is read from the file under the lock, so the lock keeps
+        // no state to be broken by a panicked thread
+        self.extend.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn _check_writable(&self) -> Result<(), io::Error> {
+        if self.file_lock == FileLock::Shared {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied, "shared table"
+            ));
+        }
+        Ok(())
+    }
+
+    fn _cache(&self) -> Option<MutexGuard<'_, BlockCache>> {
+        // The cached blocks are only a copy of the file, so they are used
+        // even if a thread panicked holding the lock
+        self.cache.as_ref().map(
+            |cache| cache.lock().unwrap_or_else(PoisonError::into_inner)
+        )
+    }
+
+    fn _replay_wal(&self, wal: &WriteAheadLog) -> Result<(), io::Error> {
+        let entries = wal.entries()?;
+        for (idx, block) in &entries {
+            self._write_file_block(block, *idx)?;
+        }
+        if !entries.is_empty() {
+            self.file.sync_data()?;
+        }
+        wal.clear()
+    }
+
+    fn _mark_dirty(
+                &self,
+                idx: usize,
+                kind: ChangeKind
+            ) -> Result<(), io::Error> {
+        if let Some(dirty) = &self.dirty {
+            dirty.mark(idx)?;
+        }
+        self.watchers.notify(idx, kind);
+        Ok(())
+    }
+
+    fn _dirty_map(&self) -> Result<&DirtyMap, io::Error> {
+        self.dirty.as_ref().ok_or_else(
+            || io::Error::new(io::ErrorKind::Unsupported, "dirty map")
+        )
+    }
+
+    fn _write_backup(
+                &self,
+                writer: &mut dyn io::Write,
+                only_dirty: bool,
+                progress: Option<&ProgressHandle>
+            ) -> Result<(), io::Error> {
+        writer.write_all(&(self._data_size() as u64).to_le_bytes())?;
+
+        if let Some(progress) = progress {
+            progress.start("backup", Some(self.size()?));
+        }
+
+        for idx in 0..self.size()? {
+            if !only_dirty || self._dirty_map()?.is_dirty(idx)? {
+                writer.write_all(&(idx as u64).to_le_bytes())?;
+                writer.write_all(&self.get(idx)?)?;
+            }
+            if let Some(progress) = progress {
+                progress.advance(1, self._data_size() as u64);
+            }
+        }
+
+        if let Some(progress) = progress {
+            progress.finish();
+        }
+
+        Ok(())
+    }
+}
+
+
+/// **count** indices of blocks spread evenly over **size** blocks,
+/// the last one included.
+fn _sample(size: usize, count: usize) -> Vec<usize> {
+    match count {
+        0 => Vec::new(),
+        1 if size > 0 => vec![size - 1],
+        _ if count >= size => (0..size).collect(),
+        _ => (0..count).map(|pos| pos * (size - 1) / (count - 1)).collect(),
+    }
+}
+
+
+const fn _crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut crc = byte as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { 0xedb88320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[byte] = crc;
+        byte += 1;
+    }
+    table
+}
+
+
+fn _crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, byte| {
+        CRC32_TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
 }
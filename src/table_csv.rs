@@ -0,0 +1,240 @@
+use std::io;
+
+use crate::bytes::*;
+use crate::varchar::*;
+use crate::field_string::*;
+use crate::table::*;
+use crate::table_trait::*;
+use crate::table_import::*;
+
+
+/// CsvField converts a field of the record to a CSV value and back.
+/// It is implemented for the numbers, **bool**, **Varchar** (as a string)
+/// and **Bytes** (as a hex string) by **FieldString**, the numbers are
+/// parsed ignoring the surrounding spaces.
+pub trait CsvField: Sized {
+    /// Renders the field as a CSV value.
+    fn to_csv(&self) -> String;
+
+    /// Parses the field from a CSV value, an error is of kind
+    /// *InvalidData*.
+    fn from_csv(value: &str) -> Result<Self, io::Error>;
+}
+
+
+macro_rules! csv_field_from_str {
+    ($($ty:ty),*) => {
+        $(
+            impl CsvField for $ty {
+                fn to_csv(&self) -> String {
+                    self.to_field_string()
+                }
+
+                fn from_csv(value: &str) -> Result<Self, io::Error> {
+                    <$ty>::from_field_string(value.trim())
+                }
+            }
+        )*
+    };
+}
+
+
+csv_field_from_str!(
+    u8, u16, u32, u64, usize, i8, i16, i32, i64, isize, f32, f64, bool
+);
+
+
+impl<const N: usize> CsvField for Varchar<N> {
+    fn to_csv(&self) -> String {
+        self.to_field_string()
+    }
+
+    fn from_csv(value: &str) -> Result<Self, io::Error> {
+        Self::from_field_string(value)
+    }
+}
+
+
+impl<const N: usize> CsvField for Bytes<N> {
+    fn to_csv(&self) -> String {
+        self.to_field_string()
+    }
+
+    fn from_csv(value: &str) -> Result<Self, io::Error> {
+        Self::from_field_string(value)
+    }
+}
+
+
+/// CsvRecord lists the fields of the record for CSV, so the table can be
+/// dumped to CSV and restored from it (e.g. for debugging or a migration).
+/// The first column is expected to be the id.
+pub trait CsvRecord: TableTrait {
+    /// The names of the columns, written as the header row.
+    const FIELDS: &'static [&'static str];
+
+    /// Renders the fields in the order of **FIELDS**.
+    fn to_csv_row(&self) -> Vec<String>;
+
+    /// Parses the record from the values in the order of **FIELDS**.
+    fn from_csv_row(row: &[&str]) -> Result<Self, io::Error>;
+
+    /// Writes the header and the records of the table skipping the erased
+    /// ones. Returns the number of written records.
+    fn export_csv(
+                table: &Table,
+                writer: impl io::Write
+            ) -> Result<usize, io::Error> {
+        let mut csv = csv::Writer::from_writer(writer);
+        csv.write_record(Self::FIELDS)?;
+
+        let mut count = 0;
+        for record in Self::all(table) {
+            let record = record?;
+            if record.id() == 0 {
+                continue;
+            }
+            csv.write_record(record.to_csv_row())?;
+            count += 1;
+        }
+
+        csv.flush()?;
+        Ok(count)
+    }
+
+    /// Reads the records written by **export_csv** and imports them keeping
+    /// their ids like **import_records**. The header must match **FIELDS**.
+    fn import_csv(
+                table: &Table,
+                reader: impl io::Read,
+                policy: ImportPolicy
+            ) -> Result<ImportReport, io::Error> {
+        let mut csv = csv::Reader::from_reader(reader);
+
+        let header = csv.headers().map_err(_csv_error)?;
+        if !header.iter().eq(Self::FIELDS.iter().copied()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData, "csv header"
+            ));
+        }
+
+        let mut records = Vec::new();
+        for row in csv.records() {
+            let row = row.map_err(_csv_error)?;
+            let values: Vec<&str> = row.iter().collect();
+            records.push(Self::from_csv_row(&values)?);
+        }
+
+        import_records(table, records.into_iter(), policy)
+    }
+}
+
+
+fn _csv_error(err: csv::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    const TABLE_PATH: &str = "test-csv-person.tbl";
+    const TABLE_IMPORTED_PATH: &str = "test-csv-person-imported.tbl";
+
+    #[derive(Debug, Copy, Clone)]
+    struct Person {
+        id: usize,
+        name: Varchar<20>,
+        age: u32,
+    }
+
+    impl TableTrait for Person {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    impl CsvRecord for Person {
+        const FIELDS: &'static [&'static str] = &["id", "name", "age"];
+
+        fn to_csv_row(&self) -> Vec<String> {
+            vec![self.id.to_csv(), self.name.to_csv(), self.age.to_csv()]
+        }
+
+        fn from_csv_row(row: &[&str]) -> Result<Self, io::Error> {
+            if row.len() != Self::FIELDS.len() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "row"));
+            }
+            Ok(Self {
+                id: usize::from_csv(row[0])?,
+                name: Varchar::<20>::from_csv(row[1])?,
+                age: u32::from_csv(row[2])?,
+            })
+        }
+    }
+
+    #[test]
+    fn test_csv() {
+        _ensure_removed_tables();
+
+        let table = Table::open::<Person>(TABLE_PATH).unwrap();
+        for (name, age) in &[("alex", 32), ("bob, jr.", 25), ("carl", 40)] {
+            let mut person = Person {
+                id: 0, name: Varchar::<20>::new(name), age: *age
+            };
+            person.insert(&table).unwrap();
+        }
+        Person::erase(&table, 3).unwrap();
+
+        // Export
+        let mut buffer = Vec::new();
+        assert_eq!(Person::export_csv(&table, &mut buffer).unwrap(), 2);
+        let text = String::from_utf8(buffer.clone()).unwrap();
+        assert_eq!(text, "id,name,age\n1,alex,32\n2,\"bob, jr.\",25\n");
+
+        // Import
+        let imported = Table::open::<Person>(TABLE_IMPORTED_PATH).unwrap();
+        let report = Person::import_csv(
+            &imported, &buffer[..], ImportPolicy::FailOnConflict
+        ).unwrap();
+        assert_eq!(report.inserted, 2);
+        assert_eq!(Person::get(&imported, 2).unwrap().name.to_string(),
+                   "bob, jr.");
+
+        // Wrong data
+        let err = Person::import_csv(
+            &imported, &b"id,name\n1,alex\n"[..], ImportPolicy::Overwrite
+        ).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let err = Person::import_csv(
+            &imported, &b"id,name,age\n5,alex,old\n"[..],
+            ImportPolicy::Overwrite
+        ).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(Varchar::<4>::from_csv("alexander").is_err());
+
+        // Bytes
+        let bytes = Bytes::<4>::new(&[0xca, 0xfe]);
+        assert_eq!(bytes.to_csv(), "cafe");
+        assert_eq!(Bytes::<4>::from_csv("cafe").unwrap(), bytes);
+        assert!(Bytes::<4>::from_csv("cafe00000000").is_err());
+
+        _ensure_removed_tables();
+    }
+
+    fn _ensure_removed_tables() {
+        if fs::metadata(TABLE_PATH).is_ok() {
+            fs::remove_file(TABLE_PATH).unwrap();
+        }
+        if fs::metadata(TABLE_IMPORTED_PATH).is_ok() {
+            fs::remove_file(TABLE_IMPORTED_PATH).unwrap();
+        }
+    }
+}
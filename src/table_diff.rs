@@ -0,0 +1,224 @@
+use std::{fs, io};
+
+use crate::table::*;
+use crate::table_trait::*;
+
+
+/// TableDiff keeps ids of the records that differ between two versions of
+/// the same table.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TableDiff {
+    /// Records that exist in the second table only.
+    pub added: Vec<usize>,
+    /// Records that exist in the first table only or were erased.
+    pub removed: Vec<usize>,
+    /// Records that exist in both tables with different data.
+    pub modified: Vec<usize>,
+}
+
+
+impl TableDiff {
+    /// Returns true if the tables hold the same records, else false.
+    pub fn empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() &&
+            self.modified.is_empty()
+    }
+}
+
+
+/// Compares two versions of the table **a** and **b** record by record
+/// matching them by id. Erased records (with **id** 0) are considered missing.
+pub fn diff_tables<T: TableTrait>(
+            a: &Table,
+            b: &Table
+        ) -> Result<TableDiff, io::Error> {
+    let mut diff = TableDiff::default();
+
+    for idx in 0..a.size()?.max(b.size()?) {
+        let block_a = _get_live::<T>(a, idx)?;
+        let block_b = _get_live::<T>(b, idx)?;
+
+        match (block_a, block_b) {
+            (None, Some(_)) => diff.added.push(idx + 1),
+            (Some(_), None) => diff.removed.push(idx + 1),
+            (Some(block_a), Some(block_b)) => {
+                if block_a != block_b {
+                    diff.modified.push(idx + 1);
+                }
+            },
+            (None, None) => {},
+        }
+    }
+
+    Ok(diff)
+}
+
+
+/// Returns true if the tables **a** and **b** hold the same records with
+/// the same ids, else false. Erased records, free slots at the end and
+/// the padding of the blocks are ignored, so the tables may be opened with
+/// different layouts (e.g. alignment). It stops on the first difference.
+pub fn tables_equal<T: TableTrait>(
+            a: &Table,
+            b: &Table
+        ) -> Result<bool, io::Error> {
+    for idx in 0..a.size()?.max(b.size()?) {
+        if _get_live::<T>(a, idx)? != _get_live::<T>(b, idx)? {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+
+/// Calculates a hash (FNV-1a) of the records in the table streaming
+/// through them. Like **tables_equal** it ignores erased records and
+/// the layout, so equal tables have equal digests.
+pub fn records_digest<T: TableTrait>(table: &Table) -> Result<u64, io::Error> {
+    let mut hash = 0xcbf29ce484222325u64;
+
+    for idx in 0..table.size()? {
+        if let Some(block) = _get_live::<T>(table, idx)? {
+            let id = (idx + 1) as u64;
+            for byte in id.to_le_bytes().iter().chain(block.iter()) {
+                hash = (hash ^ *byte as u64).wrapping_mul(0x100000001b3);
+            }
+        }
+    }
+
+    Ok(hash)
+}
+
+
+/// Calculates **records_digest** of the table file located by **path**
+/// that was created by **Table::open**.
+pub fn table_digest<T: TableTrait>(path: &str) -> Result<u64, io::Error> {
+    fs::metadata(path)?;
+    records_digest::<T>(&Table::open::<T>(path)?)
+}
+
+
+fn _get_live<T: TableTrait>(
+            table: &Table,
+            idx: usize
+        ) -> Result<Option<Vec<u8>>, io::Error> {
+    if idx >= table.size()? {
+        return Ok(None);
+    }
+
+    let block = table.get(idx)?;
+
+    if T::from_bytes(&block).id() == 0 {
+        Ok(None)
+    } else {
+        Ok(Some(block[..T::block_size()].to_vec()))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    const TABLE_A_PATH: &str = "test-diff-person-a.tbl";
+    const TABLE_B_PATH: &str = "test-diff-person-b.tbl";
+    const TABLE_EQUAL_A_PATH: &str = "test-diff-equal-person-a.tbl";
+    const TABLE_EQUAL_B_PATH: &str = "test-diff-equal-person-b.tbl";
+
+    #[derive(Debug, Copy, Clone)]
+    struct Person {
+        id: usize,
+        age: u32,
+    }
+
+    impl TableTrait for Person {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    impl Person {
+        fn new(age: u32) -> Self {
+            Self { id: 0, age }
+        }
+    }
+
+    #[test]
+    fn test_diff_tables() {
+        _ensure_removed_tables();
+
+        let a = Table::open::<Person>(TABLE_A_PATH).unwrap();
+        let b = Table::open::<Person>(TABLE_B_PATH).unwrap();
+
+        for table in &[&a, &b] {
+            Person::new(32).insert(table).unwrap();
+            Person::new(25).insert(table).unwrap();
+            Person::new(40).insert(table).unwrap();
+        }
+        assert!(diff_tables::<Person>(&a, &b).unwrap().empty());
+
+        // Modify, erase and add in the second table
+        let mut alex = Person::get(&b, 1).unwrap();
+        alex.age = 33;
+        alex.update(&b).unwrap();
+        Person::erase(&b, 2).unwrap();
+        Person::new(19).insert(&b).unwrap();
+
+        let diff = diff_tables::<Person>(&a, &b).unwrap();
+        assert_eq!(diff.added, vec![4]);
+        assert_eq!(diff.removed, vec![2]);
+        assert_eq!(diff.modified, vec![1]);
+
+        _ensure_removed_tables();
+    }
+
+    #[test]
+    fn test_tables_equal() {
+        _ensure_removed_table_file(TABLE_EQUAL_A_PATH);
+        _ensure_removed_table_file(TABLE_EQUAL_B_PATH);
+
+        let a = Table::open::<Person>(TABLE_EQUAL_A_PATH).unwrap();
+        let b = Table::with_alignment::<Person>(TABLE_EQUAL_B_PATH, 64)
+            .unwrap();
+
+        for table in &[&a, &b] {
+            Person::new(32).insert(table).unwrap();
+            Person::new(25).insert(table).unwrap();
+        }
+        Person::new(40).insert(&b).unwrap();
+        Person::erase(&b, 3).unwrap();
+
+        assert!(tables_equal::<Person>(&a, &b).unwrap());
+        let digest = table_digest::<Person>(TABLE_EQUAL_A_PATH).unwrap();
+        assert_eq!(records_digest::<Person>(&b).unwrap(), digest);
+
+        let mut bob = Person::get(&b, 2).unwrap();
+        bob.age = 26;
+        bob.update(&b).unwrap();
+        assert!(!tables_equal::<Person>(&a, &b).unwrap());
+        assert_ne!(records_digest::<Person>(&b).unwrap(), digest);
+
+        assert!(table_digest::<Person>("test-diff-missing.tbl").is_err());
+
+        _ensure_removed_table_file(TABLE_EQUAL_A_PATH);
+        _ensure_removed_table_file(TABLE_EQUAL_B_PATH);
+    }
+
+    fn _ensure_removed_tables() {
+        _ensure_removed_table_file(TABLE_A_PATH);
+        _ensure_removed_table_file(TABLE_B_PATH);
+    }
+
+    fn _ensure_removed_table_file(path: &str) {
+        if fs::metadata(path).is_ok() {
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
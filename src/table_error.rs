@@ -0,0 +1,80 @@
+use std::{error, fmt, io};
+
+
+/// TableError is returned by **Table::open** when the table file cannot be
+/// opened or does not match the record.
+#[derive(Debug)]
+pub enum TableError {
+    /// The file cannot be opened, read or written.
+    Io(io::Error),
+    /// The file is not a table file or has an unsupported format version.
+    InvalidHeader(String),
+    /// The block size stored in the header differs from the one of
+    /// the record, the file was created for another struct.
+    BlockSize {
+        /// The block size in the header.
+        stored: usize,
+        /// The block size of the record.
+        expected: usize,
+    },
+    /// The file is locked by another table in a conflicting mode (e.g. by
+    /// a writer in another process), see **Table::open_exclusive**.
+    Locked,
+    /// The write-ahead log of the table keeps an interrupted write, it is
+    /// replayed by a writer only (e.g. **Table::open_exclusive**), so
+    /// **Table::open_shared** cannot open the table until then.
+    PendingLog,
+    /// The index file was created before the index header (the tree without
+    /// the heights of the nodes), it must be rebuilt from the table, see
+    /// **TableIndex::open**.
+    LegacyIndex,
+}
+
+
+impl fmt::Display for TableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{}", err),
+            Self::InvalidHeader(reason) => {
+                write!(f, "invalid table header: {}", reason)
+            },
+            Self::BlockSize { stored, expected } => {
+                write!(f, "block size {} instead of {}", stored, expected)
+            },
+            Self::Locked => write!(f, "table file is locked"),
+            Self::PendingLog => {
+                write!(f, "table has a pending write-ahead log")
+            },
+            Self::LegacyIndex => {
+                write!(f, "index file of an older format, it must be rebuilt")
+            },
+        }
+    }
+}
+
+
+impl error::Error for TableError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+
+impl From<io::Error> for TableError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+
+impl From<TableError> for io::Error {
+    fn from(err: TableError) -> Self {
+        match err {
+            TableError::Io(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use std::io;
+
+use crate::table::*;
+use crate::table_trait::*;
+use crate::progress::*;
+
+
+/// The way to handle an imported record whose id is already taken by
+/// a record in the table.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ImportPolicy {
+    /// Keep the existing record.
+    SkipExisting,
+    /// Replace the existing record.
+    Overwrite,
+    /// Stop the import with an *AlreadyExists* error.
+    FailOnConflict,
+}
+
+
+/// The numbers of imported records by the way they were handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    /// Records written to free ids (or inserted if they had no id).
+    pub inserted: usize,
+    /// Existing records replaced.
+    pub overwritten: usize,
+    /// Existing records kept.
+    pub skipped: usize,
+}
+
+
+/// Imports the records keeping their ids, so the import can be safely
+/// repeated. Records without id (0) are inserted with a new id. A record
+/// is written to its id even if it is beyond the end of the table, the gap
+/// is read as erased records. The records imported before a conflict
+/// stay in the table with **ImportPolicy::FailOnConflict**.
+pub fn import_records<T: TableTrait>(
+            table: &Table,
+            records: impl Iterator<Item = T>,
+            policy: ImportPolicy
+        ) -> Result<ImportReport, io::Error> {
+    let mut report = ImportReport::default();
+
+    for mut record in records {
+        let id = record.id();
+
+        if id == 0 {
+            record.insert(table)?;
+            report.inserted += 1;
+            continue;
+        }
+
+        let exists = (id <= table.size()?) && (T::get(table, id)?.id() != 0);
+
+        if exists {
+            match policy {
+                ImportPolicy::SkipExisting => {
+                    report.skipped += 1;
+                    continue;
+                },
+                ImportPolicy::Overwrite => {
+                    report.overwritten += 1;
+                },
+                ImportPolicy::FailOnConflict => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists, id.to_string()
+                    ));
+                },
+            }
+        } else {
+            report.inserted += 1;
+        }
+
+        table.update(record.as_bytes(), id - 1)?;
+    }
+
+    Ok(report)
+}
+
+
+/// Imports the records like **import_records** reporting the *import*
+/// stage to **progress**. The total is known if the iterator reports its
+/// exact size.
+pub fn import_records_with_progress<T: TableTrait>(
+            table: &Table,
+            records: impl Iterator<Item = T>,
+            policy: ImportPolicy,
+            progress: &ProgressHandle
+        ) -> Result<ImportReport, io::Error> {
+    let total = records.size_hint().1;
+    import_records(
+        table,
+        progress.track("import", records, total, T::block_size()),
+        policy
+    )
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::varchar::*;
+    use super::*;
+
+    const TABLE_PATH: &str = "test-import-person.tbl";
+
+    #[derive(Debug, Copy, Clone)]
+    struct Person {
+        id: usize,
+        name: Varchar<20>,
+        age: u32,
+    }
+
+    impl TableTrait for Person {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    impl Person {
+        fn new(id: usize, name: &str, age: u32) -> Self {
+            Self { id, name: Varchar::<20>::new(name), age }
+        }
+    }
+
+    #[test]
+    fn test_import_records() {
+        _ensure_removed_table_file();
+
+        let table = Table::open::<Person>(TABLE_PATH).unwrap();
+
+        let records = vec![
+            Person::new(1, "alex", 32),
+            Person::new(2, "bob", 25),
+            Person::new(4, "dan", 19),
+        ];
+
+        // First run
+        let report = import_records(
+            &table, records.clone().into_iter(), ImportPolicy::FailOnConflict
+        ).unwrap();
+        assert_eq!(report.inserted, 3);
+        assert_eq!(table.size().unwrap(), 4);
+        assert_eq!(Person::get(&table, 3).unwrap().id, 0);
+        assert_eq!(Person::get(&table, 4).unwrap().age, 19);
+
+        // Repeated runs
+        let report = import_records(
+            &table, records.clone().into_iter(), ImportPolicy::SkipExisting
+        ).unwrap();
+        assert_eq!(report, ImportReport { inserted: 0, overwritten: 0, skipped: 3 });
+
+        let changed = vec![Person::new(2, "bob", 26), Person::new(3, "carl", 40)];
+        let report = import_records(
+            &table, changed.into_iter(), ImportPolicy::Overwrite
+        ).unwrap();
+        assert_eq!(report, ImportReport { inserted: 1, overwritten: 1, skipped: 0 });
+        assert_eq!(Person::get(&table, 2).unwrap().age, 26);
+
+        let err = import_records(
+            &table, records.into_iter(), ImportPolicy::FailOnConflict
+        ).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+
+        // Records without id
+        import_records(
+            &table, vec![Person::new(0, "eve", 50)].into_iter(),
+            ImportPolicy::FailOnConflict
+        ).unwrap();
+        assert_eq!(Person::get(&table, 5).unwrap().name.to_string(), "eve");
+
+        // Progress
+        let progress = ProgressHandle::new(|_| {});
+        let records = vec![Person::new(1, "alex", 32), Person::new(6, "fred", 27)];
+        import_records_with_progress(
+            &table, records.into_iter(), ImportPolicy::SkipExisting, &progress
+        ).unwrap();
+        let last = progress.progress();
+        assert_eq!((last.processed, last.total), (2, Some(2)));
+
+        _ensure_removed_table_file();
+    }
+
+    fn _ensure_removed_table_file() {
+        if fs::metadata(TABLE_PATH).is_ok() {
+            fs::remove_file(TABLE_PATH).unwrap();
+        }
+    }
+}
@@ -1,12 +1,20 @@
-use std::{io, iter};
+use std::{any, cmp, fs, io, iter, mem};
+use std::io::Read;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ops::Bound;
 
 use crate::table::*;
 use crate::table_trait::*;
+use crate::table_error::*;
+use crate::read_trace::*;
+use crate::index_key::*;
 
 
 /// TableIndex is a record that has TableTrait implemented, so it keeps its
 /// own table file and work as a table with fixed fields. Inside the binary
-/// tree algorithms are implemented to insert, search and iterate.
+/// tree algorithms are implemented to insert, search and iterate. The tree
+/// is kept balanced (AVL) with the height of the subtree stored in each node,
+/// so the search is O(log n) even for values added in the sorted order.
 #[derive(Debug, Copy, Clone)]
 pub struct TableIndex<T> {
     id: usize,
@@ -14,6 +22,98 @@ pub struct TableIndex<T> {
     table_id: usize,
     left: usize,
     right: usize,
+    height: usize,
+}
+
+
+const INDEX_MAGIC: &[u8; 8] = b"MYTIDX02";
+const INDEX_HEADER_SIZE: usize = 48;
+
+/// The deepest path walked by **TableIndex::check**, a balanced tree of any
+/// size is lower.
+const INDEX_MAX_DEPTH: usize = 128;
+
+
+/// IndexHeader is stored in the first block of the index table. It keeps
+/// the id of the root node, the number of nodes, the height of the tree,
+/// the tag of the value type the index was created with and the number of
+/// removed nodes whose blocks are still in the file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct IndexHeader {
+    /// The id of the root node, 0 if there are no nodes.
+    pub root: usize,
+    /// The number of nodes in the tree.
+    pub count: usize,
+    /// The number of nodes on the longest path from the root.
+    pub height: usize,
+    /// The tag of the value type.
+    pub key_tag: u64,
+    /// The number of nodes removed since the index was created or
+    /// compacted, their blocks are dead until **compact**.
+    pub removed: usize,
+}
+
+
+impl IndexHeader {
+    /// Reads the header from the index table, None if the table is empty.
+    pub fn read(table: &Table) -> Result<Option<Self>, io::Error> {
+        if table.empty()? {
+            return Ok(None);
+        }
+
+        let block = table.get(0)?;
+        if (block.len() < INDEX_HEADER_SIZE) || (&block[..8] != INDEX_MAGIC) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData, "index header"
+            ));
+        }
+
+        let field = |pos: usize| {
+            let mut bytes = [0u8; 8];
+            bytes.clone_from_slice(&block[8 * pos..8 * pos + 8]);
+            u64::from_le_bytes(bytes)
+        };
+
+        Ok(Some(Self {
+            root: field(1) as usize,
+            count: field(2) as usize,
+            height: field(3) as usize,
+            key_tag: field(4),
+            removed: field(5) as usize,
+        }))
+    }
+
+    /// The ratio of the dead blocks to all node blocks of the index file.
+    pub fn dead_ratio(&self) -> f64 {
+        match self.count + self.removed {
+            0 => 0.0,
+            total => self.removed as f64 / total as f64,
+        }
+    }
+
+    /// Writes the header to the first block of the index table.
+    pub fn write(&self, table: &Table) -> Result<(), io::Error> {
+        if table.block_size() < INDEX_HEADER_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput, "index block size"
+            ));
+        }
+
+        let mut block = INDEX_MAGIC.to_vec();
+        for field in &[
+                    self.root as u64, self.count as u64,
+                    self.height as u64, self.key_tag, self.removed as u64
+                ] {
+            block.extend_from_slice(&field.to_le_bytes());
+        }
+
+        if table.empty()? {
+            table.append(&block)?;
+        } else {
+            table.update(&block, 0)?;
+        }
+        Ok(())
+    }
 }
 
 
@@ -25,18 +125,81 @@ impl<T: Copy> TableTrait for TableIndex<T> {
     fn set_id(&mut self, id: usize) {
         self.id = id;
     }
+
+    /// Gets the root node of the tree.
+    fn get_first(table: &Table) -> Result<Self, io::Error> {
+        Self::get(table, Self::get_first_id(table)?)
+    }
+
+    /// Gets id of the root node of the tree from the index header.
+    fn get_first_id(table: &Table) -> Result<usize, io::Error> {
+        match IndexHeader::read(table)? {
+            Some(header) if header.root > 0 => Ok(header.root),
+            _ => Err(io::Error::new(io::ErrorKind::NotFound, "empty table")),
+        }
+    }
 }
 
 
-impl<'a, T: 'a + Copy + Clone + PartialOrd> TableIndex<T> {
+impl<'a, T: 'a + Copy + Clone + PartialOrd + IndexKey> TableIndex<T> {
     fn new(value: &T, table_id: usize) -> Self {
         Self {
             id: 0,
-            value: value.clone(),
-            table_id: table_id,
+            value: *value,
+            table_id,
             left: 0,
             right: 0,
+            height: 1,
+        }
+    }
+
+    /// Creates or opens the index table. The index files created before
+    /// the index header (without the header of the table too) return
+    /// **TableError::LegacyIndex**, they must be rebuilt from the table
+    /// (e.g. by **Database::open_or_repair**).
+    pub fn open(path: &str) -> Result<Table, TableError> {
+        Self::_check_format(path)?;
+        Table::open::<Self>(path)
+    }
+
+    /// Opens the index table keeping its upper levels in memory up to
+    /// **budget** bytes, so the lookups do not read the root and the nodes
+    /// near it from the disk again. As many levels are pinned as fit
+    /// the budget, they are pinned anew after each change of the tree.
+    /// The index files of an older format are detected as in **open**.
+    pub fn open_cached(path: &str, budget: usize) -> Result<Table, TableError> {
+        Self::_check_format(path)?;
+        let table = Table::with_pinned_cache::<Self>(path, budget)?;
+        Self::pin_levels(&table)?;
+        Ok(table)
+    }
+
+    /// Pins the header and the upper levels of the tree in memory, see
+    /// **Table::with_pinned_cache**. The number of levels is chosen by
+    /// the budget of the table and the size of the tree. Returns
+    /// the number of pinned levels.
+    pub fn pin_levels(table: &Table) -> Result<usize, io::Error> {
+        let pin_capacity = table.pin_capacity();
+        let mut idxs = vec![0];
+        let mut level = match Self::header(table)?.root {
+            0 => Vec::new(),
+            root => vec![root],
+        };
+        let mut levels = 0;
+
+        while !level.is_empty() && (idxs.len() + level.len() <= pin_capacity) {
+            let mut next = Vec::new();
+            for id in level {
+                let rec = Self::get(table, id)?;
+                idxs.push(id - 1);
+                next.extend([rec.left, rec.right].iter().filter(|id| **id > 0));
+            }
+            level = next;
+            levels += 1;
         }
+
+        table.pin(&idxs)?;
+        Ok(levels)
     }
 
     /// Adds an index value to the table.
@@ -45,10 +208,156 @@ impl<'a, T: 'a + Copy + Clone + PartialOrd> TableIndex<T> {
                 value: &T,
                 table_id: usize
             ) -> Result<(), io::Error> {
-        let mut record = Self::new(value, table_id);
-        let record_id = record.insert(table)?;
-        Self::_bind(table, value, record_id);
-        Ok(())
+        Self::add_batch(table, &[(*value, table_id)])
+    }
+
+    /// Adds an index value to the table if no other record is bound to
+    /// the same value, else an *AlreadyExists* error is returned. Excluded
+    /// nodes do not count. The check and the insertion are not atomic, so
+    /// concurrent writers must be synchronized (e.g. by **Table::exclusive**).
+    pub fn add_unique(
+                table: &Table,
+                value: &T,
+                table_id: usize
+            ) -> Result<(), io::Error> {
+        if Self::header(table)?.root > 0 {
+            if let Some(other) = Self::search_many(table, value).next()
+                    .transpose()? {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists, other.to_string()
+                ));
+            }
+        }
+        Self::add(table, value, table_id)
+    }
+
+    /// Reads the header of the index checking that it was created for
+    /// the same value type. A header for an empty index is returned if
+    /// the table is empty. The tag of an index created by an earlier
+    /// version is accepted and replaced by **IndexKey::key_tag** on
+    /// the next write.
+    pub fn header(table: &Table) -> Result<IndexHeader, io::Error> {
+        match IndexHeader::read(table)? {
+            Some(mut header) => {
+                if header.key_tag == Self::_legacy_key_tag() {
+                    header.key_tag = T::key_tag();
+                }
+                if header.key_tag != T::key_tag() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData, "index key type"
+                    ));
+                }
+                Ok(header)
+            },
+            None => Ok(IndexHeader {
+                root: 0,
+                count: 0,
+                height: 0,
+                key_tag: T::key_tag(),
+                removed: 0,
+            }),
+        }
+    }
+
+    /// Adds several index values with **id** of original records to
+    /// the table at once. The values are sorted and inserted median first,
+    /// so few rotations are needed to keep the tree balanced. The changed
+    /// nodes are kept in memory during the batch and every changed node is
+    /// written once at the end.
+    pub fn add_batch(
+                table: &Table,
+                entries: &[(T, usize)]
+            ) -> Result<(), io::Error> {
+        let mut sorted: Vec<&(T, usize)> = entries.iter().collect();
+        sorted.sort_by(
+            |a, b| a.0.partial_cmp(&b.0).unwrap_or(cmp::Ordering::Equal)
+        );
+
+        let mut header = Self::header(table)?;
+        if table.empty()? {
+            header.write(table)?;
+        }
+
+        let first_id = table.size()? + 1;
+        let mut next_id = first_id;
+        let mut nodes: HashMap<usize, Self> = HashMap::new();
+
+        for pos in Self::_median_order(sorted.len()) {
+            let (value, table_id) = sorted[pos];
+            let mut record = Self::new(value, *table_id);
+            record.id = next_id;
+            next_id += 1;
+            header.root = Self::_insert(table, &mut nodes, header.root, record)?;
+        }
+
+        for id in first_id..next_id {
+            table.append(nodes[&id].as_bytes())?;
+        }
+        for (id, rec) in nodes.iter() {
+            if *id < first_id {
+                rec.update(table)?;
+            }
+        }
+
+        header.count += entries.len();
+        header.height = Self::_height(table, &nodes, header.root)?;
+        header.write(table)?;
+        Self::_repin(table)
+    }
+
+    /// Builds the tree of the **entries** (index values with **id** of
+    /// original records) in an index without nodes. The entries are sorted
+    /// once and the middle of each range becomes the parent of its halves,
+    /// so the tree is perfectly balanced without rotations. The nodes are
+    /// written in the order of the values by a single append, that is much
+    /// faster than **add** or **add_batch** for a large dataset. An error of
+    /// kind *AlreadyExists* is returned if the index has nodes.
+    pub fn bulk_build(
+                table: &Table,
+                entries: impl IntoIterator<Item = (T, usize)>
+            ) -> Result<(), io::Error> {
+        let mut header = Self::header(table)?;
+        if header.root > 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists, "index is not empty"
+            ));
+        }
+
+        let mut sorted: Vec<(T, usize)> = entries.into_iter().collect();
+        sorted.sort_by(|a, b| {
+            a.0.partial_cmp(&b.0).unwrap_or(cmp::Ordering::Equal)
+                .then(a.1.cmp(&b.1))
+        });
+
+        let mut links = vec![(0, 0, 0); sorted.len()];
+        let (root, height) = Self::_link_balanced(&mut links, 0, sorted.len());
+
+        if table.empty()? {
+            header.write(table)?;
+        }
+
+        let first = table.append_many(sorted.len(), |pos, idx| {
+            // The links count the positions from 1, so the node at
+            // the position has the id idx + 1
+            let (value, table_id) = sorted[pos];
+            let (left, right, height) = links[pos];
+            let id = |link: usize| if link > 0 { idx - pos + link } else { 0 };
+            let rec = Self {
+                id: idx + 1,
+                value,
+                table_id,
+                left: id(left),
+                right: id(right),
+                height,
+            };
+            rec.as_bytes().to_vec()
+        })?;
+
+        header.root = if root > 0 { first + root } else { 0 };
+        header.count = sorted.len();
+        header.height = height;
+        header.write(table)?;
+        Self::_repin(table)
     }
 
     /// Searches for a node by **value**. The **id** of original
@@ -57,273 +366,950 @@ impl<'a, T: 'a + Copy + Clone + PartialOrd> TableIndex<T> {
                 table: &Table,
                 value: &T
             ) -> Result<usize, io::Error> {
-        for table_id in Self::search_many(table, value) {
-            return Ok(table_id);
-        }
-        return Err(io::Error::new(io::ErrorKind::NotFound, "table index"));
+        Self::search_many(table, value).next().ok_or_else(
+            || io::Error::new(io::ErrorKind::NotFound, "table index")
+        )?
     }
 
     /// Searches for all nodes with given **value**.
-    /// It returns an iterator that yields **id** of original records.
+    /// It returns an iterator that yields **id** of original records,
+    /// a node failed to be read is yielded as an error ending the search.
     pub fn search_many(
                 table: &'a Table,
                 value: &'a T
-            ) -> Box<dyn Iterator<Item = usize> + 'a> {
-        Box::new(
-            Self::_iter_by_value(table, value)
-                .filter(|rec| rec.table_id > 0)
-                .map(|rec| rec.table_id)
-        )
+            ) -> Box<dyn Iterator<Item = Result<usize, io::Error>> + 'a> {
+        Self::_table_ids(Self::_iter_by_value(table, value))
     }
 
-    /// Iterates all nodes in the order of its values.
-    pub fn iter(table: &'a Table) -> Box<dyn Iterator<Item = usize> + 'a> {
-        let mut stack = vec![(Self::get_first(table).unwrap(), 0u8)];
+    /// Searches for all nodes with given **value** like **search_many**
+    /// recording every block read on the way: the table, the block index,
+    /// whether it was taken from the cache and the result of comparing
+    /// **value** with the value of the node. It shows why a specific search
+    /// is slow (e.g. a long path or cache misses).
+    pub fn explain_search(
+                table: &Table,
+                value: &T
+            ) -> Result<LookupTrace, io::Error> {
+        Self::header(table)?;
+
+        let (found, mut reads) = trace_reads(
+            || Self::search_many(table, value).collect::<Result<Vec<_>, _>>()
+        );
+        let found = found?;
+
+        for read in reads.iter_mut() {
+            if (read.table == table.path()) && (read.idx > 0) {
+                let rec = Self::from_bytes(&read.block);
+                read.comparison = value.partial_cmp(&rec.value);
+            }
+        }
 
-        Box::new(iter::from_fn(move || {
-            let mut result = None;
+        Ok(LookupTrace { found, reads })
+    }
 
-            while !stack.is_empty() {
-                let last = stack.last_mut().unwrap();
+    /// Iterates all nodes in the order of its values. Like the other
+    /// iterators of the index, it yields **id** of original records and
+    /// a node failed to be read is yielded as an error ending the iteration.
+    pub fn iter(
+                table: &'a Table
+            ) -> Box<dyn Iterator<Item = Result<usize, io::Error>> + 'a> {
+        Self::_table_ids(Self::_iter_stack(table, Self::_root_stack(table)))
+    }
 
-                if last.1 == 0 {
-                    last.1 = 1;
-                    if last.0.left > 0 {
-                        let rec = Self::get(table, last.0.left).unwrap();
-                        stack.push((rec, 0));
-                    }
-                    continue;
-                }
+    /// Iterates the nodes in the order of its values between the given values
+    /// (**>= values_from** and **< values_to**).
+    pub fn iter_between(
+                table: &'a Table,
+                value_from: &'a T,
+                value_to: &'a T
+            ) -> Box<dyn Iterator<Item = Result<usize, io::Error>> + 'a> {
+        Self::iter_range(
+            table, Bound::Included(value_from), Bound::Excluded(value_to)
+        )
+    }
 
-                if last.1 == 1 {
-                    last.1 = 2;
-                    if last.0.table_id > 0 {
-                        result = Some(last.0.table_id);
-                        break;
-                    }
-                    continue;
-                }
+    /// Iterates the nodes in the order of its values starting from
+    /// the bound **from** (e.g. all values *> 60* with
+    /// **Bound::Excluded(&60)**).
+    pub fn iter_from(
+                table: &'a Table,
+                from: Bound<&'a T>
+            ) -> Box<dyn Iterator<Item = Result<usize, io::Error>> + 'a> {
+        Self::iter_range(table, from, Bound::Unbounded)
+    }
 
-                if last.1 == 2 {
-                    last.1 = 3;
-                    if last.0.right > 0 {
-                        let rec = Self::get(table, last.0.right).unwrap();
-                        stack.push((rec, 0));
-                    }
-                    continue;
-                }
+    /// Iterates the nodes in the order of its values up to the bound **to**.
+    pub fn iter_to(
+                table: &'a Table,
+                to: Bound<&'a T>
+            ) -> Box<dyn Iterator<Item = Result<usize, io::Error>> + 'a> {
+        Self::iter_range(table, Bound::Unbounded, to)
+    }
 
-                if last.1 == 3 {
-                    stack.remove(stack.len() - 1);
-                    continue;
-                }
-            }
+    /// Iterates the nodes in the order of its values between the bounds
+    /// **from** and **to**, each of them may be included, excluded or
+    /// unbounded.
+    pub fn iter_range(
+                table: &'a Table,
+                from: Bound<&'a T>,
+                to: Bound<&'a T>
+            ) -> Box<dyn Iterator<Item = Result<usize, io::Error>> + 'a> {
+        let stack = match from {
+            Bound::Included(value) | Bound::Excluded(value) => {
+                Self::_build_stack_from(table, value)
+            },
+            Bound::Unbounded => Self::_root_stack(table),
+        };
 
-            result
-        }))
+        Self::_table_ids(Box::new(
+            Self::_iter_stack(table, stack)
+                .skip_while(move |rec| match (rec, from) {
+                    (Ok(rec), Bound::Excluded(value)) => rec.value <= *value,
+                    _ => false,
+                })
+                .take_while(move |rec| match (rec, to) {
+                    (Ok(rec), Bound::Included(value)) => rec.value <= *value,
+                    (Ok(rec), Bound::Excluded(value)) => rec.value < *value,
+                    _ => true,
+                })
+        ))
     }
 
-    /// Iterates the nodes in the order of its values between the given values
-    /// (**>= values_from** and **< values_to**).
-    pub fn iter_between(
+    /// Iterates all nodes in the reversed order of its values (the largest
+    /// first).
+    pub fn iter_rev(
+                table: &'a Table
+            ) -> Box<dyn Iterator<Item = Result<usize, io::Error>> + 'a> {
+        Self::iter_range_rev(table, Bound::Unbounded, Bound::Unbounded)
+    }
+
+    /// Iterates the nodes in the reversed order of its values between
+    /// the given values (**>= values_from** and **< values_to**).
+    pub fn iter_between_rev(
                 table: &'a Table,
                 value_from: &'a T,
                 value_to: &'a T
-            ) -> Box<dyn Iterator<Item = usize> + 'a> {
-        let mut stack = Self::_build_stack_from(table, value_from);
+            ) -> Box<dyn Iterator<Item = Result<usize, io::Error>> + 'a> {
+        Self::iter_range_rev(
+            table, Bound::Included(value_from), Bound::Excluded(value_to)
+        )
+    }
 
-        Box::new(iter::from_fn(move || {
-            let mut result = None;
+    /// Iterates the nodes in the reversed order of its values between
+    /// the bounds **from** and **to**, starting from the largest value
+    /// within **to**.
+    pub fn iter_range_rev(
+                table: &'a Table,
+                from: Bound<&'a T>,
+                to: Bound<&'a T>
+            ) -> Box<dyn Iterator<Item = Result<usize, io::Error>> + 'a> {
+        let stack = Self::_build_stack_to(table, to);
+
+        Self::_table_ids(Box::new(
+            Self::_iter_stack_rev(table, stack)
+                .take_while(move |rec| match (rec, from) {
+                    (Ok(rec), Bound::Included(value)) => rec.value >= *value,
+                    (Ok(rec), Bound::Excluded(value)) => rec.value > *value,
+                    _ => true,
+                })
+        ))
+    }
 
-            while !stack.is_empty() {
-                let last = stack.last_mut().unwrap();
+    /// Iterates the records of the **data_table** in the order of the index
+    /// values between the given values (**>= values_from** and
+    /// **< values_to**). The ids are collected in batches of **batch_size**
+    /// and the records of a batch are fetched together by **get_many**,
+    /// a batch failed to be read is yielded as an error.
+    pub fn iter_records_between<R: TableTrait + 'a>(
+                table: &'a Table,
+                data_table: &'a Table,
+                value_from: &'a T,
+                value_to: &'a T,
+                batch_size: usize
+            ) -> Box<dyn Iterator<Item = Result<R, io::Error>> + 'a> {
+        let mut ids = Self::iter_between(table, value_from, value_to);
+        let mut batch = VecDeque::new();
 
-                if last.1 == 0 {
-                    last.1 = 1;
-                    if last.0.left > 0 {
-                        let rec = Self::get(table, last.0.left).unwrap();
-                        stack.push((rec, 0));
-                    }
-                    continue;
+        Box::new(iter::from_fn(move || {
+            if batch.is_empty() {
+                let batch_ids = ids.by_ref()
+                    .take(batch_size.max(1)).collect::<Result<Vec<_>, _>>();
+                if matches!(&batch_ids, Ok(batch_ids) if batch_ids.is_empty()) {
+                    return None;
+                }
+                match batch_ids.and_then(|batch_ids| {
+                    R::get_many(data_table, &batch_ids)
+                }) {
+                    Ok(records) => batch.extend(records.into_iter().map(Ok)),
+                    Err(err) => batch.push_back(Err(err)),
                 }
+            }
+            batch.pop_front()
+        }))
+    }
 
-                if last.1 == 1 {
-                    last.1 = 2;
-                    if last.0.value < *value_to {
-                        if last.0.table_id > 0 {
-                            result = Some(last.0.table_id);
-                            break;
-                        } else {
-                            continue;
-                        }
-                    } else {
-                        break;
-                    }
+    /// A page of at most **limit** values with the ids of their records in
+    /// the order of the index following the cursor **after** (the value and
+    /// the id of the last entry of the previous page, None for the first
+    /// page). The ids of equal values are ordered ascending, so the cursor
+    /// is a stable position: the records added or excluded meanwhile do not
+    /// shift the next pages as they do with an offset. The nodes before
+    /// the cursor are not read except the equal values.
+    pub fn page_after(
+                table: &Table,
+                after: Option<(T, usize)>,
+                limit: usize
+            ) -> Result<Vec<(T, usize)>, io::Error> {
+        Self::header(table)?;
+
+        let stack = match after {
+            Some((after_value, _)) => {
+                Self::_build_stack_from(table, &after_value)
+            },
+            None => Self::_root_stack(table),
+        };
+        let mut nodes = Self::_iter_stack(table, stack)
+            .filter(|rec| rec.as_ref().map_or(true, |rec| rec.table_id > 0))
+            .peekable();
+
+        let mut page = Vec::new();
+        while page.len() < limit {
+            let first = match nodes.next() {
+                Some(rec) => rec?,
+                None => break,
+            };
+
+            // The equal values may be in any order in the tree, an error
+            // is taken to be returned
+            let same = |rec: &Result<Self, io::Error>| {
+                rec.as_ref().map_or(true, |rec| rec.value == first.value)
+            };
+            let mut ids = vec![first.table_id];
+            while let Some(rec) = nodes.next_if(same) {
+                ids.push(rec?.table_id);
+            }
+            ids.sort_unstable();
+
+            for id in ids {
+                let skipped = match after {
+                    Some((after_value, after_id)) => {
+                        (first.value == after_value) && (id <= after_id)
+                    },
+                    None => false,
+                };
+                if !skipped && (page.len() < limit) {
+                    page.push((first.value, id));
                 }
+            }
+        }
 
-                if last.1 == 2 {
-                    last.1 = 3;
-                    if last.0.right > 0 {
-                        let rec = Self::get(table, last.0.right).unwrap();
-                        stack.push((rec, 0));
-                    }
-                    continue;
+        Ok(page)
+    }
+
+    /// The smallest value with the id of its record, None if the index is
+    /// empty. Only the path to the leftmost node is read.
+    pub fn min(table: &Table) -> Result<Option<(T, usize)>, io::Error> {
+        Self::_extreme(table, false)
+    }
+
+    /// The largest value with the id of its record, None if the index is
+    /// empty. Only the path to the rightmost node is read.
+    pub fn max(table: &Table) -> Result<Option<(T, usize)>, io::Error> {
+        Self::_extreme(table, true)
+    }
+
+    /// Counts the nodes with values between the given values
+    /// (**>= values_from** and **< values_to**) exactly. Only the nodes in
+    /// the range and the paths to them are read, the records are not (see
+    /// **estimate_count_between** for a faster estimate of a wide range).
+    pub fn count_between(
+                table: &Table,
+                value_from: &T,
+                value_to: &T
+            ) -> Result<usize, io::Error> {
+        Self::header(table)?;
+        Self::iter_between(table, value_from, value_to)
+            .map(|id| id.map(|_| 1))
+            .sum()
+    }
+
+    /// Estimates the number of nodes with values between the given values
+    /// (**>= values_from** and **< values_to**) walking **samples** random
+    /// paths from the root instead of iterating them. A path goes down
+    /// the subtrees that may contain the values only, each node in
+    /// the range counts for the product of the branching along the path
+    /// (Knuth's estimator). It is useful for progress bars and planning
+    /// where the exact count is too expensive.
+    pub fn estimate_count_between(
+                table: &Table,
+                value_from: &T,
+                value_to: &T,
+                samples: usize
+            ) -> Result<usize, io::Error> {
+        let root = match IndexHeader::read(table)? {
+            Some(header) => header.root,
+            None => 0,
+        };
+        if root == 0 {
+            return Ok(0);
+        }
+
+        let mut seed = 0x9e3779b97f4a7c15u64;
+        let mut total = 0.0;
+
+        for _ in 0..samples.max(1) {
+            let mut id = root;
+            let mut weight = 1.0;
+
+            while id > 0 {
+                let rec = Self::get(table, id)?;
+                if (rec.value >= *value_from) && (rec.value < *value_to) &&
+                        (rec.table_id > 0) {
+                    total += weight;
                 }
 
-                if last.1 == 3 {
-                    stack.remove(stack.len() - 1);
-                    continue;
+                let mut children = Vec::with_capacity(2);
+                if (rec.left > 0) && (*value_from <= rec.value) {
+                    children.push(rec.left);
+                }
+                if (rec.right > 0) && (rec.value < *value_to) {
+                    children.push(rec.right);
                 }
+                if children.is_empty() {
+                    break;
+                }
+
+                // xorshift64
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+
+                weight *= children.len() as f64;
+                id = children[(seed % children.len() as u64) as usize];
             }
+        }
 
-            result
-        }))
+        Ok((total / samples.max(1) as f64).round() as usize)
     }
 
-    /// Excludes the node by setting its **table_id** to **0**.
+    /// Removes the node with the **value** bound to **table_id** from
+    /// the tree: a node with two children is replaced by its successor and
+    /// the tree is rebalanced. The block of the node is filled with zeros,
+    /// it is reclaimed by **compact**.
     pub fn exclude(
                 table: &Table,
                 value: &T,
                 table_id: usize
             ) -> Result<(), io::Error> {
-        let rec_option = {
-            let mut result = None;
-            for rec in Self::_iter_by_value(table, value) {
-                if rec.table_id == table_id {
-                    result = Some(rec);
-                    break;
-                }
-            }
-            result
-        };
+        let mut header = Self::header(table)?;
+        let mut nodes: HashMap<usize, Self> = HashMap::new();
+        let mut removed = None;
 
-        match rec_option {
-            Some(mut rec) => {
-                rec.table_id = 0;
+        header.root = Self::_remove(
+            table, &mut nodes, header.root, value, table_id, &mut removed
+        )?;
+
+        let removed = removed.ok_or_else(|| io::Error::new(
+            io::ErrorKind::NotFound, table_id.to_string()
+        ))?;
+
+        for (id, rec) in nodes.iter() {
+            if *id != removed {
                 rec.update(table)?;
-                Ok(())
-            },
-            None => {
-                Err(io::Error::new(
-                    io::ErrorKind::NotFound, table_id.to_string()
-                ))
             }
         }
+        // The zeros are written without the flush and the check of
+        // Table::erase, they are not needed for every node
+        table.update(&vec![0; Self::block_size()], removed - 1)?;
+
+        header.count = header.count.saturating_sub(1);
+        header.removed += 1;
+        header.height = Self::_height(table, &nodes, header.root)?;
+        header.write(table)?;
+        Self::_repin(table)
     }
 
-    fn _bind(table: &Table, value: &T, record_id: usize) {
-        let mut id = Self::get_first_id(table).unwrap();
+    /// Writes the index without removed and excluded nodes to a new table
+    /// located by **path**. The values are read into memory and the new tree
+    /// is built by **bulk_build**, so it is balanced.
+    pub fn compact(table: &Table, path: &str) -> Result<Table, io::Error> {
+        if fs::metadata(path).map(|meta| meta.len() > 0).unwrap_or(false) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, path));
+        }
 
-        if id != record_id {
-            while id > 0 {
-                let mut rec = Self::get(table, id).unwrap();
+        let entries = Self::entries(table)?;
 
-                if *value < rec.value {
-                    id = rec.left;
-                    if id == 0 {
-                        rec.left = record_id;
-                    }
-                } else {
-                    id = rec.right;
-                    if id == 0 {
-                        rec.right = record_id;
-                    }
-                }
+        let compacted = Table::open::<Self>(path)?;
+        Self::bulk_build(&compacted, entries)?;
+        Ok(compacted)
+    }
 
-                if id == 0 {
-                    rec.update(table).unwrap();
-                }
+    /// The values of the nodes with **id** of original records in the order
+    /// of the values.
+    pub fn entries(table: &Table) -> Result<Vec<(T, usize)>, io::Error> {
+        Self::_iter_stack(table, Self::_root_stack(table))
+            .filter(|rec| rec.as_ref().map_or(true, |rec| rec.table_id > 0))
+            .map(|rec| rec.map(|rec| (rec.value, rec.table_id)))
+            .collect()
+    }
+
+    /// Checks the structure of the tree: every node is reached once from
+    /// the root, the values are in order, the heights are stored correctly
+    /// and balanced and the header counts all nodes. An error of kind
+    /// *InvalidData* describes the first problem found.
+    pub fn check(table: &Table) -> Result<(), io::Error> {
+        let header = Self::header(table)?;
+        let mut visited = HashSet::new();
+        let mut last = None;
+
+        let height = Self::_check(
+            table, header.root, 0, &mut visited, &mut last
+        )?;
+
+        if visited.len() != header.count {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData, "index count"
+            ));
+        }
+        if height != header.height {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData, "index height"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns true if the ratio of the dead blocks left by the removed
+    /// nodes exceeds **max_dead_ratio**, so the index should be compacted.
+    pub fn needs_compaction(
+                table: &Table,
+                max_dead_ratio: f64
+            ) -> Result<bool, io::Error> {
+        Ok(Self::header(table)?.dead_ratio() > max_dead_ratio)
+    }
+
+    /// Compacts the index to a new table located by **path** if it
+    /// **needs_compaction**, else None is returned and no file is created.
+    pub fn compact_if_needed(
+                table: &Table,
+                path: &str,
+                max_dead_ratio: f64
+            ) -> Result<Option<Table>, io::Error> {
+        if Self::needs_compaction(table, max_dead_ratio)? {
+            Self::compact(table, path).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn _check(
+                table: &Table,
+                id: usize,
+                depth: usize,
+                visited: &mut HashSet<usize>,
+                last: &mut Option<T>
+            ) -> Result<usize, io::Error> {
+        if id == 0 {
+            return Ok(0);
+        }
+
+        let invalid = || io::Error::new(
+            io::ErrorKind::InvalidData, format!("index node {}", id)
+        );
+
+        if (depth > INDEX_MAX_DEPTH) || (id > table.size()?) ||
+                !visited.insert(id) {
+            return Err(invalid());
+        }
+
+        let rec = Self::get(table, id)?;
+        if rec.id != id {
+            return Err(invalid());
+        }
+
+        let left = Self::_check(table, rec.left, depth + 1, visited, last)?;
+        if let Some(value) = last {
+            if rec.value < *value {
+                return Err(invalid());
+            }
+        }
+        *last = Some(rec.value);
+        let right = Self::_check(table, rec.right, depth + 1, visited, last)?;
+
+        if (rec.height != 1 + cmp::max(left, right)) ||
+                (left > right + 1) || (right > left + 1) {
+            return Err(invalid());
+        }
+        Ok(rec.height)
+    }
+
+    fn _insert(
+                table: &Table,
+                nodes: &mut HashMap<usize, Self>,
+                root: usize,
+                record: Self
+            ) -> Result<usize, io::Error> {
+        // Equal values go to the right, so they are iterated in the order
+        // they were added
+        let mut path = Vec::new();
+        let mut id = root;
+        while id > 0 {
+            let rec = Self::_node(table, nodes, id)?;
+            path.push(id);
+            id = if record.value < rec.value { rec.left } else { rec.right };
+        }
+
+        nodes.insert(record.id, record);
+        let mut child = record.id;
+
+        while let Some(id) = path.pop() {
+            let mut rec = Self::_node(table, nodes, id)?;
+            if record.value < rec.value {
+                rec.left = child;
+            } else {
+                rec.right = child;
             }
+            nodes.insert(id, rec);
+            child = Self::_rebalance(table, nodes, id)?;
         }
+
+        Ok(child)
+    }
+
+    fn _remove(
+                table: &Table,
+                nodes: &mut HashMap<usize, Self>,
+                id: usize,
+                value: &T,
+                table_id: usize,
+                removed: &mut Option<usize>
+            ) -> Result<usize, io::Error> {
+        if id == 0 {
+            return Ok(0);
+        }
+
+        let mut rec = Self::_node(table, nodes, id)?;
+
+        if *value < rec.value {
+            rec.left = Self::_remove(
+                table, nodes, rec.left, value, table_id, removed
+            )?;
+        } else if *value > rec.value {
+            rec.right = Self::_remove(
+                table, nodes, rec.right, value, table_id, removed
+            )?;
+        } else if rec.table_id == table_id {
+            *removed = Some(id);
+            if rec.left == 0 {
+                return Ok(rec.right);
+            }
+            if rec.right == 0 {
+                return Ok(rec.left);
+            }
+
+            // The successor takes the place of the node
+            let (right, successor) = Self::_remove_min(
+                table, nodes, rec.right
+            )?;
+            let mut next = Self::_node(table, nodes, successor)?;
+            next.left = rec.left;
+            next.right = right;
+            nodes.insert(successor, next);
+            return Self::_rebalance(table, nodes, successor);
+        } else {
+            // Equal values may be in both subtrees
+            rec.left = Self::_remove(
+                table, nodes, rec.left, value, table_id, removed
+            )?;
+            if removed.is_none() {
+                rec.right = Self::_remove(
+                    table, nodes, rec.right, value, table_id, removed
+                )?;
+            }
+        }
+
+        if removed.is_none() {
+            return Ok(id);
+        }
+        nodes.insert(id, rec);
+        Self::_rebalance(table, nodes, id)
+    }
+
+    fn _remove_min(
+                table: &Table,
+                nodes: &mut HashMap<usize, Self>,
+                id: usize
+            ) -> Result<(usize, usize), io::Error> {
+        let mut rec = Self::_node(table, nodes, id)?;
+        if rec.left == 0 {
+            return Ok((rec.right, id));
+        }
+        let (left, min) = Self::_remove_min(table, nodes, rec.left)?;
+        rec.left = left;
+        nodes.insert(id, rec);
+        Ok((Self::_rebalance(table, nodes, id)?, min))
+    }
+
+    fn _rebalance(
+                table: &Table,
+                nodes: &mut HashMap<usize, Self>,
+                id: usize
+            ) -> Result<usize, io::Error> {
+        let rec = Self::_update_height(table, nodes, id)?;
+        let left = Self::_height(table, nodes, rec.left)?;
+        let right = Self::_height(table, nodes, rec.right)?;
+
+        if left > right + 1 {
+            let child = Self::_node(table, nodes, rec.left)?;
+            if Self::_height(table, nodes, child.left)? <
+                    Self::_height(table, nodes, child.right)? {
+                let mut rec = rec;
+                rec.left = Self::_rotate_left(table, nodes, child.id)?;
+                nodes.insert(id, rec);
+            }
+            return Self::_rotate_right(table, nodes, id);
+        }
+
+        if right > left + 1 {
+            let child = Self::_node(table, nodes, rec.right)?;
+            if Self::_height(table, nodes, child.right)? <
+                    Self::_height(table, nodes, child.left)? {
+                let mut rec = rec;
+                rec.right = Self::_rotate_right(table, nodes, child.id)?;
+                nodes.insert(id, rec);
+            }
+            return Self::_rotate_left(table, nodes, id);
+        }
+
+        Ok(id)
     }
 
-    fn _build_stack_from(table: &Table, value: &T) -> Vec<(Self, u8)> {
+    fn _rotate_left(
+                table: &Table,
+                nodes: &mut HashMap<usize, Self>,
+                id: usize
+            ) -> Result<usize, io::Error> {
+        let mut rec = Self::_node(table, nodes, id)?;
+        let mut child = Self::_node(table, nodes, rec.right)?;
+        rec.right = child.left;
+        child.left = rec.id;
+        nodes.insert(rec.id, rec);
+        nodes.insert(child.id, child);
+        Self::_update_height(table, nodes, rec.id)?;
+        Self::_update_height(table, nodes, child.id)?;
+        Ok(child.id)
+    }
+
+    fn _rotate_right(
+                table: &Table,
+                nodes: &mut HashMap<usize, Self>,
+                id: usize
+            ) -> Result<usize, io::Error> {
+        let mut rec = Self::_node(table, nodes, id)?;
+        let mut child = Self::_node(table, nodes, rec.left)?;
+        rec.left = child.right;
+        child.right = rec.id;
+        nodes.insert(rec.id, rec);
+        nodes.insert(child.id, child);
+        Self::_update_height(table, nodes, rec.id)?;
+        Self::_update_height(table, nodes, child.id)?;
+        Ok(child.id)
+    }
+
+    fn _update_height(
+                table: &Table,
+                nodes: &mut HashMap<usize, Self>,
+                id: usize
+            ) -> Result<Self, io::Error> {
+        let mut rec = Self::_node(table, nodes, id)?;
+        rec.height = 1 + cmp::max(
+            Self::_height(table, nodes, rec.left)?,
+            Self::_height(table, nodes, rec.right)?
+        );
+        nodes.insert(id, rec);
+        Ok(rec)
+    }
+
+    fn _extreme(
+                table: &Table,
+                rev: bool
+            ) -> Result<Option<(T, usize)>, io::Error> {
+        let mut id = Self::header(table)?.root;
+        let mut found = None;
+
+        while id > 0 {
+            let rec = Self::get(table, id)?;
+            found = Some((rec.value, rec.table_id));
+            id = if rev { rec.right } else { rec.left };
+        }
+
+        Ok(found)
+    }
+
+    fn _height(
+                table: &Table,
+                nodes: &HashMap<usize, Self>,
+                id: usize
+            ) -> Result<usize, io::Error> {
+        if id == 0 {
+            Ok(0)
+        } else {
+            Ok(Self::_node(table, nodes, id)?.height)
+        }
+    }
+
+    fn _node(
+                table: &Table,
+                nodes: &HashMap<usize, Self>,
+                id: usize
+            ) -> Result<Self, io::Error> {
+        match nodes.get(&id) {
+            Some(rec) => Ok(*rec),
+            None => Self::get(table, id),
+        }
+    }
+
+    fn _repin(table: &Table) -> Result<(), io::Error> {
+        // The rotations change the upper levels
+        if table.pin_capacity() > 0 {
+            Self::pin_levels(table)?;
+        }
+        Ok(())
+    }
+
+    fn _check_format(path: &str) -> Result<(), TableError> {
+        let mut file = match fs::File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+
+        // The files of the versions before the headers keep the nodes
+        // without the height from the start, so they are a whole number of
+        // the shorter blocks and do not start with the magic of a table
+        let len = file.metadata()?.len() as usize;
+        let mut magic = Vec::new();
+        (&mut file).take(TABLE_MAGIC.len() as u64).read_to_end(&mut magic)?;
+        let legacy_size = Self::block_size() - mem::size_of::<usize>();
+        if (magic.len() == TABLE_MAGIC.len()) && (magic != TABLE_MAGIC) &&
+                len.is_multiple_of(legacy_size) {
+            return Err(TableError::LegacyIndex);
+        }
+        Ok(())
+    }
+
+    fn _legacy_key_tag() -> u64 {
+        // The tag of the earlier versions was made of the type name given
+        // by the compiler, it is not stable between builds
+        key_tag_of(
+            &format!("{}/{}", any::type_name::<T>(), mem::size_of::<T>())
+        )
+    }
+
+    fn _median_order(size: usize) -> Vec<usize> {
+        let mut order = Vec::with_capacity(size);
+        let mut ranges = VecDeque::new();
+        ranges.push_back((0, size));
+
+        while let Some((start, end)) = ranges.pop_front() {
+            if start < end {
+                let mid = (start + end) / 2;
+                order.push(mid);
+                ranges.push_back((start, mid));
+                ranges.push_back((mid + 1, end));
+            }
+        }
+
+        order
+    }
+
+    fn _link_balanced(
+                links: &mut [(usize, usize, usize)],
+                start: usize,
+                end: usize
+            ) -> (usize, usize) {
+        // Links the range of the sorted nodes under its middle, returns
+        // the position of the middle counted from 1 (0 for an empty range)
+        // and the height of the subtree
+        if start >= end {
+            return (0, 0);
+        }
+
+        let mid = (start + end) / 2;
+        let (left, left_height) = Self::_link_balanced(links, start, mid);
+        let (right, right_height) = Self::_link_balanced(links, mid + 1, end);
+        let height = 1 + cmp::max(left_height, right_height);
+        links[mid] = (left, right, height);
+
+        (mid + 1, height)
+    }
+
+    fn _root_stack(table: &Table) -> Result<Vec<(Self, u8)>, io::Error> {
+        Ok(match Self::header(table)?.root {
+            0 => Vec::new(),
+            root => vec![(Self::get(table, root)?, 0u8)],
+        })
+    }
+
+    fn _build_stack_from(
+                table: &Table,
+                value: &T
+            ) -> Result<Vec<(Self, u8)>, io::Error> {
+        // Rotations may move equal values to the left subtree, so the left
+        // subtree is visited on equal values too
         let mut stack = Vec::new();
 
-        let mut id = Self::get_first_id(table).unwrap();
+        let mut id = Self::header(table)?.root;
 
         while id > 0 {
-            let rec = Self::get(table, id).unwrap();
+            let rec = Self::get(table, id)?;
 
-            if *value < rec.value {
+            if *value <= rec.value {
                 stack.push((rec, 1u8));
                 id = rec.left;
-            } else if *value > rec.value {
+            } else {
                 stack.push((rec, 3u8));
                 id = rec.right;
-            } else {
-                stack.push((rec, 1u8));
-                break;
             }
         }
 
-        stack
+        Ok(stack)
     }
 
     fn _iter_by_value(
                 table: &'a Table,
                 value: &'a T
-            ) -> Box<dyn Iterator<Item = Self> + 'a> {
-        let mut id = Self::get_first_id(table).unwrap();
+            ) -> Box<dyn Iterator<Item = Result<Self, io::Error>> + 'a> {
+        let stack = Self::_build_stack_from(table, value);
+
+        Box::new(
+            Self::_iter_stack(table, stack).take_while(move |rec| {
+                rec.as_ref().map_or(true, |rec| rec.value == *value)
+            })
+        )
+    }
+
+    fn _build_stack_to(
+                table: &Table,
+                to: Bound<&T>
+            ) -> Result<Vec<(Self, u8)>, io::Error> {
+        // The stack of the reversed walk: the nodes below the bound are
+        // yielded and then their left subtrees are visited
+        let mut stack = Vec::new();
+
+        let mut id = Self::header(table)?.root;
+
+        while id > 0 {
+            let rec = Self::get(table, id)?;
+            let below = match to {
+                Bound::Included(value) => rec.value <= *value,
+                Bound::Excluded(value) => rec.value < *value,
+                Bound::Unbounded => true,
+            };
+
+            if below {
+                stack.push((rec, 1u8));
+                id = rec.right;
+            } else {
+                stack.push((rec, 3u8));
+                id = rec.left;
+            }
+        }
+
+        Ok(stack)
+    }
+
+    fn _table_ids(
+                nodes: Box<dyn Iterator<Item = Result<Self, io::Error>> + 'a>
+            ) -> Box<dyn Iterator<Item = Result<usize, io::Error>> + 'a> {
+        Box::new(
+            nodes
+                .filter(|rec| rec.as_ref().map_or(true, |rec| rec.table_id > 0))
+                .map(|rec| rec.map(|rec| rec.table_id))
+        )
+    }
+
+    fn _iter_stack(
+                table: &'a Table,
+                stack: Result<Vec<(Self, u8)>, io::Error>
+            ) -> Box<dyn Iterator<Item = Result<Self, io::Error>> + 'a> {
+        Self::_walk_stack(table, stack, false)
+    }
+
+    fn _iter_stack_rev(
+                table: &'a Table,
+                stack: Result<Vec<(Self, u8)>, io::Error>
+            ) -> Box<dyn Iterator<Item = Result<Self, io::Error>> + 'a> {
+        Self::_walk_stack(table, stack, true)
+    }
+
+    fn _walk_stack(
+                table: &'a Table,
+                stack: Result<Vec<(Self, u8)>, io::Error>,
+                rev: bool
+            ) -> Box<dyn Iterator<Item = Result<Self, io::Error>> + 'a> {
+        // The states of a node: 0 - visit the first subtree, 1 - yield
+        // the node, 2 - visit the second subtree, 3 - done. The first
+        // subtree is the left one in the ascending order
+        let children = move |rec: &Self| {
+            if rev { (rec.right, rec.left) } else { (rec.left, rec.right) }
+        };
+
+        let mut stack = match stack {
+            Ok(stack) => stack,
+            Err(err) => return Box::new(iter::once(Err(err))),
+        };
 
         Box::new(iter::from_fn(move || {
-            while id > 0 {
-                let rec = Self::get(table, id).unwrap();
+            let mut result = None;
 
-                if *value < rec.value {
-                    id = rec.left;
-                } else {
-                    id = rec.right;
+            while let Some(last) = stack.last_mut() {
 
-                    if *value == rec.value {
-                        return Some(rec);
+                if last.1 == 0 {
+                    last.1 = 1;
+                    let first = children(&last.0).0;
+                    if first > 0 {
+                        match Self::get(table, first) {
+                            Ok(rec) => stack.push((rec, 0)),
+                            Err(err) => {
+                                stack.clear();
+                                return Some(Err(err));
+                            },
+                        }
                     }
+                    continue;
+                }
+
+                if last.1 == 1 {
+                    last.1 = 2;
+                    result = Some(Ok(last.0));
+                    break;
+                }
+
+                if last.1 == 2 {
+                    last.1 = 3;
+                    let second = children(&last.0).1;
+                    if second > 0 {
+                        match Self::get(table, second) {
+                            Ok(rec) => stack.push((rec, 0)),
+                            Err(err) => {
+                                stack.clear();
+                                return Some(Err(err));
+                            },
+                        }
+                    }
+                    continue;
+                }
+
+                if last.1 == 3 {
+                    stack.remove(stack.len() - 1);
+                    continue;
                 }
             }
-            return None;
+
+            result
         }))
     }
-
-    // fn _iter_stack(table: &'a Table, stack: &'a mut Vec<(Self, u8)>) -> Box<dyn Iterator<Item = usize> + 'a> {
-    //     Box::new(iter::from_fn(move || {
-    //         let mut result = None;
-
-    //         while !stack.is_empty() {
-    //             let last = stack.last_mut().unwrap();
-
-    //             if last.1 == 0 {
-    //                 last.1 = 1;
-    //                 if last.0.left > 0 {
-    //                     let rec = Self::get(table, last.0.left).unwrap();
-    //                     stack.push((rec, 0));
-    //                 }
-    //                 continue;
-    //             }
-
-    //             if last.1 == 1 {
-    //                 last.1 = 2;
-    //                 result = Some(last.0.table_id);
-    //                 break;
-    //             }
-
-    //             if last.1 == 2 {
-    //                 last.1 = 3;
-    //                 if last.0.right > 0 {
-    //                     let rec = Self::get(table, last.0.right).unwrap();
-    //                     stack.push((rec, 0));
-    //                 }
-    //                 continue;
-    //             }
-
-    //             if last.1 == 3 {
-    //                 stack.remove(stack.len() - 1);
-    //                 continue;
-    //             }
-    //         }
-
-    //         result
-    //     }))
-    // }
 }
 
 
@@ -336,6 +1322,14 @@ mod tests {
 
     const TABLE_PATH: &str = "test-index-person.tbl";
     const TABLE_AGE_INDEX_PATH: &str = "test-index-person-age-index.tbl";
+    const TABLE_SORTED_INDEX_PATH: &str = "test-index-sorted-index.tbl";
+    const TABLE_UNIQUE_INDEX_PATH: &str = "test-index-unique-index.tbl";
+    const TABLE_COMPACT_INDEX_PATH: &str = "test-index-compact-index.tbl";
+    const TABLE_COMPACTED_INDEX_PATH: &str = "test-index-compacted-index.tbl";
+    const TABLE_CACHED_INDEX_PATH: &str = "test-index-cached-index.tbl";
+    const TABLE_EXPLAIN_INDEX_PATH: &str = "test-index-explain-index.tbl";
+    const TABLE_BULK_INDEX_PATH: &str = "test-index-bulk-index.tbl";
+    const TABLE_LEGACY_INDEX_PATH: &str = "test-index-legacy-index.tbl";
 
     #[derive(Debug, Copy, Clone)]
     struct Person {
@@ -385,8 +1379,12 @@ mod tests {
     fn test_table_index() {
         _ensure_removed_tables();
 
-        let table = Table::new::<Person>(TABLE_PATH);
-        let age_index = Table::new::<TableIndex::<u32>>(TABLE_AGE_INDEX_PATH);
+        let table = Table::open::<Person>(TABLE_PATH).unwrap();
+        let age_index = Table::open::<TableIndex::<u32>>(
+            TABLE_AGE_INDEX_PATH
+        ).unwrap();
+
+        assert_eq!(TableIndex::<u32>::min(&age_index).unwrap(), None);
 
         // Insert a person with index
         let mut alex = Person::new("alex", 32);
@@ -396,9 +1394,445 @@ mod tests {
         alex.update_age(33, &age_index).unwrap();
         alex.update(&table).unwrap();
 
+        // Iterate records
+        for (name, age) in &[("bob", 25), ("carl", 40), ("dan", 30)] {
+            Person::new(name, *age).insert_with_index(&table, &age_index)
+                .unwrap();
+        }
+        let header = TableIndex::<u32>::header(&age_index).unwrap();
+        assert_eq!(header.root, 3);
+        assert_eq!(header.count, 4);
+        assert_eq!(header.height, 3);
+
+        let names: Vec<String> = TableIndex::iter_records_between::<Person>(
+            &age_index, &table, &25u32, &40, 2
+        ).map(|p| p.unwrap().name.to_string()).collect();
+        assert_eq!(names, vec!["bob", "dan", "alex"]);
+
+        // Add batch
+        let entries: Vec<(u32, usize)> = (0..20)
+            .map(|i| (i * 5, 100 + i as usize)).rev().collect();
+        TableIndex::add_batch(&age_index, &entries).unwrap();
+        assert_eq!(age_index.size().unwrap(), 26);
+        assert_eq!(TableIndex::search_one(&age_index, &35u32).unwrap(), 107);
+        let ids: Vec<usize> = TableIndex::<u32>::iter_between(
+            &age_index, &30, &36
+        ).collect::<Result<_, _>>().unwrap();
+        assert_eq!(ids, vec![4, 106, 1, 107]);
+        assert_eq!(TableIndex::<u32>::header(&age_index).unwrap().count, 24);
+
+        // Open-ended ranges
+        let ids: Vec<usize> = TableIndex::<u32>::iter_from(
+            &age_index, Bound::Excluded(&90)
+        ).collect::<Result<_, _>>().unwrap();
+        assert_eq!(ids, vec![119]);
+        let ids: Vec<usize> = TableIndex::<u32>::iter_from(
+            &age_index, Bound::Included(&90)
+        ).collect::<Result<_, _>>().unwrap();
+        assert_eq!(ids, vec![118, 119]);
+        let ids: Vec<usize> = TableIndex::<u32>::iter_to(
+            &age_index, Bound::Included(&5)
+        ).collect::<Result<_, _>>().unwrap();
+        assert_eq!(ids, vec![100, 101]);
+        let ids: Vec<usize> = TableIndex::<u32>::iter_to(
+            &age_index, Bound::Excluded(&5)
+        ).collect::<Result<_, _>>().unwrap();
+        assert_eq!(ids, vec![100]);
+        let ids: Vec<usize> = TableIndex::<u32>::iter_range(
+            &age_index, Bound::Excluded(&30), Bound::Included(&35)
+        ).collect::<Result<_, _>>().unwrap();
+        assert_eq!(ids, vec![1, 107]);
+        assert_eq!(TableIndex::<u32>::iter_range(
+            &age_index, Bound::Unbounded, Bound::Unbounded
+        ).count(), 24);
+
+        // Reversed order
+        let ids: Vec<usize> = TableIndex::<u32>::iter_between_rev(
+            &age_index, &30, &36
+        ).collect::<Result<_, _>>().unwrap();
+        assert_eq!(ids, vec![107, 1, 106, 4]);
+        let ids: Vec<usize> = TableIndex::<u32>::iter_rev(&age_index)
+            .take(3).collect::<Result<_, _>>().unwrap();
+        assert_eq!(ids, vec![119, 118, 117]);
+        let ids: Vec<usize> = TableIndex::<u32>::iter_range_rev(
+            &age_index, Bound::Excluded(&25), Bound::Included(&30)
+        ).collect::<Result<_, _>>().unwrap();
+        assert_eq!(ids, vec![106, 4]);
+        let mut ids: Vec<usize> = TableIndex::<u32>::iter_rev(&age_index)
+            .collect::<Result<_, _>>().unwrap();
+        ids.reverse();
+        assert_eq!(ids, TableIndex::<u32>::iter(&age_index)
+            .collect::<Result<Vec<_>, _>>().unwrap());
+
+        // Aggregates
+        assert_eq!(TableIndex::<u32>::min(&age_index).unwrap(), Some((0, 100)));
+        assert_eq!(TableIndex::<u32>::max(&age_index).unwrap(),
+                   Some((95, 119)));
+        assert_eq!(TableIndex::<u32>::count_between(
+            &age_index, &30, &36
+        ).unwrap(), 4);
+        assert_eq!(TableIndex::<u32>::count_between(
+            &age_index, &96, &100
+        ).unwrap(), 0);
+
+        // Keyset pages, the ids of the equal values are ascending
+        assert_eq!(TableIndex::<u32>::page_after(&age_index, None, 2).unwrap(),
+                   vec![(0, 100), (5, 101)]);
+        let page = TableIndex::<u32>::page_after(
+            &age_index, Some((25, 2)), 3
+        ).unwrap();
+        assert_eq!(page, vec![(25, 105), (30, 4), (30, 106)]);
+        TableIndex::add(&age_index, &30u32, 5).unwrap();
+        let page = TableIndex::<u32>::page_after(
+            &age_index, Some((30, 4)), 3
+        ).unwrap();
+        assert_eq!(page, vec![(30, 5), (30, 106), (33, 1)]);
+        TableIndex::exclude(&age_index, &30u32, 5).unwrap();
+        assert!(TableIndex::<u32>::page_after(
+            &age_index, Some((95, 119)), 3
+        ).unwrap().is_empty());
+
+        // Wrong value type
+        assert!(TableIndex::add(&age_index, &35u64, 1).is_err());
+        assert!(TableIndex::<u64>::page_after(&age_index, None, 1).is_err());
+        assert!(TableIndex::<u64>::max(&age_index).is_err());
+        assert!(TableIndex::<u64>::iter(&age_index).next().unwrap().is_err());
+
+        // A broken link is an error ending the iteration, not a panic
+        let root = TableIndex::<u32>::header(&age_index).unwrap().root;
+        let mut rec = TableIndex::<u32>::get(&age_index, root).unwrap();
+        rec.left = 1000;
+        rec.update(&age_index).unwrap();
+        let ids: Vec<_> = TableIndex::<u32>::iter(&age_index).collect();
+        assert_eq!(ids.len(), 1);
+        assert!(ids[0].is_err());
+        assert!(TableIndex::search_one(&age_index, &0u32).is_err());
+        assert!(TableIndex::<u32>::entries(&age_index).is_err());
+
         _ensure_removed_tables();
     }
 
+    #[test]
+    fn test_balance() {
+        if fs::metadata(TABLE_SORTED_INDEX_PATH).is_ok() {
+            fs::remove_file(TABLE_SORTED_INDEX_PATH).unwrap();
+        }
+
+        let index = Table::open::<TableIndex<u32>>(
+            TABLE_SORTED_INDEX_PATH
+        ).unwrap();
+
+        // Sorted values with duplicates
+        for id in 1..=1000 {
+            TableIndex::add(&index, &(id as u32 / 2), id).unwrap();
+        }
+        let header = TableIndex::<u32>::header(&index).unwrap();
+        assert_eq!(header.count, 1000);
+        assert!(header.height <= 11);
+
+        let ids: Vec<usize> = TableIndex::search_many(&index, &250u32)
+            .collect::<Result<_, _>>().unwrap();
+        assert_eq!(ids, vec![500, 501]);
+        TableIndex::exclude(&index, &250u32, 501).unwrap();
+        assert_eq!(TableIndex::search_one(&index, &250u32).unwrap(), 500);
+        assert_eq!(TableIndex::search_many(&index, &250u32).count(), 1);
+        assert!(TableIndex::exclude(&index, &250u32, 501).is_err());
+
+        let ids: Vec<usize> = TableIndex::<u32>::iter(&index)
+            .collect::<Result<_, _>>().unwrap();
+        assert_eq!(ids.len(), 999);
+        assert_eq!(ids[..3], [1, 2, 3]);
+
+        // Estimated count (the exact one is 200)
+        let count = TableIndex::estimate_count_between(
+            &index, &100, &200, 200
+        ).unwrap();
+        assert!((150..=250).contains(&count));
+        assert_eq!(
+            TableIndex::estimate_count_between(&index, &600, &700, 10).unwrap(),
+            0
+        );
+
+        // Removal keeps the tree balanced
+        for id in (2..=1000).step_by(2) {
+            TableIndex::exclude(&index, &(id as u32 / 2), id).unwrap();
+        }
+        let header = TableIndex::<u32>::header(&index).unwrap();
+        assert_eq!(header.count, 499);
+        assert!(header.height <= 10);
+        let ids: Vec<usize> = TableIndex::<u32>::iter(&index)
+            .collect::<Result<_, _>>().unwrap();
+        assert_eq!(ids.len(), 499);
+        assert!(ids.iter().all(|id| id % 2 == 1));
+        assert_eq!(TableIndex::search_one(&index, &300u32).unwrap(), 601);
+        TableIndex::<u32>::check(&index).unwrap();
+
+        // Broken structure
+        let mut header = TableIndex::<u32>::header(&index).unwrap();
+        header.count += 1;
+        header.write(&index).unwrap();
+        let err = TableIndex::<u32>::check(&index).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        fs::remove_file(TABLE_SORTED_INDEX_PATH).unwrap();
+    }
+
+    #[test]
+    fn test_compact() {
+        for path in &[TABLE_COMPACT_INDEX_PATH, TABLE_COMPACTED_INDEX_PATH] {
+            if fs::metadata(path).is_ok() {
+                fs::remove_file(path).unwrap();
+            }
+        }
+
+        let index = Table::open::<TableIndex<u32>>(
+            TABLE_COMPACT_INDEX_PATH
+        ).unwrap();
+        for id in 1..=100 {
+            TableIndex::add(&index, &(id as u32 % 10), id).unwrap();
+        }
+        for id in 1..=90 {
+            TableIndex::exclude(&index, &(id as u32 % 10), id).unwrap();
+        }
+        assert_eq!(index.size().unwrap(), 101);
+
+        // Dead blocks
+        let header = TableIndex::<u32>::header(&index).unwrap();
+        assert_eq!((header.count, header.removed), (10, 90));
+        assert!((header.dead_ratio() - 0.9).abs() < 1e-9);
+        assert!(!TableIndex::<u32>::needs_compaction(&index, 0.95).unwrap());
+        assert!(TableIndex::<u32>::compact_if_needed(
+            &index, TABLE_COMPACTED_INDEX_PATH, 0.95
+        ).unwrap().is_none());
+        assert!(fs::metadata(TABLE_COMPACTED_INDEX_PATH).is_err());
+
+        let compacted = TableIndex::<u32>::compact_if_needed(
+            &index, TABLE_COMPACTED_INDEX_PATH, 0.5
+        ).unwrap().unwrap();
+        assert_eq!(compacted.size().unwrap(), 11);
+        assert_eq!(
+            TableIndex::<u32>::header(&compacted).unwrap().removed, 0
+        );
+        let ids: Vec<usize> = TableIndex::<u32>::iter(&compacted)
+            .collect::<Result<_, _>>().unwrap();
+        assert_eq!(ids, vec![100, 91, 92, 93, 94, 95, 96, 97, 98, 99]);
+        assert!(TableIndex::<u32>::compact(
+            &index, TABLE_COMPACTED_INDEX_PATH
+        ).is_err());
+
+        // Empty tree
+        for id in 91..=100 {
+            TableIndex::exclude(&compacted, &(id as u32 % 10), id).unwrap();
+        }
+        assert_eq!(TableIndex::<u32>::header(&compacted).unwrap().root, 0);
+        assert_eq!(TableIndex::<u32>::iter(&compacted).count(), 0);
+        assert_eq!(TableIndex::search_many(&compacted, &1u32).count(), 0);
+        TableIndex::add(&compacted, &5u32, 5).unwrap();
+        assert_eq!(TableIndex::search_one(&compacted, &5u32).unwrap(), 5);
+
+        for path in &[TABLE_COMPACT_INDEX_PATH, TABLE_COMPACTED_INDEX_PATH] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_bulk_build() {
+        if fs::metadata(TABLE_BULK_INDEX_PATH).is_ok() {
+            fs::remove_file(TABLE_BULK_INDEX_PATH).unwrap();
+        }
+
+        let index = Table::open::<TableIndex<u32>>(
+            TABLE_BULK_INDEX_PATH
+        ).unwrap();
+        let entries = (1..=1000).rev().map(|id| (id as u32 / 2, id));
+        TableIndex::bulk_build(&index, entries).unwrap();
+        TableIndex::<u32>::check(&index).unwrap();
+
+        let header = TableIndex::<u32>::header(&index).unwrap();
+        assert_eq!((header.count, header.height), (1000, 10));
+        assert_eq!(index.size().unwrap(), 1001);
+        let ids: Vec<usize> = TableIndex::<u32>::iter(&index).take(5)
+            .collect::<Result<_, _>>().unwrap();
+        assert_eq!(ids, vec![1, 2, 3, 4, 5]);
+        let mut ids: Vec<usize> = TableIndex::search_many(&index, &250u32)
+            .collect::<Result<_, _>>().unwrap();
+        ids.sort();
+        assert_eq!(ids, vec![500, 501]);
+        assert_eq!(TableIndex::<u32>::max(&index).unwrap(), Some((500, 1000)));
+
+        // Only an index without nodes is built
+        let err = TableIndex::bulk_build(&index, vec![(1u32, 1)]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        TableIndex::add(&index, &1000u32, 1001).unwrap();
+        TableIndex::<u32>::check(&index).unwrap();
+
+        fs::remove_file(TABLE_BULK_INDEX_PATH).unwrap();
+    }
+
+    #[test]
+    fn test_add_unique() {
+        if fs::metadata(TABLE_UNIQUE_INDEX_PATH).is_ok() {
+            fs::remove_file(TABLE_UNIQUE_INDEX_PATH).unwrap();
+        }
+
+        let index = Table::open::<TableIndex<Varchar<20>>>(
+            TABLE_UNIQUE_INDEX_PATH
+        ).unwrap();
+        let alex = Varchar::<20>::new("alex");
+
+        TableIndex::add_unique(&index, &alex, 1).unwrap();
+        TableIndex::add_unique(&index, &Varchar::<20>::new("bob"), 2).unwrap();
+        let err = TableIndex::add_unique(&index, &alex, 3).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+
+        // The value is free again after the exclusion
+        TableIndex::exclude(&index, &alex, 1).unwrap();
+        TableIndex::add_unique(&index, &alex, 3).unwrap();
+        assert_eq!(TableIndex::search_one(&index, &alex).unwrap(), 3);
+
+        // The tag of an earlier version is replaced on the next write
+        type NameIndex = TableIndex<Varchar<20>>;
+        let mut header = NameIndex::header(&index).unwrap();
+        header.key_tag = NameIndex::_legacy_key_tag();
+        header.write(&index).unwrap();
+        let stored = IndexHeader::read(&index).unwrap().unwrap();
+        assert_ne!(stored.key_tag, Varchar::<20>::key_tag());
+        assert_eq!(NameIndex::header(&index).unwrap().key_tag,
+                   Varchar::<20>::key_tag());
+        TableIndex::add_unique(&index, &Varchar::<20>::new("carl"), 4)
+            .unwrap();
+        let stored = IndexHeader::read(&index).unwrap().unwrap();
+        assert_eq!(stored.key_tag, Varchar::<20>::key_tag());
+        assert!(TableIndex::<Varchar<40>>::header(&index).is_err());
+
+        fs::remove_file(TABLE_UNIQUE_INDEX_PATH).unwrap();
+    }
+
+    #[test]
+    fn test_open_cached() {
+        if fs::metadata(TABLE_CACHED_INDEX_PATH).is_ok() {
+            fs::remove_file(TABLE_CACHED_INDEX_PATH).unwrap();
+        }
+
+        let block_size = TableIndex::<u32>::block_size();
+        let index = TableIndex::<u32>::open_cached(
+            TABLE_CACHED_INDEX_PATH, 10 * block_size
+        ).unwrap();
+        assert_eq!(index.pin_capacity(), 10);
+        assert_eq!(TableIndex::<u32>::pin_levels(&index).unwrap(), 0);
+
+        // The header and 3 levels (7 nodes) fit the budget
+        let entries: Vec<(u32, usize)> = (1..=100).map(|id| {
+            (id as u32, id)
+        }).collect();
+        TableIndex::add_batch(&index, &entries).unwrap();
+        assert_eq!(TableIndex::<u32>::pin_levels(&index).unwrap(), 3);
+        TableIndex::exclude(&index, &50u32, 50).unwrap();
+        TableIndex::add(&index, &50u32, 150).unwrap();
+        assert_eq!(TableIndex::search_one(&index, &50u32).unwrap(), 150);
+
+        // The pinned header is not read from the disk
+        let other = Table::open::<TableIndex<u32>>(
+            TABLE_CACHED_INDEX_PATH
+        ).unwrap();
+        let header = other.get(0).unwrap();
+        other.update(&vec![0; block_size], 0).unwrap();
+        assert!(TableIndex::search_one(&other, &70u32).is_err());
+        assert_eq!(TableIndex::search_one(&index, &70u32).unwrap(), 70);
+        other.update(&header, 0).unwrap();
+
+        // Not pinned
+        let err = TableIndex::<u32>::pin_levels(&other).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+
+        fs::remove_file(TABLE_CACHED_INDEX_PATH).unwrap();
+    }
+
+    #[test]
+    fn test_explain_search() {
+        if fs::metadata(TABLE_EXPLAIN_INDEX_PATH).is_ok() {
+            fs::remove_file(TABLE_EXPLAIN_INDEX_PATH).unwrap();
+        }
+
+        let block_size = TableIndex::<u32>::block_size();
+        let index = TableIndex::<u32>::open_cached(
+            TABLE_EXPLAIN_INDEX_PATH, 4 * block_size
+        ).unwrap();
+        let entries: Vec<(u32, usize)> = (1..=100).map(|id| {
+            (id as u32, id)
+        }).collect();
+        TableIndex::add_batch(&index, &entries).unwrap();
+
+        // The header and 2 pinned levels are taken from the cache
+        let trace = TableIndex::explain_search(&index, &70u32).unwrap();
+        assert_eq!(trace.found, vec![70]);
+        assert_eq!(trace.reads[0].idx, 0);
+        assert_eq!(trace.reads[0].comparison, None);
+        assert!(trace.reads[..3].iter().all(|read| read.cache_hit));
+        assert!(trace.misses() > 0);
+        assert!(trace.reads.iter().all(|read| {
+            read.table == TABLE_EXPLAIN_INDEX_PATH
+        }));
+        assert!(trace.reads[1..].iter().all(|read| {
+            read.comparison.is_some()
+        }));
+        assert_eq!(trace.reads.iter().filter(|read| {
+            read.comparison == Some(cmp::Ordering::Equal)
+        }).count(), 1);
+
+        // Not found
+        let trace = TableIndex::explain_search(&index, &500u32).unwrap();
+        assert!(trace.found.is_empty());
+        assert!(trace.reads[1..].iter().all(|read| {
+            read.comparison == Some(cmp::Ordering::Greater)
+        }));
+
+        // Nested traces
+        let ((id, inner), outer) = trace_reads(|| {
+            let node = index.get(5).unwrap();
+            (5, trace_reads(|| index.get(7).unwrap()).1.len() + node.len())
+        });
+        assert_eq!(id, 5);
+        assert_eq!(inner, 1 + block_size);
+        assert_eq!(outer.iter().map(|read| read.idx).collect::<Vec<_>>(),
+                   vec![5, 7]);
+        assert!(trace_reads(|| ()).1.is_empty());
+
+        fs::remove_file(TABLE_EXPLAIN_INDEX_PATH).unwrap();
+    }
+
+    #[test]
+    fn test_legacy_index() {
+        if fs::metadata(TABLE_LEGACY_INDEX_PATH).is_ok() {
+            fs::remove_file(TABLE_LEGACY_INDEX_PATH).unwrap();
+        }
+
+        // Two nodes without the heights and without the headers
+        let legacy_size = TableIndex::<u32>::block_size() - 8;
+        let mut nodes = vec![0u8; 2 * legacy_size];
+        nodes[0] = 1;
+        nodes[legacy_size] = 2;
+        fs::write(TABLE_LEGACY_INDEX_PATH, &nodes).unwrap();
+        assert!(matches!(
+            TableIndex::<u32>::open(TABLE_LEGACY_INDEX_PATH),
+            Err(TableError::LegacyIndex)
+        ));
+        assert!(matches!(
+            TableIndex::<u32>::open_cached(TABLE_LEGACY_INDEX_PATH, 1024),
+            Err(TableError::LegacyIndex)
+        ));
+
+        // The current files are opened
+        fs::remove_file(TABLE_LEGACY_INDEX_PATH).unwrap();
+        let index = TableIndex::<u32>::open(TABLE_LEGACY_INDEX_PATH).unwrap();
+        TableIndex::add(&index, &5u32, 1).unwrap();
+        drop(index);
+        let index = TableIndex::<u32>::open(TABLE_LEGACY_INDEX_PATH).unwrap();
+        assert_eq!(TableIndex::search_one(&index, &5u32).unwrap(), 1);
+
+        fs::remove_file(TABLE_LEGACY_INDEX_PATH).unwrap();
+    }
+
     fn _ensure_removed_tables() {
         if fs::metadata(TABLE_PATH).is_ok() {
             fs::remove_file(TABLE_PATH).unwrap();
@@ -0,0 +1,159 @@
+use std::io;
+use std::io::Write;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::table::*;
+use crate::table_trait::*;
+use crate::table_import::*;
+
+
+impl Table {
+    /// Writes the records **T** of the table as a JSON array (a record per
+    /// line) skipping the erased ones, so the table can be inspected with
+    /// tools like *jq*. Returns the number of written records.
+    pub fn dump_json<T>(
+                &self,
+                writer: impl io::Write
+            ) -> Result<usize, io::Error>
+            where T: TableTrait + Serialize {
+        let mut writer = io::BufWriter::new(writer);
+        writer.write_all(b"[")?;
+
+        let mut count = 0;
+        for record in T::all(self) {
+            let record = record?;
+            if record.id() == 0 {
+                continue;
+            }
+            writer.write_all(if count == 0 { b"\n" } else { b",\n" })?;
+            serde_json::to_writer(&mut writer, &record)?;
+            count += 1;
+        }
+
+        writer.write_all(if count == 0 { b"]\n" } else { b"\n]\n" })?;
+        writer.flush()?;
+        Ok(count)
+    }
+
+    /// Reads the array of records written by **dump_json** and imports them
+    /// keeping their ids like **import_records**. Malformed JSON is
+    /// an error of kind *InvalidData*.
+    pub fn load_json<T>(
+                &self,
+                reader: impl io::Read,
+                policy: ImportPolicy
+            ) -> Result<ImportReport, io::Error>
+            where T: TableTrait + DeserializeOwned {
+        let records: Vec<T> = serde_json::from_reader(
+            io::BufReader::new(reader)
+        )?;
+        import_records(self, records.into_iter(), policy)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde::Deserialize;
+
+    use crate::bytes::*;
+    use crate::varchar::*;
+    use super::*;
+
+    const TABLE_PATH: &str = "test-json-person.tbl";
+    const TABLE_LOADED_PATH: &str = "test-json-person-loaded.tbl";
+
+    #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+    struct Person {
+        id: usize,
+        name: Varchar<20>,
+        age: u32,
+        tag: Bytes<4>,
+    }
+
+    impl TableTrait for Person {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    #[test]
+    fn test_json() {
+        _ensure_removed_tables();
+
+        let table = Table::open::<Person>(TABLE_PATH).unwrap();
+        for (name, age) in &[("alex", 32), ("bob \"jr\"", 25), ("carl", 40)] {
+            let mut person = Person {
+                id: 0, name: Varchar::<20>::new(name), age: *age,
+                tag: Bytes::<4>::new(&[1, 2]),
+            };
+            person.insert(&table).unwrap();
+        }
+        Person::erase(&table, 3).unwrap();
+
+        // Dump
+        let mut buffer = Vec::new();
+        assert_eq!(table.dump_json::<Person>(&mut buffer).unwrap(), 2);
+        let text = String::from_utf8(buffer.clone()).unwrap();
+        assert_eq!(text, concat!(
+            "[\n",
+            "{\"id\":1,\"name\":\"alex\",\"age\":32,\"tag\":[1,2]},\n",
+            "{\"id\":2,\"name\":\"bob \\\"jr\\\"\",\"age\":25,\"tag\":[1,2]}\n",
+            "]\n"
+        ));
+
+        // Load
+        let loaded = Table::open::<Person>(TABLE_LOADED_PATH).unwrap();
+        let report = loaded.load_json::<Person>(
+            &buffer[..], ImportPolicy::FailOnConflict
+        ).unwrap();
+        assert_eq!(report.inserted, 2);
+        let bob = Person::get(&loaded, 2).unwrap();
+        assert_eq!(bob.name.to_string(), "bob \"jr\"");
+        assert_eq!(bob.tag, Bytes::<4>::new(&[1, 2]));
+
+        // No records
+        Person::erase(&table, 1).unwrap();
+        Person::erase(&table, 2).unwrap();
+        let mut buffer = Vec::new();
+        assert_eq!(table.dump_json::<Person>(&mut buffer).unwrap(), 0);
+        assert_eq!(buffer, b"[]\n");
+
+        // Wrong data
+        let err = loaded.load_json::<Person>(
+            &b"[{\"id\":5,\"name\":\"alex\"}]"[..], ImportPolicy::Overwrite
+        ).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let long = format!(
+            "[{{\"id\":5,\"name\":\"{}\",\"age\":1,\"tag\":[]}}]",
+            "x".repeat(21)
+        );
+        let err = loaded.load_json::<Person>(
+            long.as_bytes(), ImportPolicy::Overwrite
+        ).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let err = loaded.load_json::<Person>(
+            &b"[{\"id\":5,\"name\":\"a\",\"age\":1,\"tag\":[1,2,3,4,5]}]"[..],
+            ImportPolicy::Overwrite
+        ).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        _ensure_removed_tables();
+    }
+
+    fn _ensure_removed_tables() {
+        for path in &[TABLE_PATH, TABLE_LOADED_PATH] {
+            if fs::metadata(path).is_ok() {
+                fs::remove_file(path).unwrap();
+            }
+        }
+    }
+}
@@ -0,0 +1,282 @@
+use std::{fmt, io};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::table::*;
+use crate::table_trait::*;
+use crate::table_error::*;
+
+
+/// The size of a chained HMAC-SHA256 code in bytes.
+pub const LEDGER_MAC_SIZE: usize = 32;
+
+
+/// TableLedger is a record that has TableTrait implemented, so it keeps its
+/// own table file next to the data table. Every record of the ledger stores
+/// the HMAC of the data block with the same id chained with the code of the
+/// previous block, so any modification of the history is detected.
+#[derive(Debug, Copy, Clone)]
+pub struct TableLedger {
+    id: usize,
+    mac: [u8; LEDGER_MAC_SIZE],
+}
+
+
+impl TableTrait for TableLedger {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn set_id(&mut self, id: usize) {
+        self.id = id;
+    }
+}
+
+
+impl TableLedger {
+    /// Signs the block of the record **table_id** from the data table and
+    /// appends the chained code to the ledger. Records must be signed in
+    /// the order of insertion.
+    pub fn sign(
+                table: &Table,
+                ledger: &Table,
+                key: &[u8],
+                table_id: usize
+            ) -> Result<(), io::Error> {
+        if table_id != ledger.size()? + 1 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput, table_id.to_string()
+            ));
+        }
+
+        let block = table.get(table_id - 1)?;
+        let prev = Self::_prev_mac(ledger, table_id)?;
+
+        let mut record = Self { id: 0, mac: Self::_mac(key, &prev, &block)? };
+        record.insert(ledger)?;
+        Ok(())
+    }
+
+    /// Verifies the whole chain: every block of the data table must be
+    /// signed and match its code. The id of the first broken record is
+    /// returned in the error.
+    pub fn verify_chain(
+                table: &Table,
+                ledger: &Table,
+                key: &[u8]
+            ) -> Result<(), io::Error> {
+        if table.size()? != ledger.size()? {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData, "ledger size"
+            ));
+        }
+
+        let mut prev = [0u8; LEDGER_MAC_SIZE];
+
+        for (idx, (block, rec)) in table.iter()
+                .zip(Self::all(ledger)).enumerate() {
+            let mac = Self::_mac(key, &prev, &block?)?;
+            if mac != rec?.mac {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData, (idx + 1).to_string()
+                ));
+            }
+            prev = mac;
+        }
+
+        Ok(())
+    }
+
+    fn _prev_mac(
+                ledger: &Table,
+                table_id: usize
+            ) -> Result<[u8; LEDGER_MAC_SIZE], io::Error> {
+        if table_id > 1 {
+            Ok(Self::get(ledger, table_id - 1)?.mac)
+        } else {
+            Ok([0u8; LEDGER_MAC_SIZE])
+        }
+    }
+
+    fn _mac(
+                key: &[u8],
+                prev: &[u8],
+                block: &[u8]
+            ) -> Result<[u8; LEDGER_MAC_SIZE], io::Error> {
+        let mut hmac = Hmac::<Sha256>::new_from_slice(key).map_err(
+            |_| io::Error::new(io::ErrorKind::InvalidInput, "key")
+        )?;
+        hmac.update(prev);
+        hmac.update(block);
+
+        let mut mac = [0u8; LEDGER_MAC_SIZE];
+        mac.clone_from_slice(&hmac.finalize().into_bytes());
+        Ok(mac)
+    }
+}
+
+
+/// The ledger of a table opened by **Table::with_ledger**, the appended
+/// blocks are signed into it by the table.
+pub(crate) struct Ledger {
+    table: Box<Table>,
+    key: Vec<u8>,
+}
+
+
+impl Ledger {
+    pub(crate) fn open(path: &str, key: &[u8]) -> Result<Self, TableError> {
+        let table = Table::open::<TableLedger>(path)?;
+        Ok(Self { table: Box::new(table), key: key.to_vec() })
+    }
+
+    /// The number of signed blocks.
+    pub(crate) fn size(&self) -> Result<usize, io::Error> {
+        self.table.size()
+    }
+
+    pub(crate) fn sign(
+                &self,
+                table: &Table,
+                table_id: usize
+            ) -> Result<(), io::Error> {
+        TableLedger::sign(table, &self.table, &self.key, table_id)
+    }
+
+    pub(crate) fn verify(&self, table: &Table) -> Result<(), io::Error> {
+        TableLedger::verify_chain(table, &self.table, &self.key)
+    }
+}
+
+
+impl fmt::Debug for Ledger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The key is not shown
+        write!(f, "Ledger({})", self.table.path())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    const TABLE_PATH: &str = "test-ledger-person.tbl";
+    const TABLE_LEDGER_PATH: &str = "test-ledger-person-ledger.tbl";
+    const TABLE_SIGNED_PATH: &str = "test-ledger-signed.tbl";
+    const KEY: &[u8] = b"secret";
+
+    #[derive(Debug, Copy, Clone)]
+    struct Person {
+        id: usize,
+        age: u32,
+    }
+
+    impl TableTrait for Person {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    impl Person {
+        fn new(age: u32) -> Self {
+            Self { id: 0, age }
+        }
+    }
+
+    #[test]
+    fn test_table_ledger() {
+        _ensure_removed_tables();
+
+        let table = Table::open::<Person>(TABLE_PATH).unwrap();
+        let ledger = Table::open::<TableLedger>(TABLE_LEDGER_PATH).unwrap();
+
+        // Insert and sign
+        for age in &[32, 25, 40] {
+            let id = Person::new(*age).insert(&table).unwrap();
+            TableLedger::sign(&table, &ledger, KEY, id).unwrap();
+        }
+        TableLedger::verify_chain(&table, &ledger, KEY).unwrap();
+
+        // Wrong key
+        assert!(TableLedger::verify_chain(&table, &ledger, b"other").is_err());
+
+        // Tamper
+        let mut bob = Person::get(&table, 2).unwrap();
+        bob.age = 26;
+        bob.update(&table).unwrap();
+        let err = TableLedger::verify_chain(&table, &ledger, KEY).unwrap_err();
+        assert_eq!(err.to_string(), "2");
+
+        // Unsigned record
+        Person::new(19).insert(&table).unwrap();
+        assert!(TableLedger::verify_chain(&table, &ledger, KEY).is_err());
+
+        _ensure_removed_tables();
+    }
+
+    #[test]
+    fn test_with_ledger() {
+        let paths = [
+            TABLE_SIGNED_PATH.to_string(),
+            format!("{}.ledger", TABLE_SIGNED_PATH),
+            format!("{}.free", TABLE_SIGNED_PATH),
+        ];
+        for path in paths.iter() {
+            if fs::metadata(path).is_ok() {
+                fs::remove_file(path).unwrap();
+            }
+        }
+
+        // The inserts are signed by the table
+        let table = Table::with_ledger::<Person>(TABLE_SIGNED_PATH, KEY)
+            .unwrap();
+        let mut alex = Person::new(32);
+        alex.insert(&table).unwrap();
+        Person::new(25).insert(&table).unwrap();
+        let mut persons = [Person::new(40), Person::new(19)];
+        Person::insert_many(&mut persons, &table).unwrap();
+        table.verify_ledger().unwrap();
+
+        // A deleted block is not reused
+        alex.delete(&table).unwrap();
+        assert_eq!(Person::new(22).insert(&table).unwrap(), 5);
+        let err = table.verify_ledger().unwrap_err();
+        assert_eq!(err.to_string(), "1");
+
+        // Reopen with another key
+        drop(table);
+        let table = Table::with_ledger::<Person>(TABLE_SIGNED_PATH, b"other")
+            .unwrap();
+        assert!(table.verify_ledger().is_err());
+
+        // A block appended without the ledger
+        drop(table);
+        let table = Table::open::<Person>(TABLE_SIGNED_PATH).unwrap();
+        Person::insert_many(&mut [Person::new(27)], &table).unwrap();
+        let err = table.verify_ledger().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        drop(table);
+        assert!(Table::with_ledger::<Person>(TABLE_SIGNED_PATH, KEY).is_err());
+
+        for path in paths.iter() {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    fn _ensure_removed_tables() {
+        if fs::metadata(TABLE_PATH).is_ok() {
+            fs::remove_file(TABLE_PATH).unwrap();
+        }
+        if fs::metadata(TABLE_LEDGER_PATH).is_ok() {
+            fs::remove_file(TABLE_LEDGER_PATH).unwrap();
+        }
+    }
+}
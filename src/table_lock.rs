@@ -0,0 +1,265 @@
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, ThreadId};
+
+
+#[derive(Debug, Default)]
+struct LockState {
+    owner: Option<ThreadId>,
+    writers: usize,
+}
+
+
+/// TableLock synchronizes in-process writers of a table with an exclusive
+/// owner. Writes from the thread that holds the exclusive guard pass
+/// through, so the owner can keep using the usual methods.
+#[derive(Debug, Default)]
+pub(crate) struct TableLock {
+    state: Mutex<LockState>,
+    cond: Condvar,
+}
+
+
+/// A guard of a single write, it is held while the block is written.
+pub(crate) struct WriteGuard<'a> {
+    lock: &'a TableLock,
+    counted: bool,
+}
+
+
+/// An RAII guard returned by **Table::exclusive**. Other threads cannot
+/// write to the table until it is dropped.
+#[derive(Debug)]
+pub struct ExclusiveGuard<'a> {
+    lock: &'a TableLock,
+}
+
+
+impl TableLock {
+    /// Waits until no other thread holds the exclusive guard and registers
+    /// a write.
+    pub(crate) fn write(&self) -> WriteGuard<'_> {
+        let me = thread::current().id();
+        let mut state = self._state();
+
+        while state.owner.is_some() && (state.owner != Some(me)) {
+            state = self.cond.wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+
+        let counted = state.owner.is_none();
+        if counted {
+            state.writers += 1;
+        }
+
+        WriteGuard { lock: self, counted }
+    }
+
+    /// Waits until other writes are finished and takes the exclusive
+    /// ownership. It must not be called twice by the same thread.
+    pub(crate) fn exclusive(&self) -> ExclusiveGuard<'_> {
+        let mut state = self._state();
+
+        while state.owner.is_some() || (state.writers > 0) {
+            state = self.cond.wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+
+        state.owner = Some(thread::current().id());
+
+        ExclusiveGuard { lock: self }
+    }
+
+    fn _state(&self) -> MutexGuard<'_, LockState> {
+        // The state is changed by single assignments, so it is consistent
+        // even if a thread panicked holding the mutex
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+
+impl<'a> Drop for WriteGuard<'a> {
+    fn drop(&mut self) {
+        if self.counted {
+            self.lock._state().writers -= 1;
+            self.lock.cond.notify_all();
+        }
+    }
+}
+
+
+impl<'a> Drop for ExclusiveGuard<'a> {
+    fn drop(&mut self) {
+        self.lock._state().owner = None;
+        self.lock.cond.notify_all();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, thread, time};
+    use std::sync::Arc;
+
+    use crate::table::*;
+    use crate::table_trait::*;
+    use super::*;
+
+    const TABLE_PATH: &str = "test-lock-counter.tbl";
+    const TABLE_APPEND_PATH: &str = "test-lock-counter-append.tbl";
+    const TABLE_DELETE_PATH: &str = "test-lock-counter-delete.tbl";
+
+    #[derive(Debug, Copy, Clone)]
+    struct Counter {
+        id: usize,
+        value: u64,
+    }
+
+    impl TableTrait for Counter {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    #[test]
+    fn test_exclusive() {
+        _ensure_removed_table_file();
+
+        let table = Arc::new(Table::open::<Counter>(TABLE_PATH).unwrap());
+
+        let guard = table.exclusive();
+
+        // The owner writes as usual
+        Counter { id: 0, value: 1 }.insert(&table).unwrap();
+
+        // Other writers wait
+        let writer = {
+            let table = table.clone();
+            thread::spawn(move || {
+                Counter { id: 0, value: 2 }.insert(&table).unwrap();
+            })
+        };
+        thread::sleep(time::Duration::from_millis(50));
+        assert_eq!(table.size().unwrap(), 1);
+
+        drop(guard);
+        writer.join().unwrap();
+        assert_eq!(table.size().unwrap(), 2);
+        assert_eq!(Counter::get(&table, 2).unwrap().value, 2);
+
+        _ensure_removed_table_file();
+    }
+
+    #[test]
+    fn test_poisoned() {
+        let lock = Arc::new(TableLock::default());
+
+        // A thread panics holding the state
+        let result = {
+            let lock = lock.clone();
+            thread::spawn(move || {
+                let _state = lock.state.lock().unwrap();
+                panic!("poison");
+            }).join()
+        };
+        assert!(result.is_err());
+        assert!(lock.state.is_poisoned());
+
+        // The lock keeps working
+        drop(lock.exclusive());
+        drop(lock.write());
+        assert_eq!(lock._state().writers, 0);
+    }
+
+    #[test]
+    fn test_concurrent_append() {
+        if fs::metadata(TABLE_APPEND_PATH).is_ok() {
+            fs::remove_file(TABLE_APPEND_PATH).unwrap();
+        }
+
+        let table = Arc::new(
+            Table::open::<Counter>(TABLE_APPEND_PATH).unwrap()
+        );
+
+        let writers: Vec<_> = (0..8u64).map(|thread_id| {
+            let table = table.clone();
+            thread::spawn(move || {
+                (0..500u64).map(|pos| {
+                    let mut counter = Counter {
+                        id: 0, value: thread_id * 1000 + pos
+                    };
+                    counter.insert(&table).unwrap()
+                }).collect::<Vec<usize>>()
+            })
+        }).collect();
+
+        let mut ids = Vec::new();
+        for writer in writers {
+            ids.extend(writer.join().unwrap());
+        }
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 4000);
+        assert_eq!(table.size().unwrap(), 4000);
+
+        // Every record is stored at its own id
+        for counter in Counter::all(&table) {
+            let counter = counter.unwrap();
+            assert!(counter.id > 0);
+            assert_eq!(Counter::get(&table, counter.id).unwrap().value,
+                       counter.value);
+        }
+
+        fs::remove_file(TABLE_APPEND_PATH).unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_delete() {
+        let free_path = format!("{}.free", TABLE_DELETE_PATH);
+        for path in [TABLE_DELETE_PATH, &free_path] {
+            if fs::metadata(path).is_ok() {
+                fs::remove_file(path).unwrap();
+            }
+        }
+
+        let table = Arc::new(
+            Table::open::<Counter>(TABLE_DELETE_PATH).unwrap()
+        );
+        for value in 0..100u64 {
+            Counter { id: 0, value }.insert(&table).unwrap();
+        }
+
+        // Every block is deleted by all threads, only one of them succeeds
+        let deleters: Vec<_> = (0..4).map(|_| {
+            let table = table.clone();
+            thread::spawn(move || {
+                (0..100).filter(|idx| table.delete(*idx).is_ok()).count()
+            })
+        }).collect();
+        let deleted: usize = deleters.into_iter()
+            .map(|deleter| deleter.join().unwrap()).sum();
+        assert_eq!(deleted, 100);
+        assert_eq!(table.free_count().unwrap(), 100);
+
+        // The reused blocks get distinct ids
+        let mut ids: Vec<usize> = (0..100u64).map(|value| {
+            Counter { id: 0, value }.insert(&table).unwrap()
+        }).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 100);
+        assert_eq!(table.size().unwrap(), 100);
+
+        fs::remove_file(TABLE_DELETE_PATH).unwrap();
+        fs::remove_file(&free_path).unwrap();
+    }
+
+    fn _ensure_removed_table_file() {
+        if fs::metadata(TABLE_PATH).is_ok() {
+            fs::remove_file(TABLE_PATH).unwrap();
+        }
+    }
+}
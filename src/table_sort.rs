@@ -0,0 +1,356 @@
+use std::{cmp, env, fs, io, mem, process};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::table::*;
+use crate::table_trait::*;
+use crate::key_ord::*;
+use crate::progress::*;
+
+
+static RUN_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+
+type Comparator<T> = Box<dyn Fn(&T, &T) -> cmp::Ordering>;
+
+
+/// SortOrder is a list of keys to sort the records by, like *ORDER BY* in
+/// SQL: the next key is compared only if the previous ones are equal.
+pub struct SortOrder<T> {
+    keys: Vec<Comparator<T>>,
+}
+
+
+impl<T: 'static> SortOrder<T> {
+    /// Creates an empty order, all records are equal in it.
+    pub fn new() -> Self {
+        Self { keys: Vec::new() }
+    }
+
+    /// Adds the key extracted by **key** in the ascending order.
+    pub fn asc<K: PartialOrd + 'static>(self, key: fn(&T) -> K) -> Self {
+        self.by::<K, Ascending>(key)
+    }
+
+    /// Adds the key extracted by **key** in the descending order.
+    pub fn desc<K: PartialOrd + 'static>(self, key: fn(&T) -> K) -> Self {
+        self.by::<K, Descending>(key)
+    }
+
+    /// Adds the key extracted by **key** in the custom order **O**.
+    pub fn by<K: 'static, O: KeyOrd<K>>(mut self, key: fn(&T) -> K) -> Self {
+        self.keys.push(Box::new(move |a, b| O::compare(&key(a), &key(b))));
+        self
+    }
+
+    /// Compares two records by the keys.
+    pub fn compare(&self, a: &T, b: &T) -> cmp::Ordering {
+        for key in self.keys.iter() {
+            let ordering = key(a, b);
+            if ordering != cmp::Ordering::Equal {
+                return ordering;
+            }
+        }
+        cmp::Ordering::Equal
+    }
+}
+
+
+impl<T: 'static> Default for SortOrder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+struct SortRun<T> {
+    path: String,
+    table: Table,
+    idx: usize,
+    size: usize,
+    head: Option<T>,
+}
+
+
+impl<T: TableTrait> SortRun<T> {
+    fn advance(&mut self) -> Result<Option<T>, io::Error> {
+        // The block is skipped even if it fails to read, the head is kept
+        let mut next = None;
+        if self.idx < self.size {
+            self.idx += 1;
+            next = Some(T::from_bytes(&self.table.get(self.idx - 1)?));
+        }
+        Ok(mem::replace(&mut self.head, next))
+    }
+}
+
+
+/// SortedRecords iterates the records of the table in **SortOrder**.
+/// The records are sorted externally: runs of the records sorted in memory
+/// are written to temporary tables, which are merged on iteration and
+/// removed on drop. Records equal in the order keep their original order.
+/// A record that fails to be read back from its run is yielded as
+/// the error.
+pub struct SortedRecords<T> {
+    order: SortOrder<T>,
+    runs: Vec<SortRun<T>>,
+}
+
+
+impl<T: TableTrait + 'static> Iterator for SortedRecords<T> {
+    type Item = Result<T, io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut best: Option<(usize, &T)> = None;
+
+        for (pos, run) in self.runs.iter().enumerate() {
+            if let Some(head) = &run.head {
+                let better = match best {
+                    Some((_, best_head)) => self.order.compare(
+                        head, best_head
+                    ) == cmp::Ordering::Less,
+                    None => true,
+                };
+                if better {
+                    best = Some((pos, head));
+                }
+            }
+        }
+
+        let pos = best?.0;
+        self.runs[pos].advance().transpose()
+    }
+}
+
+
+impl<T> Drop for SortedRecords<T> {
+    fn drop(&mut self) {
+        for run in self.runs.iter() {
+            fs::remove_file(&run.path).ok();
+        }
+    }
+}
+
+
+/// Sorts the records of the table in **order** keeping at most
+/// **run_size** records in memory. Erased records are skipped, the records
+/// keep their ids.
+pub fn sort_records<T: TableTrait + 'static>(
+            table: &Table,
+            order: SortOrder<T>,
+            run_size: usize
+        ) -> Result<SortedRecords<T>, io::Error> {
+    _sort_records(table, order, run_size, None)
+}
+
+
+/// Exports the records of the table in **order** to a new table located by
+/// **path**, see **sort_records**. The exported records get new ids in
+/// the sorted order.
+pub fn sort_table<T: TableTrait + 'static>(
+            table: &Table,
+            order: SortOrder<T>,
+            run_size: usize,
+            path: &str
+        ) -> Result<Table, io::Error> {
+    _sort_table(table, order, run_size, path, None)
+}
+
+
+/// Exports the records of the table in **order** to a new table like
+/// **sort_table** reporting the *sort* stage (reading the table) and
+/// the *write* stage (writing the sorted records) to **progress**.
+pub fn sort_table_with_progress<T: TableTrait + 'static>(
+            table: &Table,
+            order: SortOrder<T>,
+            run_size: usize,
+            path: &str,
+            progress: &ProgressHandle
+        ) -> Result<Table, io::Error> {
+    _sort_table(table, order, run_size, path, Some(progress))
+}
+
+
+fn _sort_records<T: TableTrait + 'static>(
+            table: &Table,
+            order: SortOrder<T>,
+            run_size: usize,
+            progress: Option<&ProgressHandle>
+        ) -> Result<SortedRecords<T>, io::Error> {
+    let mut sorted = SortedRecords { order, runs: Vec::new() };
+    let mut records: Vec<T> = Vec::with_capacity(run_size.max(1));
+
+    if let Some(progress) = progress {
+        progress.start("sort", Some(table.size()?));
+    }
+
+    for idx in 0..table.size()? {
+        let record = T::from_bytes(&table.get(idx)?);
+        if let Some(progress) = progress {
+            progress.advance(1, table.block_size() as u64);
+        }
+        if record.id() == 0 {
+            continue;
+        }
+        records.push(record);
+        if records.len() >= run_size.max(1) {
+            _write_run(&mut sorted, &mut records)?;
+        }
+    }
+    if !records.is_empty() {
+        _write_run(&mut sorted, &mut records)?;
+    }
+
+    if let Some(progress) = progress {
+        progress.finish();
+    }
+
+    Ok(sorted)
+}
+
+
+fn _sort_table<T: TableTrait + 'static>(
+            table: &Table,
+            order: SortOrder<T>,
+            run_size: usize,
+            path: &str,
+            progress: Option<&ProgressHandle>
+        ) -> Result<Table, io::Error> {
+    if fs::metadata(path).map(|meta| meta.len() > 0).unwrap_or(false) {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, path));
+    }
+
+    let sorted = Table::open::<T>(path)?;
+    let records = _sort_records(table, order, run_size, progress)?;
+
+    if let Some(progress) = progress {
+        let count = records.runs.iter().map(|run| run.size).sum();
+        progress.start("write", Some(count));
+    }
+    for record in records {
+        let mut record = record?;
+        record.set_id(0);
+        record.insert(&sorted)?;
+        if let Some(progress) = progress {
+            progress.advance(1, T::block_size() as u64);
+        }
+    }
+    if let Some(progress) = progress {
+        progress.finish();
+    }
+
+    Ok(sorted)
+}
+
+
+fn _write_run<T: TableTrait + 'static>(
+            sorted: &mut SortedRecords<T>,
+            records: &mut Vec<T>
+        ) -> Result<(), io::Error> {
+    let order = &sorted.order;
+    records.sort_by(|a, b| order.compare(a, b));
+
+    let path = env::temp_dir().join(format!(
+        "mytable-sort-{}-{}.tbl", process::id(),
+        RUN_COUNTER.fetch_add(1, Ordering::SeqCst)
+    )).to_string_lossy().to_string();
+    let mut run = SortRun {
+        table: Table::open::<T>(&path)?, path, idx: 0, size: records.len(),
+        head: None
+    };
+
+    for record in records.drain(..) {
+        run.table.append(record.as_bytes())?;
+    }
+    run.advance()?;
+    sorted.runs.push(run);
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::varchar::*;
+    use super::*;
+
+    const TABLE_PATH: &str = "test-sort-person.tbl";
+    const TABLE_SORTED_PATH: &str = "test-sort-person-sorted.tbl";
+
+    #[derive(Debug, Copy, Clone)]
+    struct Person {
+        id: usize,
+        name: Varchar<20>,
+        age: u32,
+    }
+
+    impl TableTrait for Person {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    #[test]
+    fn test_sort() {
+        _ensure_removed_tables();
+
+        let table = Table::open::<Person>(TABLE_PATH).unwrap();
+        let persons = [
+            ("dan", 25), ("bob", 32), ("eve", 19), ("carl", 32),
+            ("alex", 25), ("fred", 40),
+        ];
+        for (name, age) in persons.iter() {
+            Person {
+                id: 0, name: Varchar::<20>::new(name), age: *age
+            }.insert(&table).unwrap();
+        }
+        Person::erase(&table, 6).unwrap();
+
+        let order = || SortOrder::new()
+            .desc(|p: &Person| p.age)
+            .asc(|p: &Person| p.name.to_string());
+
+        // Iterator
+        let names: Vec<String> = sort_records(&table, order(), 2).unwrap()
+            .map(|p| p.unwrap().name.to_string())
+            .collect();
+        assert_eq!(names, vec!["bob", "carl", "alex", "dan", "eve"]);
+
+        let ids: Vec<usize> = sort_records(&table, order(), 100).unwrap()
+            .map(|p| p.unwrap().id)
+            .collect();
+        assert_eq!(ids, vec![2, 4, 5, 1, 3]);
+
+        // New table
+        let sorted = sort_table(&table, order(), 2, TABLE_SORTED_PATH).unwrap();
+        assert_eq!(sorted.size().unwrap(), 5);
+        let first = Person::get(&sorted, 1).unwrap();
+        assert_eq!((first.id, first.name.to_string()), (1, "bob".to_string()));
+        assert!(sort_table(&table, order(), 2, TABLE_SORTED_PATH).is_err());
+        drop(sorted);
+
+        // Progress
+        fs::remove_file(TABLE_SORTED_PATH).unwrap();
+        let progress = ProgressHandle::new(|_| {});
+        sort_table_with_progress(
+            &table, order(), 2, TABLE_SORTED_PATH, &progress
+        ).unwrap();
+        let last = progress.progress();
+        assert_eq!(
+            (last.stage, last.processed, last.total), ("write", 5, Some(5))
+        );
+
+        _ensure_removed_tables();
+    }
+
+    fn _ensure_removed_tables() {
+        if fs::metadata(TABLE_PATH).is_ok() {
+            fs::remove_file(TABLE_PATH).unwrap();
+        }
+        if fs::metadata(TABLE_SORTED_PATH).is_ok() {
+            fs::remove_file(TABLE_SORTED_PATH).unwrap();
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use std::io;
+
+use crate::table::*;
+use crate::table_trait::*;
+
+
+/// TableVersioned provides the version of a record, it must grow on every
+/// change of the record (e.g. a counter or an **updated_at** timestamp).
+pub trait TableVersioned: TableTrait {
+    /// Gets the version of the record.
+    fn version(&self) -> u64;
+}
+
+
+/// The way to choose a record if it differs in both copies of the table.
+pub enum MergePolicy<'a, T> {
+    /// The record with the greater version wins, the local one on equal
+    /// versions.
+    LastWriterWins,
+    /// The function gets the local and the remote records and returns
+    /// the merged one.
+    Resolve(&'a dyn Fn(&T, &T) -> T),
+}
+
+
+/// Merges the **remote** copy of the table into the **local** one
+/// matching the records by id. Records that exist only in the remote copy
+/// are appended, a record erased (with **id** 0) in any copy is erased in
+/// the local one and different records are chosen by the **policy**.
+/// Returns the number of records changed in the local table.
+pub fn merge_tables<T: TableVersioned>(
+            local: &Table,
+            remote: &Table,
+            policy: MergePolicy<T>
+        ) -> Result<usize, io::Error> {
+    let mut changed = 0;
+
+    for idx in 0..remote.size()? {
+        let remote_block = remote.get(idx)?;
+
+        if idx >= local.size()? {
+            local.append(&remote_block)?;
+            changed += 1;
+            continue;
+        }
+
+        let local_block = local.get(idx)?;
+
+        if local_block == remote_block {
+            continue;
+        }
+
+        let local_rec = T::from_bytes(&local_block);
+        let remote_rec = T::from_bytes(&remote_block);
+
+        if (local_rec.id() == 0) || (remote_rec.id() == 0) {
+            if local_rec.id() != 0 {
+                local.erase(idx)?;
+                changed += 1;
+            }
+            continue;
+        }
+
+        let merged = match policy {
+            MergePolicy::LastWriterWins => {
+                if remote_rec.version() > local_rec.version() {
+                    remote_rec
+                } else {
+                    local_rec
+                }
+            },
+            MergePolicy::Resolve(resolve) => {
+                let mut rec = resolve(&local_rec, &remote_rec);
+                rec.set_id(idx + 1);
+                rec
+            },
+        };
+
+        if merged.as_bytes() != &local_block[..] {
+            local.update(merged.as_bytes(), idx)?;
+            changed += 1;
+        }
+    }
+
+    Ok(changed)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::varchar::*;
+    use super::*;
+
+    const TABLE_LOCAL_PATH: &str = "test-sync-person-local.tbl";
+    const TABLE_REMOTE_PATH: &str = "test-sync-person-remote.tbl";
+
+    #[derive(Debug, Copy, Clone)]
+    struct Person {
+        id: usize,
+        name: Varchar<20>,
+        age: u32,
+        version: u64,
+    }
+
+    impl TableTrait for Person {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    impl TableVersioned for Person {
+        fn version(&self) -> u64 {
+            self.version
+        }
+    }
+
+    impl Person {
+        fn new(name: &str, age: u32) -> Self {
+            Self { id: 0, name: Varchar::<20>::new(name), age, version: 0 }
+        }
+
+        fn set_age(&mut self, age: u32, table: &Table) {
+            self.age = age;
+            self.version += 1;
+            self.update(table).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_merge_tables() {
+        _ensure_removed_tables();
+
+        let local = Table::open::<Person>(TABLE_LOCAL_PATH).unwrap();
+        let remote = Table::open::<Person>(TABLE_REMOTE_PATH).unwrap();
+
+        for table in &[&local, &remote] {
+            Person::new("alex", 32).insert(table).unwrap();
+            Person::new("bob", 25).insert(table).unwrap();
+            Person::new("carl", 40).insert(table).unwrap();
+        }
+
+        // Diverge the copies
+        let mut alex = Person::get(&remote, 1).unwrap();
+        alex.set_age(33, &remote);
+        alex.set_age(34, &remote);
+        let mut alex = Person::get(&local, 1).unwrap();
+        alex.set_age(35, &local);
+        Person::get(&local, 2).unwrap().set_age(26, &local);
+        Person::erase(&remote, 3).unwrap();
+        Person::new("dan", 19).insert(&remote).unwrap();
+
+        // Last writer wins
+        let changed = merge_tables::<Person>(
+            &local, &remote, MergePolicy::LastWriterWins
+        ).unwrap();
+        assert_eq!(changed, 3);
+        assert_eq!(Person::get(&local, 1).unwrap().age, 34);
+        assert_eq!(Person::get(&local, 2).unwrap().age, 26);
+        assert_eq!(Person::get(&local, 3).unwrap().id, 0);
+        assert_eq!(Person::get(&local, 4).unwrap().name.to_string(), "dan");
+
+        // Callback
+        let changed = merge_tables::<Person>(
+            &remote, &local, MergePolicy::Resolve(&|l, r| {
+                if l.age > r.age { *l } else { *r }
+            })
+        ).unwrap();
+        assert_eq!(changed, 1);
+        assert_eq!(Person::get(&remote, 2).unwrap().age, 26);
+
+        _ensure_removed_tables();
+    }
+
+    fn _ensure_removed_tables() {
+        if fs::metadata(TABLE_LOCAL_PATH).is_ok() {
+            fs::remove_file(TABLE_LOCAL_PATH).unwrap();
+        }
+        if fs::metadata(TABLE_REMOTE_PATH).is_ok() {
+            fs::remove_file(TABLE_REMOTE_PATH).unwrap();
+        }
+    }
+}
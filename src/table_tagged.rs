@@ -0,0 +1,229 @@
+use std::io;
+
+use crate::table::*;
+use crate::table_error::*;
+use crate::table_trait::*;
+
+
+/// The size of the tag in front of each block, 8 bytes keep the record
+/// aligned.
+const TAG_SIZE: usize = 8;
+
+
+/// TableTagged marks a record type with a tag, so records of several types
+/// can be stored in one **TaggedTable**. The tags must be unique among
+/// the types of the table and must not be 0 (it marks erased blocks).
+pub trait TableTagged: TableTrait {
+    /// The tag of the record type.
+    const TAG: u32;
+}
+
+
+/// TaggedTable keeps records of different types in one file (e.g. events
+/// of an event log). Each block is prefixed with the tag of the record type
+/// and has the size of the largest record, the ids are common for all types.
+pub struct TaggedTable {
+    table: Table,
+}
+
+
+impl TaggedTable {
+    /// Creates or opens a file to work with records up to
+    /// **max_record_size** bytes.
+    pub fn open(path: &str, max_record_size: usize) -> Result<Self, TableError> {
+        Ok(Self {
+            table: Table::open_with_block_size(
+                path, TAG_SIZE + max_record_size
+            )?,
+        })
+    }
+
+    /// The underlying table.
+    pub fn table(&self) -> &Table {
+        &self.table
+    }
+
+    /// Inserts the record to the table.
+    pub fn insert<T: TableTagged>(
+                &self,
+                record: &mut T
+            ) -> Result<usize, io::Error> {
+        if record.id() != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "id"));
+        }
+        let idx = self.table.append(&Self::_block(record)?)?;
+        record.set_id(idx + 1);
+        self.table.update(&Self::_block(record)?, idx)?;
+        Ok(record.id())
+    }
+
+    /// Updates the record in the table, the block must have the same tag.
+    pub fn update<T: TableTagged>(&self, record: &T) -> Result<(), io::Error> {
+        self._check_tag::<T>(record.id())?;
+        self.table.update(&Self::_block(record)?, record.id() - 1)
+    }
+
+    /// Gets the tag of the record by id, 0 for an erased record.
+    pub fn tag(&self, id: usize) -> Result<u32, io::Error> {
+        if (id == 0) || (id > self.table.size()?) {
+            return Err(
+                io::Error::new(io::ErrorKind::NotFound, id.to_string())
+            );
+        }
+        Ok(Self::_tag(&self.table.get(id - 1)?))
+    }
+
+    /// Extracts the record by id. A record of another type is
+    /// an *InvalidData* error.
+    pub fn get<T: TableTagged>(&self, id: usize) -> Result<T, io::Error> {
+        self._check_tag::<T>(id)?;
+        Ok(Self::_record(&self.table.get(id - 1)?))
+    }
+
+    /// Iterates the records of type **T** skipping other types. A block
+    /// that fails to read is yielded as the error.
+    pub fn iter<T: TableTagged>(
+                &self
+            ) -> Box<dyn Iterator<Item = Result<T, io::Error>> + '_> {
+        Box::new(
+            self.table.iter().filter_map(|block| match block {
+                Ok(block) if Self::_tag(&block) != T::TAG => None,
+                block => Some(block.map(|block| Self::_record(&block))),
+            })
+        )
+    }
+
+    fn _check_tag<T: TableTagged>(&self, id: usize) -> Result<(), io::Error> {
+        let tag = self.tag(id)?;
+        if tag != T::TAG {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("tag {} instead of {}", tag, T::TAG)
+            ));
+        }
+        Ok(())
+    }
+
+    fn _block<T: TableTagged>(record: &T) -> Result<Vec<u8>, io::Error> {
+        if T::TAG == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "tag 0"));
+        }
+        let mut block = (T::TAG as u64).to_le_bytes().to_vec();
+        block.extend_from_slice(record.as_bytes());
+        Ok(block)
+    }
+
+    fn _tag(block: &[u8]) -> u32 {
+        let mut bytes = [0u8; TAG_SIZE];
+        bytes.clone_from_slice(&block[..TAG_SIZE]);
+        u64::from_le_bytes(bytes) as u32
+    }
+
+    fn _record<T: TableTagged>(block: &[u8]) -> T {
+        // Copied to a new buffer to read the record from an aligned address
+        let mut buffer = vec![0u8; T::block_size()];
+        buffer.clone_from_slice(&block[TAG_SIZE..TAG_SIZE + T::block_size()]);
+        T::from_bytes(&buffer)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, mem};
+
+    use crate::varchar::*;
+    use super::*;
+
+    const TABLE_PATH: &str = "test-tagged-events.tbl";
+
+    #[derive(Debug, Copy, Clone)]
+    struct Login {
+        id: usize,
+        user: Varchar<20>,
+    }
+
+    impl TableTrait for Login {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    impl TableTagged for Login {
+        const TAG: u32 = 1;
+    }
+
+    #[derive(Debug, Copy, Clone)]
+    struct Payment {
+        id: usize,
+        amount: u64,
+        currency: Varchar<3>,
+        user: Varchar<20>,
+    }
+
+    impl TableTrait for Payment {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    impl TableTagged for Payment {
+        const TAG: u32 = 2;
+    }
+
+    #[test]
+    fn test_tagged_table() {
+        _ensure_removed_table_file();
+
+        let max_size = mem::size_of::<Login>().max(mem::size_of::<Payment>());
+        let events = TaggedTable::open(TABLE_PATH, max_size).unwrap();
+
+        let mut login = Login { id: 0, user: Varchar::<20>::new("alex") };
+        events.insert(&mut login).unwrap();
+        let mut payment = Payment {
+            id: 0, amount: 100, currency: Varchar::<3>::new("usd"),
+            user: Varchar::<20>::new("alex"),
+        };
+        events.insert(&mut payment).unwrap();
+        events.insert(&mut Login { id: 0, user: Varchar::<20>::new("bob") })
+            .unwrap();
+        assert_eq!(events.table().size().unwrap(), 3);
+
+        // Typed access
+        assert_eq!(events.tag(2).unwrap(), 2);
+        let stored = events.get::<Payment>(2).unwrap();
+        assert_eq!(stored.amount, 100);
+        assert_eq!(stored.currency.to_string(), "usd");
+        assert_eq!(stored.user.to_string(), "alex");
+        let err = events.get::<Payment>(1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        payment.amount = 120;
+        events.update(&payment).unwrap();
+        assert!(events.update(&Login { id: 2, ..login }).is_err());
+
+        // Typed iterators
+        let users: Vec<String> = events.iter::<Login>()
+            .map(|e| e.unwrap().user.to_string()).collect();
+        assert_eq!(users, vec!["alex", "bob"]);
+        let amounts: Vec<u64> = events.iter::<Payment>()
+            .map(|e| e.unwrap().amount).collect();
+        assert_eq!(amounts, vec![120]);
+
+        _ensure_removed_table_file();
+    }
+
+    fn _ensure_removed_table_file() {
+        if fs::metadata(TABLE_PATH).is_ok() {
+            fs::remove_file(TABLE_PATH).unwrap();
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::{mem, slice, io};
+use std::{iter, mem, slice, io};
 
 use crate::table::Table;
 
@@ -13,6 +13,17 @@ pub trait TableTrait where Self: Sized + Copy {
     /// Sets id to the record.
     fn set_id(&mut self, id: usize);
 
+    /// Called on the copy of the record written by **insert**,
+    /// **insert_many** and **update** (e.g. to normalize fields, compute
+    /// derived ones or encrypt application data), the record itself is not
+    /// changed. It does nothing by default.
+    fn before_write(&mut self) {}
+
+    /// Called on the record read by **get**, **get_many**, **all** and
+    /// **iter_between** (e.g. to decrypt what **before_write** encrypted).
+    /// It is not called on the erased records. It does nothing by default.
+    fn after_read(&mut self) {}
+
     /// Returns size of the record in bytes.
     fn block_size() -> usize {
         mem::size_of::<Self>()
@@ -41,7 +52,7 @@ pub trait TableTrait where Self: Sized + Copy {
 
     /// Gets id of the first record. Returns 0 if there is no record.
     fn get_first_id(table: &Table) -> Result<usize, io::Error> {
-        if table.empty() {
+        if table.empty()? {
             Err(io::Error::new(io::ErrorKind::NotFound, "empty table"))
         } else {
             Ok(1)
@@ -53,7 +64,7 @@ pub trait TableTrait where Self: Sized + Copy {
                 table: &Table,
                 id: usize
             ) -> Result<usize, io::Error> {
-        if (id > 0) && (id <= table.size()) {
+        if (id > 0) && (id <= table.size()?) {
             Ok(id - 1)
         } else {
             Err(
@@ -64,7 +75,7 @@ pub trait TableTrait where Self: Sized + Copy {
 
     /// Extracts the record from the table by id.
     fn get(table: &Table, id: usize) -> Result<Self, io::Error> {
-        if id > table.size() {
+        if id > table.size()? {
             return Err(
                 io::Error::new(io::ErrorKind::NotFound, id.to_string())
             );
@@ -72,35 +83,126 @@ pub trait TableTrait where Self: Sized + Copy {
 
         let idx = Self::get_index_by_id(table, id)?;
         let block = table.get(idx)?;
-        let obj = Self::from_bytes(&block);
+        let obj = _read_record(&block);
 
         Ok(obj)
     }
 
-    /// Inserts the record to the table.
+    /// Extracts several records from the table by ids keeping the order.
+    /// The blocks are read in batches, see **Table::get_many**.
+    fn get_many(table: &Table, ids: &[usize]) -> Result<Vec<Self>, io::Error> {
+        let idxs = ids.iter()
+            .map(|id| Self::get_index_by_id(table, *id))
+            .collect::<Result<Vec<usize>, io::Error>>()?;
+        Ok(
+            table.get_many(&idxs)?.iter()
+                .map(|block| _read_record(block))
+                .collect()
+        )
+    }
+
+    /// Inserts the record to the table. The id of a deleted record may be
+    /// reused, see **delete**.
     fn insert(&mut self, table: &Table) -> Result<usize, io::Error> {
         if self.id() != 0 {
             return Err(io::Error::new(io::ErrorKind::InvalidInput, "id"));
         }
-        let idx = table.append(&self.as_bytes())?;
+        if table._signed() {
+            // The block is signed into the ledger as it is appended, so it
+            // is written with the id at once
+            let idx = table.append_many(1, |_, idx| {
+                self.set_id(idx + 1);
+                _written_record(self).as_bytes().to_vec()
+            })?;
+            return Ok(idx + 1);
+        }
+        let mut written = _written_record(self);
+        let idx = table.insert(written.as_bytes())?;
         self.set_id(idx + 1);
-        table.update(&self.as_bytes(), idx)?;
+        written.set_id(idx + 1);
+        table.update(written.as_bytes(), idx)?;
         Ok(self.id())
     }
 
+    /// Inserts the records to the end of the table by a single write,
+    /// the ids are assigned in order. It is much faster than **insert**
+    /// for many records, but the ids of deleted records are not reused.
+    fn insert_many(
+                records: &mut [Self],
+                table: &Table
+            ) -> Result<(), io::Error> {
+        if records.iter().any(|record| record.id() != 0) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "id"));
+        }
+        table.append_many(records.len(), |pos, idx| {
+            records[pos].set_id(idx + 1);
+            _written_record(&records[pos]).as_bytes().to_vec()
+        })?;
+        Ok(())
+    }
+
     /// Updates the record in the table.
     fn update(&self, table: &Table) -> Result<(), io::Error> {
         let idx = Self::get_index_by_id(table, self.id())?;
-        table.update(&self.as_bytes(), idx)
+        table.update(_written_record(self).as_bytes(), idx)
     }
 
-    /// Iterates all records from the table.
-    fn all(table: &Table) -> Box<dyn Iterator<Item = Self> + '_> {
+    /// Erases the record from the table overwriting its block with zeros,
+    /// the copies in the files of the table are checked to be gone too
+    /// (see **Table::erase**). The id is not reused, the erased record is
+    /// read back with **id** 0. Index entries of the record must be
+    /// excluded separately, **Repository::delete** does both.
+    fn erase(table: &Table, id: usize) -> Result<(), io::Error> {
+        let idx = Self::get_index_by_id(table, id)?;
+        table.erase(idx)
+    }
+
+    /// Deletes the record from the table. Unlike **erase**, its id is
+    /// given to a record inserted later. Index entries of the record must
+    /// be excluded separately.
+    fn delete(&self, table: &Table) -> Result<(), io::Error> {
+        let idx = Self::get_index_by_id(table, self.id())?;
+        table.delete(idx)
+    }
+
+    /// Iterates all records from the table. A record that fails to read
+    /// (e.g. a torn or corrupt block) is yielded as the error.
+    fn all(
+                table: &Table
+            ) -> Box<dyn Iterator<Item = Result<Self, io::Error>> + '_> {
         Box::new(table.iter().map(
-            |block| Self::from_bytes(&block)
+            |block| Ok(_read_record(&block?))
         ))
     }
 
+    /// Counts the records for which **predicate** returns true skipping
+    /// the erased ones.
+    fn count(
+                table: &Table,
+                predicate: &dyn Fn(&Self) -> bool
+            ) -> Result<usize, io::Error> {
+        let mut count = 0;
+        for record in Self::all(table) {
+            let record = record?;
+            if (record.id() != 0) && predicate(&record) {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Sums the values extracted from the records by **value** skipping
+    /// the erased ones.
+    fn sum<S: iter::Sum<S>>(
+                table: &Table,
+                value: &dyn Fn(&Self) -> S
+            ) -> Result<S, io::Error> {
+        Self::all(table)
+            .filter(|record| record.as_ref().map_or(true, |r| r.id() != 0))
+            .map(|record| record.map(|record| value(&record)))
+            .sum()
+    }
+
     /// Iterates the records from the table between two values
     /// that can be extracted from a record by the function
     /// **get_sorted_value**. The values must be sorted.
@@ -109,31 +211,83 @@ pub trait TableTrait where Self: Sized + Copy {
                 sorted_value_from: T,
                 sorted_value_to: T,
                 get_sorted_value: &'a dyn Fn(&Self) -> T
-            ) -> Box<dyn Iterator<Item = Self> + 'a> {
+            ) -> Result<
+                Box<dyn Iterator<Item = Result<Self, io::Error>> + 'a>,
+                io::Error
+            > {
         let idx_from = table.find_sorted(
             sorted_value_from,
-            &|block| get_sorted_value(&Self::from_bytes(&block))
-        );
+            &|block| get_sorted_value(&Self::from_bytes(block))
+        )?;
         let idx_to = table.find_sorted(
             sorted_value_to,
-            &|block| get_sorted_value(&Self::from_bytes(&block))
-        );
+            &|block| get_sorted_value(&Self::from_bytes(block))
+        )?;
 
-        Box::new(table.iter_between(idx_from, idx_to).unwrap().map(
-            |block| Self::from_bytes(&block)
-        ))
+        Ok(Box::new(table.iter_between(idx_from, idx_to)?.map(
+            |block| Ok(_read_record(&block?))
+        )))
+    }
+}
+
+
+/// Constructs the record from the block calling **after_read** unless it
+/// is erased.
+pub(crate) fn _read_record<T: TableTrait>(block: &[u8]) -> T {
+    let mut record = T::from_bytes(block);
+    if record.id() != 0 {
+        record.after_read();
     }
+    record
+}
+
+
+/// The copy of the record to write with **before_write** called.
+pub(crate) fn _written_record<T: TableTrait>(record: &T) -> T {
+    let mut written = *record;
+    written.before_write();
+    written
 }
 
 
 #[cfg(test)]
 mod tests {
     use std::fs;
+    use std::sync::{Arc, Mutex};
 
     use crate::varchar::*;
+    use crate::table::{AccessPattern, CheckDepth, CorruptBlock, TornWrite};
+    use crate::table_error::TableError;
+    use crate::wal::WriteAheadLog;
+    use crate::progress::ProgressHandle;
+    use crate::change_feed::ChangeKind;
+    use crate::compression::CompressionKind;
     use super::*;
 
     const TABLE_PATH: &str = "test-trait-person.tbl";
+    const TABLE_ERASE_PATH: &str = "test-trait-person-erase.tbl";
+    const TABLE_BACKUP_PATH: &str = "test-trait-person-backup.tbl";
+    const TABLE_RESTORE_PATH: &str = "test-trait-person-restore.tbl";
+    const TABLE_ALIGNED_PATH: &str = "test-trait-person-aligned.tbl";
+    const TABLE_STAMPED_PATH: &str = "test-trait-person-stamped.tbl";
+    const TABLE_DELETE_PATH: &str = "test-trait-person-delete.tbl";
+    const TABLE_LOAD_ALL_PATH: &str = "test-trait-person-load-all.tbl";
+    const TABLE_HEADER_PATH: &str = "test-trait-person-header.tbl";
+    const TABLE_WAL_PATH: &str = "test-trait-person-wal.tbl";
+    const TABLE_INSERT_MANY_PATH: &str = "test-trait-person-insert-many.tbl";
+    const TABLE_CACHED_PATH: &str = "test-trait-person-cached.tbl";
+    const TABLE_LOCKED_PATH: &str = "test-trait-person-locked.tbl";
+    const TABLE_META_PATH: &str = "test-trait-person-meta.tbl";
+    const TABLE_COMPRESSED_PATH: &str = "test-trait-person-compressed.tbl";
+    const TABLE_CHECKSUMS_PATH: &str = "test-trait-person-checksums.tbl";
+    const TABLE_RESERVE_PATH: &str = "test-trait-person-reserve.tbl";
+    const TABLE_HOOKS_PATH: &str = "test-trait-secret-hooks.tbl";
+    const TABLE_QUICK_CHECK_PATH: &str = "test-trait-person-quick-check.tbl";
+    const TABLE_TRUNCATE_PATH: &str = "test-trait-person-truncate.tbl";
+    const TABLE_AGGREGATE_PATH: &str = "test-trait-person-aggregate.tbl";
+    const TABLE_PAGE_PATH: &str = "test-trait-person-page.tbl";
+    const TABLE_COMPRESSION_KIND_PATH: &str =
+        "test-trait-person-compression-kind.tbl";
 
     #[derive(Debug, Copy, Clone)]
     struct Person {
@@ -160,16 +314,16 @@ mod tests {
 
     #[test]
     fn test_basic() {
-        _ensure_removed_table_file();
+        _ensure_removed_table_file(TABLE_PATH);
 
-        let table = Table::new::<Person>(TABLE_PATH);
+        let table = Table::open::<Person>(TABLE_PATH).unwrap();
 
         let mut alex = Person::new("alex", 32);
 
         // Insert
         alex.insert(&table).unwrap();
         assert_eq!(alex.id, 1);
-        assert_eq!(table.size(), 1);
+        assert_eq!(table.size().unwrap(), 1);
 
         // Update
         alex.age = 33;
@@ -179,16 +333,977 @@ mod tests {
         assert_eq!(alex2.age, 33);
 
         // All
-        let persons: Vec<Person> = Person::all(&table).collect();
+        table.advise(AccessPattern::Sequential).unwrap();
+        let persons: Vec<Person> = Person::all(&table)
+            .collect::<Result<_, _>>().unwrap();
         assert_eq!(persons.len(), 1);
         assert_eq!(persons[0].id, 1);
 
-        _ensure_removed_table_file();
+        // Get many
+        Person::new("bob", 25).insert(&table).unwrap();
+        Person::new("carl", 40).insert(&table).unwrap();
+        let persons = Person::get_many(&table, &[3, 1, 3, 2]).unwrap();
+        let ages: Vec<u32> = persons.iter().map(|p| p.age).collect();
+        assert_eq!(ages, vec![40, 33, 40, 25]);
+        assert!(Person::get_many(&table, &[1, 4]).is_err());
+
+        _ensure_removed_table_file(TABLE_PATH);
+    }
+
+    #[test]
+    fn test_erase() {
+        _ensure_removed_table_file(TABLE_ERASE_PATH);
+
+        let table = Table::open::<Person>(TABLE_ERASE_PATH).unwrap();
+
+        let mut alex = Person::new("alex", 32);
+        alex.insert(&table).unwrap();
+        let mut bob = Person::new("bob", 25);
+        bob.insert(&table).unwrap();
+
+        // Erase
+        Person::erase(&table, 1).unwrap();
+        assert!(table.get(0).unwrap().iter().all(|b| *b == 0));
+        assert_eq!(Person::get(&table, 1).unwrap().id, 0);
+        assert_eq!(Person::get(&table, 2).unwrap().age, 25);
+        assert_eq!(table.size().unwrap(), 2);
+
+        // Erase missing
+        assert!(Person::erase(&table, 3).is_err());
+
+        _ensure_removed_table_file(TABLE_ERASE_PATH);
+    }
+
+    #[test]
+    fn test_backup() {
+        _ensure_removed_table_file(TABLE_BACKUP_PATH);
+        _ensure_removed_table_file(&format!("{}.dirty", TABLE_BACKUP_PATH));
+        _ensure_removed_table_file(TABLE_RESTORE_PATH);
+
+        let table = Table::with_dirty_map::<Person>(TABLE_BACKUP_PATH)
+            .unwrap();
+        let restored = Table::open::<Person>(TABLE_RESTORE_PATH).unwrap();
+
+        let mut alex = Person::new("alex", 32);
+        alex.insert(&table).unwrap();
+        Person::new("bob", 25).insert(&table).unwrap();
+
+        // Full backup
+        let mut full = Vec::new();
+        let token = table.backup_full(&mut full).unwrap();
+        assert_eq!(restored.restore_backup(&mut &full[..]).unwrap(), 2);
+
+        // Incremental backup
+        alex.age = 33;
+        alex.update(&table).unwrap();
+        Person::new("carl", 40).insert(&table).unwrap();
+        let mut delta = Vec::new();
+        let token2 = table.backup_incremental(&mut delta, token).unwrap();
+        assert_eq!(restored.restore_backup(&mut &delta[..]).unwrap(), 2);
+        assert!(delta.len() < full.len() + Person::block_size());
+
+        let persons: Vec<Person> = Person::all(&restored)
+            .collect::<Result<_, _>>().unwrap();
+        assert_eq!(persons.len(), 3);
+        assert_eq!(persons[0].age, 33);
+        assert_eq!(persons[2].age, 40);
+
+        // Outdated token
+        assert!(table.backup_incremental(&mut Vec::new(), token).is_err());
+        assert!(table.backup_incremental(&mut Vec::new(), token2).is_ok());
+
+        // Progress
+        let progress = ProgressHandle::new(|_| {});
+        table.backup_full_with_progress(&mut Vec::new(), &progress).unwrap();
+        let last = progress.progress();
+        assert_eq!((last.stage, last.processed), ("backup", 3));
+
+        _ensure_removed_table_file(TABLE_BACKUP_PATH);
+        _ensure_removed_table_file(&format!("{}.dirty", TABLE_BACKUP_PATH));
+        _ensure_removed_table_file(TABLE_RESTORE_PATH);
+    }
+
+    #[test]
+    fn test_direct_io() {
+        _ensure_removed_table_file(TABLE_ALIGNED_PATH);
+
+        let table = Table::with_direct_io::<Person>(TABLE_ALIGNED_PATH, 4096)
+            .unwrap();
+        assert_eq!(table.block_size(), 4096);
+
+        let mut alex = Person::new("alex", 32);
+        alex.insert(&table).unwrap();
+        Person::new("bob", 25).insert(&table).unwrap();
+        alex.age = 33;
+        alex.update(&table).unwrap();
+
+        assert_eq!(table.size().unwrap(), 2);
+        // The header takes the first block
+        assert_eq!(fs::metadata(TABLE_ALIGNED_PATH).unwrap().len(), 12288);
+        assert_eq!(Person::get(&table, 1).unwrap().age, 33);
+        assert_eq!(Person::get(&table, 2).unwrap().age, 25);
+
+        let table = Table::with_alignment::<Person>(TABLE_ALIGNED_PATH, 4096)
+            .unwrap();
+        let persons: Vec<Person> = Person::all(&table)
+            .collect::<Result<_, _>>().unwrap();
+        assert_eq!(persons.len(), 2);
+        assert_eq!(persons[1].name.to_string(), "bob");
+
+        _ensure_removed_table_file(TABLE_ALIGNED_PATH);
+    }
+
+    #[test]
+    fn test_delete() {
+        _ensure_removed_table_file(TABLE_DELETE_PATH);
+        _ensure_removed_table_file(&format!("{}.free", TABLE_DELETE_PATH));
+
+        let mut table = Table::open::<Person>(TABLE_DELETE_PATH).unwrap();
+
+        let mut alex = Person::new("alex", 32);
+        alex.insert(&table).unwrap();
+        let mut bob = Person::new("bob", 25);
+        bob.insert(&table).unwrap();
+
+        alex.delete(&table).unwrap();
+        assert_eq!(table.free_count().unwrap(), 1);
+        assert_eq!(Person::get(&table, 1).unwrap().id, 0);
+        assert_eq!(alex.delete(&table).unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        // The slot is reused with its id
+        let mut carl = Person::new("carl", 40);
+        assert_eq!(carl.insert(&table).unwrap(), 1);
+        assert_eq!(table.size().unwrap(), 2);
+        assert_eq!(table.free_count().unwrap(), 0);
+        assert_eq!(Person::get(&table, 1).unwrap().name.to_string(), "carl");
+        assert_eq!(Person::get(&table, 2).unwrap().name.to_string(), "bob");
+
+        let mut dan = Person::new("dan", 19);
+        assert_eq!(dan.insert(&table).unwrap(), 3);
+
+        // The slots stored again are not reused
+        bob.delete(&table).unwrap();
+        dan.delete(&table).unwrap();
+        assert_eq!(table.free_count().unwrap(), 2);
+        let mut records = vec![carl, dan];
+        table.store_all(&mut records).unwrap();
+        assert_eq!(table.free_count().unwrap(), 1);
+        let mut eve = Person::new("eve", 35);
+        assert_eq!(eve.insert(&table).unwrap(), 2);
+        assert_eq!(table.free_count().unwrap(), 0);
+        assert_eq!(Person::get(&table, 3).unwrap().name.to_string(), "dan");
+        assert_eq!(Person::new("fay", 28).insert(&table).unwrap(), 4);
+
+        _ensure_removed_table_file(TABLE_DELETE_PATH);
+        _ensure_removed_table_file(&format!("{}.free", TABLE_DELETE_PATH));
+    }
+
+    #[test]
+    fn test_reserve_id() {
+        _ensure_removed_table_file(TABLE_RESERVE_PATH);
+        _ensure_removed_table_file(&format!("{}.free", TABLE_RESERVE_PATH));
+
+        let table = Table::open::<Person>(TABLE_RESERVE_PATH).unwrap();
+        let mut alex = Person::new("alex", 32);
+        alex.insert(&table).unwrap();
+
+        // The reserved record is empty until it is updated
+        let id = table.reserve_id().unwrap();
+        assert_eq!(id, 2);
+        assert_eq!(Person::get(&table, id).unwrap().id, 0);
+        let mut bob = Person::new("bob", 25);
+        assert_eq!(bob.insert(&table).unwrap(), 3);
+
+        let mut carl = Person::new("carl", 40);
+        carl.id = id;
+        carl.update(&table).unwrap();
+        assert_eq!(Person::get(&table, 2).unwrap().name.to_string(), "carl");
+
+        // The block of a deleted record is reserved
+        alex.delete(&table).unwrap();
+        assert_eq!(table.reserve_id().unwrap(), 1);
+        assert_eq!(table.free_count().unwrap(), 0);
+        assert_eq!(table.size().unwrap(), 3);
+
+        _ensure_removed_table_file(TABLE_RESERVE_PATH);
+        _ensure_removed_table_file(&format!("{}.free", TABLE_RESERVE_PATH));
+    }
+
+    #[derive(Debug, Copy, Clone)]
+    struct Secret {
+        id: usize,
+        code: u64,
+    }
+
+    impl TableTrait for Secret {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+
+        fn before_write(&mut self) {
+            self.code ^= 0x5a5a;
+        }
+
+        fn after_read(&mut self) {
+            self.code ^= 0x5a5a;
+        }
+    }
+
+    #[test]
+    fn test_hooks() {
+        _ensure_removed_table_file(TABLE_HOOKS_PATH);
+
+        let table = Table::open::<Secret>(TABLE_HOOKS_PATH).unwrap();
+        let mut secret = Secret { id: 0, code: 42 };
+        secret.insert(&table).unwrap();
+        assert_eq!(secret.code, 42);
+        let mut others = vec![Secret { id: 0, code: 7 }];
+        Secret::insert_many(&mut others, &table).unwrap();
+
+        // The stored code is encoded, the read one is decoded
+        let stored = Secret::from_bytes(&table.get(0).unwrap());
+        assert_eq!(stored.code, 42 ^ 0x5a5a);
+        assert_eq!(Secret::get(&table, 1).unwrap().code, 42);
+        secret.code = 43;
+        secret.update(&table).unwrap();
+        let codes: Vec<u64> = Secret::all(&table)
+            .map(|s| s.unwrap().code).collect();
+        assert_eq!(codes, vec![43, 7]);
+        let codes: Vec<u64> = Secret::get_many(&table, &[2, 1]).unwrap()
+            .iter().map(|s| s.code).collect();
+        assert_eq!(codes, vec![7, 43]);
+
+        // Not called on the erased records
+        Secret::erase(&table, 2).unwrap();
+        assert_eq!(Secret::get(&table, 2).unwrap().code, 0);
+
+        _ensure_removed_table_file(TABLE_HOOKS_PATH);
+    }
+
+    #[test]
+    fn test_load_all() {
+        _ensure_removed_table_file(TABLE_LOAD_ALL_PATH);
+
+        let mut table = Table::with_epoch_stamps::<Person>(TABLE_LOAD_ALL_PATH)
+            .unwrap();
+        for (name, age) in &[("alex", 32), ("bob", 25), ("carl", 40)] {
+            Person::new(name, *age).insert(&table).unwrap();
+        }
+        Person::erase(&table, 1).unwrap();
+
+        let mut persons = table.load_all::<Person>().unwrap();
+        assert_eq!(persons.len(), 2);
+        persons.retain(|p| p.id != 3);
+        persons[0].age = 26;
+        persons.push(Person::new("dan", 19));
+
+        table.store_all(&mut persons).unwrap();
+        assert_eq!(persons[1].id, 3);
+        assert_eq!(table.size().unwrap(), 3);
+        assert_eq!(Person::get(&table, 1).unwrap().id, 0);
+        assert_eq!(Person::get(&table, 2).unwrap().age, 26);
+        assert_eq!(Person::get(&table, 3).unwrap().name.to_string(), "dan");
+        Person::new("eve", 50).insert(&table).unwrap();
+
+        let table = Table::with_epoch_stamps::<Person>(TABLE_LOAD_ALL_PATH)
+            .unwrap();
+        let names: Vec<String> = table.load_all::<Person>().unwrap().iter()
+            .map(|p| p.name.to_string()).collect();
+        assert_eq!(names, vec!["bob", "dan", "eve"]);
+
+        _ensure_removed_table_file(TABLE_LOAD_ALL_PATH);
+    }
+
+    #[test]
+    fn test_header_mismatch() {
+        _ensure_removed_table_file(TABLE_HEADER_PATH);
+
+        let table = Table::open::<Person>(TABLE_HEADER_PATH).unwrap();
+        Person::new("alex", 32).insert(&table).unwrap();
+        let table = Table::open::<Person>(TABLE_HEADER_PATH).unwrap();
+        assert_eq!(table.size().unwrap(), 1);
+
+        #[derive(Debug, Copy, Clone)]
+        struct Other {
+            id: usize,
+        }
+
+        impl TableTrait for Other {
+            fn id(&self) -> usize {
+                self.id
+            }
+
+            fn set_id(&mut self, id: usize) {
+                self.id = id;
+            }
+        }
+
+        // Opening with another struct fails instead of misreading
+        match Table::open::<Other>(TABLE_HEADER_PATH) {
+            Err(TableError::BlockSize { stored, expected }) => {
+                assert_eq!(stored, Person::block_size());
+                assert_eq!(expected, Other::block_size());
+            },
+            _ => panic!("block size mismatch expected"),
+        }
+
+        // A file of the versions before the header has only the blocks
+        let alex = Person { id: 1, ..Person::new("alex", 32) };
+        let bob = Person { id: 2, ..Person::new("bob", 25) };
+        let mut bytes = alex.as_bytes().to_vec();
+        bytes.extend_from_slice(bob.as_bytes());
+        fs::write(TABLE_HEADER_PATH, &bytes).unwrap();
+
+        let mut table = Table::open::<Person>(TABLE_HEADER_PATH).unwrap();
+        assert_eq!(table.size().unwrap(), 2);
+        assert_eq!(Person::get(&table, 2).unwrap().name.to_string(), "bob");
+        assert!(table.quick_check(CheckDepth::Header).unwrap().clean());
+        let err = table.set_meta("schema", "1").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        Person::new("carl", 40).insert(&table).unwrap();
+        assert_eq!(fs::metadata(TABLE_HEADER_PATH).unwrap().len() as usize,
+                   3 * Person::block_size());
+
+        // It gets the header when it is rewritten
+        let mut records: Vec<Person> = Person::all(&table)
+            .collect::<Result<_, _>>().unwrap();
+        table.store_all(&mut records).unwrap();
+        let table = Table::open::<Person>(TABLE_HEADER_PATH).unwrap();
+        assert_eq!(table.size().unwrap(), 3);
+        assert_eq!(Person::get(&table, 3).unwrap().age, 40);
+        table.set_meta("schema", "1").unwrap();
+        drop(table);
+
+        // The deprecated constructor still returns the table
+        #[allow(deprecated)]
+        let table = Table::new::<Person>(TABLE_HEADER_PATH);
+        assert_eq!(table.size().unwrap(), 3);
+        drop(table);
+
+        // Not a whole number of blocks
+        fs::write(TABLE_HEADER_PATH, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(matches!(Table::open::<Person>(TABLE_HEADER_PATH),
+                         Err(TableError::InvalidHeader(_))));
+
+        // Missing directory
+        let err = Table::open::<Person>("missing-dir/person.tbl").unwrap_err();
+        assert!(matches!(err, TableError::Io(_)));
+
+        _ensure_removed_table_file(TABLE_HEADER_PATH);
+    }
+
+    #[test]
+    fn test_epoch_stamps() {
+        _ensure_removed_table_file(TABLE_STAMPED_PATH);
+
+        let table = Table::with_epoch_stamps::<Person>(TABLE_STAMPED_PATH)
+            .unwrap();
+        assert_eq!(table.block_size(), Person::block_size() + 16);
+
+        let mut alex = Person::new("alex", 32);
+        alex.insert(&table).unwrap();
+        Person::new("bob", 25).insert(&table).unwrap();
+        alex.age = 33;
+        alex.update(&table).unwrap();
+        assert_eq!(Person::get(&table, 1).unwrap().age, 33);
+        assert_eq!(Person::get_many(&table, &[2, 1]).unwrap()[0].age, 25);
+
+        // Simulate a crash after the head of the block is written
+        let raw = Table::with_alignment::<Person>(
+            TABLE_STAMPED_PATH, table.block_size()
+        ).unwrap();
+        let mut block = raw.get(0).unwrap();
+        block[0] ^= 1;
+        block[8..16].clone_from_slice(&[0xff; 8]);
+        raw.update(&block, 0).unwrap();
+
+        let err = Person::get(&table, 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let torn = err.get_ref().unwrap().downcast_ref::<TornWrite>().unwrap();
+        assert_eq!(torn.idx, 0);
+        assert!(Person::get(&table, 2).is_ok());
+
+        // The scans yield the torn block as an error and go on
+        let persons: Vec<_> = Person::all(&table).collect();
+        assert_eq!(persons.len(), 2);
+        assert_eq!(persons[0].as_ref().unwrap_err().kind(),
+                   io::ErrorKind::InvalidData);
+        assert_eq!(persons[1].as_ref().unwrap().age, 25);
+        assert!(Person::count(&table, &|_| true).is_err());
+        assert!(table.iter_between(1, 2).unwrap().all(|block| block.is_ok()));
+
+        _ensure_removed_table_file(TABLE_STAMPED_PATH);
+    }
+
+    #[test]
+    fn test_insert_many() {
+        _ensure_removed_table_file(TABLE_INSERT_MANY_PATH);
+
+        let table = Table::open::<Person>(TABLE_INSERT_MANY_PATH).unwrap();
+        Person::new("alex", 32).insert(&table).unwrap();
+
+        let mut persons: Vec<Person> = (0..1000)
+            .map(|age| Person::new("person", age))
+            .collect();
+        Person::insert_many(&mut persons, &table).unwrap();
+        assert_eq!(table.size().unwrap(), 1001);
+        assert_eq!(persons[0].id, 2);
+        assert_eq!(persons[999].id, 1001);
+        let person = Person::get(&table, 501).unwrap();
+        assert_eq!((person.id, person.age), (501, 499));
+
+        // All ids must be empty
+        let mut persons = vec![Person::new("bob", 25), persons[0]];
+        assert!(Person::insert_many(&mut persons, &table).is_err());
+        assert_eq!(table.size().unwrap(), 1001);
+
+        Person::insert_many(&mut [], &table).unwrap();
+        assert_eq!(table.size().unwrap(), 1001);
+
+        _ensure_removed_table_file(TABLE_INSERT_MANY_PATH);
+    }
+
+    #[test]
+    fn test_cache() {
+        _ensure_removed_table_file(TABLE_CACHED_PATH);
+
+        let table = Table::with_cache::<Person>(TABLE_CACHED_PATH, 2).unwrap();
+        let mut alex = Person::new("alex", 32);
+        alex.insert(&table).unwrap();
+        Person::new("bob", 25).insert(&table).unwrap();
+        assert_eq!(Person::get(&table, 1).unwrap().age, 32);
+
+        // Cached blocks are served without reading the file
+        let other = Table::open::<Person>(TABLE_CACHED_PATH).unwrap();
+        let mut changed = alex;
+        changed.age = 40;
+        changed.update(&other).unwrap();
+        assert_eq!(Person::get(&table, 1).unwrap().age, 32);
+
+        // Written blocks are dropped from the cache
+        alex.age = 33;
+        alex.update(&table).unwrap();
+        assert_eq!(Person::get(&table, 1).unwrap().age, 33);
+        let mut persons = [Person::new("carl", 40)];
+        Person::insert_many(&mut persons, &table).unwrap();
+        assert_eq!(Person::get(&table, 3).unwrap().age, 40);
+        Person::erase(&table, 3).unwrap();
+        assert_eq!(Person::get(&table, 3).unwrap().id, 0);
+
+        _ensure_removed_table_file(TABLE_CACHED_PATH);
+    }
+
+    #[test]
+    fn test_wal() {
+        let wal_path = format!("{}.wal", TABLE_WAL_PATH);
+        _ensure_removed_table_file(TABLE_WAL_PATH);
+        _ensure_removed_table_file(&wal_path);
+
+        let table = Table::with_wal::<Person>(TABLE_WAL_PATH).unwrap();
+        let mut alex = Person::new("alex", 32);
+        alex.insert(&table).unwrap();
+        alex.age = 33;
+        alex.update(&table).unwrap();
+        assert_eq!(Person::get(&table, 1).unwrap().age, 33);
+        assert_eq!(fs::metadata(&wal_path).unwrap().len(), 0);
+        drop(table);
+
+        // Simulate a crash after the block is logged
+        alex.age = 34;
+        let wal = WriteAheadLog::open(&wal_path).unwrap();
+        wal.log(0, alex.as_bytes()).unwrap();
+        drop(wal);
+
+        let table = Table::open::<Person>(TABLE_WAL_PATH).unwrap();
+        assert_eq!(Person::get(&table, 1).unwrap().age, 34);
+        assert_eq!(fs::metadata(&wal_path).unwrap().len(), 0);
+
+        // A stale entry of the record is cleared by the erase
+        drop(table);
+        let table = Table::with_wal::<Person>(TABLE_WAL_PATH).unwrap();
+        WriteAheadLog::open(&wal_path).unwrap()
+            .log(0, alex.as_bytes()).unwrap();
+        Person::erase(&table, 1).unwrap();
+        assert_eq!(fs::metadata(&wal_path).unwrap().len(), 0);
+
+        _ensure_removed_table_file(TABLE_WAL_PATH);
+        _ensure_removed_table_file(&wal_path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_lock() {
+        _ensure_removed_table_file(TABLE_LOCKED_PATH);
+
+        // Shared access needs an existing file
+        assert!(Table::open_shared::<Person>(TABLE_LOCKED_PATH).is_err());
+
+        let mut writer = Table::open_exclusive::<Person>(
+            TABLE_LOCKED_PATH
+        ).unwrap();
+        Person::new("alex", 32).insert(&writer).unwrap();
+        assert!(matches!(
+            Table::open_exclusive::<Person>(TABLE_LOCKED_PATH),
+            Err(TableError::Locked)
+        ));
+        assert!(matches!(
+            Table::open_shared::<Person>(TABLE_LOCKED_PATH),
+            Err(TableError::Locked)
+        ));
+
+        // The lock is kept when the file is replaced
+        writer.store_all(&mut [Person::new("bob", 25)]).unwrap();
+        assert!(Table::open_shared::<Person>(TABLE_LOCKED_PATH).is_err());
+        drop(writer);
+
+        // Readers
+        let reader = Table::open_shared::<Person>(TABLE_LOCKED_PATH).unwrap();
+        let other = Table::open_shared::<Person>(TABLE_LOCKED_PATH).unwrap();
+        assert_eq!(Person::get(&reader, 1).unwrap().age, 25);
+        assert_eq!(Person::get(&other, 1).unwrap().age, 25);
+        let err = Person::new("carl", 40).insert(&reader).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert!(Table::open_exclusive::<Person>(TABLE_LOCKED_PATH).is_err());
+        drop(reader);
+        drop(other);
+
+        Table::open_exclusive::<Person>(TABLE_LOCKED_PATH).unwrap();
+
+        // A pending log is replayed by the writer only
+        let wal_path = format!("{}.wal", TABLE_LOCKED_PATH);
+        let wal = WriteAheadLog::open(&wal_path).unwrap();
+        wal.log(0, Person { id: 1, ..Person::new("bob", 26) }.as_bytes())
+            .unwrap();
+        drop(wal);
+        assert!(matches!(
+            Table::open_shared::<Person>(TABLE_LOCKED_PATH),
+            Err(TableError::PendingLog)
+        ));
+        drop(Table::open_exclusive::<Person>(TABLE_LOCKED_PATH).unwrap());
+        assert_eq!(fs::metadata(&wal_path).unwrap().len(), 0);
+        let reader = Table::open_shared::<Person>(TABLE_LOCKED_PATH).unwrap();
+        assert_eq!(Person::get(&reader, 1).unwrap().age, 26);
+        drop(reader);
+
+        _ensure_removed_table_file(TABLE_LOCKED_PATH);
+        _ensure_removed_table_file(&wal_path);
+    }
+
+    #[test]
+    fn test_meta() {
+        _ensure_removed_table_file(TABLE_META_PATH);
+
+        let mut table = Table::open::<Person>(TABLE_META_PATH).unwrap();
+        Person::new("alex", 32).insert(&table).unwrap();
+        assert_eq!(table.get_meta("schema").unwrap(), None);
+
+        table.set_meta("schema", "1").unwrap();
+        table.set_meta("source", "crm").unwrap();
+        table.set_meta("schema", "2").unwrap();
+        assert_eq!(table.get_meta("schema").unwrap(), Some("2".to_string()));
+        assert!(table.remove_meta("source").unwrap());
+        assert!(!table.remove_meta("source").unwrap());
+
+        // Limits
+        let err = table.set_meta("", "1").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = table.set_meta("synced", &"x".repeat(500)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        // The metadata stays with the file and does not take the blocks
+        let mut records: Vec<Person> = Person::all(&table)
+            .collect::<Result<_, _>>().unwrap();
+        table.store_all(&mut records).unwrap();
+        let table = Table::open::<Person>(TABLE_META_PATH).unwrap();
+        assert_eq!(table.size().unwrap(), 1);
+        assert_eq!(Person::get(&table, 1).unwrap().age, 32);
+        assert_eq!(table.meta().unwrap(),
+                   vec![("schema".to_string(), "2".to_string())]);
+        drop(table);
+
+        // A file of version 1 has no metadata area until it is rewritten
+        let block_size = Person::block_size();
+        let mut bytes = b"MYTABLE\0".to_vec();
+        bytes.extend_from_slice(&1u64.to_le_bytes());
+        bytes.extend_from_slice(&(block_size as u64).to_le_bytes());
+        bytes.resize(24_usize.div_ceil(block_size) * block_size, 0);
+        let bob = Person { id: 1, ..Person::new("bob", 25) };
+        bytes.extend_from_slice(bob.as_bytes());
+        fs::write(TABLE_META_PATH, &bytes).unwrap();
+
+        let mut table = Table::open::<Person>(TABLE_META_PATH).unwrap();
+        assert_eq!(table.size().unwrap(), 1);
+        assert_eq!(Person::get(&table, 1).unwrap().age, 25);
+        assert_eq!(table.get_meta("schema").unwrap(), None);
+        let err = table.set_meta("schema", "1").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+
+        let mut records: Vec<Person> = Person::all(&table)
+            .collect::<Result<_, _>>().unwrap();
+        table.store_all(&mut records).unwrap();
+        table.set_meta("schema", "1").unwrap();
+        assert_eq!(Person::get(&table, 1).unwrap().age, 25);
+        let table = Table::open::<Person>(TABLE_META_PATH).unwrap();
+        assert_eq!(table.get_meta("schema").unwrap(), Some("1".to_string()));
+
+        _ensure_removed_table_file(TABLE_META_PATH);
+    }
+
+    #[test]
+    fn test_checksums() {
+        _ensure_removed_table_file(TABLE_CHECKSUMS_PATH);
+
+        let mut table = Table::with_checksums::<Person>(
+            TABLE_CHECKSUMS_PATH
+        ).unwrap();
+        assert_eq!(table.block_size(), Person::block_size() + 4);
+
+        let mut alex = Person::new("alex", 32);
+        alex.insert(&table).unwrap();
+        Person::new("bob", 25).insert(&table).unwrap();
+        Person::new("carl", 40).insert(&table).unwrap();
+        alex.age = 33;
+        alex.update(&table).unwrap();
+        Person::erase(&table, 3).unwrap();
+        assert_eq!(Person::get(&table, 1).unwrap().age, 33);
+        assert!(table.verify().unwrap().is_empty());
+
+        // Flip a bit on the disk
+        let raw = Table::open_with_block_size(
+            TABLE_CHECKSUMS_PATH, table.block_size()
+        ).unwrap();
+        let mut block = raw.get(1).unwrap();
+        block[10] ^= 0x04;
+        raw.update(&block, 1).unwrap();
+
+        let err = Person::get(&table, 2).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let corrupt = err.get_ref().unwrap()
+            .downcast_ref::<CorruptBlock>().unwrap();
+        assert_eq!(corrupt.idx, 1);
+        assert!(Person::get_many(&table, &[1, 2]).is_err());
+        assert_eq!(table.verify().unwrap(), vec![1]);
+
+        // A scan yields the corrupt block as an error instead of panicking
+        let errs: Vec<io::Error> = Person::all(&table)
+            .filter_map(|person| person.err()).collect();
+        assert_eq!(errs.len(), 1);
+        let corrupt = errs[0].get_ref().unwrap()
+            .downcast_ref::<CorruptBlock>().unwrap();
+        assert_eq!(corrupt.idx, 1);
+        assert!(Person::sum(&table, &|p| p.age as u64).is_err());
+
+        // The checksums are kept by store_all
+        let mut records = vec![Person::get(&table, 1).unwrap()];
+        table.store_all(&mut records).unwrap();
+        assert!(table.verify().unwrap().is_empty());
+        assert_eq!(Person::get(&table, 1).unwrap().age, 33);
+
+        _ensure_removed_table_file(TABLE_CHECKSUMS_PATH);
+    }
+
+    #[test]
+    fn test_quick_check() {
+        _ensure_removed_table_file(TABLE_QUICK_CHECK_PATH);
+
+        let table = Table::with_checksums::<Person>(
+            TABLE_QUICK_CHECK_PATH
+        ).unwrap();
+        for age in 0..10 {
+            Person::new("alex", age).insert(&table).unwrap();
+        }
+        let report = table.quick_check(CheckDepth::Sample(4)).unwrap();
+        assert!(report.clean());
+        assert_eq!(report.checked, 4);
+
+        // Flip a bit of the last block
+        let raw = Table::open_with_block_size(
+            TABLE_QUICK_CHECK_PATH, table.block_size()
+        ).unwrap();
+        let mut block = raw.get(9).unwrap();
+        block[10] ^= 0x04;
+        raw.update(&block, 9).unwrap();
+
+        assert!(table.quick_check(CheckDepth::Header).unwrap().clean());
+        let report = table.quick_check(CheckDepth::Sample(1)).unwrap();
+        assert_eq!((report.checked, report.corrupt), (1, vec![9]));
+        let report = table.quick_check(CheckDepth::Deep).unwrap();
+        assert_eq!((report.checked, report.corrupt), (10, vec![9]));
+
+        // An interrupted append
+        let len = fs::metadata(TABLE_QUICK_CHECK_PATH).unwrap().len();
+        fs::OpenOptions::new().write(true).open(TABLE_QUICK_CHECK_PATH)
+            .unwrap().set_len(len + 5).unwrap();
+        let report = table.quick_check(CheckDepth::Header).unwrap();
+        assert_eq!(report.trailing_bytes, 5);
+        assert!(!report.clean());
+
+        // A broken header
+        let mut bytes = fs::read(TABLE_QUICK_CHECK_PATH).unwrap();
+        bytes[0] = b'X';
+        fs::write(TABLE_QUICK_CHECK_PATH, &bytes).unwrap();
+        assert!(matches!(table.quick_check(CheckDepth::Header),
+                         Err(TableError::InvalidHeader(_))));
+
+        _ensure_removed_table_file(TABLE_QUICK_CHECK_PATH);
+    }
+
+    #[test]
+    fn test_aggregates() {
+        _ensure_removed_table_file(TABLE_AGGREGATE_PATH);
+
+        let table = Table::open::<Person>(TABLE_AGGREGATE_PATH).unwrap();
+        for (name, age) in &[("alex", 32), ("bob", 25), ("carl", 40)] {
+            Person::new(name, *age).insert(&table).unwrap();
+        }
+        Person::erase(&table, 2).unwrap();
+
+        assert_eq!(Person::count(&table, &|_| true).unwrap(), 2);
+        assert_eq!(Person::count(&table, &|p| p.age > 35).unwrap(), 1);
+        assert_eq!(Person::sum(&table, &|p| p.age as u64).unwrap(), 72);
+        assert_eq!(Person::sum(&table, &|p| p.age as f64 / 2.0).unwrap(),
+                   36.0);
+
+        _ensure_removed_table_file(TABLE_AGGREGATE_PATH);
+    }
+
+    #[test]
+    fn test_iter_page() {
+        _ensure_removed_table_file(TABLE_PAGE_PATH);
+
+        let table = Table::open::<Person>(TABLE_PAGE_PATH).unwrap();
+        for age in 0..10 {
+            Person::new("alex", age).insert(&table).unwrap();
+        }
+
+        let ages = |offset, limit| -> Vec<u32> {
+            table.iter_page(offset, limit).unwrap()
+                .map(|block| Person::from_bytes(&block.unwrap()).age).collect()
+        };
+        assert_eq!(ages(0, 3), vec![0, 1, 2]);
+        assert_eq!(ages(8, 3), vec![8, 9]);
+        assert!(ages(12, 3).is_empty());
+        assert_eq!(ages(9, usize::MAX), vec![9]);
+
+        _ensure_removed_table_file(TABLE_PAGE_PATH);
+    }
+
+    #[test]
+    fn test_truncate_to() {
+        let free_path = format!("{}.free", TABLE_TRUNCATE_PATH);
+        _ensure_removed_table_file(TABLE_TRUNCATE_PATH);
+        _ensure_removed_table_file(&free_path);
+
+        let table = Table::open::<Person>(TABLE_TRUNCATE_PATH).unwrap();
+        table.set_meta("schema", "1").unwrap();
+        for age in 0..6 {
+            Person::new("alex", age).insert(&table).unwrap();
+        }
+        Person::get(&table, 2).unwrap().delete(&table).unwrap();
+        Person::get(&table, 5).unwrap().delete(&table).unwrap();
+
+        // The free block below the cut is reused, the one above is not
+        let cuts = Arc::new(Mutex::new(Vec::new()));
+        let watched = Arc::clone(&cuts);
+        table.watch(move |idx, kind| {
+            if kind == ChangeKind::Truncate {
+                watched.lock().unwrap().push(idx);
+            }
+        });
+        table.truncate_to(3).unwrap();
+        assert_eq!(table.size().unwrap(), 3);
+        assert_eq!(table.free_count().unwrap(), 1);
+        assert!(Person::get(&table, 4).is_err());
+        assert_eq!(Person::new("bob", 25).insert(&table).unwrap(), 2);
+        assert_eq!(Person::new("carl", 40).insert(&table).unwrap(), 4);
+        table.truncate_to(10).unwrap();
+        assert_eq!(table.size().unwrap(), 4);
+
+        // Clear keeps the header
+        table.clear().unwrap();
+        assert!(table.empty().unwrap());
+        assert_eq!(*cuts.lock().unwrap(), vec![3, 0]);
+        let table = Table::open::<Person>(TABLE_TRUNCATE_PATH).unwrap();
+        assert!(table.empty().unwrap());
+        assert_eq!(table.get_meta("schema").unwrap(), Some("1".to_string()));
+        assert_eq!(Person::new("dan", 19).insert(&table).unwrap(), 1);
+
+        _ensure_removed_table_file(TABLE_TRUNCATE_PATH);
+        _ensure_removed_table_file(&free_path);
+    }
+
+    #[test]
+    fn test_compression() {
+        let paths = [
+            TABLE_COMPRESSED_PATH.to_string(),
+            format!("{}.free", TABLE_COMPRESSED_PATH),
+            format!("{}.overflow", TABLE_COMPRESSED_PATH),
+            format!("{}.overflow.free", TABLE_COMPRESSED_PATH),
+        ];
+        for path in paths.iter() {
+            _ensure_removed_table_file(path);
+        }
+
+        let mut table = Table::with_compression::<Person>(
+            TABLE_COMPRESSED_PATH, 32
+        ).unwrap();
+        assert_eq!(table.block_size(), 32);
+        let mut alex = Person::new("alex", 32);
+        alex.insert(&table).unwrap();
+        Person::new("bob", 25).insert(&table).unwrap();
+        let mut long = Person::new("alexander the great", 33);
+        long.insert(&table).unwrap();
+
+        // The short names fit the slots, the long one overflows
+        assert_eq!(table.size().unwrap(), 3);
+        assert_eq!(fs::metadata(&paths[0]).unwrap().len(), 512 + 3 * 32);
+        let overflow_len = fs::metadata(&paths[2]).unwrap().len();
+        assert!(overflow_len > 512);
+        assert_eq!(Person::get(&table, 1).unwrap().name.to_string(), "alex");
+        assert_eq!(Person::get(&table, 3).unwrap().name.to_string(),
+                   "alexander the great");
+        let ages: Vec<u32> = Person::all(&table)
+            .map(|p| p.unwrap().age).collect();
+        assert_eq!(ages, vec![32, 25, 33]);
+
+        // The overflow block is reused after the record shrinks
+        long.name = Varchar::<20>::new("alexander");
+        long.update(&table).unwrap();
+        alex.name = Varchar::<20>::new("alex the second one");
+        alex.update(&table).unwrap();
+        assert_eq!(fs::metadata(&paths[2]).unwrap().len(), overflow_len);
+        assert_eq!(Person::get(&table, 3).unwrap().name.to_string(),
+                   "alexander");
+        assert_eq!(Person::get(&table, 1).unwrap().name.to_string(),
+                   "alex the second one");
+
+        // Delete
+        alex.delete(&table).unwrap();
+        assert!(Person::all(&table).next().unwrap().unwrap().id == 0);
+        Person::new("carl", 40).insert(&table).unwrap();
+        assert_eq!(table.size().unwrap(), 3);
+
+        // Store and reopen
+        let mut records = table.load_all::<Person>().unwrap();
+        records.push(Person::new("alexander the great", 34));
+        table.store_all(&mut records).unwrap();
+        let table = Table::with_compression::<Person>(
+            TABLE_COMPRESSED_PATH, 32
+        ).unwrap();
+        let names: Vec<String> = Person::all(&table)
+            .map(|p| p.unwrap().name.to_string()).collect();
+        assert_eq!(names, vec!["carl", "bob", "alexander",
+                               "alexander the great"]);
+
+        // No bytes of the erased record are left in the overflow table
+        Person::erase(&table, 4).unwrap();
+        let name = b"alexander the great";
+        for path in paths.iter() {
+            let bytes = fs::read(path).unwrap();
+            assert!(!bytes.windows(name.len()).any(|bytes| bytes == name));
+        }
+
+        // Wrong slots
+        assert!(Table::with_compression::<Person>("person.tbl", 8).is_err());
+        assert!(Table::open::<Person>(TABLE_COMPRESSED_PATH).is_err());
+
+        for path in paths.iter() {
+            _ensure_removed_table_file(path);
+        }
+    }
+
+    #[test]
+    fn test_compression_kind() {
+        #[derive(Debug, Copy, Clone)]
+        struct Note {
+            id: usize,
+            text: Varchar<200>,
+        }
+
+        impl TableTrait for Note {
+            fn id(&self) -> usize {
+                self.id
+            }
+
+            fn set_id(&mut self, id: usize) {
+                self.id = id;
+            }
+        }
+
+        let paths = [
+            TABLE_COMPRESSION_KIND_PATH.to_string(),
+            format!("{}.free", TABLE_COMPRESSION_KIND_PATH),
+            format!("{}.overflow", TABLE_COMPRESSION_KIND_PATH),
+            format!("{}.overflow.free", TABLE_COMPRESSION_KIND_PATH),
+        ];
+        let repeated = "ab".repeat(75);
+        let mut seed = 1u32;
+        let mixed: String = (0..150).map(|_| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (b'a' + (seed >> 16) as u8 % 26) as char
+        }).collect();
+        let texts = ["hello", repeated.as_str(), mixed.as_str()];
+
+        let kinds = vec![
+            CompressionKind::Zeros,
+            #[cfg(feature = "lz4")]
+            CompressionKind::Lz4,
+            #[cfg(feature = "zstd")]
+            CompressionKind::Zstd,
+        ];
+
+        for kind in kinds {
+            for path in paths.iter() {
+                _ensure_removed_table_file(path);
+            }
+
+            let table = Table::with_compression_kind::<Note>(
+                TABLE_COMPRESSION_KIND_PATH, kind, 64
+            ).unwrap();
+            assert_eq!(table.get_meta("compression").unwrap().unwrap(),
+                       kind.name());
+            let mut notes: Vec<Note> = texts.iter()
+                .map(|text| Note { id: 0, text: Varchar::new(text) })
+                .collect();
+            Note::insert_many(&mut notes, &table).unwrap();
+
+            // The repeated bytes fit the slot unless only zeros are
+            // suppressed, the mixed ones overflow
+            let overflow = Table::open::<Note>(&paths[2]).unwrap();
+            let overflows = if kind == CompressionKind::Zeros { 2 } else { 1 };
+            assert_eq!(overflow.size().unwrap(), overflows);
+            Note::erase(&table, 1).unwrap();
+            drop(table);
+
+            // The kind is stored in the header
+            let table = Table::with_compression_kind::<Note>(
+                TABLE_COMPRESSION_KIND_PATH, kind, 64
+            ).unwrap();
+            let stored: Vec<String> = Note::all(&table)
+                .map(|note| note.unwrap().text.to_string()).collect();
+            assert_eq!(stored, vec!["", texts[1], texts[2]]);
+            if kind != CompressionKind::Zeros {
+                assert!(matches!(
+                    Table::with_compression::<Note>(
+                        TABLE_COMPRESSION_KIND_PATH, 64
+                    ),
+                    Err(TableError::InvalidHeader(_))
+                ));
+            }
+        }
+
+        for path in paths.iter() {
+            _ensure_removed_table_file(path);
+        }
     }
 
-    fn _ensure_removed_table_file() {
-        if fs::metadata(TABLE_PATH).is_ok() {
-            fs::remove_file(TABLE_PATH).unwrap();
+    fn _ensure_removed_table_file(path: &str) {
+        if fs::metadata(path).is_ok() {
+            fs::remove_file(path).unwrap();
         }
     }
 }
@@ -0,0 +1,140 @@
+use std::io;
+use std::marker::PhantomData;
+
+use crate::table::*;
+use crate::table_trait::*;
+
+
+/// TypedTable is a table bound to its record type **T**, returned by
+/// **Table::open_typed**. Its methods take and return **T** only, so
+/// a record of another type (e.g. an index node) cannot be read from or
+/// written to it by mistake.
+#[derive(Debug)]
+pub struct TypedTable<T> {
+    table: Table,
+    record: PhantomData<T>,
+}
+
+
+impl<T: TableTrait> TypedTable<T> {
+    pub(crate) fn new(table: Table) -> Self {
+        Self { table, record: PhantomData }
+    }
+
+    /// The untyped table, e.g. to pass it to the functions that take
+    /// **Table**.
+    pub fn table(&self) -> &Table {
+        &self.table
+    }
+
+    /// The number of records including the erased ones.
+    pub fn size(&self) -> Result<usize, io::Error> {
+        self.table.size()
+    }
+
+    /// Extracts the record from the table by id.
+    pub fn get(&self, id: usize) -> Result<T, io::Error> {
+        T::get(&self.table, id)
+    }
+
+    /// Extracts several records by their ids keeping the order.
+    pub fn get_many(&self, ids: &[usize]) -> Result<Vec<T>, io::Error> {
+        T::get_many(&self.table, ids)
+    }
+
+    /// Inserts the record to the table. Returns its id.
+    pub fn insert(&self, record: &mut T) -> Result<usize, io::Error> {
+        record.insert(&self.table)
+    }
+
+    /// Updates the record in the table.
+    pub fn update(&self, record: &T) -> Result<(), io::Error> {
+        record.update(&self.table)
+    }
+
+    /// Deletes the record, its block is reused by the next insert.
+    pub fn delete(&self, record: &T) -> Result<(), io::Error> {
+        record.delete(&self.table)
+    }
+
+    /// Iterates the records skipping the erased ones, a record that fails
+    /// to read is yielded as the error.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = Result<T, io::Error>> + '_> {
+        Box::new(T::all(&self.table).filter(
+            |record| record.as_ref().map_or(true, |record| record.id() != 0)
+        ))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::varchar::*;
+    use super::*;
+
+    const TABLE_PATH: &str = "test-typed-person.tbl";
+
+    #[derive(Debug, Copy, Clone)]
+    struct Person {
+        id: usize,
+        name: Varchar<20>,
+        age: u32,
+    }
+
+    impl TableTrait for Person {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    impl Person {
+        fn new(name: &str, age: u32) -> Self {
+            Self { id: 0, name: Varchar::<20>::new(name), age }
+        }
+    }
+
+    #[test]
+    fn test_typed_table() {
+        _ensure_removed_table_file();
+
+        let persons = Table::open_typed::<Person>(TABLE_PATH).unwrap();
+
+        let mut alex = Person::new("alex", 32);
+        assert_eq!(persons.insert(&mut alex).unwrap(), 1);
+        let mut bob = Person::new("bob", 25);
+        persons.insert(&mut bob).unwrap();
+        let mut carl = Person::new("carl", 40);
+        persons.insert(&mut carl).unwrap();
+
+        alex.age = 33;
+        persons.update(&alex).unwrap();
+        assert_eq!(persons.get(1).unwrap().age, 33);
+
+        persons.delete(&bob).unwrap();
+        let names: Vec<String> = persons.iter()
+            .map(|p| p.unwrap().name.to_string()).collect();
+        assert_eq!(names, vec!["alex", "carl"]);
+        assert_eq!(persons.size().unwrap(), 3);
+
+        let ages: Vec<u32> = persons.get_many(&[3, 1]).unwrap()
+            .iter().map(|p| p.age).collect();
+        assert_eq!(ages, vec![40, 33]);
+        assert_eq!(persons.table().free_count().unwrap(), 1);
+
+        _ensure_removed_table_file();
+    }
+
+    fn _ensure_removed_table_file() {
+        for path in &[TABLE_PATH.to_string(), format!("{}.free", TABLE_PATH)] {
+            if fs::metadata(path).is_ok() {
+                fs::remove_file(path).unwrap();
+            }
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::{error, fmt, io};
+
+use crate::table::*;
+use crate::table_trait::*;
+
+
+/// A rejected field of the record with the reason.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    /// The name of the field.
+    pub field: String,
+    /// The reason why the value is rejected.
+    pub reason: String,
+}
+
+
+/// ValidationErrors collects all rejected fields of the record, so they can
+/// be reported at once. It is carried inside **io::Error** of kind
+/// *InvalidData* by the validating insert and update.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationErrors {
+    errors: Vec<FieldError>,
+}
+
+
+impl ValidationErrors {
+    /// Creates an empty collection.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a rejected **field** with the **reason**.
+    pub fn add(&mut self, field: &str, reason: &str) {
+        self.errors.push(FieldError {
+            field: field.to_string(),
+            reason: reason.to_string(),
+        });
+    }
+
+    /// Adds a rejected **field** with the **reason** if **valid** is false.
+    pub fn check(&mut self, valid: bool, field: &str, reason: &str) {
+        if !valid {
+            self.add(field, reason);
+        }
+    }
+
+    /// Returns true if there are no errors, else false.
+    pub fn empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// All errors in the order they were added.
+    pub fn errors(&self) -> &[FieldError] {
+        &self.errors
+    }
+
+    /// The reasons of rejection of the **field**.
+    pub fn field(&self, field: &str) -> Vec<&str> {
+        self.errors.iter()
+            .filter(|err| err.field == field)
+            .map(|err| err.reason.as_str())
+            .collect()
+    }
+
+    /// Extracts the validation errors from **io::Error** returned by
+    /// the validating insert and update.
+    pub fn from_io_error(err: &io::Error) -> Option<&Self> {
+        err.get_ref()?.downcast_ref::<Self>()
+    }
+}
+
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let items: Vec<String> = self.errors.iter()
+            .map(|err| format!("{}: {}", err.field, err.reason))
+            .collect();
+        write!(f, "{}", items.join(", "))
+    }
+}
+
+
+impl error::Error for ValidationErrors {}
+
+
+impl From<ValidationErrors> for io::Error {
+    fn from(errors: ValidationErrors) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, errors)
+    }
+}
+
+
+/// Validate checks all fields of the record before it is written to
+/// the table.
+pub trait Validate: TableTrait {
+    /// Brings the fields to the canonical form (e.g. trims the strings,
+    /// see **VarcharPolicy**) before they are validated. It does nothing
+    /// by default.
+    fn normalize(&mut self) {}
+
+    /// Adds every rejected field to **errors**.
+    fn validate(&self, errors: &mut ValidationErrors);
+
+    /// Returns all validation errors of the record if any.
+    fn validated(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        self.validate(&mut errors);
+        if errors.empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Normalizes, validates and inserts the record to the table.
+    fn insert_valid(&mut self, table: &Table) -> Result<usize, io::Error> {
+        self.normalize();
+        self.validated()?;
+        self.insert(table)
+    }
+
+    /// Validates and updates the record in the table. A normalized copy of
+    /// the record is written, the record itself is not changed.
+    fn update_valid(&self, table: &Table) -> Result<(), io::Error> {
+        let mut record = *self;
+        record.normalize();
+        record.validated()?;
+        record.update(table)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::varchar::*;
+    use super::*;
+
+    const TABLE_PATH: &str = "test-validation-person.tbl";
+
+    #[derive(Debug, Copy, Clone)]
+    struct Person {
+        id: usize,
+        name: Varchar<20>,
+        age: u32,
+    }
+
+    impl TableTrait for Person {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    impl Validate for Person {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            let name = self.name.to_string();
+            errors.check(!name.is_empty(), "name", "empty");
+            errors.check(name.is_ascii(), "name", "not ascii");
+            errors.check(self.age < 150, "age", "too large");
+        }
+    }
+
+    impl Person {
+        fn new(name: &str, age: u32) -> Self {
+            Self { id: 0, name: Varchar::<20>::new(name), age }
+        }
+    }
+
+    #[test]
+    fn test_validation() {
+        _ensure_removed_table_file();
+
+        let table = Table::open::<Person>(TABLE_PATH).unwrap();
+
+        // Valid
+        let mut alex = Person::new("alex", 32);
+        alex.insert_valid(&table).unwrap();
+
+        // All errors are reported
+        let err = Person::new("", 200).insert_valid(&table).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let errors = ValidationErrors::from_io_error(&err).unwrap();
+        assert_eq!(errors.errors().len(), 2);
+        assert_eq!(errors.field("name"), vec!["empty"]);
+        assert_eq!(errors.field("age"), vec!["too large"]);
+        assert_eq!(errors.to_string(), "name: empty, age: too large");
+        assert_eq!(table.size().unwrap(), 1);
+
+        // Update
+        alex.age = 151;
+        assert!(alex.update_valid(&table).is_err());
+        assert_eq!(Person::get(&table, 1).unwrap().age, 32);
+
+        _ensure_removed_table_file();
+    }
+
+    fn _ensure_removed_table_file() {
+        if fs::metadata(TABLE_PATH).is_ok() {
+            fs::remove_file(TABLE_PATH).unwrap();
+        }
+    }
+}
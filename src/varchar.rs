@@ -1,8 +1,34 @@
-use std::fmt;
+use std::{cmp, error, fmt, hash, str};
+use std::convert::TryFrom;
+
+use crate::index_key::*;
+
+
+/// The error of a string longer than the capacity of **Varchar**.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct VarcharTooLong {
+    /// The length of the string in bytes.
+    pub len: usize,
+    /// The capacity of the Varchar in bytes.
+    pub capacity: usize,
+}
+
+
+impl fmt::Display for VarcharTooLong {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "string of {} bytes exceeds varchar of {} bytes",
+               self.len, self.capacity)
+    }
+}
+
+
+impl error::Error for VarcharTooLong {}
 
 
 /// A structure to store bytes of data and the length of the string.
-#[derive(Copy, Clone, PartialOrd, PartialEq, Eq, Hash, Ord)]
+/// Varchars are compared by the bytes of the string, so they are ordered
+/// lexicographically (e.g. "aa" < "z") like *str*.
+#[derive(Copy, Clone)]
 pub struct Varchar<const N: usize> {
     length: usize,
     bytes: [u8; N],
@@ -10,31 +36,202 @@ pub struct Varchar<const N: usize> {
 
 
 impl<const N: usize> Varchar<N> {
-    /// Creates Varchar from *str*.
+    /// Creates Varchar from *str*. It panics if **s** is longer than **N**
+    /// bytes, see **try_new**.
     pub fn new(s: &str) -> Self {
-        let s_bytes = String::from(s).into_bytes();
-        let length = s_bytes.len();
-        assert!(length <= N);
+        assert!(s.len() <= N);
+        Self::_filled(s.as_bytes())
+    }
+
+    /// Creates Varchar from *str*, **VarcharTooLong** is returned if **s**
+    /// is longer than **N** bytes.
+    pub fn try_new(s: &str) -> Result<Self, VarcharTooLong> {
+        Self::_check_len(s.len())?;
+        Ok(Self::_filled(s.as_bytes()))
+    }
+
+    /// The string. The bytes read from a damaged file are cut to the longest
+    /// valid UTF-8 prefix.
+    pub fn as_str(&self) -> &str {
+        let bytes = self._content();
+        match str::from_utf8(bytes) {
+            Ok(s) => s,
+            Err(err) => str::from_utf8(&bytes[..err.valid_up_to()])
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The length of the string in bytes.
+    pub fn len(&self) -> usize {
+        self.length.min(N)
+    }
+
+    /// Returns true if the string is empty, else false.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The greatest length of the string in bytes.
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Appends **s** to the end, **VarcharTooLong** is returned and
+    /// nothing is changed if the result does not fit.
+    pub fn push_str(&mut self, s: &str) -> Result<(), VarcharTooLong> {
+        let length = self.len();
+        Self::_check_len(length + s.len())?;
+        self.bytes[length..length + s.len()].clone_from_slice(s.as_bytes());
+        self.length = length + s.len();
+        Ok(())
+    }
+
+    /// Shortens the string to **new_len** bytes, nothing is changed if it is
+    /// not longer. It panics if **new_len** is not on a char boundary like
+    /// **String::truncate**.
+    pub fn truncate(&mut self, new_len: usize) {
+        if new_len < self.len() {
+            assert!(self.as_str().is_char_boundary(new_len));
+            for byte in self.bytes[new_len..].iter_mut() {
+                *byte = 0;
+            }
+            self.length = new_len;
+        }
+    }
+
+    /// Empties the string.
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    fn _content(&self) -> &[u8] {
+        &self.bytes[..self.len()]
+    }
+
+    fn _filled(s_bytes: &[u8]) -> Self {
         let mut bytes = [0u8; N];
-        bytes[..length].clone_from_slice(&s_bytes);
-        Self { bytes, length }
+        bytes[..s_bytes.len()].clone_from_slice(s_bytes);
+        Self { bytes, length: s_bytes.len() }
+    }
+
+    fn _check_len(len: usize) -> Result<(), VarcharTooLong> {
+        if len > N {
+            return Err(VarcharTooLong { len, capacity: N });
+        }
+        Ok(())
+    }
+}
+
+
+impl<const N: usize> TryFrom<&str> for Varchar<N> {
+    type Error = VarcharTooLong;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        Self::try_new(s)
+    }
+}
+
+
+impl<const N: usize> TryFrom<String> for Varchar<N> {
+    type Error = VarcharTooLong;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::try_new(&s)
+    }
+}
+
+
+impl<const N: usize> PartialEq for Varchar<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self._content() == other._content()
+    }
+}
+
+
+impl<const N: usize> Eq for Varchar<N> {}
+
+
+impl<const N: usize> PartialOrd for Varchar<N> {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+
+impl<const N: usize> Ord for Varchar<N> {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self._content().cmp(other._content())
+    }
+}
+
+
+impl<const N: usize> hash::Hash for Varchar<N> {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self._content().hash(state);
+    }
+}
+
+
+impl<const N: usize> IndexKey for Varchar<N> {
+    fn key_tag() -> u64 {
+        key_tag_of(&format!("Varchar/{}", N))
+    }
+}
+
+
+impl<const N: usize> AsRef<str> for Varchar<N> {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+
+impl<const N: usize> Default for Varchar<N> {
+    /// Creates an empty Varchar.
+    fn default() -> Self {
+        Self { bytes: [0u8; N], length: 0 }
     }
 }
 
 
 impl<const N: usize> fmt::Display for Varchar<N> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let s = String::from_utf8_lossy(
-            &self.bytes[..self.length]
-        ).to_string();
-        write!(f, "{}", s)
+        f.write_str(self.as_str())
     }
 }
 
 
 impl<const N: usize> fmt::Debug for Varchar<N> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Varchar<{}>(\"{}\")", N, self.to_string())
+        write!(f, "Varchar<{}>(\"{}\")", N, self)
+    }
+}
+
+
+#[cfg(feature = "serde")]
+impl<const N: usize> serde::Serialize for Varchar<N> {
+    fn serialize<S: serde::Serializer>(
+                &self,
+                serializer: S
+            ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+
+#[cfg(feature = "serde")]
+impl<'de, const N: usize> serde::Deserialize<'de> for Varchar<N> {
+    /// A string longer than **N** bytes is an error.
+    fn deserialize<D: serde::Deserializer<'de>>(
+                deserializer: D
+            ) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        if s.len() > N {
+            return Err(serde::de::Error::invalid_length(
+                s.len(), &format!("at most {} bytes", N).as_str()
+            ));
+        }
+        Ok(Self::new(&s))
     }
 }
 
@@ -50,5 +247,49 @@ mod tests {
         let v = Varchar::<32>::new("varchar32");
         assert_eq!(v.to_string(), String::from("varchar32"));
         assert_eq!(mem::size_of::<Varchar::<32>>(), 40);
+
+        // A Varchar read from a damaged file is shown without panicking
+        let damaged = Varchar::<4> { length: 100, bytes: *b"ab\xffc" };
+        assert_eq!(damaged.to_string(), "ab");
+        assert_eq!(format!("{:?}", damaged), "Varchar<4>(\"ab\")");
+    }
+
+    #[test]
+    fn test_varchar_string_api() {
+        let mut v = Varchar::<8>::try_new("abc").unwrap();
+        assert_eq!(v.as_str(), "abc");
+        assert_eq!(v.len(), 3);
+        assert_eq!(v.capacity(), 8);
+
+        v.push_str("def").unwrap();
+        assert_eq!(v.as_str(), "abcdef");
+        assert_eq!(v.push_str("xyz").unwrap_err(),
+                   VarcharTooLong { len: 9, capacity: 8 });
+        assert_eq!(v.as_str(), "abcdef");
+
+        // The cut bytes are zeroed, so equal strings are equal Varchars
+        v.truncate(2);
+        assert_eq!(v, Varchar::<8>::new("ab"));
+        v.clear();
+        assert!(v.is_empty());
+        assert_eq!(v, Varchar::<8>::default());
+
+        assert_eq!(Varchar::<4>::try_from("long string").unwrap_err().len, 11);
+        let v: Varchar<8> = Varchar::try_from(String::from("été")).unwrap();
+        assert_eq!(v.len(), 5);
+        assert_eq!(v.as_ref(), "été");
+    }
+
+    #[test]
+    fn test_varchar_ord() {
+        let mut names: Vec<Varchar<8>> = ["z", "aa", "ab", "", "a"].iter()
+            .map(|s| Varchar::<8>::new(s)).collect();
+        names.sort();
+        let names: Vec<&str> = names.iter().map(|v| v.as_str()).collect();
+        assert_eq!(names, vec!["", "a", "aa", "ab", "z"]);
+
+        assert!(Varchar::<8>::new("z") > Varchar::<8>::new("aa"));
+        assert_eq!(Varchar::<8>::new("a").cmp(&Varchar::<8>::new("a")),
+                   cmp::Ordering::Equal);
     }
 }
@@ -0,0 +1,255 @@
+use crate::varchar::*;
+use crate::validation::*;
+
+
+/// The characters allowed in a string field.
+#[derive(Debug, Copy, Clone)]
+pub enum Charset {
+    /// Any characters.
+    Any,
+    /// ASCII characters only.
+    Ascii,
+    /// ASCII letters and digits only.
+    AsciiAlphanumeric,
+    /// The characters accepted by the function.
+    Custom(fn(char) -> bool),
+}
+
+
+impl Charset {
+    /// Returns true if the character is allowed, else false.
+    pub fn allows(&self, c: char) -> bool {
+        match self {
+            Charset::Any => true,
+            Charset::Ascii => c.is_ascii(),
+            Charset::AsciiAlphanumeric => c.is_ascii_alphanumeric(),
+            Charset::Custom(allows) => allows(c),
+        }
+    }
+}
+
+
+/// VarcharPolicy describes the values allowed in a **Varchar** field (the
+/// maximum length is the size of the Varchar). It is applied to the field
+/// in **Validate::normalize** and checked in **Validate::validate**, so
+/// inconsistent strings do not reach the table and its indexes.
+#[derive(Debug, Copy, Clone)]
+pub struct VarcharPolicy {
+    charset: Charset,
+    trim: bool,
+    nfc: bool,
+    not_empty: bool,
+}
+
+
+impl VarcharPolicy {
+    /// Creates the policy allowing any string.
+    pub const fn new() -> Self {
+        Self {
+            charset: Charset::Any, trim: false, nfc: false, not_empty: false
+        }
+    }
+
+    /// Allows the characters of the **charset** only.
+    pub const fn charset(mut self, charset: Charset) -> Self {
+        self.charset = charset;
+        self
+    }
+
+    /// Removes the leading and trailing whitespaces.
+    pub const fn trim(mut self) -> Self {
+        self.trim = true;
+        self
+    }
+
+    /// Converts the string to the Unicode normalization form C, so equal
+    /// strings have equal bytes in the indexes.
+    #[cfg(feature = "unicode")]
+    pub const fn nfc(mut self) -> Self {
+        self.nfc = true;
+        self
+    }
+
+    /// Rejects empty strings (after trimming).
+    pub const fn not_empty(mut self) -> Self {
+        self.not_empty = true;
+        self
+    }
+
+    /// Returns the value trimmed and normalized by the policy. If
+    /// the result does not fit the Varchar, the value is returned as is
+    /// and rejected by **check**.
+    pub fn normalize<const N: usize>(&self, value: &Varchar<N>) -> Varchar<N> {
+        let normalized = self._normalize(value.as_str());
+        if normalized.len() <= N {
+            Varchar::<N>::new(&normalized)
+        } else {
+            *value
+        }
+    }
+
+    /// Checks the value. Returns the reason of rejection if any.
+    pub fn check<const N: usize>(
+                &self,
+                value: &Varchar<N>
+            ) -> Result<(), String> {
+        let s = value.to_string();
+        if self.not_empty && s.trim().is_empty() {
+            return Err("empty".to_string());
+        }
+        if let Some(c) = s.chars().find(|c| !self.charset.allows(*c)) {
+            return Err(format!("invalid character {:?}", c));
+        }
+        if self._normalize(&s) != s {
+            return Err("not normalized".to_string());
+        }
+        Ok(())
+    }
+
+    fn _normalize(&self, s: &str) -> String {
+        let s = if self.trim { s.trim() } else { s };
+        if self.nfc {
+            return Self::_nfc(s);
+        }
+        s.to_string()
+    }
+
+    #[cfg(feature = "unicode")]
+    fn _nfc(s: &str) -> String {
+        use unicode_normalization::UnicodeNormalization;
+        s.nfc().collect()
+    }
+
+    #[cfg(not(feature = "unicode"))]
+    fn _nfc(s: &str) -> String {
+        s.to_string()
+    }
+}
+
+
+impl Default for VarcharPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+impl ValidationErrors {
+    /// Adds a rejected **field** if its **value** does not satisfy
+    /// the **policy**.
+    pub fn check_varchar<const N: usize>(
+                &mut self,
+                field: &str,
+                value: &Varchar<N>,
+                policy: &VarcharPolicy
+            ) {
+        if let Err(reason) = policy.check(value) {
+            self.add(field, &reason);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::table::*;
+    use crate::table_trait::*;
+    use super::*;
+
+    const TABLE_PATH: &str = "test-varchar-policy-person.tbl";
+
+    const LOGIN: VarcharPolicy = VarcharPolicy::new()
+        .charset(Charset::AsciiAlphanumeric)
+        .trim()
+        .not_empty();
+
+    #[derive(Debug, Copy, Clone)]
+    struct Person {
+        id: usize,
+        login: Varchar<20>,
+        name: Varchar<20>,
+    }
+
+    impl TableTrait for Person {
+        fn id(&self) -> usize {
+            self.id
+        }
+
+        fn set_id(&mut self, id: usize) {
+            self.id = id;
+        }
+    }
+
+    impl Validate for Person {
+        fn normalize(&mut self) {
+            self.login = LOGIN.normalize(&self.login);
+        }
+
+        fn validate(&self, errors: &mut ValidationErrors) {
+            errors.check_varchar("login", &self.login, &LOGIN);
+            errors.check_varchar(
+                "name", &self.name, &VarcharPolicy::new().trim()
+            );
+        }
+    }
+
+    impl Person {
+        fn new(login: &str, name: &str) -> Self {
+            Self {
+                id: 0,
+                login: Varchar::<20>::new(login),
+                name: Varchar::<20>::new(name),
+            }
+        }
+    }
+
+    #[test]
+    fn test_varchar_policy() {
+        _ensure_removed_table_file();
+
+        let table = Table::open::<Person>(TABLE_PATH).unwrap();
+
+        // Normalized on insert
+        let mut alex = Person::new("  alex ", "Alex");
+        alex.insert_valid(&table).unwrap();
+        assert_eq!(alex.login.to_string(), "alex");
+        assert_eq!(Person::get(&table, 1).unwrap().login.to_string(), "alex");
+
+        // Rejected values
+        let err = Person::new("al ex", " Bob").insert_valid(&table)
+            .unwrap_err();
+        let errors = ValidationErrors::from_io_error(&err).unwrap();
+        assert_eq!(errors.field("login"), vec!["invalid character ' '"]);
+        assert_eq!(errors.field("name"), vec!["not normalized"]);
+        let err = Person::new("   ", "Bob").insert_valid(&table).unwrap_err();
+        let errors = ValidationErrors::from_io_error(&err).unwrap();
+        assert_eq!(errors.field("login"), vec!["empty"]);
+        assert!(Person::new("ålex", "Alex").insert_valid(&table).is_err());
+
+        // Normalized copy is written on update
+        alex.login = Varchar::<20>::new("alex2 ");
+        alex.update_valid(&table).unwrap();
+        assert_eq!(Person::get(&table, 1).unwrap().login.to_string(), "alex2");
+
+        _ensure_removed_table_file();
+    }
+
+    #[cfg(feature = "unicode")]
+    #[test]
+    fn test_nfc() {
+        let policy = VarcharPolicy::new().nfc();
+        let decomposed = Varchar::<20>::new("cafe\u{301}");
+        assert!(policy.check(&decomposed).is_err());
+        let composed = policy.normalize(&decomposed);
+        assert_eq!(composed.to_string(), "caf\u{e9}");
+        assert!(policy.check(&composed).is_ok());
+    }
+
+    fn _ensure_removed_table_file() {
+        if fs::metadata(TABLE_PATH).is_ok() {
+            fs::remove_file(TABLE_PATH).unwrap();
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use std::{fs, io};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::file_ext::FileExt;
+
+
+const FIELD_SIZE: usize = 8;
+
+
+/// WriteAheadLog keeps the blocks being written to the table in its own
+/// file, so a write interrupted by a crash can be finished on the next
+/// open. Each entry is the index of the block, the length of the data,
+/// the data and the checksum (FNV-1a) of them, 8 bytes per number.
+/// The log is cleared once the block is on the disk.
+#[derive(Debug)]
+pub struct WriteAheadLog {
+    file: Mutex<fs::File>,
+}
+
+
+impl WriteAheadLog {
+    /// Creates or opens a log file.
+    pub fn open(path: &str) -> Result<Self, io::Error> {
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        Ok(Self { file: Mutex::new(file) })
+    }
+
+    /// Logs the block, writes it by **apply** and clears the log.
+    /// The writes through the same log are serialized.
+    pub fn write(
+                &self,
+                idx: usize,
+                block: &[u8],
+                apply: impl FnOnce() -> Result<(), io::Error>
+            ) -> Result<(), io::Error> {
+        let file = self._file()?;
+        Self::_log(&file, idx, block)?;
+        apply()?;
+        Self::_clear(&file)
+    }
+
+    /// Appends an entry of the block and flushes it to the disk.
+    pub fn log(&self, idx: usize, block: &[u8]) -> Result<(), io::Error> {
+        let file = self._file()?;
+        Self::_log(&file, idx, block)
+    }
+
+    /// The logged blocks with their indices in the order of writing.
+    /// Reading stops at the first incomplete or corrupted entry, it is
+    /// the one whose logging was interrupted.
+    pub fn entries(&self) -> Result<Vec<(usize, Vec<u8>)>, io::Error> {
+        let file = self._file()?;
+        let len = file.metadata()?.len() as usize;
+        let mut entries = Vec::new();
+        let mut offset = 0;
+
+        while offset + 2 * FIELD_SIZE <= len {
+            let idx = Self::_read_field(&file, offset)? as usize;
+            let size = Self::_read_field(&file, offset + FIELD_SIZE)? as usize;
+            let end = offset + 2 * FIELD_SIZE + size;
+            if end + FIELD_SIZE > len {
+                break;
+            }
+
+            let mut bytes = vec![0u8; end - offset];
+            file.read_exact_at(&mut bytes, offset as u64)?;
+            if Self::_read_field(&file, end)? != Self::_checksum(&bytes) {
+                break;
+            }
+
+            entries.push((idx, bytes[2 * FIELD_SIZE..].to_vec()));
+            offset = end + FIELD_SIZE;
+        }
+
+        Ok(entries)
+    }
+
+    /// Removes all entries.
+    pub fn clear(&self) -> Result<(), io::Error> {
+        let file = self._file()?;
+        Self::_clear(&file)
+    }
+
+    fn _log(file: &fs::File, idx: usize, block: &[u8]) -> Result<(), io::Error> {
+        let mut entry = (idx as u64).to_le_bytes().to_vec();
+        entry.extend_from_slice(&(block.len() as u64).to_le_bytes());
+        entry.extend_from_slice(block);
+        let checksum = Self::_checksum(&entry);
+        entry.extend_from_slice(&checksum.to_le_bytes());
+
+        file.write_all_at(&entry, file.metadata()?.len())?;
+        file.sync_data()
+    }
+
+    fn _clear(file: &fs::File) -> Result<(), io::Error> {
+        file.set_len(0)?;
+        file.sync_data()
+    }
+
+    fn _read_field(file: &fs::File, offset: usize) -> Result<u64, io::Error> {
+        let mut bytes = [0u8; FIELD_SIZE];
+        file.read_exact_at(&mut bytes, offset as u64)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn _checksum(bytes: &[u8]) -> u64 {
+        bytes.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ (*byte as u64)).wrapping_mul(0x100000001b3)
+        })
+    }
+
+    fn _file(&self) -> Result<MutexGuard<'_, fs::File>, io::Error> {
+        self.file.lock().map_err(
+            |_| io::Error::other("write-ahead log lock")
+        )
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WAL_PATH: &str = "test-wal.tbl.wal";
+
+    #[test]
+    fn test_wal() {
+        _ensure_removed_file();
+
+        let wal = WriteAheadLog::open(WAL_PATH).unwrap();
+        assert!(wal.entries().unwrap().is_empty());
+
+        wal.log(3, &[1, 2, 3]).unwrap();
+        wal.log(0, &[4, 5]).unwrap();
+        assert_eq!(
+            wal.entries().unwrap(),
+            vec![(3, vec![1, 2, 3]), (0, vec![4, 5])]
+        );
+
+        // A torn entry at the end is ignored
+        let file = fs::OpenOptions::new().write(true).open(WAL_PATH).unwrap();
+        let len = file.metadata().unwrap().len();
+        file.set_len(len - 1).unwrap();
+        assert_eq!(wal.entries().unwrap(), vec![(3, vec![1, 2, 3])]);
+
+        // The log is cleared after the write
+        wal.clear().unwrap();
+        wal.write(1, &[6], || Ok(())).unwrap();
+        assert!(wal.entries().unwrap().is_empty());
+
+        // A failed write stays in the log
+        let err = wal.write(2, &[7], || {
+            Err(io::Error::other("crash"))
+        }).unwrap_err();
+        assert_eq!(err.to_string(), "crash");
+        assert_eq!(wal.entries().unwrap(), vec![(2, vec![7])]);
+
+        _ensure_removed_file();
+    }
+
+    fn _ensure_removed_file() {
+        if fs::metadata(WAL_PATH).is_ok() {
+            fs::remove_file(WAL_PATH).unwrap();
+        }
+    }
+}